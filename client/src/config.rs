@@ -1,12 +1,44 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use geist_sdk::LogLevel;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Commands run against the server. Without one the client only validates its configuration.
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Subscribe to every feed of an OPML file, keeping its folders as folders and tags
+    ImportOpml {
+        /// Path of the OPML file
+        file: PathBuf,
+        /// Subscribe the feeds for this user instead of yourself
+        #[arg(long, conflicts_with = "group_uid")]
+        user_uid: Option<String>,
+        /// Subscribe the feeds for this group instead of yourself
+        #[arg(long)]
+        group_uid: Option<String>,
+    },
+    /// Write subscriptions as an OPML file
+    ExportOpml {
+        /// Path to write the OPML file to, standard output when omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Export the subscriptions of this user instead of your own
+        #[arg(long, conflicts_with = "group_uid")]
+        user_uid: Option<String>,
+        /// Export the subscriptions of this group instead of your own
+        #[arg(long)]
+        group_uid: Option<String>,
+    },
+}
 
 #[derive(Debug, Clone, Parser)]
 #[command(name = "geist-client", version)]
 pub struct AppConfig {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(
         long,
         env = "LOG_LEVEL",
@@ -42,6 +74,15 @@ pub struct AppConfig {
         help = "HTTP server address"
     )]
     pub http_address: SocketAddr,
+
+    /// Access token sent with every request
+    #[arg(
+        long,
+        env = "ACCESS_TOKEN",
+        hide_env_values = true,
+        help = "Access token used to authenticate with the server"
+    )]
+    pub access_token: Option<String>,
 }
 
 impl AppConfig {
@@ -58,6 +99,10 @@ pub fn validate(&self) -> Result<(), Vec<String>> {
             errors.push("GRPC_ADDRESS and HTTP_ADDRESS cannot be the same".to_string());
         }
 
+        if self.command.is_some() && self.access_token.is_none() {
+            errors.push("ACCESS_TOKEN is required to run commands".to_string());
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -1 +1,3 @@
 pub mod config;
+pub mod opml;
+pub mod rpc;
@@ -2,7 +2,9 @@
 
 use color_eyre::Result;
 use dotenvy::dotenv;
-use geist_client::config::AppConfig;
+use geist_client::config::{AppConfig, Command};
+use geist_client::{opml, rpc};
+use geist_sdk::pb::meta::v1alpha::subscription_service_client::SubscriptionServiceClient;
 use std::error::Error;
 
 #[tokio::main]
@@ -34,5 +36,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
         "Starting Geist client"
     );
 
+    let Some(command) = config.command.clone() else {
+        return Ok(());
+    };
+
+    let (channel, auth) = rpc::connect(&config).await?;
+    let mut subscriptions = SubscriptionServiceClient::with_interceptor(channel, auth);
+
+    match command {
+        Command::ImportOpml {
+            file,
+            user_uid,
+            group_uid,
+        } => opml::import(&mut subscriptions, &file, user_uid, group_uid).await?,
+        Command::ExportOpml {
+            output,
+            user_uid,
+            group_uid,
+        } => opml::export(&mut subscriptions, output.as_deref(), user_uid, group_uid).await?,
+    }
+
     Ok(())
 }
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::rpc::BearerToken;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use geist_sdk::pb::meta::v1alpha::{
+    opml_outline_result::Outcome, subscription_service_client::SubscriptionServiceClient,
+    ExportOpmlRequest, ImportOpmlRequest,
+};
+use std::path::Path;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Channel;
+
+type Client = SubscriptionServiceClient<InterceptedService<Channel, BearerToken>>;
+
+/// Import an OPML file and print the outcome of each of its feeds.
+pub async fn import(
+    client: &mut Client,
+    file: &Path,
+    user_uid: Option<String>,
+    group_uid: Option<String>,
+) -> Result<()> {
+    let document =
+        std::fs::read(file).wrap_err_with(|| format!("Failed to read {}", file.display()))?;
+
+    let response = client
+        .import_opml(ImportOpmlRequest {
+            document,
+            user_uid: user_uid.unwrap_or_default(),
+            group_uid: group_uid.unwrap_or_default(),
+        })
+        .await?
+        .into_inner();
+
+    for result in &response.results {
+        let name = if result.title.is_empty() {
+            &result.xml_url
+        } else {
+            &result.title
+        };
+        match result.outcome() {
+            Outcome::Created => println!("created    {} <{}>", name, result.xml_url),
+            Outcome::Duplicate => println!("duplicate  {} <{}>", name, result.xml_url),
+            _ => println!(
+                "failed     {} <{}>: {}",
+                name, result.xml_url, result.message
+            ),
+        }
+    }
+    println!(
+        "{} created, {} duplicates, {} failed",
+        response.created, response.duplicates, response.failed
+    );
+
+    Ok(())
+}
+
+/// Export subscriptions as OPML to a file, or standard output without one.
+pub async fn export(
+    client: &mut Client,
+    output: Option<&Path>,
+    user_uid: Option<String>,
+    group_uid: Option<String>,
+) -> Result<()> {
+    let response = client
+        .export_opml(ExportOpmlRequest {
+            user_uid: user_uid.unwrap_or_default(),
+            group_uid: group_uid.unwrap_or_default(),
+        })
+        .await?
+        .into_inner();
+
+    match output {
+        Some(path) => std::fs::write(path, &response.document)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{}", String::from_utf8_lossy(&response.document)),
+    }
+
+    Ok(())
+}
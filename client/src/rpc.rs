@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::AppConfig;
+use color_eyre::eyre::{eyre, WrapErr};
+use color_eyre::Result;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+
+/// Adds the configured access token to every request.
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    value: MetadataValue<Ascii>,
+}
+
+impl BearerToken {
+    pub fn new(token: &str) -> Result<Self> {
+        let value = format!("Bearer {}", token)
+            .parse()
+            .map_err(|_| eyre!("ACCESS_TOKEN contains invalid characters"))?;
+        Ok(Self { value })
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        req.metadata_mut()
+            .insert("authorization", self.value.clone());
+        Ok(req)
+    }
+}
+
+/// Connect to the gRPC server, returning the channel and the interceptor that authenticates
+/// requests sent over it.
+pub async fn connect(config: &AppConfig) -> Result<(Channel, BearerToken)> {
+    let token = config
+        .access_token
+        .as_deref()
+        .ok_or_else(|| eyre!("ACCESS_TOKEN is required"))?;
+    let auth = BearerToken::new(token)?;
+
+    let channel = Endpoint::from_shared(format!("http://{}", config.grpc_address))?
+        .connect()
+        .await
+        .wrap_err_with(|| format!("Failed to connect to {}", config.grpc_address))?;
+
+    Ok((channel, auth))
+}
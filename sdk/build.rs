@@ -1,45 +1,49 @@
 use std::error::Error;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 
-// This build script uses Buf to download protobuf dependencies and generate
-// server and client code using the Tonic build crate. Unfortunately, the
-// generated code does not use the protobuf hierarchy as modules in the code.
-// It would also be very nice if this could be encapsulated in the Tonic crates.
+// This build script generates server and client code for the protobufs in `proto` using the
+// Tonic build crate. It uses the protoc binary and well-known types vendored by
+// `protoc-bin-vendored`, and the copy of `buf/validate/validate.proto` in `third_party`, so
+// that building does not need the Buf CLI or access to the Buf Schema Registry.
 fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo::rerun-if-changed=proto");
-    println!("cargo::rerun-if-changed=buf.lock");
-    // Export the entire protobuf dependency tree into exploded directories.
-    let path = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
-    let _ = Command::new("buf")
-        .args(["export", "--output", path.to_str().unwrap()])
-        .current_dir(env!("CARGO_MANIFEST_DIR"))
-        .spawn()?
-        .wait()
-        .expect("failed to buf export");
+    println!("cargo::rerun-if-changed=third_party");
 
-    // Get the list of protobufs filenames in the exploded directories.
-    let mut command = Command::new("buf");
-    let output = command
-        .args(["ls-files", path.to_str().unwrap()])
-        .stdout(Stdio::piped())
-        .current_dir(env!("CARGO_MANIFEST_DIR"))
-        .spawn()
-        .expect("failed to buf ls-files");
+    let path = PathBuf::from(std::env::var("OUT_DIR")?);
+    let protoc = protoc_bin_vendored::protoc_bin_path()?;
+    let include = protoc_bin_vendored::include_path()?;
 
-    // Split the command output into a vector of filename strings.
-    let output = output.wait_with_output().unwrap();
-    let files = String::from_utf8(output.stdout).unwrap();
-    let files = files
-        .split('\n')
-        .filter(|entry| !entry.is_empty())
-        .collect::<Vec<&str>>();
+    let mut files = Vec::new();
+    find_protos(Path::new("proto"), &mut files)?;
+    files.sort();
 
-    // Compile protobufs and generate Rust server and client code (gRPC).
+    // Compile protobufs and generate Rust server and client code (gRPC). The descriptor set
+    // keeps the field options, which carry the validation rules the server enforces.
+    let mut config = tonic_prost_build::Config::new();
+    config.protoc_executable(protoc);
     tonic_prost_build::configure()
         .build_server(true)
         .build_client(true)
         .build_transport(true)
-        .compile_protos(&files, &[path.to_str().unwrap()])?;
+        .file_descriptor_set_path(path.join("geist_descriptor.bin"))
+        .compile_with_config(
+            config,
+            &files,
+            &[PathBuf::from("proto"), PathBuf::from("third_party"), include],
+        )?;
 
     Ok(())
 }
+
+/// Collect the `.proto` files under `dir`.
+fn find_protos(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_protos(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "proto") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -3,6 +3,9 @@
 use clap::ValueEnum;
 
 pub mod pb {
+    /// Encoded `FileDescriptorSet` of every compiled protobuf, including their options.
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("geist_descriptor");
+
     pub mod meta {
         pub mod v1alpha {
             tonic::include_proto!("geist.meta.v1alpha");
@@ -1,4 +1,4 @@
-drop extension if exists `hstore`;
-drop extension if exists `pgcrypto`;
-drop extension if exists `citext`;
-drop extension if exists `uuid-ossp`;
\ No newline at end of file
+drop extension if exists "hstore";
+drop extension if exists "pgcrypto";
+drop extension if exists "citext";
+drop extension if exists "uuid-ossp";
\ No newline at end of file
@@ -1,4 +1,4 @@
-create extension if not exists `uuid-ossp`;
-create extension if not exists `citext`;
-create extension if not exists `pgcrypto`;
-create extension if not exists `hstore`;
\ No newline at end of file
+create extension if not exists "uuid-ossp";
+create extension if not exists "citext";
+create extension if not exists "pgcrypto";
+create extension if not exists "hstore";
\ No newline at end of file
@@ -1,5 +1,5 @@
-create type if not exists public.feed_type as enum ('rss', 'atom', 'json', 'xml');
-create type if not exists public.feed_visibility as enum ('internal', 'public', 'private', 'preview', 'global');
+create type public.feed_type as enum ('rss', 'atom', 'json', 'xml');
+create type public.feed_visibility as enum ('internal', 'public', 'private', 'preview', 'global');
 
 create table if not exists public.feeds (
     id uuid not null default gen_random_uuid(),
@@ -1,4 +1,4 @@
-create type if not exists public.group_visibility as enum ('internal', 'public', 'private', 'preview', 'global');
+create type public.group_visibility as enum ('internal', 'public', 'private', 'preview', 'global');
 
 create table if not exists public.groups (
     id uuid not null default gen_random_uuid(),
@@ -1,6 +1,6 @@
 create table if not exists public.users (
-    id uuid not null default gen_random_uuid(),
-    name citext not null primary key,
+    id uuid not null default gen_random_uuid() primary key,
+    name citext not null,
     email citext not null unique,
     username citext not null unique,
     avatar_url text,
@@ -1,5 +1,5 @@
 -- Create identity provider enum
-create type if not exists public.identity_provider as enum (
+create type public.identity_provider as enum (
     'google',
     'github',
     'twitter',
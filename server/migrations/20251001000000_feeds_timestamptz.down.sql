@@ -0,0 +1,3 @@
+alter table public.feeds
+    alter column create_time type timestamp using create_time at time zone 'utc',
+    alter column update_time type timestamp using update_time at time zone 'utc';
@@ -0,0 +1,4 @@
+-- Store feed timestamps with a time zone so they map onto DateTime<Utc>
+alter table public.feeds
+    alter column create_time type timestamptz using create_time at time zone 'utc',
+    alter column update_time type timestamptz using update_time at time zone 'utc';
@@ -0,0 +1,9 @@
+alter table public.user_identities
+    alter column token_expires_at type timestamp using token_expires_at at time zone 'utc',
+    alter column create_time type timestamp using create_time at time zone 'utc',
+    alter column update_time type timestamp using update_time at time zone 'utc',
+    alter column last_used_at type timestamp using last_used_at at time zone 'utc';
+
+alter table public.users
+    alter column create_time type timestamp using create_time at time zone 'utc',
+    alter column update_time type timestamp using update_time at time zone 'utc';
@@ -0,0 +1,10 @@
+-- Store user and identity timestamps with a time zone so they map onto DateTime<Utc>
+alter table public.users
+    alter column create_time type timestamptz using create_time at time zone 'utc',
+    alter column update_time type timestamptz using update_time at time zone 'utc';
+
+alter table public.user_identities
+    alter column token_expires_at type timestamptz using token_expires_at at time zone 'utc',
+    alter column create_time type timestamptz using create_time at time zone 'utc',
+    alter column update_time type timestamptz using update_time at time zone 'utc',
+    alter column last_used_at type timestamptz using last_used_at at time zone 'utc';
@@ -0,0 +1,4 @@
+-- Store group timestamps with a time zone so they map onto DateTime<Utc>
+alter table public.groups
+    alter column create_time type timestamptz using create_time at time zone 'utc',
+    alter column update_time type timestamptz using update_time at time zone 'utc';
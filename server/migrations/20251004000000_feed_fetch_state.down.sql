@@ -0,0 +1,11 @@
+drop index if exists idx_feeds_next_fetch_time;
+
+alter table public.feeds
+    drop column if exists next_fetch_time,
+    drop column if exists fetch_failures,
+    drop column if exists last_fetch_error,
+    drop column if exists last_fetch_status,
+    drop column if exists last_fetch_time,
+    drop column if exists last_modified,
+    drop column if exists etag,
+    drop column if exists poll_interval_secs;
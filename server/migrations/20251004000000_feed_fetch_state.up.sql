@@ -0,0 +1,12 @@
+-- Polling schedule and conditional request state for the feed fetcher
+alter table public.feeds
+    add column if not exists poll_interval_secs integer not null default 3600,
+    add column if not exists etag text,
+    add column if not exists last_modified text,
+    add column if not exists last_fetch_time timestamptz,
+    add column if not exists last_fetch_status integer,
+    add column if not exists last_fetch_error text,
+    add column if not exists fetch_failures integer not null default 0,
+    add column if not exists next_fetch_time timestamptz not null default now();
+
+create index if not exists idx_feeds_next_fetch_time on public.feeds(next_fetch_time);
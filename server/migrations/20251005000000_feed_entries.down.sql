@@ -0,0 +1,6 @@
+drop table if exists public.feed_entry_states;
+drop table if exists public.feed_entries;
+drop function if exists public.guid_hash(text);
+
+drop index if exists idx_users_id_unique;
+drop index if exists idx_feeds_id_unique;
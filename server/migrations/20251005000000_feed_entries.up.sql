@@ -0,0 +1,48 @@
+-- Foreign keys need a unique constraint on the referenced uuid columns
+create unique index if not exists idx_feeds_id_unique on public.feeds(id);
+create unique index if not exists idx_users_id_unique on public.users(id);
+
+-- convert_to is only stable, so wrap it for use in a generated column; the encoding is fixed
+create or replace function public.guid_hash(guid text) returns bytea
+    language sql immutable strict parallel safe
+    as $$ select sha256(convert_to(guid, 'UTF8')) $$;
+
+create table if not exists public.feed_entries (
+    id uuid not null default gen_random_uuid() primary key,
+    feed_id uuid not null references public.feeds(id) on delete cascade,
+    guid text not null,
+    guid_hash bytea not null generated always as (public.guid_hash(guid)) stored,
+    title text,
+    link text,
+    summary text,
+    content text,
+    authors jsonb not null default '[]'::jsonb,
+    enclosures jsonb not null default '[]'::jsonb,
+    categories text[] not null default '{}',
+    publish_time timestamptz not null default now(),
+    create_time timestamptz not null default now(),
+    update_time timestamptz not null default now(),
+
+    -- Entries are deduplicated per feed by their guid (or link when the feed has no guid)
+    unique(feed_id, guid_hash)
+);
+
+create index if not exists idx_feed_entries_feed_publish_time
+    on public.feed_entries(feed_id, publish_time desc, id desc);
+create index if not exists idx_feed_entries_publish_time
+    on public.feed_entries(publish_time desc, id desc);
+
+-- Per-user read and starred state
+create table if not exists public.feed_entry_states (
+    user_id uuid not null references public.users(id) on delete cascade,
+    entry_id uuid not null references public.feed_entries(id) on delete cascade,
+    read boolean not null default false,
+    starred boolean not null default false,
+    update_time timestamptz not null default now(),
+
+    primary key (user_id, entry_id)
+);
+
+create index if not exists idx_feed_entry_states_entry_id on public.feed_entry_states(entry_id);
+create index if not exists idx_feed_entry_states_starred
+    on public.feed_entry_states(user_id) where starred = true;
@@ -0,0 +1,2 @@
+drop trigger if exists feed_entries_notify on public.feed_entries;
+drop function if exists public.notify_feed_entry();
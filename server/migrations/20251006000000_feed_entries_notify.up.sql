@@ -0,0 +1,12 @@
+-- Announce new entries so that every server instance can push them to watching clients
+create or replace function public.notify_feed_entry() returns trigger as $$
+begin
+    perform pg_notify('feed_entries', new.id::text);
+    return new;
+end;
+$$ language plpgsql;
+
+drop trigger if exists feed_entries_notify on public.feed_entries;
+create trigger feed_entries_notify
+    after insert on public.feed_entries
+    for each row execute function public.notify_feed_entry();
@@ -0,0 +1,19 @@
+-- Refresh tokens are stored as SHA-256 hashes; the raw token is only ever returned to the client
+create table if not exists public.refresh_tokens (
+    id uuid not null default gen_random_uuid() primary key,
+    user_id uuid not null references public.users(id) on delete cascade,
+    identity_id uuid references public.user_identities(id) on delete set null,
+    -- Every token rotated from the same sign-in shares a family, so reuse of a rotated token
+    -- revokes the whole chain
+    family_id uuid not null,
+    token_hash bytea not null unique,
+    scope text not null default '',
+    expire_time timestamptz not null,
+    create_time timestamptz not null default now(),
+    revoke_time timestamptz,
+    replaced_by uuid references public.refresh_tokens(id) on delete set null
+);
+
+create index if not exists idx_refresh_tokens_user_id on public.refresh_tokens(user_id);
+create index if not exists idx_refresh_tokens_family_id on public.refresh_tokens(family_id);
+create index if not exists idx_refresh_tokens_expire_time on public.refresh_tokens(expire_time);
@@ -0,0 +1 @@
+drop table if exists public.oauth_login_states;
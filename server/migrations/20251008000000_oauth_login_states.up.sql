@@ -0,0 +1,12 @@
+-- Pending OAuth sign-ins, keyed by the SHA-256 hash of the state parameter. Each row is consumed
+-- by the matching callback and holds the PKCE verifier and OIDC nonce for the attempt
+create table if not exists public.oauth_login_states (
+    state_hash bytea not null primary key,
+    provider identity_provider not null,
+    code_verifier text not null,
+    nonce text not null,
+    create_time timestamptz not null default now(),
+    expire_time timestamptz not null
+);
+
+create index if not exists idx_oauth_login_states_expire_time on public.oauth_login_states(expire_time);
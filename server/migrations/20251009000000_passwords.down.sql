@@ -0,0 +1,2 @@
+drop table if exists public.password_reset_tokens;
+drop table if exists public.user_passwords;
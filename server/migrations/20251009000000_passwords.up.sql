@@ -0,0 +1,19 @@
+-- Argon2id hashes for email identities, kept apart from the identity rows that are read for
+-- every request
+create table if not exists public.user_passwords (
+    identity_id uuid not null primary key references public.user_identities(id) on delete cascade,
+    password_hash text not null,
+    create_time timestamptz not null default now(),
+    update_time timestamptz not null default now()
+);
+
+-- Password reset tokens are stored as SHA-256 hashes and deleted when used
+create table if not exists public.password_reset_tokens (
+    token_hash bytea not null primary key,
+    identity_id uuid not null references public.user_identities(id) on delete cascade,
+    expire_time timestamptz not null,
+    create_time timestamptz not null default now()
+);
+
+create index if not exists idx_password_reset_tokens_identity_id on public.password_reset_tokens(identity_id);
+create index if not exists idx_password_reset_tokens_expire_time on public.password_reset_tokens(expire_time);
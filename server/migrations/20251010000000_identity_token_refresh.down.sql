@@ -0,0 +1,7 @@
+drop index if exists idx_user_identities_token_expires_at;
+
+alter table public.user_identities
+    drop column if exists next_token_refresh_time,
+    drop column if exists token_refresh_error,
+    drop column if exists token_refresh_failures,
+    drop column if exists needs_reauth;
@@ -0,0 +1,11 @@
+-- Background refresh of provider tokens. Identities whose refresh token stops working are
+-- marked as needing the user to sign in with the provider again
+alter table public.user_identities
+    add column if not exists needs_reauth boolean not null default false,
+    add column if not exists token_refresh_failures integer not null default 0,
+    add column if not exists token_refresh_error text,
+    add column if not exists next_token_refresh_time timestamptz;
+
+create index if not exists idx_user_identities_token_expires_at
+    on public.user_identities(token_expires_at)
+    where refresh_token_encrypted is not null and not needs_reauth;
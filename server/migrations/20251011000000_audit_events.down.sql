@@ -0,0 +1,4 @@
+drop trigger if exists audit_events_no_truncate on public.audit_events;
+drop trigger if exists audit_events_append_only on public.audit_events;
+drop function if exists public.reject_audit_event_change();
+drop table if exists public.audit_events;
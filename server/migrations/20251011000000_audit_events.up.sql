@@ -0,0 +1,32 @@
+-- Append-only log of security-relevant changes. Users are referenced without foreign keys so
+-- that events outlive the users they describe
+create table if not exists public.audit_events (
+    id uuid not null default gen_random_uuid() primary key,
+    actor_user_id uuid,
+    target_user_id uuid not null,
+    action text not null,
+    identity_id uuid,
+    ip_address inet,
+    trace_id text,
+    create_time timestamptz not null default now()
+);
+
+create index if not exists idx_audit_events_target_user_id on public.audit_events(target_user_id, create_time desc);
+create index if not exists idx_audit_events_actor_user_id on public.audit_events(actor_user_id, create_time desc) where actor_user_id is not null;
+create index if not exists idx_audit_events_create_time on public.audit_events(create_time desc);
+
+create or replace function public.reject_audit_event_change() returns trigger as $$
+begin
+    raise exception 'audit_events is append-only';
+end;
+$$ language plpgsql;
+
+drop trigger if exists audit_events_append_only on public.audit_events;
+create trigger audit_events_append_only
+    before update or delete on public.audit_events
+    for each row execute function public.reject_audit_event_change();
+
+drop trigger if exists audit_events_no_truncate on public.audit_events;
+create trigger audit_events_no_truncate
+    before truncate on public.audit_events
+    for each statement execute function public.reject_audit_event_change();
@@ -0,0 +1,11 @@
+drop index if exists idx_groups_owner_id;
+drop index if exists idx_feeds_owner_id;
+
+alter table public.groups
+    drop column if exists owner_id;
+
+alter table public.feeds
+    drop column if exists owner_id;
+
+alter table public.users
+    drop column if exists is_admin;
@@ -0,0 +1,13 @@
+-- Roles and ownership for authorization. Admins may act on any user or resource, everyone
+-- else only on themselves and on the feeds and groups they created
+alter table public.users
+    add column if not exists is_admin boolean not null default false;
+
+alter table public.feeds
+    add column if not exists owner_id uuid references public.users(id) on delete set null;
+
+alter table public.groups
+    add column if not exists owner_id uuid references public.users(id) on delete set null;
+
+create index if not exists idx_feeds_owner_id on public.feeds(owner_id);
+create index if not exists idx_groups_owner_id on public.groups(owner_id);
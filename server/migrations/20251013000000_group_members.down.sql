@@ -0,0 +1,4 @@
+drop index if exists idx_group_members_user_id;
+drop table if exists public.group_members;
+drop index if exists idx_groups_id_unique;
+drop type if exists public.group_member_role;
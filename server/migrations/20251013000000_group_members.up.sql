@@ -0,0 +1,22 @@
+-- Group membership with a role per member. Every group keeps at least one owner
+create type public.group_member_role as enum ('owner', 'admin', 'member');
+
+-- Foreign keys need a unique constraint on the referenced uuid columns
+create unique index if not exists idx_groups_id_unique on public.groups(id);
+
+create table if not exists public.group_members (
+    group_id uuid not null references public.groups(id) on delete cascade,
+    user_id uuid not null references public.users(id) on delete cascade,
+    role group_member_role not null default 'member',
+    create_time timestamptz not null default now(),
+    update_time timestamptz not null default now(),
+    primary key (group_id, user_id)
+);
+
+create index if not exists idx_group_members_user_id on public.group_members(user_id);
+
+-- Creators of existing groups become their owners
+insert into public.group_members (group_id, user_id, role)
+select id, owner_id, 'owner' from public.groups
+where owner_id is not null
+on conflict do nothing;
@@ -0,0 +1,5 @@
+drop index if exists idx_subscriptions_tags;
+drop index if exists idx_subscriptions_feed_id;
+drop index if exists idx_subscriptions_group_feed;
+drop index if exists idx_subscriptions_user_feed;
+drop table if exists public.subscriptions;
@@ -0,0 +1,22 @@
+-- Subscriptions of users and groups to feeds, with how the subscriber files and polls them
+create table if not exists public.subscriptions (
+    id uuid not null primary key,
+    feed_id uuid not null references public.feeds(id) on delete cascade,
+    user_id uuid references public.users(id) on delete cascade,
+    group_id uuid references public.groups(id) on delete cascade,
+    title text,
+    folder text,
+    tags text[] not null default '{}',
+    poll_interval_secs integer,
+    create_time timestamptz not null default now(),
+    update_time timestamptz not null default now(),
+    -- Subscribed by exactly one user or group
+    check ((user_id is null) <> (group_id is null))
+);
+
+create unique index if not exists idx_subscriptions_user_feed
+    on public.subscriptions(user_id, feed_id) where user_id is not null;
+create unique index if not exists idx_subscriptions_group_feed
+    on public.subscriptions(group_id, feed_id) where group_id is not null;
+create index if not exists idx_subscriptions_feed_id on public.subscriptions(feed_id);
+create index if not exists idx_subscriptions_tags on public.subscriptions using gin(tags);
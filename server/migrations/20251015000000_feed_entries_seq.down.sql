@@ -0,0 +1,15 @@
+create or replace function public.notify_feed_entry() returns trigger as $$
+begin
+    perform pg_notify('feed_entries', new.id::text);
+    return new;
+end;
+$$ language plpgsql;
+
+drop trigger if exists feed_entries_notify on public.feed_entries;
+create trigger feed_entries_notify
+    after insert on public.feed_entries
+    for each row execute function public.notify_feed_entry();
+
+drop index if exists idx_feed_entries_seq;
+alter table public.feed_entries drop column if exists seq;
+drop sequence if exists public.feed_entries_seq;
@@ -0,0 +1,42 @@
+-- Entry ids are v7 uuids generated before the inserting transaction commits, so concurrent
+-- ingests can commit them out of order. Watch cursors use this sequence instead, which is
+-- assigned at commit time under a lock and therefore follows commit order.
+create sequence if not exists public.feed_entries_seq;
+
+alter table public.feed_entries
+    add column if not exists seq bigint;
+
+update public.feed_entries e
+set seq = ordered.seq
+from (
+    select id, row_number() over (order by create_time, id) as seq
+    from public.feed_entries
+) ordered
+where e.id = ordered.id;
+
+select setval('public.feed_entries_seq', coalesce(max(seq), 0) + 1, false)
+from public.feed_entries;
+
+create unique index if not exists idx_feed_entries_seq on public.feed_entries(seq);
+
+-- The trigger runs when the inserting transaction commits. Holding the lock until the commit
+-- completes means a reader that sees an entry also sees every entry with a lower seq.
+create or replace function public.notify_feed_entry() returns trigger as $$
+declare
+    entry_seq bigint;
+begin
+    perform pg_advisory_xact_lock(hashtext('feed_entries_seq'));
+    update public.feed_entries
+    set seq = nextval('public.feed_entries_seq')
+    where id = new.id
+    returning seq into entry_seq;
+    perform pg_notify('feed_entries', entry_seq::text);
+    return null;
+end;
+$$ language plpgsql;
+
+drop trigger if exists feed_entries_notify on public.feed_entries;
+create constraint trigger feed_entries_notify
+    after insert on public.feed_entries
+    deferrable initially deferred
+    for each row execute function public.notify_feed_entry();
@@ -0,0 +1 @@
+alter table public.oauth_login_states drop column if exists user_id;
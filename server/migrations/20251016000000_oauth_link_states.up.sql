@@ -0,0 +1,4 @@
+-- A sign-in started by a signed-in user to link another provider account to themselves. The
+-- callback attaches the identity the provider verified to this user instead of signing in
+alter table public.oauth_login_states
+    add column if not exists user_id uuid references public.users(id) on delete cascade;
@@ -0,0 +1 @@
+drop table if exists public.oauth_pending_merges;
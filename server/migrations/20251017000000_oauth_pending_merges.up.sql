@@ -0,0 +1,19 @@
+-- Sign-ins whose verified provider email matched the primary email of an existing user. The
+-- identity is only attached to that user once they confirm the merge while signed in, with the
+-- token whose SHA-256 hash keys the row
+create table if not exists public.oauth_pending_merges (
+    token_hash bytea not null primary key,
+    user_id uuid not null references public.users(id) on delete cascade,
+    provider identity_provider not null,
+    provider_user_id text not null,
+    provider_email citext not null,
+    provider_username text,
+    provider_avatar_url text,
+    access_token_encrypted text,
+    refresh_token_encrypted text,
+    token_expires_at timestamptz,
+    create_time timestamptz not null default now(),
+    expire_time timestamptz not null
+);
+
+create index if not exists idx_oauth_pending_merges_expire_time on public.oauth_pending_merges(expire_time);
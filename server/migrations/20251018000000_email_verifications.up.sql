@@ -0,0 +1,17 @@
+-- Email and password sign-ups and registrations waiting for the owner of the email to verify
+-- it. The identity is only created once the emailed token comes back with the password, so an
+-- address cannot be claimed by someone who does not control it. Tokens are stored as SHA-256
+-- hashes and deleted when used
+create table if not exists public.email_verifications (
+    token_hash bytea not null primary key,
+    email citext not null,
+    -- The user adding an email identity, or null to create a new user
+    user_id uuid references public.users(id) on delete cascade,
+    username text,
+    password_hash text not null,
+    expire_time timestamptz not null,
+    create_time timestamptz not null default now()
+);
+
+create index if not exists idx_email_verifications_email on public.email_verifications(email);
+create index if not exists idx_email_verifications_expire_time on public.email_verifications(expire_time);
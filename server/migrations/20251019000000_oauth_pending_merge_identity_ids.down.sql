@@ -0,0 +1 @@
+alter table public.oauth_pending_merges drop column if exists identity_id;
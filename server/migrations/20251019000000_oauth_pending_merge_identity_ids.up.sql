@@ -0,0 +1,5 @@
+-- Provider tokens are encrypted for the identity row they are stored in, so a pending merge
+-- keeps the id its identity will be created with. Earlier offers cannot be confirmed anymore
+delete from public.oauth_pending_merges;
+
+alter table public.oauth_pending_merges add column if not exists identity_id uuid not null;
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authorization checks applied by the services once `TokenInterceptor` has identified the
+//! caller. Every denial is reported as `PERMISSION_DENIED` with the same message, so it does
+//! not reveal which check failed.
+
+use super::token::Principal;
+use super::ADMIN_SCOPE;
+use crate::validate;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+/// The status returned whenever a check fails.
+pub fn permission_denied() -> Status {
+    Status::permission_denied("Not allowed to perform this operation")
+}
+
+impl Principal {
+    pub fn is_admin(&self) -> bool {
+        self.has_scope(ADMIN_SCOPE)
+    }
+
+    /// Allow admins only.
+    pub fn authorize_admin(&self) -> Result<(), Status> {
+        if self.is_admin() {
+            Ok(())
+        } else {
+            Err(permission_denied())
+        }
+    }
+
+    /// Allow the user themselves only, for data that not even admins may manage on a user's
+    /// behalf, such as passwords.
+    pub fn authorize_self(&self, user_id: Uuid) -> Result<(), Status> {
+        if self.user_uid == user_id {
+            Ok(())
+        } else {
+            Err(permission_denied())
+        }
+    }
+
+    /// Allow the user themselves or an admin to act on the user's data.
+    pub fn authorize_user(&self, user_id: Uuid) -> Result<(), Status> {
+        if self.user_uid == user_id || self.is_admin() {
+            Ok(())
+        } else {
+            Err(permission_denied())
+        }
+    }
+
+    /// Allow the owner of a resource or an admin. Resources without an owner, such as those
+    /// created before ownership was tracked, can only be changed by admins.
+    pub fn authorize_owner(&self, owner_id: Option<Uuid>) -> Result<(), Status> {
+        if owner_id == Some(self.user_uid) || self.is_admin() {
+            Ok(())
+        } else {
+            Err(permission_denied())
+        }
+    }
+}
+
+/// Parse the `user_uid` a request acts on and check that it is the caller, or that the caller
+/// is an admin.
+pub fn authorize_user_uid<T>(request: &Request<T>, user_uid: &str) -> Result<Uuid, Status> {
+    let principal = super::principal(request)?;
+    let user_id = validate::parse_uuid("user_uid", user_uid)?;
+    principal.authorize_user(user_id)?;
+
+    Ok(user_id)
+}
@@ -0,0 +1,521 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::AppConfig;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use rand::RngCore;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+const TAG_BYTES: usize = 16;
+
+/// Rows re-encrypted per transaction by `reencrypt_identity_tokens`.
+const REENCRYPT_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug)]
+pub enum CipherError {
+    /// A key in the configuration is malformed.
+    Key(String),
+    /// The stored value is not in the `<key id>.<wrapped key>.<ciphertext>` format.
+    Format,
+    /// The value was encrypted with a key that is no longer configured.
+    UnknownKey(String),
+    /// Authentication of the ciphertext failed.
+    Decrypt,
+}
+
+impl std::fmt::Display for CipherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CipherError::Key(message) => write!(f, "Invalid encryption key: {}", message),
+            CipherError::Format => write!(f, "Malformed encrypted value"),
+            CipherError::UnknownKey(id) => write!(f, "Unknown encryption key id {}", id),
+            CipherError::Decrypt => write!(f, "Failed to decrypt value"),
+        }
+    }
+}
+
+impl std::error::Error for CipherError {}
+
+/// The column of `user_identities` an encrypted token is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenColumn {
+    AccessToken,
+    RefreshToken,
+}
+
+impl TokenColumn {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenColumn::AccessToken => "access_token_encrypted",
+            TokenColumn::RefreshToken => "refresh_token_encrypted",
+        }
+    }
+}
+
+/// Associated data of a token ciphertext, which binds it to where it is stored.
+fn token_aad(identity_id: Uuid, column: TokenColumn) -> Vec<u8> {
+    format!("{}.{}", identity_id, column.as_str()).into_bytes()
+}
+
+/// Envelope encryption for OAuth tokens stored at rest.
+///
+/// Every value is sealed with a fresh data key using AES-256-GCM, and the data key is in turn
+/// sealed with a key encryption key from the configuration. Stored values have the form
+/// `<key id>.<wrapped data key>.<ciphertext>`, so values written under a retired key can still
+/// be read while `reencrypt_identity_tokens` moves them to the current one. The ciphertext is
+/// bound to the identity and column it is stored in, so a value moved to another row or column
+/// fails to decrypt.
+pub struct TokenCipher {
+    current: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+impl std::fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ids: Vec<&String> = self.keys.keys().collect();
+        ids.sort();
+
+        f.debug_struct("TokenCipher")
+            .field("current", &self.current)
+            .field("keys", &ids)
+            .finish()
+    }
+}
+
+/// Parse a `<key id>:<base64 key>` pair from the configuration.
+fn parse_key(value: &str) -> Result<(String, Aes256Gcm), CipherError> {
+    let (id, encoded) = value
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| CipherError::Key("expected <key id>:<base64 key>".to_string()))?;
+
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(CipherError::Key(format!(
+            "key id {:?} must only contain letters, digits, '-' and '_'",
+            id
+        )));
+    }
+
+    let bytes = STANDARD
+        .decode(encoded)
+        .or_else(|_| URL_SAFE_NO_PAD.decode(encoded))
+        .map_err(|e| CipherError::Key(format!("key {}: {}", id, e)))?;
+    if bytes.len() != KEY_BYTES {
+        return Err(CipherError::Key(format!(
+            "key {} must be {} bytes, got {}",
+            id,
+            KEY_BYTES,
+            bytes.len()
+        )));
+    }
+
+    Ok((
+        id.to_string(),
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+    ))
+}
+
+fn nonce() -> [u8; NONCE_BYTES] {
+    let mut nonce = [0u8; NONCE_BYTES];
+    rand::rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+fn seal(key: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let nonce = nonce();
+    let ciphertext = key
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+fn open(key: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, CipherError> {
+    if sealed.len() < NONCE_BYTES {
+        return Err(CipherError::Format);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+    key.decrypt(
+        Nonce::from_slice(nonce),
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    )
+    .map_err(|_| CipherError::Decrypt)
+}
+
+/// Whether a value looks like the output of `TokenCipher::encrypt`, judged by the size of the
+/// wrapped data key rather than by whether its key id is known.
+fn is_envelope(value: &str) -> bool {
+    let mut parts = value.splitn(3, '.');
+    let (Some(id), Some(wrapped), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && URL_SAFE_NO_PAD
+            .decode(wrapped)
+            .is_ok_and(|wrapped| wrapped.len() == NONCE_BYTES + KEY_BYTES + TAG_BYTES)
+}
+
+impl TokenCipher {
+    /// Build a cipher from the current key and any retired keys still needed for decryption,
+    /// each given as `<key id>:<base64 key>`.
+    pub fn new(current: &str, retired: &[String]) -> Result<Self, CipherError> {
+        let (current, key) = parse_key(current)?;
+
+        let mut keys = HashMap::new();
+        keys.insert(current.clone(), key);
+        for value in retired {
+            let (id, key) = parse_key(value)?;
+            if keys.insert(id.clone(), key).is_some() {
+                return Err(CipherError::Key(format!("duplicate key id {}", id)));
+            }
+        }
+
+        Ok(Self { current, keys })
+    }
+
+    /// Build the cipher from `OAUTH_ENCRYPTION_KEY`, or `None` when it is not set.
+    pub fn from_config(config: &AppConfig) -> Result<Option<Self>, CipherError> {
+        config
+            .oauth_encryption_key
+            .as_deref()
+            .map(|key| Self::new(key, &config.oauth_encryption_old_keys))
+            .transpose()
+    }
+
+    /// Id of the key new values are encrypted with.
+    pub fn current_key_id(&self) -> &str {
+        &self.current
+    }
+
+    pub fn encrypt(&self, plaintext: &str, identity_id: Uuid, column: TokenColumn) -> String {
+        let kek = &self.keys[&self.current];
+
+        let mut dek_bytes = [0u8; KEY_BYTES];
+        rand::rng().fill_bytes(&mut dek_bytes);
+        let dek = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek_bytes));
+
+        // The key id is authenticated on the wrapped key so it cannot be swapped.
+        let wrapped = seal(kek, &dek_bytes, self.current.as_bytes());
+        let ciphertext = seal(&dek, plaintext.as_bytes(), &token_aad(identity_id, column));
+
+        format!(
+            "{}.{}.{}",
+            self.current,
+            URL_SAFE_NO_PAD.encode(wrapped),
+            URL_SAFE_NO_PAD.encode(ciphertext)
+        )
+    }
+
+    pub fn decrypt(
+        &self,
+        value: &str,
+        identity_id: Uuid,
+        column: TokenColumn,
+    ) -> Result<String, CipherError> {
+        let mut parts = value.splitn(3, '.');
+        let (Some(id), Some(wrapped), Some(ciphertext)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(CipherError::Format);
+        };
+
+        let kek = self
+            .keys
+            .get(id)
+            .ok_or_else(|| CipherError::UnknownKey(id.to_string()))?;
+        let wrapped = URL_SAFE_NO_PAD
+            .decode(wrapped)
+            .map_err(|_| CipherError::Format)?;
+        let ciphertext = URL_SAFE_NO_PAD
+            .decode(ciphertext)
+            .map_err(|_| CipherError::Format)?;
+
+        let dek_bytes = open(kek, &wrapped, id.as_bytes())?;
+        if dek_bytes.len() != KEY_BYTES {
+            return Err(CipherError::Format);
+        }
+        let dek = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek_bytes));
+
+        let plaintext = open(&dek, &ciphertext, &token_aad(identity_id, column))?;
+        String::from_utf8(plaintext).map_err(|_| CipherError::Decrypt)
+    }
+
+    /// Whether a stored value was encrypted with a key other than the current one.
+    pub fn needs_reencryption(&self, value: &str) -> bool {
+        value.split_once('.').map(|(id, _)| id) != Some(self.current.as_str())
+    }
+}
+
+/// Re-encrypt every stored OAuth token that is not yet under the cipher's current key,
+/// returning the number of identities updated. Values that predate encryption are encrypted
+/// as they are. Safe to interrupt and run again.
+pub async fn reencrypt_identity_tokens(
+    pool: &PgPool,
+    cipher: &TokenCipher,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = format!("{}.", cipher.current_key_id());
+    let mut after = Uuid::nil();
+    let mut updated = 0;
+
+    loop {
+        let mut tx = pool.begin().await?;
+
+        let rows: Vec<(Uuid, Option<String>, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT id, access_token_encrypted, refresh_token_encrypted
+            FROM public.user_identities
+            WHERE id > $1
+              AND (
+                  (access_token_encrypted IS NOT NULL
+                   AND left(access_token_encrypted, length($2)) <> $2)
+                  OR (refresh_token_encrypted IS NOT NULL
+                   AND left(refresh_token_encrypted, length($2)) <> $2)
+              )
+            ORDER BY id ASC
+            LIMIT $3
+            FOR UPDATE
+            "#,
+        )
+        .bind(after)
+        .bind(&prefix)
+        .bind(REENCRYPT_BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let Some((last, _, _)) = rows.last() else {
+            return Ok(updated);
+        };
+        after = *last;
+
+        for (id, access_token, refresh_token) in &rows {
+            let access_token = access_token
+                .as_deref()
+                .map(|value| reencrypt(cipher, value, *id, TokenColumn::AccessToken))
+                .transpose()?;
+            let refresh_token = refresh_token
+                .as_deref()
+                .map(|value| reencrypt(cipher, value, *id, TokenColumn::RefreshToken))
+                .transpose()?;
+
+            sqlx::query(
+                r#"
+                UPDATE public.user_identities
+                SET access_token_encrypted = $2,
+                    refresh_token_encrypted = $3,
+                    update_time = now()
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(access_token)
+            .bind(refresh_token)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        updated += rows.len() as u64;
+        tracing::info!(updated, "Re-encrypted identity tokens");
+    }
+}
+
+fn reencrypt(
+    cipher: &TokenCipher,
+    value: &str,
+    identity_id: Uuid,
+    column: TokenColumn,
+) -> Result<String, CipherError> {
+    if !cipher.needs_reencryption(value) {
+        return Ok(value.to_string());
+    }
+
+    // Tokens stored before encryption was introduced are plain text.
+    let plaintext = if is_envelope(value) {
+        cipher.decrypt(value, identity_id, column)?
+    } else {
+        value.to_string()
+    };
+
+    Ok(cipher.encrypt(&plaintext, identity_id, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{CreateIdentity, IdentityRepository};
+
+    fn key(id: &str, byte: u8) -> String {
+        format!("{}:{}", id, STANDARD.encode([byte; KEY_BYTES]))
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = TokenCipher::new(&key("k1", 1), &[]).unwrap();
+        let id = Uuid::now_v7();
+
+        let value = cipher.encrypt("provider-token", id, TokenColumn::AccessToken);
+        assert!(value.starts_with("k1."));
+        assert!(!value.contains("provider-token"));
+        assert!(is_envelope(&value));
+        assert_ne!(
+            value,
+            cipher.encrypt("provider-token", id, TokenColumn::AccessToken)
+        );
+
+        let plaintext = cipher.decrypt(&value, id, TokenColumn::AccessToken);
+        assert_eq!(plaintext.unwrap(), "provider-token");
+    }
+
+    #[test]
+    fn decrypts_values_of_retired_keys() {
+        let old = TokenCipher::new(&key("k1", 1), &[]).unwrap();
+        let rotated = TokenCipher::new(&key("k2", 2), &[key("k1", 1)]).unwrap();
+        let id = Uuid::now_v7();
+        let value = old.encrypt("provider-token", id, TokenColumn::RefreshToken);
+
+        assert!(rotated.needs_reencryption(&value));
+        let plaintext = rotated.decrypt(&value, id, TokenColumn::RefreshToken);
+        assert_eq!(plaintext.unwrap(), "provider-token");
+
+        let reencrypted = reencrypt(&rotated, &value, id, TokenColumn::RefreshToken).unwrap();
+        assert!(reencrypted.starts_with("k2."));
+        assert!(!rotated.needs_reencryption(&reencrypted));
+        let plaintext = rotated.decrypt(&reencrypted, id, TokenColumn::RefreshToken);
+        assert_eq!(plaintext.unwrap(), "provider-token");
+
+        let retired = TokenCipher::new(&key("k2", 2), &[]).unwrap();
+        let err = retired.decrypt(&value, id, TokenColumn::RefreshToken);
+        assert!(matches!(err, Err(CipherError::UnknownKey(id)) if id == "k1"));
+    }
+
+    #[test]
+    fn rejects_tampered_or_moved_values() {
+        let cipher = TokenCipher::new(&key("k1", 1), &[key("k2", 2)]).unwrap();
+        let id = Uuid::now_v7();
+        let value = cipher.encrypt("provider-token", id, TokenColumn::AccessToken);
+        let decrypt = |value: &str, id, column| cipher.decrypt(value, id, column);
+
+        // Copied to another identity or column.
+        let err = decrypt(&value, Uuid::now_v7(), TokenColumn::AccessToken);
+        assert!(matches!(err, Err(CipherError::Decrypt)));
+        let err = decrypt(&value, id, TokenColumn::RefreshToken);
+        assert!(matches!(err, Err(CipherError::Decrypt)));
+
+        // A flipped bit in the ciphertext.
+        let (prefix, ciphertext) = value.rsplit_once('.').unwrap();
+        let mut bytes = URL_SAFE_NO_PAD.decode(ciphertext).unwrap();
+        bytes[NONCE_BYTES] ^= 1;
+        let tampered = format!("{}.{}", prefix, URL_SAFE_NO_PAD.encode(bytes));
+        let err = decrypt(&tampered, id, TokenColumn::AccessToken);
+        assert!(matches!(err, Err(CipherError::Decrypt)));
+
+        // Relabelled with another configured key id.
+        let relabelled = format!("k2{}", value.strip_prefix("k1").unwrap());
+        let err = decrypt(&relabelled, id, TokenColumn::AccessToken);
+        assert!(matches!(err, Err(CipherError::Decrypt)));
+
+        let err = decrypt("k1.not-a-value", id, TokenColumn::AccessToken);
+        assert!(matches!(err, Err(CipherError::Format)));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn reencrypts_every_token_in_batches(pool: PgPool) {
+        let old = TokenCipher::new(&key("k1", 1), &[]).unwrap();
+        let cipher = TokenCipher::new(&key("k2", 2), &[key("k1", 1)]).unwrap();
+        let count = REENCRYPT_BATCH_SIZE as usize * 2 + 1;
+
+        let mut tx = pool.begin().await.unwrap();
+        let mut ids = Vec::new();
+        for i in 0..count {
+            let id = Uuid::now_v7();
+            // Tokens from before encryption, under the retired key and under the current key.
+            let (access_token, refresh_token) = match i % 3 {
+                0 => (format!("access-{}", i), None),
+                1 => (
+                    old.encrypt(&format!("access-{}", i), id, TokenColumn::AccessToken),
+                    Some(old.encrypt(&format!("refresh-{}", i), id, TokenColumn::RefreshToken)),
+                ),
+                _ => (
+                    cipher.encrypt(&format!("access-{}", i), id, TokenColumn::AccessToken),
+                    None,
+                ),
+            };
+            IdentityRepository::insert_with_user(
+                &mut tx,
+                &CreateIdentity {
+                    id,
+                    user_id: Uuid::now_v7(),
+                    provider: "google".to_string(),
+                    provider_user_id: format!("user-{}", i),
+                    provider_email: None,
+                    provider_username: Some(format!("user{}", i)),
+                    provider_avatar_url: None,
+                    access_token_encrypted: Some(access_token),
+                    refresh_token_encrypted: refresh_token,
+                    token_expires_at: None,
+                    metadata: None,
+                    is_primary: true,
+                    verified: false,
+                },
+            )
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+        tx.commit().await.unwrap();
+
+        let updated = reencrypt_identity_tokens(&pool, &cipher).await.unwrap();
+        assert_eq!(updated as usize, count - count / 3);
+
+        for (i, id) in ids.iter().enumerate() {
+            let (access_token, refresh_token): (String, Option<String>) = sqlx::query_as(
+                r#"
+                SELECT access_token_encrypted, refresh_token_encrypted
+                FROM public.user_identities
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+            assert!(access_token.starts_with("k2."));
+            let plaintext = cipher.decrypt(&access_token, *id, TokenColumn::AccessToken);
+            assert_eq!(plaintext.unwrap(), format!("access-{}", i));
+
+            if let Some(refresh_token) = refresh_token {
+                let plaintext = cipher.decrypt(&refresh_token, *id, TokenColumn::RefreshToken);
+                assert_eq!(plaintext.unwrap(), format!("refresh-{}", i));
+            }
+        }
+
+        assert_eq!(reencrypt_identity_tokens(&pool, &cipher).await.unwrap(), 0);
+    }
+}
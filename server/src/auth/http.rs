@@ -0,0 +1,843 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::oauth::{LoginError, LoginOutcome, OAuthLogin, PendingMerge};
+use super::token::TokenError;
+use axum::extract::{Path, Query, State};
+use axum::http::header::{AUTHORIZATION, COOKIE, SET_COOKIE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use chrono::Utc;
+use geist_sdk::pb::meta::v1alpha::TokenResponse;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Cookie that ties the callback to the browser that started the sign-in.
+const STATE_COOKIE: &str = "geist_oauth_state";
+
+/// Routes for signing in with an OAuth provider:
+///
+/// - `GET /auth/{provider}/start` redirects to the provider.
+/// - `POST /auth/{provider}/link` starts linking a provider account to the user of the bearer
+///   token, returning the provider URL to send them to.
+/// - `GET|POST /auth/{provider}/callback` completes the sign-in and returns Geist tokens, or
+///   `202 Accepted` with a merge token when the verified email belongs to an existing user.
+/// - `POST /auth/merge` attaches the provider account of a merge token to the user of the
+///   bearer token, who must be that existing user, and returns Geist tokens.
+pub fn router(login: Arc<OAuthLogin>) -> Router {
+    Router::new()
+        .route("/auth/{provider}/start", get(start))
+        .route("/auth/{provider}/link", post(link))
+        .route("/auth/merge", post(merge))
+        .route(
+            "/auth/{provider}/callback",
+            get(callback_query).post(callback_form),
+        )
+        .with_state(login)
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Tokens issued at the end of a sign-in, in the shape of an OAuth token response.
+#[derive(Debug, Serialize)]
+struct LoginResponse {
+    access_token: String,
+    token_type: String,
+    expires_in: i64,
+    refresh_token: String,
+    user_uid: String,
+}
+
+impl From<TokenResponse> for LoginResponse {
+    fn from(tokens: TokenResponse) -> Self {
+        let expires_in = tokens
+            .expire_time
+            .map(|ts| ts.seconds - Utc::now().timestamp())
+            .unwrap_or_default()
+            .max(0);
+
+        Self {
+            access_token: tokens.access_token,
+            token_type: tokens.token_type,
+            expires_in,
+            refresh_token: tokens.refresh_token,
+            user_uid: tokens.user_uid,
+        }
+    }
+}
+
+/// A sign-in that the existing user with the same verified email has to confirm.
+#[derive(Debug, Serialize)]
+struct MergeRequiredResponse {
+    merge_token: String,
+    email: String,
+    expires_in: i64,
+}
+
+impl From<PendingMerge> for MergeRequiredResponse {
+    fn from(merge: PendingMerge) -> Self {
+        Self {
+            merge_token: merge.token,
+            email: merge.email,
+            expires_in: (merge.expire_time.timestamp() - Utc::now().timestamp()).max(0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    merge_token: String,
+}
+
+/// Where to send a user who is linking a provider account.
+#[derive(Debug, Serialize)]
+struct LinkResponse {
+    authorization_url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+    error_description: String,
+}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        let (status, error) = match &self {
+            LoginError::UnknownProvider(_) => (StatusCode::NOT_FOUND, "unknown_provider"),
+            LoginError::InvalidState => (StatusCode::BAD_REQUEST, "invalid_state"),
+            LoginError::Denied(_) => (StatusCode::FORBIDDEN, "access_denied"),
+            LoginError::Unauthenticated(_) => (StatusCode::UNAUTHORIZED, "invalid_token"),
+            LoginError::AlreadyLinked(_) => (StatusCode::CONFLICT, "already_linked"),
+            LoginError::Provider(_) => (StatusCode::BAD_GATEWAY, "provider_error"),
+            LoginError::Login(status) if status.code() == tonic::Code::Unauthenticated => {
+                (StatusCode::UNAUTHORIZED, "access_denied")
+            }
+            LoginError::Database(_) | LoginError::Login(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "server_error")
+            }
+        };
+
+        // Provider and database details stay in the logs.
+        let error_description = if status.is_server_error() {
+            tracing::error!(error = %self, "OAuth sign-in failed");
+            "Sign-in failed".to_string()
+        } else {
+            tracing::info!(error = %self, "OAuth sign-in rejected");
+            self.to_string()
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error,
+                error_description,
+            }),
+        )
+            .into_response()
+    }
+}
+
+fn state_cookie(login: &OAuthLogin, value: &str, max_age: u64) -> String {
+    // Providers that POST the callback need the cookie sent on a cross-site request.
+    let same_site = if login.is_secure() {
+        "SameSite=None; Secure"
+    } else {
+        "SameSite=Lax"
+    };
+
+    format!(
+        "{}={}; Path=/auth/; Max-Age={}; HttpOnly; {}",
+        STATE_COOKIE, value, max_age, same_site
+    )
+}
+
+fn cookie_state(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == STATE_COOKIE)
+        .map(|(_, value)| value)
+}
+
+#[tracing::instrument(skip_all, fields(provider = %provider))]
+async fn start(
+    State(login): State<Arc<OAuthLogin>>,
+    Path(provider): Path<String>,
+) -> Result<Response, LoginError> {
+    let redirect = login.start(&provider).await?;
+    let cookie = state_cookie(&login, &redirect.state, login.state_ttl().as_secs());
+
+    Ok(([(SET_COOKIE, cookie)], Redirect::to(redirect.url.as_str())).into_response())
+}
+
+fn bearer_token(headers: &HeaderMap) -> Result<&str, LoginError> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .ok_or_else(|| {
+            LoginError::Unauthenticated(TokenError::Malformed("missing bearer token".to_string()))
+        })
+}
+
+#[tracing::instrument(skip_all, fields(provider = %provider))]
+async fn link(
+    State(login): State<Arc<OAuthLogin>>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, LoginError> {
+    let redirect = login.start_link(&provider, bearer_token(&headers)?).await?;
+    let cookie = state_cookie(&login, &redirect.state, login.state_ttl().as_secs());
+
+    Ok((
+        [(SET_COOKIE, cookie)],
+        Json(LinkResponse {
+            authorization_url: redirect.url.to_string(),
+        }),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(skip_all)]
+async fn merge(
+    State(login): State<Arc<OAuthLogin>>,
+    headers: HeaderMap,
+    Json(request): Json<MergeRequest>,
+) -> Result<Response, LoginError> {
+    let tokens = login
+        .confirm_merge(&request.merge_token, bearer_token(&headers)?)
+        .await?;
+
+    Ok(Json(LoginResponse::from(tokens)).into_response())
+}
+
+#[tracing::instrument(skip_all, fields(provider = %provider))]
+async fn callback_query(
+    State(login): State<Arc<OAuthLogin>>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<CallbackParams>,
+) -> Result<Response, LoginError> {
+    callback(&login, &provider, &headers, params).await
+}
+
+#[tracing::instrument(skip_all, fields(provider = %provider))]
+async fn callback_form(
+    State(login): State<Arc<OAuthLogin>>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<CallbackParams>,
+) -> Result<Response, LoginError> {
+    callback(&login, &provider, &headers, params).await
+}
+
+async fn callback(
+    login: &OAuthLogin,
+    provider: &str,
+    headers: &HeaderMap,
+    params: CallbackParams,
+) -> Result<Response, LoginError> {
+    if let Some(error) = params.error {
+        let description = params.error_description.unwrap_or_default();
+        return Err(LoginError::Denied(
+            format!("{} {}", error, description).trim().to_string(),
+        ));
+    }
+
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return Err(LoginError::Denied("missing code or state".to_string()));
+    };
+
+    // Stops an attacker from completing their own sign-in in someone else's browser.
+    if cookie_state(headers) != Some(state.as_str()) {
+        return Err(LoginError::InvalidState);
+    }
+
+    let cookie = [(SET_COOKIE, state_cookie(login, "", 0))];
+    Ok(match login.finish(provider, &code, &state).await? {
+        LoginOutcome::SignedIn(tokens) => {
+            (cookie, Json(LoginResponse::from(tokens))).into_response()
+        }
+        LoginOutcome::MergeRequired(merge) => (
+            StatusCode::ACCEPTED,
+            cookie,
+            Json(MergeRequiredResponse::from(merge)),
+        )
+            .into_response(),
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::auth::provider::OAuthProvider;
+    use crate::auth::{AuthServer, PasswordReset, TokenSigner};
+    use crate::config::AppConfig;
+    use crate::mail::Mailer;
+    use axum::body::Body;
+    use axum::http::header::{CONTENT_TYPE, LOCATION};
+    use axum::http::Request;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use clap::Parser;
+    use jwt::{AlgorithmType, PKeyWithDigest, SignWithKey, Token};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use sqlx::PgPool;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    const KEY_ID: &str = "mock-key";
+
+    /// An authorization the mock provider granted, waiting to be exchanged for tokens.
+    struct Grant {
+        subject: String,
+        redirect_uri: String,
+        code_challenge: String,
+        nonce: String,
+    }
+
+    /// How the mock provider answers a refresh token.
+    #[derive(Clone, Copy)]
+    pub(in crate::auth) enum Refresh {
+        /// Issue new tokens, valid for the given number of seconds if it is set.
+        Tokens { expires_in: Option<i64> },
+        /// Fail with an error worth retrying.
+        Unavailable,
+    }
+
+    struct ProviderState {
+        base: String,
+        key: PKey<Private>,
+        grants: HashMap<String, Grant>,
+        /// Refresh tokens the provider accepts. Unknown ones are rejected.
+        refresh_tokens: HashMap<String, Refresh>,
+        /// Replaces the nonce of the next ID token, to test that mismatches are rejected.
+        nonce_override: Option<String>,
+        /// Replaces the email of the next ID token, and whether it is verified.
+        email_override: Option<(String, bool)>,
+    }
+
+    /// An OpenID Connect provider serving a token endpoint and signing keys over HTTP.
+    #[derive(Clone)]
+    pub(in crate::auth) struct MockProvider {
+        state: Arc<Mutex<ProviderState>>,
+    }
+
+    impl MockProvider {
+        pub(in crate::auth) async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+            let mock = Self {
+                state: Arc::new(Mutex::new(ProviderState {
+                    base,
+                    key,
+                    grants: HashMap::new(),
+                    refresh_tokens: HashMap::new(),
+                    nonce_override: None,
+                    email_override: None,
+                })),
+            };
+
+            let app = Router::new()
+                .route("/jwks", get(jwks))
+                .route("/token", axum::routing::post(token))
+                .with_state(mock.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            mock
+        }
+
+        pub(in crate::auth) fn provider(&self, name: &'static str) -> OAuthProvider {
+            let base = self.state.lock().unwrap().base.clone();
+            OAuthProvider::mock(name, &base).unwrap()
+        }
+
+        /// Approve the authorization request at `location` as `subject`, returning the query
+        /// string the provider redirects back to the callback with.
+        pub(in crate::auth) fn authorize(&self, location: &str, subject: &str) -> String {
+            let url = url::Url::parse(location).unwrap();
+            let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+            assert_eq!(params["response_type"], "code");
+            assert_eq!(params["code_challenge_method"], "S256");
+
+            let code = URL_SAFE_NO_PAD.encode(uuid::Uuid::now_v7().as_bytes());
+            self.state.lock().unwrap().grants.insert(
+                code.clone(),
+                Grant {
+                    subject: subject.to_string(),
+                    redirect_uri: params["redirect_uri"].clone(),
+                    code_challenge: params["code_challenge"].clone(),
+                    nonce: params["nonce"].clone(),
+                },
+            );
+
+            format!("code={}&state={}", code, params["state"])
+        }
+
+        /// Answer `refresh_token` the way `refresh` says when it is used.
+        pub(in crate::auth) fn accept_refresh_token(&self, refresh_token: &str, refresh: Refresh) {
+            self.state
+                .lock()
+                .unwrap()
+                .refresh_tokens
+                .insert(refresh_token.to_string(), refresh);
+        }
+
+        fn override_nonce(&self, nonce: &str) {
+            self.state.lock().unwrap().nonce_override = Some(nonce.to_string());
+        }
+
+        fn override_email(&self, email: &str, verified: bool) {
+            self.state.lock().unwrap().email_override = Some((email.to_string(), verified));
+        }
+    }
+
+    async fn jwks(State(mock): State<MockProvider>) -> Json<Value> {
+        let state = mock.state.lock().unwrap();
+        let rsa = state.key.rsa().unwrap();
+        Json(json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": KEY_ID,
+                "alg": "RS256",
+                "use": "sig",
+                "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+            }]
+        }))
+    }
+
+    async fn token(
+        State(mock): State<MockProvider>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Json<Value> {
+        let mut state = mock.state.lock().unwrap();
+        let invalid = || Json(json!({ "error": "invalid_grant" }));
+
+        if form.get("client_id").map(String::as_str) != Some("geist")
+            || form.get("client_secret").map(String::as_str) != Some("secret")
+        {
+            return Json(json!({ "error": "invalid_client" }));
+        }
+        if form.get("grant_type").map(String::as_str) == Some("refresh_token") {
+            let refresh_token = form.get("refresh_token").cloned().unwrap_or_default();
+            return match state.refresh_tokens.remove(&refresh_token) {
+                Some(Refresh::Tokens { expires_in }) => Json(json!({
+                    "access_token": "refreshed-access-token",
+                    "token_type": "Bearer",
+                    "refresh_token": format!("{}-rotated", refresh_token),
+                    "expires_in": expires_in,
+                })),
+                Some(Refresh::Unavailable) => Json(json!({ "error": "temporarily_unavailable" })),
+                None => invalid(),
+            };
+        }
+        let Some(grant) = form.get("code").and_then(|code| state.grants.remove(code)) else {
+            return invalid();
+        };
+        let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+        if form.get("redirect_uri") != Some(&grant.redirect_uri)
+            || URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != grant.code_challenge
+        {
+            return invalid();
+        }
+
+        let nonce = state.nonce_override.take().unwrap_or(grant.nonce);
+        let (email, email_verified) = state
+            .email_override
+            .take()
+            .unwrap_or_else(|| (format!("{}@example.com", grant.subject), true));
+        let header = jwt::Header {
+            algorithm: AlgorithmType::Rs256,
+            key_id: Some(KEY_ID.to_string()),
+            ..Default::default()
+        };
+        let claims = json!({
+            "iss": state.base,
+            "aud": "geist",
+            "sub": grant.subject,
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": nonce,
+            "email": email,
+            "email_verified": email_verified,
+        });
+        let key = PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: state.key.clone(),
+        };
+        let id_token = Token::new(header, claims).sign_with_key(&key).unwrap();
+
+        Json(json!({
+            "access_token": "provider-access-token",
+            "token_type": "Bearer",
+            "expires_in": 3600,
+            "id_token": id_token.as_str(),
+        }))
+    }
+
+    pub(in crate::auth) fn config() -> AppConfig {
+        AppConfig::try_parse_from([
+            "geist-server",
+            "--jwt-secret",
+            "a-test-secret-that-is-long-enough-for-hs256",
+        ])
+        .unwrap()
+    }
+
+    /// The sign-in routes, with `mock` standing in for Google and Microsoft.
+    pub(in crate::auth) fn app(pool: PgPool, mock: &MockProvider) -> Router {
+        let config = config();
+        let password_reset =
+            PasswordReset::from_config(&config, Mailer::from_config(&config).unwrap()).unwrap();
+        let auth = AuthServer::new(
+            pool.clone(),
+            TokenSigner::from_config(&config).unwrap(),
+            config.refresh_token_ttl(),
+            Arc::new(password_reset),
+        );
+        let login = OAuthLogin::from_config(&config, pool, None, auth)
+            .unwrap()
+            .with_provider(mock.provider("google"))
+            .with_provider(mock.provider("microsoft"));
+        router(Arc::new(login))
+    }
+
+    pub(in crate::auth) async fn send(
+        app: &Router,
+        request: Request<Body>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        (status, headers, body)
+    }
+
+    /// Start a sign-in, returning the provider redirect and the state cookie.
+    pub(in crate::auth) async fn start(app: &Router, path: &str) -> (String, String) {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let (status, headers, _) = send(app, request).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+
+        let location = headers[LOCATION].to_str().unwrap().to_string();
+        let cookie = headers[SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        (location, cookie)
+    }
+
+    pub(in crate::auth) async fn callback(
+        app: &Router,
+        provider: &str,
+        query: &str,
+        cookie: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::get(format!("/auth/{}/callback?{}", provider, query))
+            .header(COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        (status, body)
+    }
+
+    async fn sign_in(
+        app: &Router,
+        mock: &MockProvider,
+        provider: &str,
+        subject: &str,
+    ) -> (StatusCode, Value) {
+        let (location, cookie) = start(app, &format!("/auth/{}/start", provider)).await;
+        let query = mock.authorize(&location, subject);
+        callback(app, provider, &query, &cookie).await
+    }
+
+    /// Link the `provider` account `subject` to the user of `access_token`.
+    async fn link(
+        app: &Router,
+        mock: &MockProvider,
+        provider: &str,
+        access_token: &str,
+        subject: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::post(format!("/auth/{}/link", provider))
+            .header(AUTHORIZATION, format!("Bearer {}", access_token))
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let cookie = headers[SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap();
+        let query = mock.authorize(body["authorization_url"].as_str().unwrap(), subject);
+        callback(app, provider, &query, cookie).await
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn sign_in_creates_the_user_once(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (status, first) = sign_in(&app, &mock, "google", "alice").await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        assert_eq!(first["token_type"], "Bearer");
+        assert!(!first["access_token"].as_str().unwrap().is_empty());
+        assert!(!first["refresh_token"].as_str().unwrap().is_empty());
+
+        let (status, second) = sign_in(&app, &mock, "google", "alice").await;
+        assert_eq!(status, StatusCode::OK, "{}", second);
+        assert_eq!(second["user_uid"], first["user_uid"]);
+
+        let (status, other) = sign_in(&app, &mock, "google", "bob").await;
+        assert_eq!(status, StatusCode::OK, "{}", other);
+        assert_ne!(other["user_uid"], first["user_uid"]);
+
+        let identities: Vec<(String, Option<String>, bool)> = sqlx::query_as(
+            "SELECT provider_user_id, provider_email, verified FROM user_identities
+             WHERE provider = 'google' ORDER BY provider_user_id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            identities,
+            vec![
+                (
+                    "alice".to_string(),
+                    Some("alice@example.com".to_string()),
+                    true
+                ),
+                ("bob".to_string(), Some("bob@example.com".to_string()), true),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn callback_requires_the_state_of_this_browser(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool, &mock);
+
+        // A state started in another browser.
+        let (location, _) = start(&app, "/auth/google/start").await;
+        let (_, cookie) = start(&app, "/auth/google/start").await;
+        let query = mock.authorize(&location, "alice");
+        let (status, body) = callback(&app, "google", &query, &cookie).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_state");
+
+        // A state can only be used once.
+        let (location, cookie) = start(&app, "/auth/google/start").await;
+        let query = mock.authorize(&location, "alice");
+        let (status, _) = callback(&app, "google", &query, &cookie).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = callback(&app, "google", &query, &cookie).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_state");
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn callback_rejects_id_tokens_for_another_sign_in(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (location, cookie) = start(&app, "/auth/google/start").await;
+        let query = mock.authorize(&location, "mallory");
+        mock.override_nonce("replayed");
+        let (status, body) = callback(&app, "google", &query, &cookie).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["error"], "provider_error");
+
+        let users: i64 = sqlx::query_scalar("SELECT count(*) FROM users")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(users, 0);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn callback_reports_denied_and_unknown_providers(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool, &mock);
+
+        let (_, cookie) = start(&app, "/auth/google/start").await;
+        let (status, body) = callback(&app, "google", "error=access_denied", &cookie).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "access_denied");
+
+        let request = Request::get("/auth/myspace/start")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "unknown_provider");
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn link_attaches_the_account_to_the_signed_in_user(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (_, alice) = sign_in(&app, &mock, "google", "alice").await;
+        let access_token = alice["access_token"].as_str().unwrap();
+
+        let (status, linked) = link(&app, &mock, "microsoft", access_token, "alice-ms").await;
+        assert_eq!(status, StatusCode::OK, "{}", linked);
+        assert_eq!(linked["user_uid"], alice["user_uid"]);
+
+        // The linked account now signs in as the same user.
+        let (status, again) = sign_in(&app, &mock, "microsoft", "alice-ms").await;
+        assert_eq!(status, StatusCode::OK, "{}", again);
+        assert_eq!(again["user_uid"], alice["user_uid"]);
+
+        let primary: Vec<(String, bool)> = sqlx::query_as(
+            "SELECT provider::text, is_primary FROM user_identities ORDER BY provider",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            primary,
+            vec![
+                ("google".to_string(), true),
+                ("microsoft".to_string(), false)
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn link_never_takes_over_another_users_account(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (_, alice) = sign_in(&app, &mock, "google", "alice").await;
+        let (_, bob) = sign_in(&app, &mock, "microsoft", "bob").await;
+        let access_token = alice["access_token"].as_str().unwrap();
+
+        // Bob's account stays his, even when Alice signs in to it.
+        let (status, body) = link(&app, &mock, "microsoft", access_token, "bob").await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["error"], "already_linked");
+        let (_, again) = sign_in(&app, &mock, "microsoft", "bob").await;
+        assert_eq!(again["user_uid"], bob["user_uid"]);
+
+        // One account per provider.
+        let (status, body) = link(&app, &mock, "google", access_token, "alice-2").await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+
+        // Linking needs the user's own access token.
+        for authorization in [None, Some("Bearer not-a-token")] {
+            let mut request = Request::post("/auth/microsoft/link");
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            let (status, _, body) = send(&app, request.body(Body::empty()).unwrap()).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["error"], "invalid_token");
+        }
+
+        let identities: i64 = sqlx::query_scalar("SELECT count(*) FROM user_identities")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(identities, 2);
+    }
+
+    /// Confirm a merge as the user of `access_token`.
+    async fn merge(app: &Router, merge_token: &str, access_token: &str) -> (StatusCode, Value) {
+        let request = Request::post("/auth/merge")
+            .header(AUTHORIZATION, format!("Bearer {}", access_token))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "merge_token": merge_token }).to_string(),
+            ))
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        (status, body)
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn verified_email_of_a_user_merges_only_when_they_confirm(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (_, alice) = sign_in(&app, &mock, "google", "alice").await;
+        let (_, bob) = sign_in(&app, &mock, "google", "bob").await;
+
+        // A Microsoft account with Alice's verified email waits for her.
+        mock.override_email("alice@example.com", true);
+        let (status, pending) = sign_in(&app, &mock, "microsoft", "alice-ms").await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", pending);
+        assert_eq!(pending["email"], "alice@example.com");
+        assert!(pending.get("access_token").is_none());
+        let merge_token = pending["merge_token"].as_str().unwrap();
+
+        let (status, body) = merge(&app, merge_token, "not-a-token").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "invalid_token");
+        let (status, body) = merge(&app, merge_token, bob["access_token"].as_str().unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_state");
+
+        let identities = || async {
+            sqlx::query_scalar::<_, i64>("SELECT count(*) FROM user_identities")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        assert_eq!(identities().await, 2);
+
+        let access_token = alice["access_token"].as_str().unwrap();
+        let (status, merged) = merge(&app, merge_token, access_token).await;
+        assert_eq!(status, StatusCode::OK, "{}", merged);
+        assert_eq!(merged["user_uid"], alice["user_uid"]);
+        assert_eq!(identities().await, 3);
+
+        // The token only works once, and the account now signs in as Alice.
+        let (status, _) = merge(&app, merge_token, access_token).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, again) = sign_in(&app, &mock, "microsoft", "alice-ms").await;
+        assert_eq!(status, StatusCode::OK, "{}", again);
+        assert_eq!(again["user_uid"], alice["user_uid"]);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn unverified_email_of_a_user_never_merges(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let app = app(pool.clone(), &mock);
+
+        let (_, alice) = sign_in(&app, &mock, "google", "alice").await;
+        mock.override_email("alice@example.com", false);
+        let (status, other) = sign_in(&app, &mock, "microsoft", "mallory").await;
+        assert_eq!(status, StatusCode::OK, "{}", other);
+        assert_ne!(other["user_uid"], alice["user_uid"]);
+
+        let primary_emails: Vec<Option<String>> =
+            sqlx::query_scalar("SELECT primary_email::text FROM users ORDER BY create_time")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            primary_emails,
+            vec![Some("alice@example.com".to_string()), None]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authentication: signing in through OAuth providers or with a password, issuing and verifying
+//! access tokens and identifying the caller of a request.
+
+mod authz;
+mod cipher;
+mod http;
+mod oauth;
+mod password;
+mod provider;
+mod refresh;
+mod renewal;
+mod service;
+mod token;
+
+pub use authz::{authorize_user_uid, permission_denied};
+pub use cipher::{reencrypt_identity_tokens, CipherError, TokenCipher, TokenColumn};
+pub use http::router;
+pub use oauth::{LoginError, LoginOutcome, LoginRedirect, OAuthLogin, PendingMerge};
+pub use password::PasswordReset;
+pub use provider::{OAuthProvider, ProviderError, ProviderTokens, ProviderUser};
+pub use renewal::{TokenRefresher, TokenRefresherConfig};
+pub use service::AuthServer;
+pub use token::{Audience, Claims, Principal, TokenError, TokenSigner, TokenVerifier};
+
+pub(crate) use password::{
+    check_policy, hash_password, normalize_email, verify_password, PasswordRepository,
+};
+pub(crate) use refresh::RefreshTokenRepository;
+
+use tonic::{Request, Status};
+
+/// Scope granting access to other users' data and resources, given to users marked as admins.
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// The authenticated caller of a request that passed through `TokenInterceptor`.
+pub fn principal<T>(request: &Request<T>) -> Result<&Principal, Status> {
+    request
+        .extensions()
+        .get::<Principal>()
+        .ok_or_else(|| Status::unauthenticated("Request is not authenticated"))
+}
@@ -0,0 +1,650 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::cipher::{TokenCipher, TokenColumn};
+use super::provider::{OAuthProvider, ProviderError, ProviderTokens, ProviderUser};
+use super::service::AuthServer;
+use super::token::{TokenError, TokenVerifier};
+use crate::config::AppConfig;
+use crate::meta::{AuditAction, AuditContext, CreateIdentity, IdentityRepository, LinkOutcome};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::TokenResponse;
+use rand::RngCore;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Status;
+use uuid::Uuid;
+
+/// Number of random bytes in the state, PKCE verifier and nonce of a sign-in.
+const SECRET_BYTES: usize = 32;
+
+#[derive(Debug)]
+pub enum LoginError {
+    UnknownProvider(String),
+    /// The state is unknown, expired, already used or was not started by this browser.
+    InvalidState,
+    /// The user declined, or the provider reported an error in the callback.
+    Denied(String),
+    /// Linking was requested without a valid access token.
+    Unauthenticated(TokenError),
+    /// The provider account is linked to another user, or the user already has one from the
+    /// provider.
+    AlreadyLinked(String),
+    Provider(ProviderError),
+    Database(sqlx::Error),
+    /// Issuing our own tokens failed.
+    Login(Status),
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginError::UnknownProvider(name) => write!(f, "Unknown provider {}", name),
+            LoginError::InvalidState => write!(f, "Invalid or expired sign-in state"),
+            LoginError::Denied(message) => write!(f, "Sign-in was not authorized: {}", message),
+            LoginError::Unauthenticated(e) => write!(f, "Invalid access token: {}", e),
+            LoginError::AlreadyLinked(message) => write!(f, "{}", message),
+            LoginError::Provider(e) => write!(f, "{}", e),
+            LoginError::Database(e) => write!(f, "Database error: {}", e),
+            LoginError::Login(status) => write!(f, "Failed to sign in: {}", status.message()),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+impl From<ProviderError> for LoginError {
+    fn from(e: ProviderError) -> Self {
+        LoginError::Provider(e)
+    }
+}
+
+impl From<TokenError> for LoginError {
+    fn from(e: TokenError) -> Self {
+        LoginError::Unauthenticated(e)
+    }
+}
+
+impl From<sqlx::Error> for LoginError {
+    fn from(e: sqlx::Error) -> Self {
+        LoginError::Database(e)
+    }
+}
+
+fn random_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn hash(state: &str) -> Vec<u8> {
+    Sha256::digest(state.as_bytes()).to_vec()
+}
+
+/// The S256 PKCE challenge for a verifier.
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[derive(Debug, FromRow)]
+struct LoginState {
+    provider: String,
+    code_verifier: String,
+    nonce: String,
+    expire_time: DateTime<Utc>,
+    /// The user linking the provider account, or `None` for a sign-in.
+    user_id: Option<Uuid>,
+}
+
+/// Sign-ins that were started and are waiting for the provider's callback.
+struct LoginStateRepository {
+    pool: PgPool,
+}
+
+impl LoginStateRepository {
+    fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn create(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        user_id: Option<Uuid>,
+        ttl: Duration,
+    ) -> Result<(), sqlx::Error> {
+        // Abandoned sign-ins are cleaned up as new ones start.
+        sqlx::query(
+            r#"
+            DELETE FROM public.oauth_login_states
+            WHERE expire_time < now()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO public.oauth_login_states
+                (state_hash, provider, code_verifier, nonce, user_id, expire_time)
+            VALUES ($1, $2::identity_provider, $3, $4, $5, now() + make_interval(secs => $6))
+            "#,
+        )
+        .bind(hash(state))
+        .bind(provider)
+        .bind(code_verifier)
+        .bind(nonce)
+        .bind(user_id)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove and return the sign-in for a state, so each state can only be used once.
+    async fn take(&self, state: &str) -> Result<Option<LoginState>, sqlx::Error> {
+        sqlx::query_as::<_, LoginState>(
+            r#"
+            DELETE FROM public.oauth_login_states
+            WHERE state_hash = $1
+            RETURNING provider::text AS provider, code_verifier, nonce, expire_time, user_id
+            "#,
+        )
+        .bind(hash(state))
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+/// A provider account waiting to be merged into the user with the same verified email.
+#[derive(Debug, FromRow)]
+struct MergeState {
+    identity_id: Uuid,
+    provider: String,
+    provider_user_id: String,
+    provider_email: String,
+    provider_username: Option<String>,
+    provider_avatar_url: Option<String>,
+    access_token_encrypted: Option<String>,
+    refresh_token_encrypted: Option<String>,
+    token_expires_at: Option<DateTime<Utc>>,
+}
+
+/// Sign-ins that are waiting for an existing user to confirm a merge.
+struct PendingMergeRepository {
+    pool: PgPool,
+}
+
+impl PendingMergeRepository {
+    fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Offer to merge `identity` into its user, returning when the offer expires.
+    async fn create(
+        &self,
+        token: &str,
+        identity: &CreateIdentity,
+        ttl: Duration,
+    ) -> Result<DateTime<Utc>, sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM public.oauth_pending_merges
+            WHERE expire_time < now()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let (expire_time,): (DateTime<Utc>,) = sqlx::query_as(
+            r#"
+            INSERT INTO public.oauth_pending_merges
+                (token_hash, user_id, identity_id, provider, provider_user_id,
+                 provider_email, provider_username, provider_avatar_url,
+                 access_token_encrypted, refresh_token_encrypted, token_expires_at,
+                 expire_time)
+            VALUES ($1, $2, $3, $4::identity_provider, $5, $6, $7, $8, $9, $10, $11,
+                    now() + make_interval(secs => $12))
+            RETURNING expire_time
+            "#,
+        )
+        .bind(hash(token))
+        .bind(identity.user_id)
+        .bind(identity.id)
+        .bind(&identity.provider)
+        .bind(&identity.provider_user_id)
+        .bind(&identity.provider_email)
+        .bind(&identity.provider_username)
+        .bind(&identity.provider_avatar_url)
+        .bind(&identity.access_token_encrypted)
+        .bind(&identity.refresh_token_encrypted)
+        .bind(identity.token_expires_at)
+        .bind(ttl.as_secs_f64())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(expire_time)
+    }
+
+    /// Remove and return the unexpired merge offered to `user_id` for a token, so each offer
+    /// can only be confirmed once, and only by that user.
+    async fn take(
+        tx: &mut PgConnection,
+        token: &str,
+        user_id: Uuid,
+    ) -> Result<Option<MergeState>, sqlx::Error> {
+        sqlx::query_as::<_, MergeState>(
+            r#"
+            DELETE FROM public.oauth_pending_merges
+            WHERE token_hash = $1 AND user_id = $2 AND expire_time > now()
+            RETURNING identity_id, provider::text AS provider, provider_user_id,
+                      provider_email::text AS provider_email, provider_username,
+                      provider_avatar_url, access_token_encrypted, refresh_token_encrypted,
+                      token_expires_at
+            "#,
+        )
+        .bind(hash(token))
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await
+    }
+}
+
+/// A sign-in whose verified email is the primary email of an existing user. The provider
+/// account is only attached to that user once they confirm it with
+/// `OAuthLogin::confirm_merge`, while signed in.
+#[derive(Debug)]
+pub struct PendingMerge {
+    pub token: String,
+    /// The email the provider account and the user share.
+    pub email: String,
+    pub expire_time: DateTime<Utc>,
+}
+
+/// The end of a sign-in.
+#[derive(Debug)]
+pub enum LoginOutcome {
+    /// Tokens for the user the provider account belongs to.
+    SignedIn(TokenResponse),
+    MergeRequired(PendingMerge),
+}
+
+/// What the callback did with the provider account.
+enum Stored {
+    /// The identity and its user.
+    Identity(Uuid, Uuid),
+    MergeRequired(PendingMerge),
+}
+
+/// A sign-in started with `OAuthLogin::start`.
+#[derive(Debug)]
+pub struct LoginRedirect {
+    /// The provider's authorization URL to send the user to.
+    pub url: Url,
+    /// Must come back with the callback, from the same browser.
+    pub state: String,
+}
+
+/// Signs users in with the authorization code flow, using PKCE and, for OpenID Connect
+/// providers, a nonce bound to the ID token.
+#[derive(Debug)]
+pub struct OAuthLogin {
+    pool: PgPool,
+    http: reqwest::Client,
+    providers: HashMap<&'static str, OAuthProvider>,
+    redirect_base: Url,
+    state_ttl: Duration,
+    cipher: Option<Arc<TokenCipher>>,
+    auth: AuthServer,
+    /// Authenticates the users that link provider accounts.
+    verifier: TokenVerifier,
+}
+
+impl OAuthLogin {
+    pub fn from_config(
+        config: &AppConfig,
+        pool: PgPool,
+        cipher: Option<Arc<TokenCipher>>,
+        auth: AuthServer,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("geist/", env!("CARGO_PKG_VERSION")))
+            .timeout(config.server_timeout())
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Self {
+            pool,
+            http,
+            providers: OAuthProvider::from_config(config)?,
+            redirect_base: Url::parse(&config.oauth_redirect_base())?,
+            state_ttl: config.oauth_state_ttl(),
+            cipher,
+            auth,
+            verifier: TokenVerifier::from_config(config)?,
+        })
+    }
+
+    /// Add a provider, replacing the configured one of the same name.
+    #[cfg(test)]
+    pub(super) fn with_provider(mut self, provider: OAuthProvider) -> Self {
+        self.providers.insert(provider.name(), provider);
+        self
+    }
+
+    /// Names of the providers users can sign in with.
+    pub fn providers(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.providers.keys().copied()
+    }
+
+    /// Whether the public URL is served over HTTPS, so cookies can be marked secure.
+    pub fn is_secure(&self) -> bool {
+        self.redirect_base.scheme() == "https"
+    }
+
+    pub fn state_ttl(&self) -> Duration {
+        self.state_ttl
+    }
+
+    fn provider(&self, name: &str) -> Result<&OAuthProvider, LoginError> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| LoginError::UnknownProvider(name.to_string()))
+    }
+
+    fn redirect_uri(&self, provider: &str) -> String {
+        format!(
+            "{}/auth/{}/callback",
+            self.redirect_base.as_str().trim_end_matches('/'),
+            provider
+        )
+    }
+
+    /// Start a sign-in, returning where to send the user.
+    pub async fn start(&self, provider: &str) -> Result<LoginRedirect, LoginError> {
+        self.begin(provider, None).await
+    }
+
+    /// Start linking an account at `provider` to the user that `access_token` was issued to.
+    /// The identity is only attached once that user signs in with the provider, so a user can
+    /// only link accounts they control.
+    pub async fn start_link(
+        &self,
+        provider: &str,
+        access_token: &str,
+    ) -> Result<LoginRedirect, LoginError> {
+        let principal = self.verifier.verify(access_token)?;
+        self.begin(provider, Some(principal.user_uid)).await
+    }
+
+    async fn begin(
+        &self,
+        provider: &str,
+        user_id: Option<Uuid>,
+    ) -> Result<LoginRedirect, LoginError> {
+        let provider = self.provider(provider)?;
+
+        let state = random_secret();
+        let code_verifier = random_secret();
+        let nonce = random_secret();
+
+        LoginStateRepository::new(self.pool.clone())
+            .create(
+                &state,
+                provider.name(),
+                &code_verifier,
+                &nonce,
+                user_id,
+                self.state_ttl,
+            )
+            .await?;
+
+        let url = provider.authorize_url(
+            &self.redirect_uri(provider.name()),
+            &state,
+            &code_challenge(&code_verifier),
+            &nonce,
+        );
+
+        Ok(LoginRedirect { url, state })
+    }
+
+    /// Complete a sign-in from the provider's callback, creating the user on first sign-in.
+    /// When the sign-in was started to link an account, the identity is attached to the user
+    /// that started it, who is then signed in. When the verified email belongs to a user, that
+    /// user has to confirm the merge first.
+    pub async fn finish(
+        &self,
+        provider: &str,
+        code: &str,
+        state: &str,
+    ) -> Result<LoginOutcome, LoginError> {
+        let provider = self.provider(provider)?;
+
+        let pending = LoginStateRepository::new(self.pool.clone())
+            .take(state)
+            .await?
+            .ok_or(LoginError::InvalidState)?;
+        if pending.provider != provider.name() || pending.expire_time <= Utc::now() {
+            return Err(LoginError::InvalidState);
+        }
+
+        let tokens = provider
+            .exchange(
+                &self.http,
+                code,
+                &self.redirect_uri(provider.name()),
+                &pending.code_verifier,
+            )
+            .await?;
+        let user = provider.user(&self.http, &tokens, &pending.nonce).await?;
+
+        match self
+            .store(provider.name(), &user, &tokens, pending.user_id)
+            .await?
+        {
+            Stored::Identity(user_id, identity_id) => self
+                .login(user_id, identity_id)
+                .await
+                .map(LoginOutcome::SignedIn),
+            Stored::MergeRequired(merge) => Ok(LoginOutcome::MergeRequired(merge)),
+        }
+    }
+
+    /// Attach the provider account of a pending merge to the user `access_token` was issued
+    /// to, and sign them in with it. Only the user the merge was offered to can confirm it.
+    pub async fn confirm_merge(
+        &self,
+        merge_token: &str,
+        access_token: &str,
+    ) -> Result<TokenResponse, LoginError> {
+        let user_id = self.verifier.verify(access_token)?.user_uid;
+
+        // The offer, the user's email and the new identity are checked and written together.
+        let mut tx = self.pool.begin().await?;
+        let pending = PendingMergeRepository::take(&mut tx, merge_token, user_id)
+            .await?
+            .ok_or(LoginError::InvalidState)?;
+        let identity = CreateIdentity {
+            id: pending.identity_id,
+            user_id,
+            provider: pending.provider,
+            provider_user_id: pending.provider_user_id,
+            provider_email: Some(pending.provider_email),
+            provider_username: pending.provider_username,
+            provider_avatar_url: pending.provider_avatar_url,
+            access_token_encrypted: pending.access_token_encrypted,
+            refresh_token_encrypted: pending.refresh_token_encrypted,
+            token_expires_at: pending.token_expires_at,
+            metadata: None,
+            is_primary: false,
+            verified: true,
+        };
+        let merged = IdentityRepository::merge(&mut tx, &identity)
+            .await
+            .map_err(|e| link_error(e, &identity.provider))?
+            .ok_or(LoginError::InvalidState)?;
+        tx.commit().await?;
+
+        tracing::info!(
+            user_uid = %user_id,
+            provider = %identity.provider,
+            "Merged provider account by verified email"
+        );
+        self.record_link(user_id, merged.id).await;
+
+        self.login(user_id, merged.id).await
+    }
+
+    async fn login(&self, user_id: Uuid, identity_id: Uuid) -> Result<TokenResponse, LoginError> {
+        self.auth
+            .login(user_id, identity_id)
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(LoginError::Login)
+    }
+
+    async fn record_link(&self, user_id: Uuid, identity_id: Uuid) {
+        AuditContext {
+            actor_user_id: Some(user_id),
+            ip_address: None,
+            trace_id: None,
+        }
+        .record(
+            &self.pool,
+            AuditAction::IdentityLinked,
+            user_id,
+            Some(identity_id),
+        )
+        .await;
+    }
+
+    /// Record the provider's profile and tokens on the identity, creating the identity and
+    /// its user if this is the first sign-in, or attaching it to `link_user`. A first sign-in
+    /// with the verified email of an existing user is kept as a pending merge instead.
+    async fn store(
+        &self,
+        provider: &str,
+        user: &ProviderUser,
+        tokens: &ProviderTokens,
+        link_user: Option<Uuid>,
+    ) -> Result<Stored, LoginError> {
+        let repo = IdentityRepository::new(self.pool.clone());
+        let existing = repo.find_by_provider(provider, &user.id).await?;
+        if let (Some(existing), Some(link_user)) = (&existing, link_user) {
+            if existing.user_id != link_user {
+                return Err(LoginError::AlreadyLinked(format!(
+                    "This {} account is linked to another user",
+                    provider
+                )));
+            }
+        }
+
+        let identity_id = existing
+            .as_ref()
+            .map(|identity| identity.id)
+            .unwrap_or_else(Uuid::now_v7);
+        let (access_token, refresh_token) = match self.cipher.as_deref() {
+            Some(cipher) => (
+                Some(cipher.encrypt(&tokens.access_token, identity_id, TokenColumn::AccessToken)),
+                tokens
+                    .refresh_token
+                    .as_deref()
+                    .map(|t| cipher.encrypt(t, identity_id, TokenColumn::RefreshToken)),
+            ),
+            None => {
+                tracing::debug!(
+                    provider,
+                    "Token encryption is not configured, not storing provider tokens"
+                );
+                (None, None)
+            }
+        };
+
+        let identity = CreateIdentity {
+            id: identity_id,
+            user_id: existing
+                .as_ref()
+                .map(|identity| identity.user_id)
+                .or(link_user)
+                .unwrap_or_else(Uuid::now_v7),
+            provider: provider.to_string(),
+            provider_user_id: user.id.clone(),
+            provider_email: user.email.clone(),
+            provider_username: user.username.clone(),
+            provider_avatar_url: user.avatar_url.clone(),
+            token_expires_at: access_token.as_ref().map(|_| tokens.expire_time()),
+            access_token_encrypted: access_token,
+            refresh_token_encrypted: refresh_token,
+            metadata: None,
+            is_primary: existing.is_none(),
+            verified: user.email_verified,
+        };
+
+        match (existing, link_user) {
+            (Some(existing), _) => {
+                repo.update_from_provider(existing.id, &identity).await?;
+                Ok(Stored::Identity(existing.user_id, existing.id))
+            }
+            (None, Some(user_id)) => {
+                let created = repo
+                    .attach(&identity)
+                    .await
+                    .map_err(|e| link_error(e, provider))?;
+                tracing::info!(user_uid = %user_id, provider, "Linked provider account");
+                self.record_link(user_id, created.id).await;
+
+                Ok(Stored::Identity(user_id, created.id))
+            }
+            (None, None) => match repo.link_new_user(&identity).await? {
+                LinkOutcome::Created(created) => {
+                    tracing::info!(
+                        user_uid = %created.user_id,
+                        provider,
+                        "Created user on first sign-in"
+                    );
+                    Ok(Stored::Identity(created.user_id, created.id))
+                }
+                LinkOutcome::MergeRequired(user_id) => {
+                    let token = random_secret();
+                    let identity = CreateIdentity {
+                        user_id,
+                        ..identity
+                    };
+                    let expire_time = PendingMergeRepository::new(self.pool.clone())
+                        .create(&token, &identity, self.state_ttl)
+                        .await?;
+                    tracing::info!(
+                        user_uid = %user_id,
+                        provider,
+                        "Sign-in matches the verified email of a user, waiting for them to merge"
+                    );
+
+                    Ok(Stored::MergeRequired(PendingMerge {
+                        token,
+                        email: identity.provider_email.unwrap_or_default(),
+                        expire_time,
+                    }))
+                }
+            },
+        }
+    }
+}
+
+/// Report attaching an identity that breaks the one-account-per-provider rules as a conflict.
+fn link_error(e: sqlx::Error, provider: &str) -> LoginError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => LoginError::AlreadyLinked(
+            format!("The user already has a {} account linked", provider),
+        ),
+        e => LoginError::from(e),
+    }
+}
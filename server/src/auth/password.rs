@@ -0,0 +1,667 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::AppConfig;
+use crate::mail::Mailer;
+use crate::meta::{CreateIdentity, IdentityRepository, UserIdentity};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tonic::Status;
+use uuid::Uuid;
+
+/// Identity provider label of email and password identities.
+pub const EMAIL_PROVIDER: &str = "email";
+
+pub const MIN_PASSWORD_LEN: usize = 12;
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+/// Longest email address accepted, per RFC 5321.
+const MAX_EMAIL_LEN: usize = 254;
+
+/// Number of random bytes in a password reset or email verification token.
+const RESET_TOKEN_BYTES: usize = 32;
+
+/// Passwords that meet the length requirement but are still guessed first.
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456789012",
+    "1234567890123",
+    "qwertyuiopas",
+    "passwordpassword",
+    "password1234",
+    "password12345",
+    "iloveyou1234",
+    "letmeinletmein",
+    "correcthorsebatterystaple",
+];
+
+/// Normalize an email address for use as the identity's provider user id.
+pub fn normalize_email(email: &str) -> Result<String, Status> {
+    let email = email.trim().to_lowercase();
+
+    let valid = email.len() <= MAX_EMAIL_LEN
+        && !email.chars().any(char::is_whitespace)
+        && email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && domain.contains('.') && !domain.contains('@')
+        });
+    if !valid {
+        return Err(Status::invalid_argument("Invalid email address"));
+    }
+
+    Ok(email)
+}
+
+/// Check a new password against the password policy.
+pub fn check_policy(password: &str, email: &str) -> Result<(), Status> {
+    let len = password.chars().count();
+    if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&len) {
+        return Err(Status::invalid_argument(format!(
+            "Password must be between {} and {} characters",
+            MIN_PASSWORD_LEN, MAX_PASSWORD_LEN
+        )));
+    }
+
+    let lowered = password.to_lowercase();
+    let local = email.split('@').next().unwrap_or_default();
+    if lowered.contains(email) || (local.len() >= 4 && lowered.contains(local)) {
+        return Err(Status::invalid_argument(
+            "Password must not contain the email address",
+        ));
+    }
+
+    let mut chars = password.chars();
+    let first = chars.next();
+    if chars.all(|c| Some(c) == first) || COMMON_PASSWORDS.contains(&lowered.as_str()) {
+        return Err(Status::invalid_argument("Password is too easy to guess"));
+    }
+
+    Ok(())
+}
+
+/// Hash a password with Argon2id. Hashing is deliberately slow, so it runs off the async
+/// runtime.
+pub async fn hash_password(password: String) -> Result<String, Status> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|e| Status::internal(format!("Password hashing failed: {}", e)))?
+    .map_err(|e| Status::internal(format!("Password hashing failed: {}", e)))
+}
+
+/// A hash of a random password, verified against when there is no stored hash so that unknown
+/// emails take as long to reject as wrong passwords.
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(random_token().as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Check a password against a stored hash. The hash comparison is constant-time.
+pub async fn verify_password(password: String, hash: Option<String>) -> Result<bool, Status> {
+    tokio::task::spawn_blocking(move || {
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| dummy_hash().to_string());
+        let parsed = PasswordHash::new(&hash)
+            .map_err(|e| Status::internal(format!("Invalid stored password hash: {}", e)))?;
+
+        let matches = Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok();
+        Ok(known && matches)
+    })
+    .await
+    .map_err(|e| Status::internal(format!("Password verification failed: {}", e)))?
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; RESET_TOKEN_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// The email identity of a user and its password hash.
+#[derive(Clone, FromRow)]
+pub struct PasswordRecord {
+    pub identity_id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub password_hash: String,
+    /// Whether the owner of the email has proven control of it. Unverified identities cannot
+    /// sign in until their password is reset.
+    pub verified: bool,
+}
+
+impl std::fmt::Debug for PasswordRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasswordRecord")
+            .field("identity_id", &self.identity_id)
+            .field("user_id", &self.user_id)
+            .field("email", &self.email)
+            .field("verified", &self.verified)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A sign-up, or a password registration of `user_id`, waiting for its email to be verified.
+#[derive(Clone, FromRow)]
+pub struct PendingVerification {
+    pub email: String,
+    pub user_id: Option<Uuid>,
+    pub username: Option<String>,
+    pub password_hash: String,
+}
+
+impl std::fmt::Debug for PendingVerification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingVerification")
+            .field("email", &self.email)
+            .field("user_id", &self.user_id)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct PasswordRepository {
+    pool: PgPool,
+}
+
+impl PasswordRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<PasswordRecord>, sqlx::Error> {
+        sqlx::query_as::<_, PasswordRecord>(
+            r#"
+            SELECT i.id AS identity_id, i.user_id, i.provider_user_id AS email, p.password_hash,
+                   i.verified
+            FROM public.user_identities i
+            JOIN public.user_passwords p ON p.identity_id = i.id
+            WHERE i.provider = 'email' AND i.provider_user_id = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_user_id(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<PasswordRecord>, sqlx::Error> {
+        sqlx::query_as::<_, PasswordRecord>(
+            r#"
+            SELECT i.id AS identity_id, i.user_id, i.provider_user_id AS email, p.password_hash,
+                   i.verified
+            FROM public.user_identities i
+            JOIN public.user_passwords p ON p.identity_id = i.id
+            WHERE i.provider = 'email' AND i.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Hold a sign-up or registration until the owner of `email` returns the token. Earlier
+    /// pending ones for the email are kept, so someone who does not control the address
+    /// cannot block its owner. Returns the raw token.
+    pub async fn create_verification(
+        &self,
+        email: &str,
+        user_id: Option<Uuid>,
+        username: Option<&str>,
+        password_hash: &str,
+        ttl: Duration,
+    ) -> Result<String, sqlx::Error> {
+        let token = random_token();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM public.email_verifications WHERE expire_time < now()")
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO public.email_verifications
+                (token_hash, email, user_id, username, password_hash, expire_time)
+            VALUES ($1, $2, $3, $4, $5, now() + make_interval(secs => $6))
+            "#,
+        )
+        .bind(hash_token(&token))
+        .bind(email)
+        .bind(user_id)
+        .bind(username)
+        .bind(password_hash)
+        .bind(ttl.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    /// The sign-up or registration an unexpired verification token was issued for.
+    pub async fn find_verification(
+        &self,
+        token: &str,
+    ) -> Result<Option<PendingVerification>, sqlx::Error> {
+        sqlx::query_as::<_, PendingVerification>(
+            r#"
+            SELECT email::text AS email, user_id, username, password_hash
+            FROM public.email_verifications
+            WHERE token_hash = $1 AND expire_time > now()
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Use a verification token to create its email identity, verified, with the password it
+    /// was issued with: for a new user, or added to the user that registered it. Other pending
+    /// verifications of the email are dropped. Returns `None` if the token was already used or
+    /// expired.
+    pub async fn verify(&self, token: &str) -> Result<Option<UserIdentity>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let pending = sqlx::query_as::<_, PendingVerification>(
+            r#"
+            DELETE FROM public.email_verifications
+            WHERE token_hash = $1 AND expire_time > now()
+            RETURNING email::text AS email, user_id, username, password_hash
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(pending) = pending else {
+            return Ok(None);
+        };
+
+        sqlx::query("DELETE FROM public.email_verifications WHERE email = $1")
+            .bind(&pending.email)
+            .execute(&mut *tx)
+            .await?;
+
+        let created = match pending.user_id {
+            Some(user_id) => {
+                let identity = CreateIdentity {
+                    verified: true,
+                    ..email_identity(user_id, &pending.email, pending.username)
+                };
+                IdentityRepository::attach_in(&mut tx, &identity).await?
+            }
+            None => {
+                let identity = CreateIdentity {
+                    is_primary: true,
+                    verified: true,
+                    ..email_identity(Uuid::now_v7(), &pending.email, pending.username)
+                };
+                IdentityRepository::insert_with_user(&mut tx, &identity).await?
+            }
+        };
+        Self::insert(&mut tx, created.id, &pending.password_hash).await?;
+
+        tx.commit().await?;
+        Ok(Some(created))
+    }
+
+    async fn insert(
+        tx: &mut sqlx::PgConnection,
+        identity_id: Uuid,
+        password_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO public.user_passwords (identity_id, password_hash)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(identity_id)
+        .bind(password_hash)
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update(&self, identity_id: Uuid, password_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.user_passwords
+            SET password_hash = $2, update_time = now()
+            WHERE identity_id = $1
+            "#,
+        )
+        .bind(identity_id)
+        .bind(password_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Issue a reset token for an identity, replacing any earlier ones. Returns the raw token.
+    pub async fn create_reset_token(
+        &self,
+        identity_id: Uuid,
+        ttl: Duration,
+    ) -> Result<String, sqlx::Error> {
+        let token = random_token();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM public.password_reset_tokens
+            WHERE identity_id = $1 OR expire_time < now()
+            "#,
+        )
+        .bind(identity_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO public.password_reset_tokens (token_hash, identity_id, expire_time)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            "#,
+        )
+        .bind(hash_token(&token))
+        .bind(identity_id)
+        .bind(ttl.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    /// The identity an unexpired reset token was issued for.
+    pub async fn find_by_reset_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<PasswordRecord>, sqlx::Error> {
+        sqlx::query_as::<_, PasswordRecord>(
+            r#"
+            SELECT i.id AS identity_id, i.user_id, i.provider_user_id AS email, p.password_hash,
+                   i.verified
+            FROM public.password_reset_tokens t
+            JOIN public.user_identities i ON i.id = t.identity_id
+            JOIN public.user_passwords p ON p.identity_id = i.id
+            WHERE t.token_hash = $1 AND t.expire_time > now()
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Use a reset token to set a new password. The token is consumed, and since the reset
+    /// proves control of the email, the identity is marked verified. The old hash is replaced,
+    /// so a password set by whoever created an unverified identity stops working. Returns the
+    /// user whose password was reset, or `None` if the token was already used or expired.
+    pub async fn reset(
+        &self,
+        token: &str,
+        password_hash: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let identity_id: Option<(Uuid,)> = sqlx::query_as(
+            r#"
+            DELETE FROM public.password_reset_tokens
+            WHERE token_hash = $1 AND expire_time > now()
+            RETURNING identity_id
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((identity_id,)) = identity_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE public.user_passwords
+            SET password_hash = $2, update_time = now()
+            WHERE identity_id = $1
+            "#,
+        )
+        .bind(identity_id)
+        .bind(password_hash)
+        .execute(&mut *tx)
+        .await?;
+
+        let (user_id,): (Uuid,) = sqlx::query_as(
+            r#"
+            UPDATE public.user_identities
+            SET verified = true, update_time = now()
+            WHERE id = $1
+            RETURNING user_id
+            "#,
+        )
+        .bind(identity_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+}
+
+/// Emails password reset and email verification tokens.
+#[derive(Debug)]
+pub struct PasswordReset {
+    mailer: Mailer,
+    url: Option<Url>,
+    verification_url: Option<Url>,
+    ttl: Duration,
+}
+
+impl PasswordReset {
+    pub fn from_config(
+        config: &AppConfig,
+        mailer: Mailer,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let url = config
+            .password_reset_url
+            .as_deref()
+            .map(Url::parse)
+            .transpose()?;
+        let verification_url = config
+            .email_verification_url
+            .as_deref()
+            .map(Url::parse)
+            .transpose()?;
+
+        Ok(Self {
+            mailer,
+            url,
+            verification_url,
+            ttl: config.password_reset_ttl(),
+        })
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Send a reset token to the email it was issued for.
+    pub async fn send(&self, email: &str, token: &str) {
+        let body = format!(
+            "Someone asked to reset the password of your Geist account.\n\n{}\n\n\
+             It expires in {} minutes. If you did not ask for this, you can ignore this email.\n",
+            instructions(self.url.as_ref(), token, "choose a new password"),
+            self.ttl.as_secs().div_ceil(60)
+        );
+
+        if let Err(e) = self
+            .mailer
+            .send(email, "Reset your Geist password", body)
+            .await
+        {
+            tracing::error!(error = %e, "Failed to send password reset email");
+        }
+    }
+
+    /// Send a verification token to the email it was issued for.
+    pub async fn send_verification(&self, email: &str, token: &str) {
+        let body = format!(
+            "Someone asked to sign in to Geist with this email and a password.\n\n{}\n\n\
+             You will need the password they chose. It expires in {} minutes. If you did not \
+             ask for this, you can ignore this email.\n",
+            instructions(self.verification_url.as_ref(), token, "verify your email"),
+            self.ttl.as_secs().div_ceil(60)
+        );
+
+        if let Err(e) = self
+            .mailer
+            .send(email, "Verify your Geist email", body)
+            .await
+        {
+            tracing::error!(error = %e, "Failed to send email verification email");
+        }
+    }
+}
+
+/// How to use an emailed `token`: a link to `url` when there is one, or the token itself.
+fn instructions(url: Option<&Url>, token: &str, action: &str) -> String {
+    match url {
+        Some(url) => {
+            let mut url = url.clone();
+            url.query_pairs_mut().append_pair("token", token);
+            format!("Open this link to {}:\n\n{}", action, url)
+        }
+        None => format!("Use this token to {}:\n\n{}", action, token),
+    }
+}
+
+/// An email identity for `user_id`, as stored for a password sign-in.
+fn email_identity(user_id: Uuid, email: &str, username: Option<String>) -> CreateIdentity {
+    CreateIdentity {
+        id: Uuid::now_v7(),
+        user_id,
+        provider: EMAIL_PROVIDER.to_string(),
+        provider_user_id: email.to_string(),
+        provider_email: Some(email.to_string()),
+        provider_username: username,
+        provider_avatar_url: None,
+        access_token_encrypted: None,
+        refresh_token_encrypted: None,
+        token_expires_at: None,
+        metadata: None,
+        is_primary: false,
+        verified: false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::mail::tests::{config, SmtpCapture};
+    use std::sync::Arc;
+
+    /// Password reset and verification emails sent through `capture`.
+    pub(crate) fn password_reset(capture: &SmtpCapture) -> Arc<PasswordReset> {
+        let config = config(capture);
+        let mailer = Mailer::from_config(&config).unwrap();
+        Arc::new(PasswordReset::from_config(&config, mailer).unwrap())
+    }
+
+    /// Move every unexpired token of `table` into the past.
+    pub(crate) async fn expire_tokens(pool: &PgPool, table: &str) {
+        sqlx::query(&format!(
+            "UPDATE public.{} SET expire_time = now() - interval '1 second'",
+            table
+        ))
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn verification_creates_one_identity_for_the_email(pool: PgPool) {
+        let repo = PasswordRepository::new(pool.clone());
+        let ttl = Duration::from_secs(60);
+        let squatter = repo
+            .create_verification("alice@example.com", None, None, "squatter", ttl)
+            .await
+            .unwrap();
+        let owner = repo
+            .create_verification("alice@example.com", None, Some("alice"), "owner", ttl)
+            .await
+            .unwrap();
+
+        // A pending sign-up does not claim the email, or make it usable for sign-in.
+        assert!(repo
+            .find_by_email("alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+
+        let created = repo.verify(&owner).await.unwrap().unwrap();
+        assert!(created.verified && created.is_primary);
+        assert_eq!(created.provider_username.as_deref(), Some("alice"));
+
+        let record = repo
+            .find_by_email("alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.user_id, created.user_id);
+        assert_eq!(record.password_hash, "owner");
+        assert!(record.verified);
+
+        // Tokens are single-use, and the other sign-ups of the email are dropped.
+        assert!(repo.verify(&owner).await.unwrap().is_none());
+        assert!(repo.find_verification(&squatter).await.unwrap().is_none());
+        assert!(repo.verify(&squatter).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn verification_tokens_expire(pool: PgPool) {
+        let repo = PasswordRepository::new(pool.clone());
+        let token = repo
+            .create_verification(
+                "alice@example.com",
+                None,
+                None,
+                "hash",
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+
+        expire_tokens(&pool, "email_verifications").await;
+
+        assert!(repo.find_verification(&token).await.unwrap().is_none());
+        assert!(repo.verify(&token).await.unwrap().is_none());
+        assert!(repo
+            .find_by_email("alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,797 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::token::Audience;
+use crate::config::AppConfig;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use jwt::{Header, PKeyWithDigest, Token, VerifyWithKey};
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Rsa;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Minimum time between fetches of a provider's signing keys, so that ID tokens naming unknown
+/// key ids cannot be used to hammer the provider.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Clock skew tolerated when checking the expiry of ID tokens, in seconds.
+const ID_TOKEN_LEEWAY_SECS: u64 = 60;
+
+/// Lifetime assumed for access tokens the provider does not give an `expires_in` for.
+const DEFAULT_TOKEN_LIFETIME_SECS: i64 = 3600;
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The provider could not be reached or answered with an error status.
+    Http(reqwest::Error),
+    /// The provider rejected the request, e.g. because the authorization code expired.
+    OAuth(String),
+    /// The provider's response could not be understood.
+    Response(String),
+    /// The ID token failed verification.
+    IdToken(String),
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::Http(e) => write!(f, "Provider request failed: {}", e),
+            ProviderError::OAuth(message) => write!(f, "Provider returned an error: {}", message),
+            ProviderError::Response(message) => {
+                write!(f, "Unexpected provider response: {}", message)
+            }
+            ProviderError::IdToken(message) => write!(f, "Invalid ID token: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl ProviderError {
+    /// Whether the provider rejected the request outright, so retrying it cannot succeed, e.g.
+    /// because a refresh token was revoked.
+    pub fn is_rejected(&self) -> bool {
+        match self {
+            ProviderError::OAuth(message) => {
+                !message.starts_with("temporarily_unavailable")
+                    && !message.starts_with("server_error")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        ProviderError::Http(e)
+    }
+}
+
+/// How the client authenticates itself to the token endpoint.
+#[derive(Debug, Clone, Copy)]
+enum ClientAuth {
+    /// `client_id` and `client_secret` in the form body.
+    Post,
+    /// HTTP Basic authentication.
+    Basic,
+}
+
+/// Where the profile of the signed-in user comes from.
+#[derive(Debug)]
+enum Profile {
+    /// The claims of an OpenID Connect ID token, verified against the provider's published
+    /// keys. `{tenantid}` in the issuer stands for the token's `tid` claim.
+    IdToken {
+        issuer: String,
+        jwks: Jwks,
+    },
+    Github,
+    Discord,
+    Twitter,
+}
+
+/// What a provider told us about the person signing in.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderUser {
+    /// The provider's stable id for the user.
+    pub id: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+/// Tokens returned by a provider's token endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: Option<i64>,
+    pub id_token: Option<String>,
+}
+
+impl ProviderTokens {
+    /// When the access token expires. Tokens are only refreshed once they are about to
+    /// expire, so one the provider gives no lifetime for is assumed to last an hour.
+    pub fn expire_time(&self) -> DateTime<Utc> {
+        Utc::now()
+            + chrono::Duration::seconds(self.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS))
+    }
+}
+
+/// An OAuth 2.0 provider users can sign in with.
+pub struct OAuthProvider {
+    /// Matches the `identity_provider` enum in the database.
+    name: &'static str,
+    client_id: String,
+    client_secret: String,
+    authorize_url: Url,
+    token_url: Url,
+    scopes: &'static [&'static str],
+    /// Extra parameters for the authorization request.
+    params: &'static [(&'static str, &'static str)],
+    client_auth: ClientAuth,
+    /// Whether the callback arrives as a form POST instead of a redirect with a query string.
+    form_post: bool,
+    profile: Profile,
+}
+
+impl std::fmt::Debug for OAuthProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthProvider")
+            .field("name", &self.name)
+            .field("client_id", &self.client_id)
+            .field("authorize_url", &self.authorize_url.as_str())
+            .field("profile", &self.profile)
+            .finish()
+    }
+}
+
+fn url(value: &str) -> Result<Url, ProviderError> {
+    Url::parse(value).map_err(|e| ProviderError::Response(format!("{}: {}", value, e)))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl OAuthProvider {
+    /// The providers that have a client configured, keyed by name.
+    pub fn from_config(
+        config: &AppConfig,
+    ) -> Result<HashMap<&'static str, OAuthProvider>, ProviderError> {
+        let clients = [
+            (
+                "google",
+                &config.oauth_google_client_id,
+                &config.oauth_google_client_secret,
+            ),
+            (
+                "github",
+                &config.oauth_github_client_id,
+                &config.oauth_github_client_secret,
+            ),
+            (
+                "twitter",
+                &config.oauth_twitter_client_id,
+                &config.oauth_twitter_client_secret,
+            ),
+            (
+                "discord",
+                &config.oauth_discord_client_id,
+                &config.oauth_discord_client_secret,
+            ),
+            (
+                "apple",
+                &config.oauth_apple_client_id,
+                &config.oauth_apple_client_secret,
+            ),
+            (
+                "microsoft",
+                &config.oauth_microsoft_client_id,
+                &config.oauth_microsoft_client_secret,
+            ),
+        ];
+
+        let mut providers = HashMap::new();
+        for (name, client_id, client_secret) in clients {
+            let (Some(client_id), Some(client_secret)) = (client_id, client_secret) else {
+                continue;
+            };
+
+            let provider = Self::builtin(
+                name,
+                client_id.clone(),
+                client_secret.clone(),
+                &config.oauth_microsoft_tenant,
+            )?;
+            providers.insert(name, provider);
+        }
+
+        Ok(providers)
+    }
+
+    fn builtin(
+        name: &'static str,
+        client_id: String,
+        client_secret: String,
+        microsoft_tenant: &str,
+    ) -> Result<Self, ProviderError> {
+        let provider = match name {
+            "google" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url("https://accounts.google.com/o/oauth2/v2/auth")?,
+                token_url: url("https://oauth2.googleapis.com/token")?,
+                scopes: &["openid", "email", "profile"],
+                // Google only issues refresh tokens for offline access.
+                params: &[("access_type", "offline"), ("prompt", "consent")],
+                client_auth: ClientAuth::Post,
+                form_post: false,
+                profile: Profile::IdToken {
+                    issuer: "https://accounts.google.com".to_string(),
+                    jwks: Jwks::new(url("https://www.googleapis.com/oauth2/v3/certs")?),
+                },
+            },
+            "github" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url("https://github.com/login/oauth/authorize")?,
+                token_url: url("https://github.com/login/oauth/access_token")?,
+                scopes: &["read:user", "user:email"],
+                params: &[],
+                client_auth: ClientAuth::Post,
+                form_post: false,
+                profile: Profile::Github,
+            },
+            "twitter" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url("https://x.com/i/oauth2/authorize")?,
+                token_url: url("https://api.x.com/2/oauth2/token")?,
+                scopes: &["users.read", "tweet.read", "offline.access"],
+                params: &[],
+                client_auth: ClientAuth::Basic,
+                form_post: false,
+                profile: Profile::Twitter,
+            },
+            "discord" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url("https://discord.com/oauth2/authorize")?,
+                token_url: url("https://discord.com/api/oauth2/token")?,
+                scopes: &["identify", "email"],
+                params: &[],
+                client_auth: ClientAuth::Post,
+                form_post: false,
+                profile: Profile::Discord,
+            },
+            // Apple expects `client_secret` to be a JWT signed with the team's key, which has to
+            // be regenerated at least every six months.
+            "apple" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url("https://appleid.apple.com/auth/authorize")?,
+                token_url: url("https://appleid.apple.com/auth/token")?,
+                scopes: &["name", "email"],
+                params: &[],
+                client_auth: ClientAuth::Post,
+                // Apple requires form_post whenever the name or email scope is requested.
+                form_post: true,
+                profile: Profile::IdToken {
+                    issuer: "https://appleid.apple.com".to_string(),
+                    jwks: Jwks::new(url("https://appleid.apple.com/auth/keys")?),
+                },
+            },
+            "microsoft" => Self {
+                name,
+                client_id,
+                client_secret,
+                authorize_url: url(&format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/authorize",
+                    microsoft_tenant
+                ))?,
+                token_url: url(&format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                    microsoft_tenant
+                ))?,
+                scopes: &["openid", "email", "profile", "offline_access"],
+                params: &[],
+                client_auth: ClientAuth::Post,
+                form_post: false,
+                profile: Profile::IdToken {
+                    // Multi-tenant apps receive tokens issued by the user's own tenant.
+                    issuer: "https://login.microsoftonline.com/{tenantid}/v2.0".to_string(),
+                    jwks: Jwks::new(url(&format!(
+                        "https://login.microsoftonline.com/{}/discovery/v2.0/keys",
+                        microsoft_tenant
+                    ))?),
+                },
+            },
+            _ => {
+                return Err(ProviderError::Response(format!(
+                    "unsupported provider {}",
+                    name
+                )))
+            }
+        };
+
+        Ok(provider)
+    }
+
+    /// An OpenID Connect provider served at `base`, standing in for `name` in tests.
+    #[cfg(test)]
+    pub(super) fn mock(name: &'static str, base: &str) -> Result<Self, ProviderError> {
+        Ok(Self {
+            name,
+            client_id: "geist".to_string(),
+            client_secret: "secret".to_string(),
+            authorize_url: url(&format!("{}/authorize", base))?,
+            token_url: url(&format!("{}/token", base))?,
+            scopes: &["openid", "email", "profile"],
+            params: &[],
+            client_auth: ClientAuth::Post,
+            form_post: false,
+            profile: Profile::IdToken {
+                issuer: base.to_string(),
+                jwks: Jwks::new(url(&format!("{}/jwks", base))?),
+            },
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The URL to send the user to, asking the provider to authorize this client.
+    pub fn authorize_url(
+        &self,
+        redirect_uri: &str,
+        state: &str,
+        code_challenge: &str,
+        nonce: &str,
+    ) -> Url {
+        let mut url = self.authorize_url.clone();
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("redirect_uri", redirect_uri)
+                .append_pair("scope", &self.scopes.join(" "))
+                .append_pair("state", state)
+                .append_pair("code_challenge", code_challenge)
+                .append_pair("code_challenge_method", "S256");
+
+            if matches!(self.profile, Profile::IdToken { .. }) {
+                query.append_pair("nonce", nonce);
+            }
+            if self.form_post {
+                query.append_pair("response_mode", "form_post");
+            }
+            for (key, value) in self.params {
+                query.append_pair(key, value);
+            }
+        }
+
+        url
+    }
+
+    /// Exchange an authorization code for tokens, proving possession of the PKCE verifier.
+    pub async fn exchange(
+        &self,
+        http: &reqwest::Client,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<ProviderTokens, ProviderError> {
+        self.token_request(
+            http,
+            vec![
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("code_verifier", code_verifier),
+            ],
+        )
+        .await
+    }
+
+    /// Exchange a refresh token for new tokens. Providers that do not rotate refresh tokens
+    /// leave `refresh_token` empty in the response.
+    pub async fn refresh(
+        &self,
+        http: &reqwest::Client,
+        refresh_token: &str,
+    ) -> Result<ProviderTokens, ProviderError> {
+        self.token_request(
+            http,
+            vec![
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+        )
+        .await
+    }
+
+    async fn token_request(
+        &self,
+        http: &reqwest::Client,
+        mut form: Vec<(&str, &str)>,
+    ) -> Result<ProviderTokens, ProviderError> {
+        let request = http
+            .post(self.token_url.clone())
+            .header(reqwest::header::ACCEPT, "application/json");
+        let request = match self.client_auth {
+            ClientAuth::Post => {
+                form.push(("client_id", &self.client_id));
+                form.push(("client_secret", &self.client_secret));
+                request
+            }
+            ClientAuth::Basic => {
+                form.push(("client_id", &self.client_id));
+                request.basic_auth(&self.client_id, Some(&self.client_secret))
+            }
+        };
+
+        // Some providers report OAuth errors with a 200 status, so look at the body first.
+        let response = request.form(&form).send().await?;
+        let body: serde_json::Value = response.json().await?;
+        if let Some(error) = body.get("error") {
+            let description = body
+                .get("error_description")
+                .and_then(|d| d.as_str())
+                .unwrap_or_default();
+            return Err(ProviderError::OAuth(
+                format!("{} {}", error.as_str().unwrap_or("error"), description)
+                    .trim()
+                    .to_string(),
+            ));
+        }
+
+        serde_json::from_value(body).map_err(|e| ProviderError::Response(e.to_string()))
+    }
+
+    /// Look up the signed-in user. For OpenID Connect providers this verifies the ID token,
+    /// including that it carries the `nonce` sent with the authorization request.
+    pub async fn user(
+        &self,
+        http: &reqwest::Client,
+        tokens: &ProviderTokens,
+        nonce: &str,
+    ) -> Result<ProviderUser, ProviderError> {
+        match &self.profile {
+            Profile::IdToken { issuer, jwks } => {
+                let id_token = tokens
+                    .id_token
+                    .as_deref()
+                    .ok_or_else(|| ProviderError::IdToken("missing id_token".to_string()))?;
+                self.verify_id_token(http, issuer, jwks, id_token, nonce)
+                    .await
+            }
+            Profile::Github => github_user(http, &tokens.access_token).await,
+            Profile::Discord => discord_user(http, &tokens.access_token).await,
+            Profile::Twitter => twitter_user(http, &tokens.access_token).await,
+        }
+    }
+
+    async fn verify_id_token(
+        &self,
+        http: &reqwest::Client,
+        issuer: &str,
+        jwks: &Jwks,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<ProviderUser, ProviderError> {
+        let invalid = |message: &str| ProviderError::IdToken(message.to_string());
+
+        let unverified: Token<Header, IdTokenClaims, _> =
+            Token::parse_unverified(id_token).map_err(|e| ProviderError::IdToken(e.to_string()))?;
+        let kid = unverified
+            .header()
+            .key_id
+            .clone()
+            .ok_or_else(|| invalid("missing kid header"))?;
+        let key = PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: jwks.key(http, &kid).await?,
+        };
+        let token: Token<Header, IdTokenClaims, _> = unverified
+            .verify_with_key(&key)
+            .map_err(|e| ProviderError::IdToken(e.to_string()))?;
+        let (_, claims) = token.into();
+
+        let expected_issuer = if issuer.contains("{tenantid}") {
+            let tenant = claims
+                .tid
+                .as_deref()
+                .ok_or_else(|| invalid("missing tid claim"))?;
+            issuer.replace("{tenantid}", tenant)
+        } else {
+            issuer.to_string()
+        };
+        if claims.iss.as_deref() != Some(expected_issuer.as_str()) {
+            return Err(invalid("issuer mismatch"));
+        }
+
+        match &claims.aud {
+            Some(aud) if aud.contains(&self.client_id) => {}
+            _ => return Err(invalid("audience mismatch")),
+        }
+        // With several audiences, the party the token was issued to must be this client.
+        if matches!(&claims.aud, Some(Audience::Many(auds)) if auds.len() > 1)
+            && claims.azp.as_deref() != Some(self.client_id.as_str())
+        {
+            return Err(invalid("authorized party mismatch"));
+        }
+
+        match claims.exp {
+            Some(exp) if now_secs() <= exp.saturating_add(ID_TOKEN_LEEWAY_SECS) => {}
+            Some(_) => return Err(invalid("token has expired")),
+            None => return Err(invalid("missing exp claim")),
+        }
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce mismatch"));
+        }
+
+        let id = claims
+            .sub
+            .filter(|sub| !sub.is_empty())
+            .ok_or_else(|| invalid("missing sub claim"))?;
+
+        // Apple sends `email_verified` as the string "true".
+        let email_verified = match &claims.email_verified {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+
+        Ok(ProviderUser {
+            id,
+            email: claims.email,
+            email_verified,
+            username: claims.preferred_username,
+            name: claims.name,
+            avatar_url: claims.picture,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    iss: Option<String>,
+    sub: Option<String>,
+    aud: Option<Audience>,
+    azp: Option<String>,
+    exp: Option<u64>,
+    nonce: Option<String>,
+    /// Microsoft tenant the token was issued by.
+    tid: Option<String>,
+    email: Option<String>,
+    email_verified: Option<serde_json::Value>,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    picture: Option<String>,
+}
+
+/// A provider's published ID token signing keys, fetched on demand and cached by key id.
+#[derive(Debug)]
+struct Jwks {
+    uri: Url,
+    cache: RwLock<JwksCache>,
+}
+
+#[derive(Debug, Default)]
+struct JwksCache {
+    keys: HashMap<String, PKey<Public>>,
+    fetched: Option<Instant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl Jwk {
+    /// The RSA public key, or `None` for other kinds of key.
+    fn rsa_key(&self) -> Option<(String, PKey<Public>)> {
+        if self.kty != "RSA" {
+            return None;
+        }
+
+        let component = |value: &Option<String>| {
+            let bytes = URL_SAFE_NO_PAD.decode(value.as_deref()?).ok()?;
+            BigNum::from_slice(&bytes).ok()
+        };
+        let rsa = Rsa::from_public_components(component(&self.n)?, component(&self.e)?).ok()?;
+
+        Some((self.kid.clone()?, PKey::from_rsa(rsa).ok()?))
+    }
+}
+
+impl Jwks {
+    fn new(uri: Url) -> Self {
+        Self {
+            uri,
+            cache: RwLock::new(JwksCache::default()),
+        }
+    }
+
+    /// The key with id `kid`, refetching the key set when the provider may have rotated keys.
+    async fn key(&self, http: &reqwest::Client, kid: &str) -> Result<PKey<Public>, ProviderError> {
+        {
+            let cache = self.cache.read().expect("JWKS cache lock poisoned");
+            if let Some(key) = cache.keys.get(kid) {
+                return Ok(key.clone());
+            }
+            if cache
+                .fetched
+                .is_some_and(|fetched| fetched.elapsed() < JWKS_REFRESH_INTERVAL)
+            {
+                return Err(ProviderError::IdToken(format!("unknown key id {}", kid)));
+            }
+        }
+
+        let set: JwkSet = http
+            .get(self.uri.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let keys: HashMap<_, _> = set.keys.iter().filter_map(Jwk::rsa_key).collect();
+        tracing::debug!(uri = %self.uri, keys = keys.len(), "Fetched provider signing keys");
+
+        let mut cache = self.cache.write().expect("JWKS cache lock poisoned");
+        cache.keys = keys;
+        cache.fetched = Some(Instant::now());
+        cache
+            .keys
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| ProviderError::IdToken(format!("unknown key id {}", kid)))
+    }
+}
+
+async fn get_json<T: DeserializeOwned>(
+    http: &reqwest::Client,
+    url: &str,
+    access_token: &str,
+) -> Result<T, ProviderError> {
+    Ok(http
+        .get(url)
+        .bearer_auth(access_token)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn github_user(
+    http: &reqwest::Client,
+    access_token: &str,
+) -> Result<ProviderUser, ProviderError> {
+    #[derive(Deserialize)]
+    struct User {
+        id: u64,
+        login: String,
+        name: Option<String>,
+        avatar_url: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Email {
+        email: String,
+        primary: bool,
+        verified: bool,
+    }
+
+    let user: User = get_json(http, "https://api.github.com/user", access_token).await?;
+    // The profile only shows the public email, so ask for the primary one.
+    let emails: Vec<Email> =
+        get_json(http, "https://api.github.com/user/emails", access_token).await?;
+    let primary = emails.into_iter().find(|email| email.primary);
+
+    Ok(ProviderUser {
+        id: user.id.to_string(),
+        email_verified: primary.as_ref().is_some_and(|email| email.verified),
+        email: primary.map(|email| email.email),
+        username: Some(user.login),
+        name: user.name,
+        avatar_url: user.avatar_url,
+    })
+}
+
+async fn discord_user(
+    http: &reqwest::Client,
+    access_token: &str,
+) -> Result<ProviderUser, ProviderError> {
+    #[derive(Deserialize)]
+    struct User {
+        id: String,
+        username: String,
+        global_name: Option<String>,
+        avatar: Option<String>,
+        email: Option<String>,
+        verified: Option<bool>,
+    }
+
+    let user: User = get_json(http, "https://discord.com/api/users/@me", access_token).await?;
+
+    Ok(ProviderUser {
+        avatar_url: user.avatar.map(|hash| {
+            format!(
+                "https://cdn.discordapp.com/avatars/{}/{}.png",
+                user.id, hash
+            )
+        }),
+        id: user.id,
+        email: user.email,
+        email_verified: user.verified.unwrap_or(false),
+        username: Some(user.username),
+        name: user.global_name,
+    })
+}
+
+async fn twitter_user(
+    http: &reqwest::Client,
+    access_token: &str,
+) -> Result<ProviderUser, ProviderError> {
+    #[derive(Deserialize)]
+    struct Response {
+        data: User,
+    }
+
+    #[derive(Deserialize)]
+    struct User {
+        id: String,
+        name: Option<String>,
+        username: String,
+        profile_image_url: Option<String>,
+    }
+
+    let response: Response = get_json(
+        http,
+        "https://api.x.com/2/users/me?user.fields=profile_image_url",
+        access_token,
+    )
+    .await?;
+    let user = response.data;
+
+    // X does not share email addresses through this API.
+    Ok(ProviderUser {
+        id: user.id,
+        email: None,
+        email_verified: false,
+        username: Some(user.username),
+        name: user.name,
+        avatar_url: user.profile_image_url,
+    })
+}
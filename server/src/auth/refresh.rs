@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Number of random bytes in a refresh token.
+const REFRESH_TOKEN_BYTES: usize = 32;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct RefreshTokenRecord {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub identity_id: Option<Uuid>,
+    pub family_id: Uuid,
+    pub scope: String,
+    pub expire_time: DateTime<Utc>,
+    pub revoke_time: Option<DateTime<Utc>>,
+}
+
+impl RefreshTokenRecord {
+    pub fn scopes(&self) -> Vec<String> {
+        self.scope.split_whitespace().map(str::to_string).collect()
+    }
+}
+
+/// Outcome of presenting a refresh token.
+#[derive(Debug)]
+pub enum Rotation {
+    /// The token was valid and has been replaced by `token`.
+    Rotated {
+        token: String,
+        record: RefreshTokenRecord,
+    },
+    /// The token had already been rotated or revoked. Its whole family is now revoked, since
+    /// the token has most likely been stolen.
+    Reused(RefreshTokenRecord),
+    /// The token is unknown or expired.
+    Invalid,
+}
+
+/// Generate a new random refresh token and the hash it is stored under.
+fn generate() -> (String, Vec<u8>) {
+    let mut bytes = [0u8; REFRESH_TOKEN_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    let hash = hash(&token);
+    (token, hash)
+}
+
+fn hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+pub struct RefreshTokenRepository {
+    pool: PgPool,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Start a new token family for a fresh sign-in, returning the raw token.
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        identity_id: Option<Uuid>,
+        scope: &str,
+        ttl: Duration,
+    ) -> Result<(String, RefreshTokenRecord), sqlx::Error> {
+        let (token, token_hash) = generate();
+
+        let record = sqlx::query_as::<_, RefreshTokenRecord>(
+            r#"
+            INSERT INTO public.refresh_tokens
+                (id, user_id, identity_id, family_id, token_hash, scope, expire_time)
+            VALUES ($1, $2, $3, $1, $4, $5, now() + make_interval(secs => $6))
+            RETURNING id, user_id, identity_id, family_id, scope, expire_time, revoke_time
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(user_id)
+        .bind(identity_id)
+        .bind(token_hash)
+        .bind(scope)
+        .bind(ttl.as_secs_f64())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((token, record))
+    }
+
+    /// Exchange a refresh token for a new one in the same family. Each token can be used once.
+    pub async fn rotate(&self, token: &str, ttl: Duration) -> Result<Rotation, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query_as::<_, RefreshTokenRecord>(
+            r#"
+            SELECT id, user_id, identity_id, family_id, scope, expire_time, revoke_time
+            FROM public.refresh_tokens
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(hash(token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(current) = current else {
+            return Ok(Rotation::Invalid);
+        };
+
+        if current.revoke_time.is_some() {
+            sqlx::query(
+                r#"
+                UPDATE public.refresh_tokens
+                SET revoke_time = now()
+                WHERE family_id = $1 AND revoke_time IS NULL
+                "#,
+            )
+            .bind(current.family_id)
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+            return Ok(Rotation::Reused(current));
+        }
+
+        if current.expire_time <= Utc::now() {
+            return Ok(Rotation::Invalid);
+        }
+
+        let (next, next_hash) = generate();
+        let record = sqlx::query_as::<_, RefreshTokenRecord>(
+            r#"
+            INSERT INTO public.refresh_tokens
+                (id, user_id, identity_id, family_id, token_hash, scope, expire_time)
+            VALUES ($1, $2, $3, $4, $5, $6, now() + make_interval(secs => $7))
+            RETURNING id, user_id, identity_id, family_id, scope, expire_time, revoke_time
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(current.user_id)
+        .bind(current.identity_id)
+        .bind(current.family_id)
+        .bind(next_hash)
+        .bind(&current.scope)
+        .bind(ttl.as_secs_f64())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE public.refresh_tokens
+            SET revoke_time = now(), replaced_by = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(current.id)
+        .bind(record.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Rotation::Rotated {
+            token: next,
+            record,
+        })
+    }
+
+    /// Revoke the family a refresh token belongs to. Returns false for unknown tokens.
+    pub async fn revoke(&self, token: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE public.refresh_tokens
+            SET revoke_time = now()
+            WHERE revoke_time IS NULL
+              AND family_id = (
+                  SELECT family_id FROM public.refresh_tokens WHERE token_hash = $1
+              )
+            "#,
+        )
+        .bind(hash(token))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revoke every refresh token of a user, signing out all of their sessions.
+    pub async fn revoke_all(&self, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE public.refresh_tokens
+            SET revoke_time = now()
+            WHERE user_id = $1 AND revoke_time IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{CreateIdentity, IdentityRepository};
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    async fn create_user(pool: &PgPool) -> Uuid {
+        let mut tx = pool.begin().await.unwrap();
+        let identity = IdentityRepository::insert_with_user(
+            &mut tx,
+            &CreateIdentity {
+                id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                provider: "google".to_string(),
+                provider_user_id: "alice".to_string(),
+                provider_email: None,
+                provider_username: Some("alice".to_string()),
+                provider_avatar_url: None,
+                access_token_encrypted: None,
+                refresh_token_encrypted: None,
+                token_expires_at: None,
+                metadata: None,
+                is_primary: true,
+                verified: true,
+            },
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        identity.user_id
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn reusing_a_rotated_token_revokes_its_family(pool: PgPool) {
+        let repo = RefreshTokenRepository::new(pool.clone());
+        let user_id = create_user(&pool).await;
+        let (first, _) = repo.create(user_id, None, "", TTL).await.unwrap();
+        let (other_session, _) = repo.create(user_id, None, "", TTL).await.unwrap();
+
+        let Rotation::Rotated { token: second, .. } = repo.rotate(&first, TTL).await.unwrap()
+        else {
+            panic!("a fresh token should rotate");
+        };
+
+        // The replay is detected, and the token it was replaced by stops working too.
+        let replay = repo.rotate(&first, TTL).await.unwrap();
+        assert!(matches!(replay, Rotation::Reused(ref record) if record.user_id == user_id));
+        assert!(matches!(
+            repo.rotate(&second, TTL).await.unwrap(),
+            Rotation::Reused(_)
+        ));
+
+        // Other sessions of the user are not affected.
+        assert!(matches!(
+            repo.rotate(&other_session, TTL).await.unwrap(),
+            Rotation::Rotated { .. }
+        ));
+        assert!(matches!(
+            repo.rotate("unknown", TTL).await.unwrap(),
+            Rotation::Invalid
+        ));
+    }
+}
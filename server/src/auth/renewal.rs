@@ -0,0 +1,511 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::cipher::{TokenCipher, TokenColumn};
+use super::provider::OAuthProvider;
+use crate::config::AppConfig;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+/// Maximum number of identities refreshed at the same time.
+const BATCH_SIZE: usize = 16;
+
+/// How long a claimed identity is held before another worker may pick it up again.
+const LEASE: Duration = Duration::from_secs(300);
+
+/// First retry delay after a failed refresh; doubled on every consecutive failure.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Upper bound on the retry delay for identities whose refresh keeps failing.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub struct TokenRefresherConfig {
+    /// How often the worker looks for tokens that are about to expire.
+    pub tick: Duration,
+    /// Tokens expiring within this window are refreshed.
+    pub window: Duration,
+    /// Consecutive failed refreshes after which the identity needs re-authentication, for
+    /// errors that might be temporary.
+    pub max_failures: u32,
+}
+
+#[derive(FromRow)]
+struct DueIdentity {
+    id: Uuid,
+    user_id: Uuid,
+    provider: String,
+    refresh_token_encrypted: String,
+    token_refresh_failures: i32,
+}
+
+/// Refreshes stored provider tokens before they expire, so they stay usable without the user
+/// signing in again. Identities whose refresh token is rejected are marked as needing
+/// re-authentication.
+///
+/// Like the feed scheduler, identities are claimed with `FOR UPDATE SKIP LOCKED` and leased by
+/// pushing `next_token_refresh_time` forward, so several server instances can run it.
+#[derive(Debug, Clone)]
+pub struct TokenRefresher {
+    pool: PgPool,
+    http: reqwest::Client,
+    providers: Arc<HashMap<&'static str, OAuthProvider>>,
+    cipher: Arc<TokenCipher>,
+    config: TokenRefresherConfig,
+}
+
+impl TokenRefresher {
+    pub fn from_config(
+        config: &AppConfig,
+        pool: PgPool,
+        cipher: Arc<TokenCipher>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("geist/", env!("CARGO_PKG_VERSION")))
+            .timeout(config.server_timeout())
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Self {
+            pool,
+            http,
+            providers: Arc::new(OAuthProvider::from_config(config)?),
+            cipher,
+            config: TokenRefresherConfig {
+                tick: config.oauth_token_refresh_tick(),
+                window: config.oauth_token_refresh_window(),
+                max_failures: config.oauth_token_refresh_max_failures,
+            },
+        })
+    }
+
+    /// Run the refresh loop forever.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.config.tick);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            match self.refresh_due().await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!(count, "Refreshed provider tokens"),
+                Err(e) => tracing::error!(error = %e, "Failed to refresh provider tokens"),
+            }
+        }
+    }
+
+    /// Refresh every identity whose tokens are about to expire, returning how many were tried.
+    pub async fn refresh_due(&self) -> Result<usize, sqlx::Error> {
+        let mut total = 0;
+
+        loop {
+            let identities = self.claim_due().await?;
+            let claimed = identities.len();
+            total += claimed;
+
+            let mut tasks = JoinSet::new();
+            for identity in identities {
+                let this = self.clone();
+                tasks.spawn(async move { this.refresh(identity).await });
+            }
+
+            while let Some(result) = tasks.join_next().await {
+                if let Err(e) = result {
+                    tracing::error!(error = %e, "Token refresh task failed");
+                }
+            }
+
+            if claimed < BATCH_SIZE {
+                return Ok(total);
+            }
+        }
+    }
+
+    async fn claim_due(&self) -> Result<Vec<DueIdentity>, sqlx::Error> {
+        // Only providers that are configured can refresh their tokens.
+        let providers: Vec<String> = self.providers.keys().map(|p| p.to_string()).collect();
+
+        sqlx::query_as::<_, DueIdentity>(
+            r#"
+            UPDATE public.user_identities
+            SET next_token_refresh_time = now() + make_interval(secs => $3)
+            WHERE id IN (
+                SELECT id FROM public.user_identities
+                WHERE refresh_token_encrypted IS NOT NULL
+                  AND NOT needs_reauth
+                  AND token_expires_at <= now() + make_interval(secs => $2)
+                  AND (next_token_refresh_time IS NULL OR next_token_refresh_time <= now())
+                  AND provider::text = ANY($4)
+                ORDER BY token_expires_at ASC
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, user_id, provider::text AS provider, refresh_token_encrypted,
+                      token_refresh_failures
+            "#,
+        )
+        .bind(BATCH_SIZE as i64)
+        .bind(self.config.window.as_secs_f64())
+        .bind(LEASE.as_secs_f64())
+        .bind(providers)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(user_uid = %identity.user_id, provider = %identity.provider)
+    )]
+    async fn refresh(&self, identity: DueIdentity) {
+        let Some(provider) = self.providers.get(identity.provider.as_str()) else {
+            return;
+        };
+
+        let result = match self.cipher.decrypt(
+            &identity.refresh_token_encrypted,
+            identity.id,
+            TokenColumn::RefreshToken,
+        ) {
+            Ok(refresh_token) => match provider.refresh(&self.http, &refresh_token).await {
+                Ok(tokens) => {
+                    metrics::counter!("oauth_token_refreshes_total", "outcome" => "refreshed")
+                        .increment(1);
+
+                    self.record_success(
+                        identity.id,
+                        self.cipher.encrypt(
+                            &tokens.access_token,
+                            identity.id,
+                            TokenColumn::AccessToken,
+                        ),
+                        tokens.refresh_token.as_deref().map(|t| {
+                            self.cipher
+                                .encrypt(t, identity.id, TokenColumn::RefreshToken)
+                        }),
+                        tokens.expire_time(),
+                    )
+                    .await
+                }
+                Err(e) => {
+                    metrics::counter!("oauth_token_refreshes_total", "outcome" => "failed")
+                        .increment(1);
+
+                    let failures = identity.token_refresh_failures + 1;
+                    let needs_reauth =
+                        e.is_rejected() || failures as u32 >= self.config.max_failures;
+                    tracing::warn!(error = %e, failures, needs_reauth, "Token refresh failed");
+
+                    self.record_failure(identity.id, &e.to_string(), needs_reauth, failures)
+                        .await
+                }
+            },
+            Err(e) => {
+                tracing::warn!(error = %e, "Stored refresh token cannot be decrypted");
+                self.record_failure(identity.id, &e.to_string(), true, 1)
+                    .await
+            }
+        };
+
+        if let Err(e) = result {
+            tracing::error!(error = %e, "Failed to record token refresh");
+        }
+    }
+
+    async fn record_success(
+        &self,
+        id: Uuid,
+        access_token: String,
+        refresh_token: Option<String>,
+        expire_time: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.user_identities
+            SET access_token_encrypted = $2,
+                refresh_token_encrypted = COALESCE($3, refresh_token_encrypted),
+                token_expires_at = $4,
+                token_refresh_failures = 0,
+                token_refresh_error = NULL,
+                next_token_refresh_time = NULL,
+                update_time = now()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(access_token)
+        .bind(refresh_token)
+        .bind(expire_time)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_failure(
+        &self,
+        id: Uuid,
+        error: &str,
+        needs_reauth: bool,
+        failures: i32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.user_identities
+            SET token_refresh_failures = token_refresh_failures + 1,
+                token_refresh_error = $2,
+                needs_reauth = $3,
+                next_token_refresh_time = now() + make_interval(secs => $4),
+                update_time = now()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(needs_reauth)
+        .bind(retry_delay(failures).as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Exponential backoff before retrying an identity that has failed `failures` times in a row.
+fn retry_delay(failures: i32) -> Duration {
+    let exponent = (failures.max(1) - 1).min(16) as u32;
+    BASE_RETRY_DELAY
+        .saturating_mul(1 << exponent)
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::http::tests::{MockProvider, Refresh};
+    use crate::meta::{CreateIdentity, IdentityRepository};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    #[derive(Debug, FromRow)]
+    struct Stored {
+        access_token_encrypted: Option<String>,
+        refresh_token_encrypted: Option<String>,
+        token_expires_at: Option<DateTime<Utc>>,
+        token_refresh_failures: i32,
+        token_refresh_error: Option<String>,
+        next_token_refresh_time: Option<DateTime<Utc>>,
+        needs_reauth: bool,
+    }
+
+    fn refresher(pool: &PgPool, mock: &MockProvider) -> TokenRefresher {
+        let key = format!("k1:{}", STANDARD.encode([1u8; 32]));
+        TokenRefresher {
+            pool: pool.clone(),
+            http: reqwest::Client::new(),
+            providers: Arc::new(HashMap::from([("google", mock.provider("google"))])),
+            cipher: Arc::new(TokenCipher::new(&key, &[]).unwrap()),
+            config: TokenRefresherConfig {
+                tick: Duration::from_secs(60),
+                window: Duration::from_secs(600),
+                max_failures: 3,
+            },
+        }
+    }
+
+    /// A Google identity whose tokens expire in a minute, with `failures` failed refreshes.
+    async fn create_identity(
+        refresher: &TokenRefresher,
+        refresh_token: &str,
+        failures: i32,
+    ) -> Uuid {
+        let id = Uuid::now_v7();
+        let cipher = &refresher.cipher;
+        let mut tx = refresher.pool.begin().await.unwrap();
+        IdentityRepository::insert_with_user(
+            &mut tx,
+            &CreateIdentity {
+                id,
+                user_id: Uuid::now_v7(),
+                provider: "google".to_string(),
+                provider_user_id: refresh_token.to_string(),
+                provider_email: None,
+                provider_username: None,
+                provider_avatar_url: None,
+                access_token_encrypted: Some(cipher.encrypt(
+                    "access-token",
+                    id,
+                    TokenColumn::AccessToken,
+                )),
+                refresh_token_encrypted: Some(cipher.encrypt(
+                    refresh_token,
+                    id,
+                    TokenColumn::RefreshToken,
+                )),
+                token_expires_at: Some(Utc::now() + chrono::Duration::seconds(60)),
+                metadata: None,
+                is_primary: true,
+                verified: true,
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query("UPDATE public.user_identities SET token_refresh_failures = $2 WHERE id = $1")
+            .bind(id)
+            .bind(failures)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        id
+    }
+
+    async fn stored(pool: &PgPool, id: Uuid) -> Stored {
+        sqlx::query_as::<_, Stored>(
+            r#"
+            SELECT access_token_encrypted, refresh_token_encrypted, token_expires_at,
+                   token_refresh_failures, token_refresh_error, next_token_refresh_time,
+                   needs_reauth
+            FROM public.user_identities
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    /// Seconds from now until `time`.
+    fn secs_until(time: Option<DateTime<Utc>>) -> i64 {
+        (time.unwrap() - Utc::now()).num_seconds()
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_maximum() {
+        assert_eq!(retry_delay(0), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(1), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(2), BASE_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), BASE_RETRY_DELAY * 8);
+        assert_eq!(retry_delay(7), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(i32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn refresh_stores_new_tokens_and_resets_failures(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let refresher = refresher(&pool, &mock);
+        let id = create_identity(&refresher, "refresh-token", 2).await;
+        mock.accept_refresh_token(
+            "refresh-token",
+            Refresh::Tokens {
+                expires_in: Some(1800),
+            },
+        );
+
+        assert_eq!(refresher.refresh_due().await.unwrap(), 1);
+
+        let stored = stored(&pool, id).await;
+        let decrypt = |value: Option<String>, column| {
+            refresher
+                .cipher
+                .decrypt(&value.unwrap(), id, column)
+                .unwrap()
+        };
+        assert_eq!(
+            decrypt(stored.access_token_encrypted, TokenColumn::AccessToken),
+            "refreshed-access-token"
+        );
+        assert_eq!(
+            decrypt(stored.refresh_token_encrypted, TokenColumn::RefreshToken),
+            "refresh-token-rotated"
+        );
+        assert!((1790..=1800).contains(&secs_until(stored.token_expires_at)));
+        assert_eq!(stored.token_refresh_failures, 0);
+        assert_eq!(stored.token_refresh_error, None);
+        assert_eq!(stored.next_token_refresh_time, None);
+        assert!(!stored.needs_reauth);
+
+        // The new tokens are not due yet.
+        assert_eq!(refresher.refresh_due().await.unwrap(), 0);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn tokens_without_a_lifetime_stay_scheduled(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let refresher = refresher(&pool, &mock);
+        let id = create_identity(&refresher, "refresh-token", 0).await;
+        mock.accept_refresh_token("refresh-token", Refresh::Tokens { expires_in: None });
+
+        assert_eq!(refresher.refresh_due().await.unwrap(), 1);
+
+        let stored = stored(&pool, id).await;
+        // Assumed to last an hour.
+        assert!((3590..=3600).contains(&secs_until(stored.token_expires_at)));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn rejected_refresh_tokens_need_reauthentication(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let refresher = refresher(&pool, &mock);
+        let id = create_identity(&refresher, "revoked-token", 0).await;
+
+        assert_eq!(refresher.refresh_due().await.unwrap(), 1);
+
+        let stored = stored(&pool, id).await;
+        assert!(stored.needs_reauth);
+        assert_eq!(stored.token_refresh_failures, 1);
+        assert!(stored
+            .token_refresh_error
+            .unwrap()
+            .contains("invalid_grant"));
+        assert_eq!(refresher.refresh_due().await.unwrap(), 0);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn temporary_failures_back_off_until_the_limit(pool: PgPool) {
+        let mock = MockProvider::start().await;
+        let refresher = refresher(&pool, &mock);
+        let id = create_identity(&refresher, "refresh-token", 0).await;
+        mock.accept_refresh_token("refresh-token", Refresh::Unavailable);
+
+        assert_eq!(refresher.refresh_due().await.unwrap(), 1);
+
+        let first = stored(&pool, id).await;
+        assert!(!first.needs_reauth);
+        assert_eq!(first.token_refresh_failures, 1);
+        let delay = retry_delay(1).as_secs() as i64;
+        assert!((delay - 10..=delay).contains(&secs_until(first.next_token_refresh_time)));
+
+        // Not retried before the delay is over.
+        assert_eq!(refresher.refresh_due().await.unwrap(), 0);
+
+        // The last allowed failure marks the identity as needing re-authentication.
+        sqlx::query(
+            r#"
+            UPDATE public.user_identities
+            SET next_token_refresh_time = now(), token_refresh_failures = 2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        mock.accept_refresh_token("refresh-token", Refresh::Unavailable);
+
+        assert_eq!(refresher.refresh_due().await.unwrap(), 1);
+
+        let last = stored(&pool, id).await;
+        assert!(last.needs_reauth);
+        assert_eq!(last.token_refresh_failures, 3);
+        let delay = retry_delay(3).as_secs() as i64;
+        assert!((delay - 10..=delay).contains(&secs_until(last.next_token_refresh_time)));
+    }
+}
@@ -0,0 +1,567 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::password::{
+    check_policy, hash_password, normalize_email, verify_password, PasswordRepository,
+    PasswordReset,
+};
+use super::refresh::{RefreshTokenRepository, Rotation};
+use super::token::TokenSigner;
+use super::ADMIN_SCOPE;
+use crate::error::GeistError;
+use crate::meta::{to_timestamp, AuditAction, AuditContext, IdentityRepository, UserRepository};
+use crate::ServerResult;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::{
+    auth_service_server::AuthService, PasswordLoginRequest, RefreshTokenRequest,
+    RequestPasswordResetRequest, RequestPasswordResetResponse, ResetPasswordRequest,
+    ResetPasswordResponse, RevokeTokenRequest, RevokeTokenResponse, SignUpRequest, SignUpResponse,
+    TokenResponse, VerifyEmailRequest,
+};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone)]
+pub struct AuthServer {
+    pool: Option<PgPool>,
+    signer: Option<Arc<TokenSigner>>,
+    refresh_ttl: Duration,
+    password_reset: Option<Arc<PasswordReset>>,
+}
+
+impl AuthServer {
+    pub fn new(
+        pool: PgPool,
+        signer: TokenSigner,
+        refresh_ttl: Duration,
+        password_reset: Arc<PasswordReset>,
+    ) -> Self {
+        Self {
+            pool: Some(pool),
+            signer: Some(Arc::new(signer)),
+            refresh_ttl,
+            password_reset: Some(password_reset),
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    fn signer(&self) -> Result<&TokenSigner, Status> {
+        self.signer
+            .as_deref()
+            .ok_or_else(|| Status::internal("Token signer not initialized"))
+    }
+
+    fn password_reset(&self) -> Result<&Arc<PasswordReset>, Status> {
+        self.password_reset
+            .as_ref()
+            .ok_or_else(|| Status::internal("Password reset not initialized"))
+    }
+
+    /// Issue tokens for the identity `identity_id` of `user_id`, which the caller has just
+    /// authenticated: by password in `VerifyEmail` and `PasswordLogin`, and through the provider in
+    /// the OAuth callback. Starts a new refresh token family for the session.
+    pub(super) async fn login(
+        &self,
+        user_id: Uuid,
+        identity_id: Uuid,
+    ) -> ServerResult<TokenResponse> {
+        let pool = self.pool()?.clone();
+
+        IdentityRepository::new(pool.clone())
+            .update_last_used(identity_id)
+            .await
+            .map_err(GeistError::from)?;
+
+        let (refresh_token, record) = RefreshTokenRepository::new(pool)
+            .create(user_id, Some(identity_id), "", self.refresh_ttl)
+            .await
+            .map_err(GeistError::from)?;
+
+        tracing::info!(user_uid = %user_id, identity_uid = %identity_id, "Issued tokens for sign-in");
+
+        let scopes = self.scopes(user_id, record.scopes()).await?;
+        self.respond(user_id, &scopes, refresh_token, record.expire_time)
+    }
+
+    /// Scopes for a new access token: those granted to the refresh token plus the ones that
+    /// come with the user's role. The role is looked up on every issue, so revoking admin
+    /// rights takes effect at the next refresh.
+    async fn scopes(&self, user_id: Uuid, granted: Vec<String>) -> Result<Vec<String>, Status> {
+        let is_admin = UserRepository::new(self.pool()?.clone())
+            .is_admin(user_id)
+            .await
+            .map_err(GeistError::from)?;
+
+        let mut scopes: Vec<String> = granted.into_iter().filter(|s| s != ADMIN_SCOPE).collect();
+        if is_admin {
+            scopes.push(ADMIN_SCOPE.to_string());
+        }
+
+        Ok(scopes)
+    }
+
+    fn respond(
+        &self,
+        user_id: Uuid,
+        scopes: &[String],
+        refresh_token: String,
+        refresh_expire_time: DateTime<Utc>,
+    ) -> ServerResult<TokenResponse> {
+        let (access_token, expire_time) = self
+            .signer()?
+            .sign(user_id, scopes)
+            .map_err(|e| Status::internal(format!("Failed to sign access token: {}", e)))?;
+
+        Ok(tonic::Response::new(TokenResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expire_time: Some(to_timestamp(expire_time)),
+            refresh_token,
+            refresh_expire_time: Some(to_timestamp(refresh_expire_time)),
+            user_uid: user_id.to_string(),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl AuthService for AuthServer {
+    #[tracing::instrument(skip_all)]
+    async fn refresh_token(
+        &self,
+        request: Request<RefreshTokenRequest>,
+    ) -> ServerResult<TokenResponse> {
+        let req = request.into_inner();
+        if req.refresh_token.is_empty() {
+            return Err(Status::invalid_argument("refresh_token is required"));
+        }
+
+        let repo = RefreshTokenRepository::new(self.pool()?.clone());
+        let rotation = repo
+            .rotate(&req.refresh_token, self.refresh_ttl)
+            .await
+            .map_err(GeistError::from)?;
+
+        match rotation {
+            Rotation::Rotated { token, record } => {
+                let scopes = self.scopes(record.user_id, record.scopes()).await?;
+                self.respond(record.user_id, &scopes, token, record.expire_time)
+            }
+            Rotation::Reused(record) => {
+                tracing::warn!(
+                    user_uid = %record.user_id,
+                    family = %record.family_id,
+                    "Refresh token reused, revoked its family"
+                );
+                Err(Status::unauthenticated("Invalid refresh token"))
+            }
+            Rotation::Invalid => Err(Status::unauthenticated("Invalid refresh token")),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn revoke_token(
+        &self,
+        request: Request<RevokeTokenRequest>,
+    ) -> ServerResult<RevokeTokenResponse> {
+        let req = request.into_inner();
+        if req.refresh_token.is_empty() {
+            return Err(Status::invalid_argument("refresh_token is required"));
+        }
+
+        // Unknown tokens are not an error, so the response reveals nothing about them.
+        RefreshTokenRepository::new(self.pool()?.clone())
+            .revoke(&req.refresh_token)
+            .await
+            .map_err(GeistError::from)?;
+
+        Ok(tonic::Response::new(RevokeTokenResponse {}))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn sign_up(&self, request: Request<SignUpRequest>) -> ServerResult<SignUpResponse> {
+        let req = request.into_inner();
+        let email = normalize_email(&req.email)?;
+        check_policy(&req.password, &email)?;
+        let username = Some(req.username).filter(|u| !u.trim().is_empty());
+        let reset = self.password_reset()?.clone();
+        let repo = PasswordRepository::new(self.pool()?.clone());
+
+        let password_hash = hash_password(req.password).await?;
+        let registered = repo.find_by_email(&email).await.map_err(GeistError::from)?;
+
+        // The response is the same whether or not the email is known.
+        if registered.is_none() {
+            let token = repo
+                .create_verification(
+                    &email,
+                    None,
+                    username.as_deref(),
+                    &password_hash,
+                    reset.ttl(),
+                )
+                .await
+                .map_err(GeistError::from)?;
+
+            tracing::info!("Issued email verification token for sign-up");
+            tokio::spawn(async move { reset.send_verification(&email, &token).await });
+        }
+
+        Ok(tonic::Response::new(SignUpResponse {}))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn verify_email(
+        &self,
+        request: Request<VerifyEmailRequest>,
+    ) -> ServerResult<TokenResponse> {
+        let audit = AuditContext::from_request(&request);
+        let req = request.into_inner();
+        if req.verification_token.is_empty() {
+            return Err(Status::invalid_argument("verification_token is required"));
+        }
+
+        let pool = self.pool()?.clone();
+        let repo = PasswordRepository::new(pool.clone());
+        let invalid = || Status::invalid_argument("Invalid or expired verification token");
+
+        // The password must match the one chosen at sign-up, so the owner of the email cannot
+        // be tricked into verifying an account whose password someone else knows.
+        let pending = repo
+            .find_verification(&req.verification_token)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(invalid)?;
+        if !verify_password(req.password, Some(pending.password_hash)).await? {
+            return Err(invalid());
+        }
+
+        let created = repo
+            .verify(&req.verification_token)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                    GeistError::AlreadyExists(
+                        "The email is already registered, or the user already has a password"
+                            .to_string(),
+                    )
+                }
+                _ => GeistError::from(e),
+            })?
+            .ok_or_else(invalid)?;
+
+        tracing::info!(user_uid = %created.user_id, "Verified email identity");
+        audit
+            .record(
+                &pool,
+                AuditAction::PasswordRegistered,
+                created.user_id,
+                Some(created.id),
+            )
+            .await;
+
+        self.login(created.user_id, created.id).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn password_login(
+        &self,
+        request: Request<PasswordLoginRequest>,
+    ) -> ServerResult<TokenResponse> {
+        let req = request.into_inner();
+        let email = normalize_email(&req.email)?;
+
+        let record = PasswordRepository::new(self.pool()?.clone())
+            .find_by_email(&email)
+            .await
+            .map_err(GeistError::from)?;
+
+        // Unknown emails are checked against a dummy hash so they are rejected just as slowly.
+        // Unverified identities may have been created by someone who does not own the email.
+        let identity = record
+            .as_ref()
+            .filter(|r| r.verified)
+            .map(|r| (r.user_id, r.identity_id));
+        let hash = record.map(|r| r.password_hash);
+        if !verify_password(req.password, hash).await? {
+            return Err(Status::unauthenticated("Invalid email or password"));
+        }
+        let (user_id, identity_id) =
+            identity.ok_or_else(|| Status::unauthenticated("Invalid email or password"))?;
+
+        self.login(user_id, identity_id).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn request_password_reset(
+        &self,
+        request: Request<RequestPasswordResetRequest>,
+    ) -> ServerResult<RequestPasswordResetResponse> {
+        let req = request.into_inner();
+        let email = normalize_email(&req.email)?;
+        let reset = self.password_reset()?.clone();
+        let repo = PasswordRepository::new(self.pool()?.clone());
+
+        let record = repo.find_by_email(&email).await.map_err(GeistError::from)?;
+
+        // The response is the same whether or not the email is known.
+        if let Some(record) = record {
+            let token = repo
+                .create_reset_token(record.identity_id, reset.ttl())
+                .await
+                .map_err(GeistError::from)?;
+
+            tracing::info!(user_uid = %record.user_id, "Issued password reset token");
+            tokio::spawn(async move { reset.send(&record.email, &token).await });
+        }
+
+        Ok(tonic::Response::new(RequestPasswordResetResponse {}))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn reset_password(
+        &self,
+        request: Request<ResetPasswordRequest>,
+    ) -> ServerResult<ResetPasswordResponse> {
+        let audit = AuditContext::from_request(&request);
+        let req = request.into_inner();
+        if req.reset_token.is_empty() {
+            return Err(Status::invalid_argument("reset_token is required"));
+        }
+
+        let pool = self.pool()?.clone();
+        let repo = PasswordRepository::new(pool.clone());
+        let invalid = || Status::invalid_argument("Invalid or expired reset token");
+
+        let record = repo
+            .find_by_reset_token(&req.reset_token)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(invalid)?;
+        check_policy(&req.new_password, &record.email)?;
+
+        let password_hash = hash_password(req.new_password).await?;
+        let user_id = repo
+            .reset(&req.reset_token, &password_hash)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(invalid)?;
+
+        let revoked = RefreshTokenRepository::new(pool.clone())
+            .revoke_all(user_id)
+            .await
+            .map_err(GeistError::from)?;
+        tracing::info!(user_uid = %user_id, revoked, "Reset password");
+
+        audit
+            .record(
+                &pool,
+                AuditAction::PasswordReset,
+                user_id,
+                Some(record.identity_id),
+            )
+            .await;
+
+        Ok(tonic::Response::new(ResetPasswordResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::password::tests::{expire_tokens, password_reset};
+    use crate::mail::tests::{config, SmtpCapture};
+    use crate::meta::CreateIdentity;
+
+    const PASSWORD: &str = "a long and unusual password";
+
+    async fn server(pool: &PgPool) -> (AuthServer, SmtpCapture) {
+        let capture = SmtpCapture::start().await;
+        let config = config(&capture);
+        let server = AuthServer::new(
+            pool.clone(),
+            TokenSigner::from_config(&config).unwrap(),
+            config.refresh_token_ttl(),
+            password_reset(&capture),
+        );
+        (server, capture)
+    }
+
+    async fn sign_up(server: &AuthServer, email: &str) {
+        server
+            .sign_up(Request::new(SignUpRequest {
+                email: email.to_string(),
+                password: PASSWORD.to_string(),
+                username: String::new(),
+            }))
+            .await
+            .unwrap();
+    }
+
+    async fn verify(
+        server: &AuthServer,
+        token: &str,
+        password: &str,
+    ) -> ServerResult<TokenResponse> {
+        server
+            .verify_email(Request::new(VerifyEmailRequest {
+                verification_token: token.to_string(),
+                password: password.to_string(),
+            }))
+            .await
+    }
+
+    async fn login(server: &AuthServer, email: &str, password: &str) -> Result<String, Status> {
+        server
+            .password_login(Request::new(PasswordLoginRequest {
+                email: email.to_string(),
+                password: password.to_string(),
+            }))
+            .await
+            .map(|response| response.into_inner().user_uid)
+    }
+
+    /// A user with an unverified email identity, as sign-up created them before emails were
+    /// verified, whose password was chosen by someone else.
+    async fn create_unverified(pool: &PgPool, email: &str) -> Uuid {
+        let mut tx = pool.begin().await.unwrap();
+        let identity = IdentityRepository::insert_with_user(
+            &mut tx,
+            &CreateIdentity {
+                id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                provider: "email".to_string(),
+                provider_user_id: email.to_string(),
+                provider_email: Some(email.to_string()),
+                provider_username: None,
+                provider_avatar_url: None,
+                access_token_encrypted: None,
+                refresh_token_encrypted: None,
+                token_expires_at: None,
+                metadata: None,
+                is_primary: true,
+                verified: false,
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO public.user_passwords (identity_id, password_hash) VALUES ($1, $2)",
+        )
+        .bind(identity.id)
+        .bind(hash_password(PASSWORD.to_string()).await.unwrap())
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        identity.user_id
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn sign_up_creates_the_user_once_the_email_is_verified(pool: PgPool) {
+        let (server, mut mail) = server(&pool).await;
+
+        sign_up(&server, "Alice@Example.com").await;
+        let email = mail.next().await;
+        assert_eq!(email.to, "alice@example.com");
+        assert_eq!(email.subject, "Verify your Geist email");
+        let token = email.link_token();
+
+        let unverified = login(&server, "alice@example.com", PASSWORD)
+            .await
+            .unwrap_err();
+        assert_eq!(unverified.code(), tonic::Code::Unauthenticated);
+
+        // Someone else's password does not complete the sign-up, so the owner of the email
+        // cannot be made to verify an account started by someone else.
+        let wrong = verify(&server, &token, "another long password")
+            .await
+            .unwrap_err();
+        assert_eq!(wrong.code(), tonic::Code::InvalidArgument);
+
+        let user_uid = verify(&server, &token, PASSWORD)
+            .await
+            .unwrap()
+            .into_inner()
+            .user_uid;
+        assert_eq!(
+            login(&server, "alice@example.com", PASSWORD).await.unwrap(),
+            user_uid
+        );
+
+        let replayed = verify(&server, &token, PASSWORD).await.unwrap_err();
+        assert_eq!(replayed.code(), tonic::Code::InvalidArgument);
+
+        // Signing up a registered email succeeds without sending anything.
+        sign_up(&server, "alice@example.com").await;
+        mail.assert_none().await;
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn password_login_rejects_wrong_passwords_and_unverified_emails(pool: PgPool) {
+        let (server, mut mail) = server(&pool).await;
+        sign_up(&server, "alice@example.com").await;
+        verify(&server, &mail.next().await.link_token(), PASSWORD)
+            .await
+            .unwrap();
+        create_unverified(&pool, "bob@example.com").await;
+
+        for (email, password) in [
+            ("alice@example.com", "another long password"),
+            ("carol@example.com", PASSWORD),
+            ("bob@example.com", PASSWORD),
+        ] {
+            let err = login(&server, email, password).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unauthenticated, "{}", email);
+        }
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn reset_replaces_the_password_and_verifies_the_email(pool: PgPool) {
+        let (server, mut mail) = server(&pool).await;
+        let user_id = create_unverified(&pool, "bob@example.com").await;
+        let request_reset = || {
+            server.request_password_reset(Request::new(RequestPasswordResetRequest {
+                email: "bob@example.com".to_string(),
+            }))
+        };
+        let reset = |token: String| {
+            server.reset_password(Request::new(ResetPasswordRequest {
+                reset_token: token,
+                new_password: "the password of the owner".to_string(),
+            }))
+        };
+
+        request_reset().await.unwrap();
+        let email = mail.next().await;
+        assert_eq!(email.subject, "Reset your Geist password");
+        let token = email.link_token();
+        reset(token.clone()).await.unwrap();
+
+        // The password set before the owner proved control of the email no longer works.
+        let old = login(&server, "bob@example.com", PASSWORD)
+            .await
+            .unwrap_err();
+        assert_eq!(old.code(), tonic::Code::Unauthenticated);
+        let user_uid = login(&server, "bob@example.com", "the password of the owner")
+            .await
+            .unwrap();
+        assert_eq!(user_uid, user_id.to_string());
+
+        let replayed = reset(token).await.unwrap_err();
+        assert_eq!(replayed.code(), tonic::Code::InvalidArgument);
+
+        request_reset().await.unwrap();
+        let token = mail.next().await.link_token();
+        expire_tokens(&pool, "password_reset_tokens").await;
+        let expired = reset(token).await.unwrap_err();
+        assert_eq!(expired.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,520 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{AppConfig, JwtAlgorithm};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use jwt::{PKeyWithDigest, SignWithKey, VerifyWithKey};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private, Public};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// The `aud` claim, which RFC 7519 allows to be a single string or an array.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    pub(crate) fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == audience,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+/// Claims carried by Geist access tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: Option<String>,
+    /// Uid of the user the token was issued to.
+    pub sub: Option<String>,
+    pub aud: Option<Audience>,
+    pub exp: Option<u64>,
+    pub nbf: Option<u64>,
+    pub iat: Option<u64>,
+    pub jti: Option<String>,
+    /// Space-delimited list of granted scopes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+}
+
+/// The authenticated caller of a request, inserted into the request extensions by
+/// `TokenInterceptor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub user_uid: Uuid,
+    pub scopes: Vec<String>,
+}
+
+impl Principal {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    /// The key material in the configuration could not be loaded.
+    Key(String),
+    Malformed(String),
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    InvalidIssuer,
+    InvalidAudience,
+    InvalidSubject,
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Key(message) => write!(f, "Invalid signing key: {}", message),
+            TokenError::Malformed(message) => write!(f, "Malformed token: {}", message),
+            TokenError::InvalidSignature => write!(f, "Invalid token signature"),
+            TokenError::Expired => write!(f, "Token has expired"),
+            TokenError::NotYetValid => write!(f, "Token is not valid yet"),
+            TokenError::InvalidIssuer => write!(f, "Invalid token issuer"),
+            TokenError::InvalidAudience => write!(f, "Invalid token audience"),
+            TokenError::InvalidSubject => write!(f, "Invalid token subject"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Read a PEM encoded RSA key from `path`, naming the setting it came from in errors.
+fn read_rsa_key<T>(
+    path: Option<&Path>,
+    setting: &str,
+    parse: impl FnOnce(&[u8]) -> Result<PKey<T>, openssl::error::ErrorStack>,
+) -> Result<PKey<T>, TokenError> {
+    let path = path.ok_or_else(|| TokenError::Key(format!("{} is not set", setting)))?;
+    let pem =
+        std::fs::read(path).map_err(|e| TokenError::Key(format!("{}: {}", path.display(), e)))?;
+    let key = parse(&pem).map_err(|e| TokenError::Key(format!("{}: {}", path.display(), e)))?;
+    if key.id() != openssl::pkey::Id::RSA {
+        return Err(TokenError::Key(format!(
+            "{}: not an RSA key",
+            path.display()
+        )));
+    }
+
+    Ok(key)
+}
+
+fn hs256_key(config: &AppConfig) -> Result<Hmac<Sha256>, TokenError> {
+    let secret = config
+        .jwt_secret
+        .as_deref()
+        .ok_or_else(|| TokenError::Key("JWT_SECRET is not set".to_string()))?;
+    Hmac::new_from_slice(secret.as_bytes()).map_err(|e| TokenError::Key(e.to_string()))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl From<jwt::Error> for TokenError {
+    fn from(e: jwt::Error) -> Self {
+        match e {
+            // HMAC verification reports a wrong signature as a MAC error.
+            jwt::Error::InvalidSignature | jwt::Error::RustCryptoMac(_) => {
+                TokenError::InvalidSignature
+            }
+            e => TokenError::Malformed(e.to_string()),
+        }
+    }
+}
+
+enum VerifyingKey {
+    Hs256(Hmac<Sha256>),
+    Rs256(PKeyWithDigest<Public>),
+}
+
+/// Verifies signed access tokens against the configured key, issuer and audience.
+pub struct TokenVerifier {
+    key: VerifyingKey,
+    issuer: String,
+    audience: String,
+    leeway: u64,
+}
+
+impl std::fmt::Debug for TokenVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let algorithm = match self.key {
+            VerifyingKey::Hs256(_) => "HS256",
+            VerifyingKey::Rs256(_) => "RS256",
+        };
+
+        f.debug_struct("TokenVerifier")
+            .field("algorithm", &algorithm)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("leeway", &self.leeway)
+            .finish()
+    }
+}
+
+impl TokenVerifier {
+    pub fn from_config(config: &AppConfig) -> Result<Self, TokenError> {
+        let key = match config.jwt_algorithm {
+            JwtAlgorithm::Hs256 => VerifyingKey::Hs256(hs256_key(config)?),
+            JwtAlgorithm::Rs256 => VerifyingKey::Rs256(PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: read_rsa_key(
+                    config.jwt_public_key_file.as_deref(),
+                    "JWT_PUBLIC_KEY_FILE",
+                    PKey::public_key_from_pem,
+                )?,
+            }),
+        };
+
+        Ok(Self {
+            key,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            leeway: config.jwt_leeway_secs,
+        })
+    }
+
+    /// Verify a token's signature and registered claims, returning the caller it identifies.
+    /// The token's `alg` header must match the configured algorithm.
+    pub fn verify(&self, token: &str) -> Result<Principal, TokenError> {
+        let claims: Claims = match &self.key {
+            VerifyingKey::Hs256(key) => token.verify_with_key(key)?,
+            VerifyingKey::Rs256(key) => token.verify_with_key(key)?,
+        };
+
+        let now = now_secs();
+
+        match claims.exp {
+            None => return Err(TokenError::Malformed("missing exp claim".to_string())),
+            Some(exp) if now > exp.saturating_add(self.leeway) => return Err(TokenError::Expired),
+            Some(_) => {}
+        }
+
+        if claims
+            .nbf
+            .is_some_and(|nbf| now.saturating_add(self.leeway) < nbf)
+        {
+            return Err(TokenError::NotYetValid);
+        }
+
+        if claims.iss.as_deref() != Some(self.issuer.as_str()) {
+            return Err(TokenError::InvalidIssuer);
+        }
+
+        if !claims
+            .aud
+            .as_ref()
+            .is_some_and(|aud| aud.contains(&self.audience))
+        {
+            return Err(TokenError::InvalidAudience);
+        }
+
+        let user_uid = claims
+            .sub
+            .as_deref()
+            .and_then(|sub| Uuid::parse_str(sub).ok())
+            .ok_or(TokenError::InvalidSubject)?;
+
+        Ok(Principal {
+            user_uid,
+            scopes: claims
+                .scope
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+enum SigningKey {
+    Hs256(Hmac<Sha256>),
+    Rs256(PKeyWithDigest<Private>),
+}
+
+/// Signs access tokens that `TokenVerifier` accepts.
+pub struct TokenSigner {
+    key: SigningKey,
+    issuer: String,
+    audience: String,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for TokenSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let algorithm = match self.key {
+            SigningKey::Hs256(_) => "HS256",
+            SigningKey::Rs256(_) => "RS256",
+        };
+
+        f.debug_struct("TokenSigner")
+            .field("algorithm", &algorithm)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl TokenSigner {
+    pub fn from_config(config: &AppConfig) -> Result<Self, TokenError> {
+        let key = match config.jwt_algorithm {
+            JwtAlgorithm::Hs256 => SigningKey::Hs256(hs256_key(config)?),
+            JwtAlgorithm::Rs256 => SigningKey::Rs256(PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: read_rsa_key(
+                    config.jwt_private_key_file.as_deref(),
+                    "JWT_PRIVATE_KEY_FILE",
+                    PKey::private_key_from_pem,
+                )?,
+            }),
+        };
+
+        Ok(Self {
+            key,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            ttl: config.access_token_ttl(),
+        })
+    }
+
+    /// Sign an access token for `user_uid`, returning it with its expiry time.
+    pub fn sign(
+        &self,
+        user_uid: Uuid,
+        scopes: &[String],
+    ) -> Result<(String, DateTime<Utc>), TokenError> {
+        let now = now_secs();
+        let exp = now + self.ttl.as_secs();
+
+        let claims = Claims {
+            iss: Some(self.issuer.clone()),
+            sub: Some(user_uid.to_string()),
+            aud: Some(Audience::One(self.audience.clone())),
+            exp: Some(exp),
+            nbf: Some(now),
+            iat: Some(now),
+            jti: Some(Uuid::now_v7().to_string()),
+            scope: scopes.join(" "),
+        };
+
+        let token = match &self.key {
+            SigningKey::Hs256(key) => claims.sign_with_key(key)?,
+            SigningKey::Rs256(key) => claims.sign_with_key(key)?,
+        };
+        let expire_time = DateTime::from_timestamp(exp as i64, 0).unwrap_or_else(Utc::now);
+
+        Ok((token, expire_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenInterceptor;
+    use clap::Parser;
+    use openssl::rsa::Rsa;
+    use tonic::service::Interceptor;
+
+    const SECRET: &str = "a-test-secret-that-is-long-enough-for-hs256";
+
+    fn hs256_config() -> AppConfig {
+        AppConfig::try_parse_from(["geist-server", "--jwt-secret", SECRET]).unwrap()
+    }
+
+    /// A verifier for RS256 tokens signed with `rsa`, configured like `hs256_config`.
+    fn rs256_verifier(rsa: &Rsa<Private>) -> TokenVerifier {
+        let pem = rsa.public_key_to_pem().unwrap();
+        TokenVerifier {
+            key: VerifyingKey::Rs256(PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: PKey::public_key_from_pem(&pem).unwrap(),
+            }),
+            ..TokenVerifier::from_config(&hs256_config()).unwrap()
+        }
+    }
+
+    fn rs256_signer(rsa: &Rsa<Private>) -> TokenSigner {
+        TokenSigner {
+            key: SigningKey::Rs256(PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: PKey::from_rsa(rsa.clone()).unwrap(),
+            }),
+            ..TokenSigner::from_config(&hs256_config()).unwrap()
+        }
+    }
+
+    fn claims() -> Claims {
+        let now = now_secs();
+        Claims {
+            iss: Some("geist".to_string()),
+            sub: Some(Uuid::now_v7().to_string()),
+            aud: Some(Audience::One("geist".to_string())),
+            exp: Some(now + 300),
+            nbf: Some(now),
+            iat: Some(now),
+            jti: None,
+            scope: "feeds".to_string(),
+        }
+    }
+
+    fn hs256(claims: &Claims, secret: &[u8]) -> String {
+        let key: Hmac<Sha256> = Hmac::new_from_slice(secret).unwrap();
+        claims.sign_with_key(&key).unwrap()
+    }
+
+    /// The status a request with `token` gets from the interceptor in front of every service.
+    fn intercept(verifier: TokenVerifier, token: &str) -> Result<Principal, tonic::Status> {
+        let mut request = tonic::Request::new(());
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+
+        TokenInterceptor::new(verifier)
+            .call(request)
+            .map(|request| request.extensions().get::<Principal>().unwrap().clone())
+    }
+
+    fn assert_rejected(verifier: TokenVerifier, token: &str, expected: &str) {
+        let status = intercept(verifier, token).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", expected);
+        assert!(
+            status.message().contains(expected),
+            "expected {:?}, got {:?}",
+            expected,
+            status.message()
+        );
+    }
+
+    #[test]
+    fn accepts_tokens_it_signed() {
+        let config = hs256_config();
+        let user_uid = Uuid::now_v7();
+        let (token, _) = TokenSigner::from_config(&config)
+            .unwrap()
+            .sign(user_uid, &["feeds".to_string()])
+            .unwrap();
+
+        let principal = intercept(TokenVerifier::from_config(&config).unwrap(), &token).unwrap();
+        assert_eq!(principal.user_uid, user_uid);
+        assert_eq!(principal.scopes, ["feeds"]);
+    }
+
+    #[test]
+    fn rejects_tokens_outside_their_lifetime() {
+        let config = hs256_config();
+        let verifier = || TokenVerifier::from_config(&config).unwrap();
+        let now = now_secs();
+
+        // Within the leeway, a token is still accepted.
+        let recent = Claims {
+            exp: Some(now - 10),
+            ..claims()
+        };
+        intercept(verifier(), &hs256(&recent, SECRET.as_bytes())).unwrap();
+
+        let expired = Claims {
+            exp: Some(now - 120),
+            nbf: Some(now - 600),
+            ..claims()
+        };
+        assert_rejected(verifier(), &hs256(&expired, SECRET.as_bytes()), "expired");
+
+        let future = Claims {
+            nbf: Some(now + 120),
+            ..claims()
+        };
+        assert_rejected(
+            verifier(),
+            &hs256(&future, SECRET.as_bytes()),
+            "not valid yet",
+        );
+
+        let no_exp = Claims {
+            exp: None,
+            ..claims()
+        };
+        assert_rejected(verifier(), &hs256(&no_exp, SECRET.as_bytes()), "exp");
+    }
+
+    #[test]
+    fn rejects_tokens_for_other_issuers_or_audiences() {
+        let config = hs256_config();
+        let verifier = || TokenVerifier::from_config(&config).unwrap();
+
+        for iss in [None, Some("other".to_string())] {
+            let claims = Claims { iss, ..claims() };
+            assert_rejected(verifier(), &hs256(&claims, SECRET.as_bytes()), "issuer");
+        }
+
+        for aud in [
+            None,
+            Some(Audience::One("other".to_string())),
+            Some(Audience::Many(vec!["other".to_string()])),
+        ] {
+            let claims = Claims { aud, ..claims() };
+            assert_rejected(verifier(), &hs256(&claims, SECRET.as_bytes()), "audience");
+        }
+
+        let many = Claims {
+            aud: Some(Audience::Many(vec![
+                "other".to_string(),
+                "geist".to_string(),
+            ])),
+            ..claims()
+        };
+        intercept(verifier(), &hs256(&many, SECRET.as_bytes())).unwrap();
+    }
+
+    #[test]
+    fn rejects_tokens_without_a_user() {
+        let config = hs256_config();
+
+        for sub in [None, Some("alice".to_string())] {
+            let claims = Claims { sub, ..claims() };
+            let verifier = TokenVerifier::from_config(&config).unwrap();
+            assert_rejected(verifier, &hs256(&claims, SECRET.as_bytes()), "subject");
+        }
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_algorithm_or_key() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let hs256_verifier = || TokenVerifier::from_config(&hs256_config()).unwrap();
+        let (rs256_token, _) = rs256_signer(&rsa).sign(Uuid::now_v7(), &[]).unwrap();
+        intercept(rs256_verifier(&rsa), &rs256_token).unwrap();
+
+        assert_rejected(hs256_verifier(), &rs256_token, "Malformed token");
+        assert_rejected(
+            hs256_verifier(),
+            &hs256(&claims(), b"another-secret-that-is-long-enough"),
+            "signature",
+        );
+
+        // An HS256 token keyed with the public key must not pass as RS256.
+        let public_pem = rsa.public_key_to_pem().unwrap();
+        let forged = hs256(&claims(), &public_pem);
+        assert_rejected(rs256_verifier(&rsa), &forged, "Malformed token");
+        assert_rejected(
+            rs256_verifier(&rsa),
+            &hs256(&claims(), SECRET.as_bytes()),
+            "Malformed token",
+        );
+
+        let other = Rsa::generate(2048).unwrap();
+        assert_rejected(rs256_verifier(&other), &rs256_token, "signature");
+    }
+}
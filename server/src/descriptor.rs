@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The parts of `google/protobuf/descriptor.proto` the server reads from the SDK's file
+//! descriptor set: the messages, enums and services it declares, and the `buf.validate` rules
+//! on messages, oneofs and fields. Fields that are not declared here are skipped when decoding.
+
+use std::collections::HashMap;
+
+pub const TYPE_DOUBLE: i32 = 1;
+pub const TYPE_FLOAT: i32 = 2;
+pub const TYPE_INT64: i32 = 3;
+pub const TYPE_UINT64: i32 = 4;
+pub const TYPE_INT32: i32 = 5;
+pub const TYPE_FIXED64: i32 = 6;
+pub const TYPE_FIXED32: i32 = 7;
+pub const TYPE_BOOL: i32 = 8;
+pub const TYPE_STRING: i32 = 9;
+pub const TYPE_MESSAGE: i32 = 11;
+pub const TYPE_BYTES: i32 = 12;
+pub const TYPE_UINT32: i32 = 13;
+pub const TYPE_ENUM: i32 = 14;
+pub const TYPE_SFIXED32: i32 = 15;
+pub const TYPE_SFIXED64: i32 = 16;
+pub const TYPE_SINT32: i32 = 17;
+pub const TYPE_SINT64: i32 = 18;
+
+pub const LABEL_REPEATED: i32 = 3;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    pub file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileDescriptorProto {
+    #[prost(string, tag = "2")]
+    pub package: String,
+    #[prost(message, repeated, tag = "4")]
+    pub message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    pub service: Vec<ServiceDescriptorProto>,
+    /// `proto3` or `editions`; empty for proto2 files.
+    #[prost(string, tag = "12")]
+    pub syntax: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub field: Vec<FieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    pub nested_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "4")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, optional, tag = "7")]
+    pub options: Option<MessageOptions>,
+    #[prost(message, repeated, tag = "8")]
+    pub oneof_decl: Vec<OneofDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageOptions {
+    /// Set on the entry messages generated for `map` fields.
+    #[prost(bool, tag = "7")]
+    pub map_entry: bool,
+    /// The `(buf.validate.message)` extension, still encoded.
+    #[prost(bytes = "vec", optional, tag = "1159")]
+    pub rules: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OneofDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "2")]
+    pub options: Option<OneofOptions>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OneofOptions {
+    /// The `(buf.validate.oneof)` extension, still encoded.
+    #[prost(bytes = "vec", optional, tag = "1159")]
+    pub rules: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int32, tag = "3")]
+    pub number: i32,
+    #[prost(int32, tag = "4")]
+    pub label: i32,
+    #[prost(int32, tag = "5")]
+    pub r#type: i32,
+    #[prost(string, tag = "6")]
+    pub type_name: String,
+    #[prost(message, optional, tag = "8")]
+    pub options: Option<FieldOptions>,
+    /// Set for the fields of a oneof, including the synthetic oneof of a proto3 `optional`.
+    #[prost(int32, optional, tag = "9")]
+    pub oneof_index: Option<i32>,
+    #[prost(string, tag = "10")]
+    pub json_name: String,
+    /// Set on proto3 fields declared `optional`, which have explicit presence.
+    #[prost(bool, tag = "17")]
+    pub proto3_optional: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldOptions {
+    /// The `(buf.validate.field)` extension, still encoded so that rules which are not declared
+    /// in [`FieldRules`] can be found.
+    #[prost(bytes = "vec", optional, tag = "1159")]
+    pub rules: Option<Vec<u8>>,
+}
+
+/// `buf.validate.FieldRules`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldRules {
+    #[prost(message, optional, tag = "5")]
+    pub uint32: Option<UInt32Rules>,
+    #[prost(message, optional, tag = "14")]
+    pub string: Option<StringRules>,
+    /// A `buf.validate.Ignore` value.
+    #[prost(int32, tag = "27")]
+    pub ignore: i32,
+}
+
+/// `buf.validate.Ignore.IGNORE_IF_ZERO_VALUE`
+pub const IGNORE_IF_ZERO_VALUE: i32 = 1;
+/// `buf.validate.Ignore.IGNORE_ALWAYS`
+pub const IGNORE_ALWAYS: i32 = 3;
+
+/// `buf.validate.StringRules`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StringRules {
+    #[prost(uint64, optional, tag = "2")]
+    pub min_len: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    pub max_len: Option<u64>,
+    #[prost(bool, tag = "12")]
+    pub email: bool,
+    #[prost(bool, tag = "17")]
+    pub uri: bool,
+    #[prost(bool, tag = "22")]
+    pub uuid: bool,
+}
+
+/// `buf.validate.UInt32Rules`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UInt32Rules {
+    #[prost(uint32, optional, tag = "2")]
+    pub lt: Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub lte: Option<u32>,
+    #[prost(uint32, optional, tag = "4")]
+    pub gt: Option<u32>,
+    #[prost(uint32, optional, tag = "5")]
+    pub gte: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EnumDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub value: Vec<EnumValueDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EnumValueDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int32, tag = "2")]
+    pub number: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServiceDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MethodDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub input_type: String,
+    #[prost(string, tag = "3")]
+    pub output_type: String,
+    #[prost(bool, tag = "5")]
+    pub client_streaming: bool,
+}
+
+/// Append `name` to a fully qualified name. Names start with a dot, as in `type_name`.
+pub fn qualify(parent: &str, name: &str) -> String {
+    if name.is_empty() {
+        parent.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+/// Index the messages of a file, including nested ones, by fully qualified name.
+pub fn collect_messages<'a>(
+    prefix: &str,
+    messages: &'a [DescriptorProto],
+    descriptors: &mut HashMap<String, &'a DescriptorProto>,
+) {
+    for message in messages {
+        let name = qualify(prefix, &message.name);
+        collect_messages(&name, &message.nested_type, descriptors);
+        descriptors.insert(name, message);
+    }
+}
+
+/// Index the enums of a file, including those nested in messages, by fully qualified name.
+pub fn collect_enums<'a>(
+    prefix: &str,
+    enums: &'a [EnumDescriptorProto],
+    messages: &'a [DescriptorProto],
+    descriptors: &mut HashMap<String, &'a EnumDescriptorProto>,
+) {
+    for descriptor in enums {
+        descriptors.insert(qualify(prefix, &descriptor.name), descriptor);
+    }
+    for message in messages {
+        let name = qualify(prefix, &message.name);
+        collect_enums(&name, &message.enum_type, &message.nested_type, descriptors);
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Errors returned by the services. Each becomes a gRPC status with `google.rpc.ErrorInfo`
+//! details that carry a reason clients can match on and the trace id of the request. The
+//! causes of database errors are logged, never sent to the client.
+
+use sqlx::error::ErrorKind;
+use std::collections::HashMap;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// The `domain` of every `ErrorInfo` returned by the server.
+pub const ERROR_DOMAIN: &str = "geist";
+
+#[derive(Debug)]
+pub enum GeistError {
+    NotFound(String),
+    AlreadyExists(String),
+    FailedPrecondition(String),
+    InvalidArgument(String),
+    /// A database error that the caller cannot fix, reported as `INTERNAL`.
+    Database(sqlx::Error),
+}
+
+impl GeistError {
+    pub fn code(&self) -> Code {
+        match self {
+            GeistError::NotFound(_) => Code::NotFound,
+            GeistError::AlreadyExists(_) => Code::AlreadyExists,
+            GeistError::FailedPrecondition(_) => Code::FailedPrecondition,
+            GeistError::InvalidArgument(_) => Code::InvalidArgument,
+            GeistError::Database(_) => Code::Internal,
+        }
+    }
+
+    /// The `ErrorInfo` reason.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            GeistError::NotFound(_) => "NOT_FOUND",
+            GeistError::AlreadyExists(_) => "ALREADY_EXISTS",
+            GeistError::FailedPrecondition(_) => "FAILED_PRECONDITION",
+            GeistError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            GeistError::Database(_) => "DATABASE_ERROR",
+        }
+    }
+}
+
+impl std::fmt::Display for GeistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeistError::NotFound(message)
+            | GeistError::AlreadyExists(message)
+            | GeistError::FailedPrecondition(message)
+            | GeistError::InvalidArgument(message) => write!(f, "{}", message),
+            GeistError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GeistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GeistError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Classify a database error by the constraint it violated. Queries that can violate a
+/// constraint for a known reason should map it to a more specific message first.
+impl From<sqlx::Error> for GeistError {
+    fn from(e: sqlx::Error) -> Self {
+        let kind = match &e {
+            sqlx::Error::RowNotFound => {
+                return GeistError::NotFound("Resource not found".to_string());
+            }
+            sqlx::Error::Database(db) => db.kind(),
+            _ => return GeistError::Database(e),
+        };
+
+        let error = match kind {
+            ErrorKind::UniqueViolation => {
+                GeistError::AlreadyExists("Resource already exists".to_string())
+            }
+            ErrorKind::ForeignKeyViolation => {
+                GeistError::FailedPrecondition("A referenced resource does not exist".to_string())
+            }
+            ErrorKind::NotNullViolation | ErrorKind::CheckViolation => {
+                GeistError::InvalidArgument("Request violates a constraint".to_string())
+            }
+            _ => return GeistError::Database(e),
+        };
+        tracing::debug!(error = %e, "Database constraint violated");
+        error
+    }
+}
+
+impl From<GeistError> for Status {
+    fn from(e: GeistError) -> Self {
+        let trace_id = crate::current_trace_id();
+
+        let message = match &e {
+            GeistError::Database(cause) => {
+                tracing::error!(trace_id = trace_id.as_deref(), error = %cause, "Database error");
+                "Internal error".to_string()
+            }
+            _ => e.to_string(),
+        };
+
+        let mut metadata = HashMap::new();
+        if let Some(trace_id) = &trace_id {
+            metadata.insert("trace_id".to_string(), trace_id.clone());
+        }
+        let details = ErrorDetails::with_error_info(e.reason(), ERROR_DOMAIN, metadata);
+        let mut status = Status::with_error_details(e.code(), message, details);
+
+        if let Some(value) = trace_id.and_then(|id| AsciiMetadataValue::try_from(id).ok()) {
+            status.metadata_mut().insert("x-trace-id", value);
+        }
+        status
+    }
+}
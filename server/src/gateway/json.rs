@@ -0,0 +1,655 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion between protobuf messages and their proto3 JSON mapping, driven by the SDK's file
+//! descriptor set. Fields are written with their JSON names and read by either name; 64-bit
+//! integers are strings, enums are names, bytes are base64 and timestamps and durations use
+//! their string forms. Fields with default values are omitted from the output.
+//!
+//! Reading is lenient where HTTP needs it: scalars may be given as strings, as they are in
+//! query parameters, and a repeated field may be given a single value.
+
+use crate::descriptor::{self, collect_enums, collect_messages, qualify, FileDescriptorSet};
+use crate::validate::invalid_argument;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat};
+use prost::bytes::Buf;
+use prost::encoding::{self, decode_varint, WireType};
+use prost::Message;
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use tonic::Status;
+use tonic_types::FieldViolation;
+
+const TIMESTAMP: &str = ".google.protobuf.Timestamp";
+const DURATION: &str = ".google.protobuf.Duration";
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    json_name: String,
+    number: u32,
+    kind: i32,
+    type_name: String,
+    repeated: bool,
+}
+
+#[derive(Debug, Default)]
+struct MessageSchema {
+    fields: Vec<Field>,
+    /// Field indexes by number, and by both proto and JSON name.
+    by_number: HashMap<u32, usize>,
+    by_name: HashMap<String, usize>,
+    map_entry: bool,
+}
+
+impl MessageSchema {
+    fn field(&self, number: u32) -> Option<&Field> {
+        self.by_number.get(&number).map(|&i| &self.fields[i])
+    }
+
+    fn field_by_name(&self, name: &str) -> Option<&Field> {
+        self.by_name.get(name).map(|&i| &self.fields[i])
+    }
+}
+
+#[derive(Debug, Default)]
+struct EnumSchema {
+    names: HashMap<i32, String>,
+    numbers: HashMap<String, i32>,
+}
+
+/// The messages, enums and methods of the compiled protobufs.
+#[derive(Debug, Default)]
+pub struct Schema {
+    messages: HashMap<String, MessageSchema>,
+    enums: HashMap<String, EnumSchema>,
+    /// Request and response message of each method, by gRPC path.
+    methods: HashMap<String, (String, String)>,
+}
+
+impl Schema {
+    pub fn from_descriptor_set(bytes: &[u8]) -> Result<Self, prost::DecodeError> {
+        let set = FileDescriptorSet::decode(bytes)?;
+        let mut schema = Schema::default();
+
+        for file in &set.file {
+            let package = qualify("", &file.package);
+
+            let mut messages = HashMap::new();
+            collect_messages(&package, &file.message_type, &mut messages);
+            for (name, message) in messages {
+                let mut schema_message = MessageSchema {
+                    map_entry: message.options.as_ref().is_some_and(|o| o.map_entry),
+                    ..Default::default()
+                };
+                for (i, field) in message.field.iter().enumerate() {
+                    schema_message.by_number.insert(field.number as u32, i);
+                    schema_message.by_name.insert(field.name.clone(), i);
+                    if !field.json_name.is_empty() {
+                        schema_message.by_name.insert(field.json_name.clone(), i);
+                    }
+                    schema_message.fields.push(Field {
+                        name: field.name.clone(),
+                        json_name: if field.json_name.is_empty() {
+                            field.name.clone()
+                        } else {
+                            field.json_name.clone()
+                        },
+                        number: field.number as u32,
+                        kind: field.r#type,
+                        type_name: field.type_name.clone(),
+                        repeated: field.label == descriptor::LABEL_REPEATED,
+                    });
+                }
+                schema.messages.insert(name, schema_message);
+            }
+
+            let mut enums = HashMap::new();
+            collect_enums(&package, &file.enum_type, &file.message_type, &mut enums);
+            for (name, descriptor) in enums {
+                let mut schema_enum = EnumSchema::default();
+                for value in &descriptor.value {
+                    schema_enum.names.insert(value.number, value.name.clone());
+                    schema_enum.numbers.insert(value.name.clone(), value.number);
+                }
+                schema.enums.insert(name, schema_enum);
+            }
+
+            for service in &file.service {
+                let service_name = qualify(&package, &service.name);
+                for method in &service.method {
+                    let path = format!("/{}/{}", &service_name[1..], method.name);
+                    let types = (method.input_type.clone(), method.output_type.clone());
+                    schema.methods.insert(path, types);
+                }
+            }
+        }
+
+        Ok(schema)
+    }
+
+    /// The request and response message of the method at a gRPC path.
+    pub fn method(&self, path: &str) -> Option<(&str, &str)> {
+        self.methods
+            .get(path)
+            .map(|(input, output)| (input.as_str(), output.as_str()))
+    }
+
+    /// Encode a JSON value as the named message. A value that does not fit its field is
+    /// rejected with `INVALID_ARGUMENT` and a violation that names the field.
+    pub fn encode(&self, message: &str, value: &Value) -> Result<Vec<u8>, Status> {
+        self.encode_message(message, value, "")
+    }
+
+    /// Decode the named message to JSON.
+    pub fn decode(&self, message: &str, bytes: &[u8]) -> Result<Value, Status> {
+        self.decode_message(message, bytes).map_err(|e| {
+            tracing::error!(message, error = %e, "Failed to decode a response as JSON");
+            Status::internal("Failed to encode the response")
+        })
+    }
+
+    fn message(&self, name: &str) -> Result<&MessageSchema, Status> {
+        self.messages
+            .get(name)
+            .ok_or_else(|| Status::internal(format!("Unknown message {}", name)))
+    }
+
+    fn encode_message(&self, name: &str, value: &Value, path: &str) -> Result<Vec<u8>, Status> {
+        match name {
+            TIMESTAMP => return encode_timestamp(value, path),
+            DURATION => return encode_duration(value, path),
+            _ => {}
+        }
+
+        let schema = self.message(name)?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| violation(path, "value must be an object"))?;
+
+        let mut buf = Vec::new();
+        for (key, value) in object {
+            let field = schema
+                .field_by_name(key)
+                .ok_or_else(|| violation(&join(path, key), "unknown field"))?;
+            if value.is_null() {
+                continue;
+            }
+            let path = join(path, &field.name);
+
+            let entry = self.messages.get(&field.type_name).filter(|m| m.map_entry);
+            if let Some(entry) = entry {
+                let object = value
+                    .as_object()
+                    .ok_or_else(|| violation(&path, "value must be an object"))?;
+                let (Some(key_field), Some(value_field)) = (entry.field(1), entry.field(2)) else {
+                    return Err(Status::internal("Invalid map entry"));
+                };
+                for (key, value) in object {
+                    let path = format!("{}[{}]", path, key);
+                    let mut entry_buf = Vec::new();
+                    self.encode_value(
+                        key_field,
+                        &Value::String(key.clone()),
+                        &path,
+                        &mut entry_buf,
+                    )?;
+                    self.encode_value(value_field, value, &path, &mut entry_buf)?;
+                    encoding::bytes::encode(field.number, &entry_buf, &mut buf);
+                }
+            } else if field.repeated {
+                let items = match value {
+                    Value::Array(items) => items.iter().collect(),
+                    value => vec![value],
+                };
+                for (i, item) in items.into_iter().enumerate() {
+                    self.encode_value(field, item, &format!("{}[{}]", path, i), &mut buf)?;
+                }
+            } else {
+                self.encode_value(field, value, &path, &mut buf)?;
+            }
+        }
+
+        Ok(buf)
+    }
+
+    fn encode_value(
+        &self,
+        field: &Field,
+        value: &Value,
+        path: &str,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Status> {
+        let tag = field.number;
+        let integer = || violation(path, "value must be an integer");
+        let number = || violation(path, "value must be a number");
+
+        match field.kind {
+            descriptor::TYPE_DOUBLE => {
+                encoding::double::encode(tag, &parse_f64(value).ok_or_else(number)?, buf)
+            }
+            descriptor::TYPE_FLOAT => {
+                encoding::float::encode(tag, &(parse_f64(value).ok_or_else(number)? as f32), buf)
+            }
+            descriptor::TYPE_INT64 | descriptor::TYPE_SINT64 | descriptor::TYPE_SFIXED64 => {
+                let value = parse_i64(value).ok_or_else(integer)?;
+                match field.kind {
+                    descriptor::TYPE_SINT64 => encoding::sint64::encode(tag, &value, buf),
+                    descriptor::TYPE_SFIXED64 => encoding::sfixed64::encode(tag, &value, buf),
+                    _ => encoding::int64::encode(tag, &value, buf),
+                }
+            }
+            descriptor::TYPE_UINT64 | descriptor::TYPE_FIXED64 => {
+                let value = parse_u64(value).ok_or_else(integer)?;
+                match field.kind {
+                    descriptor::TYPE_FIXED64 => encoding::fixed64::encode(tag, &value, buf),
+                    _ => encoding::uint64::encode(tag, &value, buf),
+                }
+            }
+            descriptor::TYPE_INT32 | descriptor::TYPE_SINT32 | descriptor::TYPE_SFIXED32 => {
+                let value = parse_i64(value)
+                    .and_then(|value| i32::try_from(value).ok())
+                    .ok_or_else(integer)?;
+                match field.kind {
+                    descriptor::TYPE_SINT32 => encoding::sint32::encode(tag, &value, buf),
+                    descriptor::TYPE_SFIXED32 => encoding::sfixed32::encode(tag, &value, buf),
+                    _ => encoding::int32::encode(tag, &value, buf),
+                }
+            }
+            descriptor::TYPE_UINT32 | descriptor::TYPE_FIXED32 => {
+                let value = parse_u64(value)
+                    .and_then(|value| u32::try_from(value).ok())
+                    .ok_or_else(integer)?;
+                match field.kind {
+                    descriptor::TYPE_FIXED32 => encoding::fixed32::encode(tag, &value, buf),
+                    _ => encoding::uint32::encode(tag, &value, buf),
+                }
+            }
+            descriptor::TYPE_BOOL => {
+                let value = match value {
+                    Value::Bool(value) => Some(*value),
+                    Value::String(value) => value.parse().ok(),
+                    _ => None,
+                };
+                let value = value.ok_or_else(|| violation(path, "value must be a boolean"))?;
+                encoding::bool::encode(tag, &value, buf)
+            }
+            descriptor::TYPE_STRING => {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| violation(path, "value must be a string"))?;
+                encoding::string::encode(tag, &value.to_string(), buf)
+            }
+            descriptor::TYPE_BYTES => {
+                let value = value
+                    .as_str()
+                    .and_then(|value| {
+                        STANDARD
+                            .decode(value)
+                            .or_else(|_| URL_SAFE.decode(value))
+                            .ok()
+                    })
+                    .ok_or_else(|| violation(path, "value must be base64 encoded"))?;
+                encoding::bytes::encode(tag, &value, buf)
+            }
+            descriptor::TYPE_ENUM => {
+                let values = self.enums.get(&field.type_name);
+                let value = match value {
+                    Value::String(name) => values
+                        .and_then(|values| values.numbers.get(name).copied())
+                        .or_else(|| name.parse().ok()),
+                    value => parse_i64(value).and_then(|value| i32::try_from(value).ok()),
+                };
+                let value = value.ok_or_else(|| violation(path, "value must be an enum value"))?;
+                encoding::int32::encode(tag, &value, buf)
+            }
+            descriptor::TYPE_MESSAGE => {
+                let message = self.encode_message(&field.type_name, value, path)?;
+                encoding::bytes::encode(tag, &message, buf)
+            }
+            _ => return Err(violation(path, "field type is not supported")),
+        }
+        Ok(())
+    }
+
+    fn decode_message(&self, name: &str, mut buf: &[u8]) -> Result<Value, String> {
+        match name {
+            TIMESTAMP => return decode_timestamp(buf),
+            DURATION => return decode_duration(buf),
+            _ => {}
+        }
+
+        let schema = self
+            .messages
+            .get(name)
+            .ok_or_else(|| format!("unknown message {}", name))?;
+        let mut object = Map::new();
+
+        while buf.has_remaining() {
+            let key = decode_varint(&mut buf).map_err(|e| e.to_string())?;
+            let wire_type = WireType::try_from(key & 0x7).map_err(|e| e.to_string())?;
+            let value = read_value(wire_type, &mut buf)?;
+            let Some(field) = schema.field((key >> 3) as u32) else {
+                continue;
+            };
+
+            let entry = self.messages.get(&field.type_name).filter(|m| m.map_entry);
+            if let (Some(entry), RawValue::Bytes(bytes)) = (entry, &value) {
+                let (key, value) = self.decode_map_entry(entry, &field.type_name, bytes)?;
+                let map = object
+                    .entry(field.json_name.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Value::Object(map) = map {
+                    map.insert(key, value);
+                }
+            } else if field.repeated {
+                let items = object
+                    .entry(field.json_name.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                let Value::Array(items) = items else {
+                    continue;
+                };
+                match value {
+                    // Repeated scalars are packed into a single length-delimited value.
+                    RawValue::Bytes(mut packed) if is_packable(field.kind) => {
+                        let wire_type = packed_wire_type(field.kind);
+                        while packed.has_remaining() {
+                            let value = read_value(wire_type, &mut packed)?;
+                            items.push(self.decode_value(field, value)?);
+                        }
+                    }
+                    value => items.push(self.decode_value(field, value)?),
+                }
+            } else {
+                object.insert(field.json_name.clone(), self.decode_value(field, value)?);
+            }
+        }
+
+        Ok(Value::Object(object))
+    }
+
+    fn decode_map_entry(
+        &self,
+        entry: &MessageSchema,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(String, Value), String> {
+        let decoded = self.decode_message(name, bytes)?;
+        let field_value = |number| {
+            let field = entry.field(number)?;
+            Some(
+                decoded
+                    .get(&field.json_name)
+                    .cloned()
+                    .unwrap_or_else(|| default_value(field)),
+            )
+        };
+
+        let key = match field_value(1) {
+            Some(Value::String(key)) => key,
+            Some(key) => key.to_string(),
+            None => String::new(),
+        };
+        Ok((key, field_value(2).unwrap_or(Value::Null)))
+    }
+
+    fn decode_value(&self, field: &Field, value: RawValue) -> Result<Value, String> {
+        let invalid = || format!("invalid value for {}", field.name);
+
+        let value = match (field.kind, value) {
+            (descriptor::TYPE_INT64, RawValue::Varint(v)) => Value::String((v as i64).to_string()),
+            (descriptor::TYPE_UINT64, RawValue::Varint(v)) => Value::String(v.to_string()),
+            (descriptor::TYPE_SINT64, RawValue::Varint(v)) => Value::String(zigzag(v).to_string()),
+            (descriptor::TYPE_INT32, RawValue::Varint(v)) => Value::from(v as i32),
+            (descriptor::TYPE_UINT32, RawValue::Varint(v)) => Value::from(v as u32),
+            (descriptor::TYPE_SINT32, RawValue::Varint(v)) => Value::from(zigzag(v) as i32),
+            (descriptor::TYPE_BOOL, RawValue::Varint(v)) => Value::Bool(v != 0),
+            (descriptor::TYPE_ENUM, RawValue::Varint(v)) => {
+                let number = v as i32;
+                self.enums
+                    .get(&field.type_name)
+                    .and_then(|values| values.names.get(&number))
+                    .map(|name| Value::String(name.clone()))
+                    .unwrap_or_else(|| Value::from(number))
+            }
+            (descriptor::TYPE_FIXED64, RawValue::Fixed64(v)) => Value::String(v.to_string()),
+            (descriptor::TYPE_SFIXED64, RawValue::Fixed64(v)) => {
+                Value::String((v as i64).to_string())
+            }
+            (descriptor::TYPE_DOUBLE, RawValue::Fixed64(v)) => float_value(f64::from_bits(v)),
+            (descriptor::TYPE_FIXED32, RawValue::Fixed32(v)) => Value::from(v),
+            (descriptor::TYPE_SFIXED32, RawValue::Fixed32(v)) => Value::from(v as i32),
+            (descriptor::TYPE_FLOAT, RawValue::Fixed32(v)) => float_value(f32::from_bits(v) as f64),
+            (descriptor::TYPE_STRING, RawValue::Bytes(bytes)) => {
+                Value::String(String::from_utf8(bytes.to_vec()).map_err(|_| invalid())?)
+            }
+            (descriptor::TYPE_BYTES, RawValue::Bytes(bytes)) => {
+                Value::String(STANDARD.encode(bytes))
+            }
+            (descriptor::TYPE_MESSAGE, RawValue::Bytes(bytes)) => {
+                self.decode_message(&field.type_name, bytes)?
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(value)
+    }
+}
+
+/// A field value as read from the wire, before its type is applied.
+enum RawValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+}
+
+fn read_value<'a>(wire_type: WireType, buf: &mut &'a [u8]) -> Result<RawValue<'a>, String> {
+    let truncated = || "truncated message".to_string();
+    match wire_type {
+        WireType::Varint => Ok(RawValue::Varint(
+            decode_varint(buf).map_err(|e| e.to_string())?,
+        )),
+        WireType::SixtyFourBit => {
+            if buf.remaining() < 8 {
+                return Err(truncated());
+            }
+            Ok(RawValue::Fixed64(buf.get_u64_le()))
+        }
+        WireType::ThirtyTwoBit => {
+            if buf.remaining() < 4 {
+                return Err(truncated());
+            }
+            Ok(RawValue::Fixed32(buf.get_u32_le()))
+        }
+        WireType::LengthDelimited => {
+            let len = decode_varint(buf).map_err(|e| e.to_string())? as usize;
+            let bytes = buf.get(..len).ok_or_else(truncated)?;
+            buf.advance(len);
+            Ok(RawValue::Bytes(bytes))
+        }
+        WireType::StartGroup | WireType::EndGroup => Err("groups are not supported".to_string()),
+    }
+}
+
+fn is_packable(kind: i32) -> bool {
+    !matches!(
+        kind,
+        descriptor::TYPE_STRING | descriptor::TYPE_BYTES | descriptor::TYPE_MESSAGE
+    )
+}
+
+fn packed_wire_type(kind: i32) -> WireType {
+    match kind {
+        descriptor::TYPE_DOUBLE | descriptor::TYPE_FIXED64 | descriptor::TYPE_SFIXED64 => {
+            WireType::SixtyFourBit
+        }
+        descriptor::TYPE_FLOAT | descriptor::TYPE_FIXED32 | descriptor::TYPE_SFIXED32 => {
+            WireType::ThirtyTwoBit
+        }
+        _ => WireType::Varint,
+    }
+}
+
+/// The JSON of a field's default value, for map entries that leave it out.
+fn default_value(field: &Field) -> Value {
+    match field.kind {
+        descriptor::TYPE_STRING | descriptor::TYPE_BYTES => Value::String(String::new()),
+        descriptor::TYPE_BOOL => Value::Bool(false),
+        descriptor::TYPE_INT64
+        | descriptor::TYPE_UINT64
+        | descriptor::TYPE_SINT64
+        | descriptor::TYPE_FIXED64
+        | descriptor::TYPE_SFIXED64 => Value::String("0".to_string()),
+        descriptor::TYPE_MESSAGE => Value::Object(Map::new()),
+        _ => Value::from(0),
+    }
+}
+
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// A float as a JSON number, or the string proto3 JSON uses for NaN and the infinities.
+fn float_value(value: f64) -> Value {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or_else(|| {
+            let name = if value.is_nan() {
+                "NaN"
+            } else if value > 0.0 {
+                "Infinity"
+            } else {
+                "-Infinity"
+            };
+            Value::String(name.to_string())
+        })
+}
+
+fn parse_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.as_i64().or_else(|| {
+            number
+                .as_f64()
+                .filter(|f| f.fract() == 0.0)
+                .map(|f| f as i64)
+        }),
+        Value::String(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_u64(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number.as_u64().or_else(|| {
+            number
+                .as_f64()
+                .filter(|f| f.fract() == 0.0 && *f >= 0.0)
+                .map(|f| f as u64)
+        }),
+        Value::String(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(value) => match value.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            value => value.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+fn encode_timestamp(value: &Value, path: &str) -> Result<Vec<u8>, Status> {
+    let time = value
+        .as_str()
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .ok_or_else(|| violation(path, "value must be an RFC 3339 timestamp"))?;
+
+    Ok(prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+    .encode_to_vec())
+}
+
+fn decode_timestamp(buf: &[u8]) -> Result<Value, String> {
+    let timestamp = prost_types::Timestamp::decode(buf).map_err(|e| e.to_string())?;
+    let time = DateTime::from_timestamp(timestamp.seconds, timestamp.nanos.max(0) as u32)
+        .ok_or_else(|| "timestamp out of range".to_string())?;
+    Ok(Value::String(
+        time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+    ))
+}
+
+/// Parse a duration such as `1.5s`, which is how proto3 JSON writes them.
+fn encode_duration(value: &Value, path: &str) -> Result<Vec<u8>, Status> {
+    let invalid = || {
+        violation(
+            path,
+            "value must be a duration in seconds, such as \"1.5s\"",
+        )
+    };
+    let value = value
+        .as_str()
+        .and_then(|value| value.strip_suffix('s'))
+        .ok_or_else(invalid)?;
+
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 9 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let seconds: i64 = seconds.parse().map_err(|_| invalid())?;
+    let nanos: i32 = format!("{:0<9}", fraction).parse().map_err(|_| invalid())?;
+    let sign = if negative { -1 } else { 1 };
+
+    Ok(prost_types::Duration {
+        seconds: sign * seconds,
+        nanos: sign as i32 * nanos,
+    }
+    .encode_to_vec())
+}
+
+fn decode_duration(buf: &[u8]) -> Result<Value, String> {
+    let duration = prost_types::Duration::decode(buf).map_err(|e| e.to_string())?;
+    let sign = if duration.seconds < 0 || duration.nanos < 0 {
+        "-"
+    } else {
+        ""
+    };
+    let seconds = duration.seconds.unsigned_abs();
+    let nanos = duration.nanos.unsigned_abs();
+
+    // Proto3 JSON writes 0, 3, 6 or 9 fractional digits.
+    let mut fraction = format!("{:09}", nanos);
+    while fraction.ends_with("000") {
+        fraction.truncate(fraction.len() - 3);
+    }
+    let value = if fraction.is_empty() {
+        format!("{}{}s", sign, seconds)
+    } else {
+        format!("{}{}.{}s", sign, seconds, fraction)
+    };
+    Ok(Value::String(value))
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+fn violation(field: &str, description: &str) -> Status {
+    invalid_argument(vec![FieldViolation::new(field, description)])
+}
@@ -0,0 +1,773 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A REST/JSON mapping of the user, feed, group and identity services for clients that cannot
+//! speak gRPC. Each route encodes its JSON request as the protobuf of an RPC and calls the gRPC
+//! services in-process, through the same interceptors and validation, so both protocols run the
+//! same handlers and return the same errors.
+//!
+//! Requests are built from the body, the path parameters and the query string, in that order;
+//! query parameters name fields with dots, as in `?page.size=10&page.etag=...`. Responses and
+//! errors use the proto3 JSON mapping. Errors have the shape of `google.rpc.Status`:
+//!
+//! ```json
+//! {"error": {"code": 404, "status": "NOT_FOUND", "message": "...", "details": [...]}}
+//! ```
+
+mod json;
+
+pub use json::Schema;
+
+use axum::body::{Bytes, HttpBody};
+use axum::extract::{Path, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, TE};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{on, MethodFilter};
+use axum::{BoxError, Json, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Map, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tonic::body::Body;
+use tonic::{Code, Status};
+use tonic_types::StatusExt;
+use tower::util::BoxCloneSyncService;
+use tower::{Service, ServiceExt};
+
+/// Where the JSON body of a request goes in the RPC request message.
+#[derive(Debug, Clone, Copy)]
+enum BodyMapping {
+    /// The route takes no body.
+    None,
+    /// The body is the request message.
+    All,
+    /// The body is the named field of the request message.
+    Field(&'static str),
+}
+
+#[derive(Debug)]
+struct Route {
+    method: MethodFilter,
+    path: &'static str,
+    /// The gRPC path of the RPC the route calls.
+    rpc: &'static str,
+    body: BodyMapping,
+    /// The request field each path parameter fills in.
+    params: &'static [(&'static str, &'static str)],
+}
+
+const fn route(
+    method: MethodFilter,
+    path: &'static str,
+    rpc: &'static str,
+    body: BodyMapping,
+    params: &'static [(&'static str, &'static str)],
+) -> Route {
+    Route {
+        method,
+        path,
+        rpc,
+        body,
+        params,
+    }
+}
+
+/// The gRPC path of a method of the `geist.meta.v1alpha` services.
+macro_rules! rpc {
+    ($method:literal) => {
+        concat!("/geist.meta.v1alpha.", $method)
+    };
+}
+
+use BodyMapping::{All, Field};
+use MethodFilter as M;
+
+static ROUTES: &[Route] = &[
+    route(
+        M::GET,
+        "/v1alpha/users",
+        rpc!("UserService/ListUsers"),
+        BodyMapping::None,
+        &[],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/users",
+        rpc!("UserService/CreateUser"),
+        Field("user"),
+        &[],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/users/{user}",
+        rpc!("UserService/GetUser"),
+        BodyMapping::None,
+        &[("user", "uid")],
+    ),
+    route(
+        M::PATCH,
+        "/v1alpha/users/{user}",
+        rpc!("UserService/UpdateUser"),
+        Field("user"),
+        &[("user", "user.uid")],
+    ),
+    route(
+        M::DELETE,
+        "/v1alpha/users/{user}",
+        rpc!("UserService/DeleteUser"),
+        BodyMapping::None,
+        &[("user", "user.uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/users/{user}/groups",
+        rpc!("UserService/ListUserGroups"),
+        BodyMapping::None,
+        &[("user", "user_uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/feeds",
+        rpc!("FeedService/ListFeeds"),
+        BodyMapping::None,
+        &[],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/feeds",
+        rpc!("FeedService/CreateFeed"),
+        Field("feed"),
+        &[],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/feeds/{feed}",
+        rpc!("FeedService/GetFeed"),
+        BodyMapping::None,
+        &[("feed", "uid")],
+    ),
+    route(
+        M::PATCH,
+        "/v1alpha/feeds/{feed}",
+        rpc!("FeedService/UpdateFeed"),
+        Field("feed"),
+        &[("feed", "feed.uid")],
+    ),
+    route(
+        M::DELETE,
+        "/v1alpha/feeds/{feed}",
+        rpc!("FeedService/DeleteFeed"),
+        BodyMapping::None,
+        &[("feed", "feed.uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/groups",
+        rpc!("GroupService/ListGroups"),
+        BodyMapping::None,
+        &[],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/groups",
+        rpc!("GroupService/CreateGroup"),
+        Field("group"),
+        &[],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/groups/{group}",
+        rpc!("GroupService/GetGroup"),
+        BodyMapping::None,
+        &[("group", "uid")],
+    ),
+    route(
+        M::PATCH,
+        "/v1alpha/groups/{group}",
+        rpc!("GroupService/UpdateGroup"),
+        Field("group"),
+        &[("group", "group.uid")],
+    ),
+    route(
+        M::DELETE,
+        "/v1alpha/groups/{group}",
+        rpc!("GroupService/DeleteGroup"),
+        BodyMapping::None,
+        &[("group", "group.uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/groups/{group}/members",
+        rpc!("GroupService/ListMembers"),
+        BodyMapping::None,
+        &[("group", "group_uid")],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/groups/{group}/members",
+        rpc!("GroupService/AddMember"),
+        All,
+        &[("group", "group_uid")],
+    ),
+    route(
+        M::PATCH,
+        "/v1alpha/groups/{group}/members/{user}",
+        rpc!("GroupService/UpdateMemberRole"),
+        All,
+        &[("group", "group_uid"), ("user", "user_uid")],
+    ),
+    route(
+        M::DELETE,
+        "/v1alpha/groups/{group}/members/{user}",
+        rpc!("GroupService/RemoveMember"),
+        BodyMapping::None,
+        &[("group", "group_uid"), ("user", "user_uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/identities/{identity}",
+        rpc!("IdentityService/GetIdentity"),
+        BodyMapping::None,
+        &[("identity", "uid")],
+    ),
+    route(
+        M::GET,
+        "/v1alpha/users/{user}/identities",
+        rpc!("IdentityService/ListIdentities"),
+        BodyMapping::None,
+        &[("user", "user_uid")],
+    ),
+    route(
+        M::DELETE,
+        "/v1alpha/users/{user}/identities/{identity}",
+        rpc!("IdentityService/UnlinkIdentity"),
+        BodyMapping::None,
+        &[("user", "user_uid"), ("identity", "identity_uid")],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/users/{user}/identities/{identity}/primary",
+        rpc!("IdentityService/SetPrimaryIdentity"),
+        BodyMapping::None,
+        &[("user", "user_uid"), ("identity", "identity_uid")],
+    ),
+    route(
+        M::POST,
+        "/v1alpha/users/{user}/password",
+        rpc!("IdentityService/RegisterPassword"),
+        All,
+        &[("user", "user_uid")],
+    ),
+    route(
+        M::PUT,
+        "/v1alpha/users/{user}/password",
+        rpc!("IdentityService/ChangePassword"),
+        All,
+        &[("user", "user_uid")],
+    ),
+];
+
+type GrpcService = BoxCloneSyncService<http::Request<Body>, http::Response<Body>, Infallible>;
+
+/// Forwards JSON requests to the gRPC services.
+#[derive(Clone)]
+pub struct Gateway {
+    grpc: GrpcService,
+    schema: Arc<Schema>,
+}
+
+impl std::fmt::Debug for Gateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Gateway").finish_non_exhaustive()
+    }
+}
+
+impl Gateway {
+    /// `grpc` serves the gRPC services, with the same layers as the gRPC server.
+    pub fn new<S, ResBody>(grpc: S, schema: Schema) -> Self
+    where
+        S: Service<http::Request<Body>, Response = http::Response<ResBody>, Error = Infallible>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Future: Send,
+        ResBody: HttpBody<Data = Bytes> + Send + 'static,
+        ResBody::Error: Into<BoxError>,
+    {
+        let grpc = grpc.map_response(|response| response.map(Body::new));
+        Self {
+            grpc: BoxCloneSyncService::new(grpc),
+            schema: Arc::new(schema),
+        }
+    }
+
+    async fn forward(
+        &self,
+        route: &Route,
+        params: Vec<(String, String)>,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Value, Status> {
+        let (input, output) = self
+            .schema
+            .method(route.rpc)
+            .ok_or_else(|| Status::unimplemented(format!("Unknown method {}", route.rpc)))?;
+
+        let mut request = Value::Object(Map::new());
+        let body = match route.body {
+            BodyMapping::None => None,
+            _ if body.is_empty() => Some(Value::Object(Map::new())),
+            _ => Some(serde_json::from_slice(body).map_err(|e| {
+                Status::invalid_argument(format!("Request body is not valid JSON: {}", e))
+            })?),
+        };
+        match (route.body, body) {
+            (Field(field), Some(body)) => set_field(&mut request, field, body),
+            (_, Some(body)) => request = body,
+            _ => {}
+        }
+
+        for (name, value) in params {
+            if let Some((_, field)) = route.params.iter().find(|(param, _)| *param == name) {
+                set_field(&mut request, field, Value::String(value));
+            }
+        }
+
+        let query = axum::extract::Query::<Vec<(String, String)>>::try_from_uri(uri)
+            .map_err(|e| Status::invalid_argument(format!("Invalid query string: {}", e)))?;
+        for (name, value) in query.0 {
+            add_field(&mut request, &name, Value::String(value));
+        }
+
+        let message = self.schema.encode(input, &request)?;
+        let response = self.call(route.rpc, headers, message).await?;
+        self.schema.decode(output, &response)
+    }
+
+    /// Call an RPC with an encoded request message and return the encoded response.
+    async fn call(
+        &self,
+        rpc: &str,
+        headers: &HeaderMap,
+        message: Vec<u8>,
+    ) -> Result<Bytes, Status> {
+        // A gRPC message is framed by an uncompressed flag and its length.
+        let mut frame = Vec::with_capacity(message.len() + 5);
+        frame.push(0);
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&message);
+
+        let mut request = http::Request::builder()
+            .method(Method::POST)
+            .uri(rpc)
+            .header(CONTENT_TYPE, "application/grpc")
+            .header(TE, "trailers")
+            .body(Body::new(http_body_util::Full::new(Bytes::from(frame))))
+            .map_err(|e| Status::internal(format!("Failed to build request: {}", e)))?;
+        for name in [AUTHORIZATION.as_str(), "x-trace-id"] {
+            if let Some(value) = headers.get(name) {
+                request.headers_mut().insert(name, value.clone());
+            }
+        }
+
+        let response = match self.grpc.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(e) => match e {},
+        };
+        let (parts, body) = response.into_parts();
+        let collected = body
+            .collect()
+            .await
+            .map_err(|e| Status::internal(format!("Failed to read response: {}", e)))?;
+
+        // Errors may be sent in the headers, without a body or trailers.
+        let status = Status::from_header_map(&parts.headers)
+            .or_else(|| collected.trailers().and_then(Status::from_header_map))
+            .unwrap_or_else(|| Status::internal("Response has no gRPC status"));
+        if status.code() != Code::Ok {
+            return Err(status);
+        }
+
+        let bytes = collected.to_bytes();
+        let len = bytes
+            .get(1..5)
+            .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .filter(|len| bytes[0] == 0 && bytes.len() >= len + 5)
+            .ok_or_else(|| Status::internal("Response is not an uncompressed gRPC message"))?;
+        Ok(bytes.slice(5..len + 5))
+    }
+}
+
+/// Routes for the JSON mapping of the services, under `/v1alpha`.
+pub fn router(gateway: Gateway) -> Router {
+    let mut router = Router::new();
+    for route in ROUTES {
+        let handler = move |State(gateway): State<Gateway>,
+                            params: Option<Path<Vec<(String, String)>>>,
+                            uri: Uri,
+                            headers: HeaderMap,
+                            body: Bytes| async move {
+            let params = params.map(|Path(params)| params).unwrap_or_default();
+            match gateway.forward(route, params, &uri, &headers, &body).await {
+                Ok(response) => Json(response).into_response(),
+                Err(status) => error_response(&status),
+            }
+        };
+        router = router.route(route.path, on(route.method, handler));
+    }
+    router.with_state(gateway)
+}
+
+/// Set a field of a JSON object by its dotted path, creating the objects along it.
+fn set_field(object: &mut Value, path: &str, value: Value) {
+    let (parent, name) = match path.rsplit_once('.') {
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    };
+
+    let mut object = object;
+    for name in parent.split('.').filter(|name| !name.is_empty()) {
+        if !object.get(name).is_some_and(Value::is_object) {
+            object[name] = Value::Object(Map::new());
+        }
+        object = &mut object[name];
+    }
+    object[name] = value;
+}
+
+/// Like `set_field`, but a field given more than once becomes a list, as repeated query
+/// parameters do.
+fn add_field(object: &mut Value, path: &str, value: Value) {
+    let existing = path
+        .split('.')
+        .try_fold(&mut *object, |object, name| object.get_mut(name));
+
+    match existing {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => set_field(object, path, value),
+    }
+}
+
+/// Render a status in the shape of `google.rpc.Status`, with the HTTP status its code maps to.
+fn error_response(status: &Status) -> Response {
+    let (http_status, name) = match status.code() {
+        Code::Ok => (StatusCode::OK, "OK"),
+        Code::Cancelled => (StatusCode::from_u16(499).unwrap(), "CANCELLED"),
+        Code::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "UNKNOWN"),
+        Code::InvalidArgument => (StatusCode::BAD_REQUEST, "INVALID_ARGUMENT"),
+        Code::DeadlineExceeded => (StatusCode::GATEWAY_TIMEOUT, "DEADLINE_EXCEEDED"),
+        Code::NotFound => (StatusCode::NOT_FOUND, "NOT_FOUND"),
+        Code::AlreadyExists => (StatusCode::CONFLICT, "ALREADY_EXISTS"),
+        Code::PermissionDenied => (StatusCode::FORBIDDEN, "PERMISSION_DENIED"),
+        Code::ResourceExhausted => (StatusCode::TOO_MANY_REQUESTS, "RESOURCE_EXHAUSTED"),
+        Code::FailedPrecondition => (StatusCode::BAD_REQUEST, "FAILED_PRECONDITION"),
+        Code::Aborted => (StatusCode::CONFLICT, "ABORTED"),
+        Code::OutOfRange => (StatusCode::BAD_REQUEST, "OUT_OF_RANGE"),
+        Code::Unimplemented => (StatusCode::NOT_IMPLEMENTED, "UNIMPLEMENTED"),
+        Code::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL"),
+        Code::Unavailable => (StatusCode::SERVICE_UNAVAILABLE, "UNAVAILABLE"),
+        Code::DataLoss => (StatusCode::INTERNAL_SERVER_ERROR, "DATA_LOSS"),
+        Code::Unauthenticated => (StatusCode::UNAUTHORIZED, "UNAUTHENTICATED"),
+    };
+
+    let error_details = status.get_error_details();
+    let mut details = Vec::new();
+    if let Some(info) = error_details.error_info() {
+        details.push(json!({
+            "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+            "reason": info.reason,
+            "domain": info.domain,
+            "metadata": info.metadata,
+        }));
+    }
+    if let Some(bad_request) = error_details.bad_request() {
+        let violations: Vec<Value> = bad_request
+            .field_violations
+            .iter()
+            .map(|v| json!({"field": v.field, "description": v.description}))
+            .collect();
+        details.push(json!({
+            "@type": "type.googleapis.com/google.rpc.BadRequest",
+            "fieldViolations": violations,
+        }));
+    }
+
+    let body = json!({
+        "error": {
+            "code": http_status.as_u16(),
+            "status": name,
+            "message": status.message(),
+            "details": details,
+        }
+    });
+
+    let mut response = (http_status, Json(body)).into_response();
+    if let Some(trace_id) = status.metadata().get("x-trace-id") {
+        if let Ok(value) = HeaderValue::from_bytes(trace_id.as_bytes()) {
+            response.headers_mut().insert("x-trace-id", value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{TokenSigner, TokenVerifier};
+    use crate::config::AppConfig;
+    use crate::meta::{FeedServer, Paginator, UserServer, VisibilityPolicy};
+    use crate::validate::{ValidateLayer, Validator};
+    use crate::{TokenInterceptor, TraceInterceptor, TraceScopeLayer};
+    use clap::Parser;
+    use geist_sdk::pb::meta::v1alpha::feed_request::Params;
+    use geist_sdk::pb::meta::v1alpha::feed_service_client::FeedServiceClient;
+    use geist_sdk::pb::meta::v1alpha::feed_service_server::FeedServiceServer;
+    use geist_sdk::pb::meta::v1alpha::user_service_server::UserServiceServer;
+    use geist_sdk::pb::meta::v1alpha::FeedRequest;
+    use sqlx::PgPool;
+    use tonic::service::{InterceptorLayer, Routes};
+    use uuid::Uuid;
+
+    /// The gRPC services with the layers of the server, and a signer for their access tokens.
+    fn services(pool: PgPool) -> (GrpcService, TokenSigner) {
+        let config = AppConfig::try_parse_from([
+            "geist-server",
+            "--jwt-secret",
+            "a-test-secret-that-is-long-enough-for-hs256",
+        ])
+        .unwrap();
+        let auth = TokenInterceptor::new(TokenVerifier::from_config(&config).unwrap());
+        let pages = Paginator::from_config(&config);
+        let users = UserServiceServer::with_interceptor(
+            UserServer::new(pool.clone(), pages.clone()),
+            auth.clone(),
+        );
+        let feeds = FeedServiceServer::with_interceptor(
+            FeedServer::new(
+                pool,
+                VisibilityPolicy::new(config.environment),
+                config.url_policy(),
+                pages,
+            ),
+            auth,
+        );
+
+        let validator = Validator::from_descriptor_set(geist_sdk::pb::FILE_DESCRIPTOR_SET).unwrap();
+        let grpc = tower::ServiceBuilder::new()
+            .layer(InterceptorLayer::new(TraceInterceptor))
+            .layer(TraceScopeLayer)
+            .layer(ValidateLayer::new(validator))
+            .service(Routes::new(users).add_service(feeds));
+        let grpc = grpc.map_response(|response| response.map(Body::new));
+        (
+            BoxCloneSyncService::new(grpc),
+            TokenSigner::from_config(&config).unwrap(),
+        )
+    }
+
+    fn app(grpc: GrpcService) -> Router {
+        let schema = Schema::from_descriptor_set(geist_sdk::pb::FILE_DESCRIPTOR_SET).unwrap();
+        router(Gateway::new(grpc, schema))
+    }
+
+    async fn create_user(pool: &PgPool, username: &str) -> Uuid {
+        sqlx::query_scalar(
+            "INSERT INTO public.users (name, email, username) VALUES ($1, $2, $1) RETURNING id",
+        )
+        .bind(username)
+        .bind(format!("{}@example.com", username))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    fn token(signer: &TokenSigner, user_uid: Uuid) -> String {
+        format!("Bearer {}", signer.sign(user_uid, &[]).unwrap().0)
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, token);
+        }
+        let body = match body {
+            Some(body) => axum::body::Body::from(body.to_string()),
+            None => axum::body::Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(
+                request
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn create_feed(app: &Router, token: &str, name: &str, visibility: &str) -> Value {
+        let feed = json!({
+            "name": name,
+            "url": format!("https://example.com/{}.xml", name),
+            "type": "TYPE_RSS",
+            "visibility": visibility,
+        });
+        let (status, body) =
+            send(app, Method::POST, "/v1alpha/feeds", Some(token), Some(feed)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        body["feeds"][0].clone()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn feeds_are_created_read_and_listed_as_json(pool: PgPool) {
+        let (grpc, signer) = services(pool.clone());
+        let app = app(grpc);
+        let alice = token(&signer, create_user(&pool, "alice").await);
+
+        let feed = create_feed(&app, &alice, "first", "VISIBILITY_PUBLIC").await;
+        assert_eq!(feed["url"], "https://example.com/first.xml");
+        assert_eq!(feed["visibility"], "VISIBILITY_PUBLIC");
+        create_feed(&app, &alice, "second", "VISIBILITY_PUBLIC").await;
+        create_feed(&app, &alice, "third", "VISIBILITY_PUBLIC").await;
+
+        let uri = format!("/v1alpha/feeds/{}", feed["uid"].as_str().unwrap());
+        let (status, body) = send(&app, Method::GET, &uri, Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["feeds"][0], feed);
+
+        // Query parameters fill in nested fields, and etags continue the listing.
+        let (status, first) = send(
+            &app,
+            Method::GET,
+            "/v1alpha/feeds?page.size=2",
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        assert_eq!(first["feeds"].as_array().unwrap().len(), 2);
+        let etag = first["page"]["etag"].as_str().unwrap();
+        assert!(!etag.is_empty());
+
+        let uri = format!("/v1alpha/feeds?page.size=2&page.etag={}", etag);
+        let (status, rest) = send(&app, Method::GET, &uri, Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK, "{}", rest);
+        let names: Vec<_> = [&first, &rest]
+            .iter()
+            .flat_map(|page| page["feeds"].as_array().unwrap())
+            .map(|feed| feed["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), 3);
+        for name in ["first", "second", "third"] {
+            assert!(names.contains(&name), "{:?}", names);
+        }
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn errors_come_from_the_grpc_layers(pool: PgPool) {
+        let (grpc, signer) = services(pool.clone());
+        let app = app(grpc);
+        let alice = token(&signer, create_user(&pool, "alice").await);
+        let bob = token(&signer, create_user(&pool, "bob").await);
+
+        // The token interceptor.
+        let (status, body) = send(&app, Method::GET, "/v1alpha/feeds", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"]["status"], "UNAUTHENTICATED");
+        assert_eq!(body["error"]["code"], 401);
+
+        // The validation layer, with the violated fields as details.
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/v1alpha/feeds/not-a-uuid",
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(body["error"]["status"], "INVALID_ARGUMENT");
+        let details = &body["error"]["details"][0];
+        assert_eq!(
+            details["@type"],
+            "type.googleapis.com/google.rpc.BadRequest"
+        );
+        assert_eq!(details["fieldViolations"][0]["field"], "uid");
+
+        let (status, body) = send(
+            &app,
+            Method::GET,
+            "/v1alpha/feeds?page.size=101",
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(
+            body["error"]["details"][0]["fieldViolations"][0]["field"],
+            "page.size"
+        );
+
+        // The handlers, which hide private feeds from everyone else.
+        let feed = create_feed(&app, &alice, "private", "VISIBILITY_PRIVATE").await;
+        let uri = format!("/v1alpha/feeds/{}", feed["uid"].as_str().unwrap());
+        let (status, body) = send(&app, Method::GET, &uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        assert_eq!(body["error"]["status"], "NOT_FOUND");
+        let changed = json!({"description": "Changed"});
+        let (status, _) = send(&app, Method::PATCH, &uri, Some(&bob), Some(changed)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, Method::DELETE, &uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn json_and_grpc_clients_get_the_same_answers(pool: PgPool) {
+        let (grpc, signer) = services(pool.clone());
+        let app = app(grpc.clone());
+        let alice = token(&signer, create_user(&pool, "alice").await);
+        let bob = token(&signer, create_user(&pool, "bob").await);
+        let feed = create_feed(&app, &alice, "private", "VISIBILITY_PRIVATE").await;
+        let uid = feed["uid"].as_str().unwrap();
+
+        let mut client = FeedServiceClient::new(grpc);
+        let get = |token: &str| {
+            let mut request = tonic::Request::new(FeedRequest {
+                params: Some(Params::Uid(uid.to_string())),
+                page: None,
+            });
+            request
+                .metadata_mut()
+                .insert("authorization", token.parse().unwrap());
+            request
+        };
+
+        let response = client.get_feed(get(&alice)).await.unwrap().into_inner();
+        assert_eq!(response.feeds[0].name, "private");
+        assert_eq!(response.feeds[0].uid, uid);
+
+        let status = client.get_feed(get(&bob)).await.unwrap_err();
+        let uri = format!("/v1alpha/feeds/{}", uid);
+        let (_, body) = send(&app, Method::GET, &uri, Some(&bob), None).await;
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(body["error"]["message"], status.message());
+    }
+}
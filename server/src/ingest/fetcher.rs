@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::url_policy::{PolicyResolver, UrlPolicy};
+use chrono::{DateTime, Utc};
+use reqwest::header::{
+    HeaderMap, ACCEPT, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    RETRY_AFTER,
+};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::StatusCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Redirects followed before a fetch fails.
+const MAX_REDIRECTS: usize = 5;
+
+/// Largest feed document the fetcher is willing to download.
+const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+const ACCEPT_FEEDS: &str = "application/atom+xml, application/rss+xml, application/feed+json, \
+                            application/xml;q=0.9, application/json;q=0.9, text/xml;q=0.8, */*;q=0.5";
+
+#[derive(Debug)]
+pub enum FetchOutcome {
+    /// The server returned a new copy of the document.
+    Modified {
+        status: u16,
+        body: Vec<u8>,
+        content_type: Option<String>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// The server confirmed that the previously fetched document is still current.
+    NotModified,
+}
+
+#[derive(Debug)]
+pub struct FetchError {
+    /// HTTP status of the response, absent when the request never completed.
+    pub status: Option<u16>,
+    pub message: String,
+    /// Delay the server asked for through a `Retry-After` header.
+    pub retry_after: Option<Duration>,
+}
+
+impl FetchError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            status: None,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "{} (status {})", self.message, status),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Describe a request error with its causes, which carry the reason a connection or redirect
+/// was refused.
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        let message = if e.is_timeout() {
+            "Request timed out".to_string()
+        } else {
+            let mut message = e.to_string();
+            let mut source = std::error::Error::source(&e);
+            while let Some(cause) = source {
+                message.push_str(": ");
+                message.push_str(&cause.to_string());
+                source = cause.source();
+            }
+            message
+        };
+
+        Self {
+            status: e.status().map(|s| s.as_u16()),
+            message,
+            retry_after: None,
+        }
+    }
+}
+
+/// HTTP client that performs conditional GET requests for feed documents.
+///
+/// Every URL it requests, including redirect targets, and every address it connects to is
+/// checked against the `UrlPolicy`. Proxies are not used, as they would resolve hosts
+/// themselves.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    client: reqwest::Client,
+    policy: UrlPolicy,
+}
+
+impl Fetcher {
+    pub fn new(timeout: Duration, policy: UrlPolicy) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(concat!("geist/", env!("CARGO_PKG_VERSION")))
+            .redirect(Policy::custom(move |attempt| follow(policy, attempt)))
+            .dns_resolver(Arc::new(PolicyResolver { policy }))
+            .no_proxy()
+            .build()?;
+
+        Ok(Self { client, policy })
+    }
+
+    /// Fetch a feed document, sending the validators from the previous fetch so that an
+    /// unchanged document is answered with `304 Not Modified`.
+    pub async fn fetch(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<FetchOutcome, FetchError> {
+        let url = self
+            .policy
+            .check(url)
+            .map_err(|e| FetchError::new(e.message))?;
+
+        let mut request = self.client.get(url).header(ACCEPT, ACCEPT_FEEDS);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let mut response = request.send().await?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(FetchOutcome::NotModified);
+        }
+
+        if !status.is_success() {
+            return Err(FetchError {
+                status: Some(status.as_u16()),
+                message: format!("Unexpected response {}", status),
+                retry_after: retry_after(response.headers()),
+            });
+        }
+
+        let headers = response.headers();
+        let content_type = header_string(headers, CONTENT_TYPE);
+        let etag = header_string(headers, ETAG);
+        let last_modified = header_string(headers, LAST_MODIFIED);
+
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_BODY_BYTES as u64)
+        {
+            return Err(FetchError::new("Feed document is too large"));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > MAX_BODY_BYTES {
+                return Err(FetchError::new("Feed document is too large"));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(FetchOutcome::Modified {
+            status: status.as_u16(),
+            body,
+            content_type,
+            etag,
+            last_modified,
+        })
+    }
+}
+
+/// Follow a redirect if it stays within the limit and its target passes the policy.
+fn follow(policy: UrlPolicy, attempt: Attempt) -> reqwest::redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error("Too many redirects");
+    }
+    match policy.check(attempt.url().as_str()) {
+        Ok(_) => attempt.follow(),
+        Err(e) => attempt.error(e),
+    }
+}
+
+fn header_string(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Parse a `Retry-After` header given either in seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+    use axum::response::{IntoResponse, Redirect, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub(in crate::ingest) const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Example</title>
+<item><guid>https://example.com/1</guid><title>First</title></item>
+</channel></rss>"#;
+
+    /// Serve `app` on an ephemeral local port, returning its base url.
+    pub(in crate::ingest) async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", address)
+    }
+
+    fn fetcher(policy: UrlPolicy) -> Fetcher {
+        Fetcher::new(Duration::from_secs(5), policy).unwrap()
+    }
+
+    /// A feed that answers conditional requests for its current version.
+    async fn conditional_feed(headers: HeaderMap) -> Response {
+        let etag = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok());
+        let since = headers.get(IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok());
+        if etag == Some("\"v1\"") || since == Some("Wed, 01 Oct 2025 00:00:00 GMT") {
+            return StatusCode::NOT_MODIFIED.into_response();
+        }
+        (
+            [
+                (CONTENT_TYPE, "application/rss+xml"),
+                (ETAG, "\"v1\""),
+                (LAST_MODIFIED, "Wed, 01 Oct 2025 00:00:00 GMT"),
+            ],
+            RSS,
+        )
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn fetch_sends_validators_from_the_previous_fetch() {
+        let base = serve(Router::new().route("/feed.xml", get(conditional_feed))).await;
+        let url = format!("{}/feed.xml", base);
+        let fetcher = fetcher(UrlPolicy::new(true));
+
+        let FetchOutcome::Modified {
+            status,
+            body,
+            content_type,
+            etag,
+            last_modified,
+        } = fetcher.fetch(&url, None, None).await.unwrap()
+        else {
+            panic!("first fetch must return the document");
+        };
+        assert_eq!(status, 200);
+        assert_eq!(body, RSS.as_bytes());
+        assert_eq!(content_type.as_deref(), Some("application/rss+xml"));
+
+        let outcome = fetcher.fetch(&url, etag.as_deref(), None).await.unwrap();
+        assert!(matches!(outcome, FetchOutcome::NotModified));
+        let outcome = fetcher
+            .fetch(&url, None, last_modified.as_deref())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, FetchOutcome::NotModified));
+    }
+
+    #[tokio::test]
+    async fn fetch_reports_status_and_retry_after() {
+        let app = Router::new()
+            .route(
+                "/busy",
+                get(|| async { (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, "120")]) }),
+            )
+            .route("/gone", get(|| async { StatusCode::GONE }));
+        let base = serve(app).await;
+        let fetcher = fetcher(UrlPolicy::new(true));
+
+        let err = fetcher
+            .fetch(&format!("{}/busy", base), None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, Some(503));
+        assert_eq!(err.retry_after, Some(Duration::from_secs(120)));
+
+        let err = fetcher
+            .fetch(&format!("{}/gone", base), None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, Some(410));
+        assert_eq!(err.retry_after, None);
+    }
+
+    #[tokio::test]
+    async fn fetch_rejects_private_addresses() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/feed.xml",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                RSS
+            }),
+        );
+        let base = serve(app).await;
+        let port = base.rsplit(':').next().unwrap();
+        let fetcher = fetcher(UrlPolicy::default());
+
+        for url in [
+            format!("{}/feed.xml", base),
+            format!("http://localhost:{}/feed.xml", port),
+            format!("http://[::ffff:127.0.0.1]:{}/feed.xml", port),
+            "file:///etc/passwd".to_string(),
+        ] {
+            assert!(fetcher.fetch(&url, None, None).await.is_err(), "{}", url);
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn fetch_limits_redirects() {
+        let app = Router::new()
+            .route("/feed.xml", get(|| async { RSS }))
+            .route("/moved", get(|| async { Redirect::permanent("/feed.xml") }))
+            .route("/loop", get(|| async { Redirect::temporary("/loop") }));
+        let base = serve(app).await;
+        let fetcher = fetcher(UrlPolicy::new(true));
+
+        let outcome = fetcher
+            .fetch(&format!("{}/moved", base), None, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            FetchOutcome::Modified { status: 200, .. }
+        ));
+
+        let err = fetcher
+            .fetch(&format!("{}/loop", base), None, None)
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Too many redirects"), "{}", err);
+    }
+}
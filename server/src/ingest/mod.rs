@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed ingestion: downloading feed documents, parsing them into entries and scheduling when
+//! each feed is polled, and reading and writing the OPML subscription lists feeds are imported
+//! from.
+
+mod fetcher;
+mod opml;
+mod parser;
+mod scheduler;
+mod url_policy;
+
+pub use fetcher::{FetchError, FetchOutcome, Fetcher};
+pub use opml::{parse_opml, write_opml, OpmlError, OpmlFeed};
+pub use parser::{detect, parse, Author, Enclosure, Entry, ParseError, ParsedFeed};
+pub use scheduler::{Scheduler, SchedulerConfig};
+pub use url_policy::{UrlError, UrlPolicy};
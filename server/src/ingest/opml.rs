@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reading and writing OPML subscription lists, the format feed readers import and export.
+//! Outlines nested inside other outlines are treated as folders.
+
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+
+/// A feed outline, together with the folders it was nested in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpmlFeed {
+    /// Empty when the outline had no `xmlUrl`, which cannot be subscribed to.
+    pub xml_url: String,
+    pub title: Option<String>,
+    pub html_url: Option<String>,
+    /// The outline's `type` attribute, such as `rss` or `atom`.
+    pub feed_type: Option<String>,
+    /// Enclosing folders, outermost first.
+    pub folders: Vec<String>,
+    /// Entries of the outline's `category` attribute.
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct OpmlError {
+    pub message: String,
+}
+
+impl std::fmt::Display for OpmlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for OpmlError {}
+
+impl From<quick_xml::Error> for OpmlError {
+    fn from(e: quick_xml::Error) -> Self {
+        OpmlError {
+            message: format!("Invalid OPML document: {}", e),
+        }
+    }
+}
+
+/// An outline being read: a folder until it turns out to have an `xmlUrl`.
+struct Outline {
+    feed: OpmlFeed,
+    has_children: bool,
+}
+
+/// Parse the feed outlines of an OPML document in document order. Outlines without an
+/// `xmlUrl` and without children are returned with an empty `xml_url`, so callers can report
+/// them instead of silently dropping them.
+pub fn parse_opml(document: &[u8]) -> Result<Vec<OpmlFeed>, OpmlError> {
+    let mut reader = Reader::from_reader(document);
+    reader.config_mut().trim_text(true);
+
+    let mut feeds = Vec::new();
+    let mut open: Vec<Outline> = Vec::new();
+    let mut seen_root = false;
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(element) | Event::Empty(element)
+                if !seen_root && element.local_name().as_ref() != b"opml" =>
+            {
+                return Err(OpmlError {
+                    message: "Not an OPML document".to_string(),
+                });
+            }
+            Event::Start(element) if element.local_name().as_ref() == b"outline" => {
+                if let Some(parent) = open.last_mut() {
+                    parent.has_children = true;
+                }
+                let folders = folder_path(&open);
+                open.push(Outline {
+                    feed: read_outline(&reader, &element, folders)?,
+                    has_children: false,
+                });
+            }
+            Event::Empty(element) if element.local_name().as_ref() == b"outline" => {
+                if let Some(parent) = open.last_mut() {
+                    parent.has_children = true;
+                }
+                feeds.push(read_outline(&reader, &element, folder_path(&open))?);
+            }
+            Event::End(element) if element.local_name().as_ref() == b"outline" => {
+                if let Some(outline) = open.pop() {
+                    // A folder that also has an `xmlUrl` is still a feed.
+                    if !outline.has_children || !outline.feed.xml_url.is_empty() {
+                        feeds.push(outline.feed);
+                    }
+                }
+            }
+            Event::Start(_) | Event::Empty(_) => seen_root = true,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if !seen_root {
+        return Err(OpmlError {
+            message: "Not an OPML document".to_string(),
+        });
+    }
+
+    Ok(feeds)
+}
+
+/// The titles of the open outlines, which are the folders of the next one.
+fn folder_path(open: &[Outline]) -> Vec<String> {
+    open.iter()
+        .filter_map(|outline| outline.feed.title.clone())
+        .collect()
+}
+
+fn read_outline(
+    reader: &Reader<&[u8]>,
+    element: &BytesStart,
+    folders: Vec<String>,
+) -> Result<OpmlFeed, OpmlError> {
+    let mut feed = OpmlFeed {
+        folders,
+        ..Default::default()
+    };
+    let mut text = None;
+
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|e| OpmlError {
+            message: format!("Invalid outline attribute: {}", e),
+        })?;
+        let value = attribute
+            .decoded_and_normalized_value(XmlVersion::default(), reader.decoder())?
+            .trim()
+            .to_string();
+        if value.is_empty() {
+            continue;
+        }
+
+        match attribute.key.local_name().as_ref() {
+            b"xmlUrl" => feed.xml_url = value,
+            b"htmlUrl" => feed.html_url = Some(value),
+            b"title" => feed.title = Some(value),
+            b"text" => text = Some(value),
+            b"type" => feed.feed_type = Some(value.to_lowercase()),
+            // Categories are comma separated, each a `/` separated path.
+            b"category" => {
+                feed.categories = value
+                    .split([',', '/'])
+                    .map(str::trim)
+                    .filter(|category| !category.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+
+    // Readers disagree on which of `title` and `text` they fill in.
+    feed.title = feed.title.or(text);
+    Ok(feed)
+}
+
+/// Write an OPML document, nesting each feed in outlines for its folders. Feeds are expected
+/// to be sorted by folder so that each folder is written once.
+pub fn write_opml(title: &str, feeds: &[OpmlFeed]) -> String {
+    let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    document.push_str("<opml version=\"2.0\">\n");
+    document.push_str("  <head>\n");
+    document.push_str(&format!("    <title>{}</title>\n", escape(title)));
+    document.push_str("  </head>\n");
+    document.push_str("  <body>\n");
+
+    let mut open: Vec<&str> = Vec::new();
+    for feed in feeds {
+        // Close the folders this feed is not in, then open the ones it is.
+        let common = open
+            .iter()
+            .zip(&feed.folders)
+            .take_while(|(open, folder)| **open == folder.as_str())
+            .count();
+        while open.len() > common {
+            open.pop();
+            document.push_str(&format!("{}</outline>\n", indent(open.len())));
+        }
+        for name in &feed.folders[common..] {
+            let folder = escape(name.as_str());
+            document.push_str(&format!(
+                "{}<outline text=\"{}\" title=\"{}\">\n",
+                indent(open.len()),
+                folder,
+                folder
+            ));
+            open.push(name.as_str());
+        }
+
+        let title = escape(feed.title.as_deref().unwrap_or(&feed.xml_url)).into_owned();
+        let mut outline = format!(
+            "{}<outline text=\"{}\" title=\"{}\" type=\"{}\" xmlUrl=\"{}\"",
+            indent(open.len()),
+            title,
+            title,
+            escape(feed.feed_type.as_deref().unwrap_or("rss")),
+            escape(feed.xml_url.as_str())
+        );
+        if let Some(html_url) = &feed.html_url {
+            outline.push_str(&format!(" htmlUrl=\"{}\"", escape(html_url.as_str())));
+        }
+        if !feed.categories.is_empty() {
+            let categories = feed.categories.join(",");
+            outline.push_str(&format!(" category=\"{}\"", escape(categories.as_str())));
+        }
+        outline.push_str("/>\n");
+        document.push_str(&outline);
+    }
+    while !open.is_empty() {
+        open.pop();
+        document.push_str(&format!("{}</outline>\n", indent(open.len())));
+    }
+
+    document.push_str("  </body>\n");
+    document.push_str("</opml>\n");
+    document
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth + 2)
+}
@@ -0,0 +1,560 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use feed_rs::model;
+use feed_rs::parser::{ParseErrorKind, ParseFeedError};
+use geist_sdk::pb::meta::v1alpha::feed::Type as FeedType;
+
+/// UTF-8 byte order mark, which some publishers prepend to otherwise valid documents.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How far into a document to look for the root element when detecting its format.
+const SNIFF_BYTES: usize = 1024;
+
+/// A feed document reduced to the fields Geist stores, independent of its source format.
+#[derive(Debug, Clone)]
+pub struct ParsedFeed {
+    /// Format the document was actually written in.
+    pub format: FeedType,
+    pub title: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// A single item of a feed, normalized across RSS, Atom and JSON Feed.
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    /// Identifier assigned by the publisher, or the entry link when the document does not
+    /// provide one.
+    pub guid: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub summary: Option<String>,
+    pub content: Option<String>,
+    pub authors: Vec<Author>,
+    pub published: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+    pub enclosures: Vec<Enclosure>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Author {
+    pub name: String,
+    pub email: Option<String>,
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Enclosure {
+    pub url: String,
+    pub media_type: Option<String>,
+    pub length: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseFeedError> for ParseError {
+    fn from(e: ParseFeedError) -> Self {
+        let message = match e {
+            ParseFeedError::ParseError(ParseErrorKind::NoFeedRoot) => {
+                "Document is not an RSS, Atom or JSON feed".to_string()
+            }
+            e => e.to_string(),
+        };
+
+        Self { message }
+    }
+}
+
+/// Guess the format of a feed document from its first bytes, falling back to the
+/// `Content-Type` the server sent when the document itself is inconclusive.
+pub fn detect(body: &[u8], content_type: Option<&str>) -> FeedType {
+    let body = trim_prelude(body);
+    let head = &body[..body.len().min(SNIFF_BYTES)];
+
+    match head.first() {
+        Some(b'{') => return FeedType::Json,
+        Some(b'<') => {
+            let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+            if head.contains("<rss") || head.contains("<rdf:rdf") {
+                return FeedType::Rss;
+            }
+            if head.contains("<feed") {
+                return FeedType::Atom;
+            }
+        }
+        _ => {}
+    }
+
+    let mime = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase());
+
+    match mime.as_deref() {
+        Some("application/rss+xml" | "application/rdf+xml") => FeedType::Rss,
+        Some("application/atom+xml") => FeedType::Atom,
+        Some("application/feed+json" | "application/json") => FeedType::Json,
+        Some("application/xml" | "text/xml") => FeedType::Xml,
+        _ if head.first() == Some(&b'<') => FeedType::Xml,
+        _ => FeedType::Unspecified,
+    }
+}
+
+/// Parse a feed document into normalized entries.
+///
+/// `declared` is the type registered for the feed; when it is `TYPE_UNSPECIFIED` the format is
+/// detected from the document. Publishers frequently mislabel their feeds, so a declared type
+/// that disagrees with the document is logged rather than treated as an error. Documents that
+/// `detect` cannot place at all, given the `Content-Type` they were served with, are rejected
+/// without trying each format on them. `base_url` is used to resolve relative links.
+pub fn parse(
+    body: &[u8],
+    declared: FeedType,
+    content_type: Option<&str>,
+    base_url: Option<&str>,
+) -> Result<ParsedFeed, ParseError> {
+    let body = trim_prelude(body);
+
+    if detect(body, content_type) == FeedType::Unspecified {
+        return Err(ParseError {
+            message: format!(
+                "Document is not an RSS, Atom or JSON feed (served as {})",
+                content_type.unwrap_or("unknown content type")
+            ),
+        });
+    }
+
+    let parsed = feed_rs::parser::Builder::new()
+        .base_uri(base_url)
+        .id_generator(entry_id)
+        .build()
+        .parse(body)?;
+
+    let format = match parsed.feed_type {
+        model::FeedType::Atom => FeedType::Atom,
+        model::FeedType::JSON => FeedType::Json,
+        model::FeedType::RSS0 | model::FeedType::RSS1 | model::FeedType::RSS2 => FeedType::Rss,
+    };
+
+    if !matches!(declared, FeedType::Unspecified | FeedType::Xml) && declared != format {
+        tracing::debug!(
+            declared = declared.as_str_name(),
+            detected = format.as_str_name(),
+            "Feed document does not match its declared type"
+        );
+    }
+
+    let feed_authors: Vec<Author> = parsed.authors.iter().filter_map(author).collect();
+    // An empty enclosure url resolves to the feed itself.
+    let base_url = base_url.and_then(|url| url::Url::parse(url).ok());
+    let entries = parsed
+        .entries
+        .into_iter()
+        .map(|e| normalize(e, &feed_authors, format, base_url.as_ref()))
+        .collect();
+
+    Ok(ParsedFeed {
+        format,
+        title: parsed.title.and_then(text),
+        entries,
+    })
+}
+
+fn normalize(
+    entry: model::Entry,
+    feed_authors: &[Author],
+    format: FeedType,
+    base_url: Option<&url::Url>,
+) -> Entry {
+    // feed-rs turns JSON Feed attachments into links without a rel, which only differ from the
+    // item url in having a media type.
+    let is_enclosure = |l: &model::Link| match l.rel.as_deref() {
+        Some(rel) => rel == "enclosure",
+        None => format == FeedType::Json && l.media_type.is_some(),
+    };
+
+    let link = entry
+        .links
+        .iter()
+        .filter(|l| !is_enclosure(l))
+        .find(|l| matches!(l.rel.as_deref(), None | Some("alternate")))
+        .or_else(|| entry.links.iter().find(|l| !is_enclosure(l)))
+        .map(|l| l.href.trim().to_string())
+        .filter(|href| !href.is_empty());
+
+    let mut authors: Vec<Author> = entry.authors.iter().filter_map(author).collect();
+    if authors.is_empty() {
+        authors = feed_authors.to_vec();
+    }
+
+    let mut enclosures: Vec<Enclosure> = Vec::new();
+    let media = entry.media.iter().flat_map(|m| m.content.iter());
+    for content in media {
+        let Some(url) = &content.url else {
+            continue;
+        };
+        push_unique(
+            &mut enclosures,
+            Enclosure {
+                url: url.to_string(),
+                media_type: content.content_type.as_ref().map(|t| t.to_string()),
+                length: content.size,
+            },
+        );
+    }
+    for l in entry.links.iter().filter(|l| is_enclosure(l)) {
+        push_unique(
+            &mut enclosures,
+            Enclosure {
+                url: l.href.trim().to_string(),
+                media_type: l.media_type.clone(),
+                length: l.length,
+            },
+        );
+    }
+
+    if let Some(base_url) = base_url {
+        enclosures.retain(|e| e.url != base_url.as_str());
+    }
+
+    // Categories are matched without regard to case, keeping the first spelling.
+    let mut categories: Vec<String> = Vec::new();
+    for category in &entry.categories {
+        let name = category.label.as_deref().unwrap_or(&category.term).trim();
+        if !name.is_empty()
+            && !categories
+                .iter()
+                .any(|c| c.to_lowercase() == name.to_lowercase())
+        {
+            categories.push(name.to_string());
+        }
+    }
+
+    Entry {
+        guid: entry.id.trim().to_string(),
+        title: entry.title.and_then(text),
+        link,
+        summary: entry.summary.and_then(text),
+        content: entry
+            .content
+            .and_then(|c| c.body)
+            .filter(|body| !body.trim().is_empty()),
+        authors,
+        // Many RSS feeds only carry one timestamp; treat it as the publish time as well.
+        published: entry.published.or(entry.updated),
+        updated: entry.updated,
+        enclosures,
+        categories,
+    }
+}
+
+/// Identify entries that carry no guid by their link, so that an edited title does not turn an
+/// existing entry into a new one. Entries without any link fall back to the feed-rs hash.
+fn entry_id(links: &[model::Link], title: &Option<model::Text>, uri: Option<&str>) -> String {
+    links
+        .iter()
+        .find(|l| matches!(l.rel.as_deref(), None | Some("alternate")))
+        .map(|l| l.href.trim().to_string())
+        .filter(|href| !href.is_empty())
+        .unwrap_or_else(|| feed_rs::parser::generate_id(links, title, uri))
+}
+
+fn author(person: &model::Person) -> Option<Author> {
+    // feed-rs names Atom authors that have no name "unknown".
+    let name = match person.name.trim() {
+        "unknown" => "",
+        name => name,
+    };
+    let email = person
+        .email
+        .as_deref()
+        .map(str::trim)
+        .filter(|e| !e.is_empty());
+    if name.is_empty() && email.is_none() {
+        return None;
+    }
+
+    Some(Author {
+        name: if name.is_empty() {
+            email.unwrap_or_default().to_string()
+        } else {
+            name.to_string()
+        },
+        email: email.map(str::to_string),
+        uri: person.uri.clone(),
+    })
+}
+
+fn text(text: model::Text) -> Option<String> {
+    let content = text.content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+fn push_unique(enclosures: &mut Vec<Enclosure>, enclosure: Enclosure) {
+    if !enclosure.url.is_empty() && !enclosures.iter().any(|e| e.url == enclosure.url) {
+        enclosures.push(enclosure);
+    }
+}
+
+/// Skip a byte order mark and any whitespace before the document proper; both are common in
+/// the wild and make XML parsers reject the prolog.
+fn trim_prelude(body: &[u8]) -> &[u8] {
+    let body = body.strip_prefix(UTF8_BOM).unwrap_or(body);
+    let start = body
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(body.len());
+    &body[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document from the corpus of malformed feeds in `tests/fixtures/feeds`.
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!(
+            "{}/tests/fixtures/feeds/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    fn parse_fixture(name: &str) -> ParsedFeed {
+        parse(
+            &fixture(name),
+            FeedType::Unspecified,
+            None,
+            Some("https://relative.example.com/feed.xml"),
+        )
+        .unwrap_or_else(|e| panic!("{}: {}", name, e))
+    }
+
+    fn time(value: &str) -> Option<DateTime<Utc>> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn corpus_formats_and_ids() {
+        for (name, format, guids) in [
+            ("rss-bom-and-whitespace.xml", FeedType::Rss, &["bom-1"][..]),
+            (
+                "rss-missing-guids.xml",
+                FeedType::Rss,
+                &[
+                    "https://noguid.example.com/first",
+                    "https://noguid.example.com/second",
+                ][..],
+            ),
+            (
+                "rss-odd-dates.xml",
+                FeedType::Rss,
+                &["dates-1", "dates-2", "dates-3", "dates-4"][..],
+            ),
+            (
+                "rss-html-and-cdata.xml",
+                FeedType::Rss,
+                &["markup-1", "markup-2"][..],
+            ),
+            (
+                "rss-duplicate-enclosures.xml",
+                FeedType::Rss,
+                &["episode-1"][..],
+            ),
+            (
+                "rss1-rdf.xml",
+                FeedType::Rss,
+                &["https://rdf.example.com/one"][..],
+            ),
+            (
+                "atom-relative-links.xml",
+                FeedType::Atom,
+                &[
+                    "tag:relative.example.com,2025:1",
+                    "https://relative.example.com/posts/no-id",
+                ][..],
+            ),
+            ("json-feed-loose.json", FeedType::Json, &["1", "2"][..]),
+        ] {
+            let feed = parse_fixture(name);
+            assert_eq!(feed.format, format, "{}", name);
+            let ids: Vec<_> = feed.entries.iter().map(|e| e.guid.as_str()).collect();
+            assert_eq!(ids, guids, "{}", name);
+        }
+    }
+
+    #[test]
+    fn corpus_dates() {
+        let feed = parse_fixture("rss-bom-and-whitespace.xml");
+        assert_eq!(feed.title.as_deref(), Some("BOM Weekly"));
+        assert_eq!(feed.entries[0].published, time("2025-10-06T09:30:00Z"));
+
+        let feed = parse_fixture("rss-odd-dates.xml");
+        let published: Vec<_> = feed.entries.iter().map(|e| e.published).collect();
+        assert_eq!(
+            published,
+            vec![
+                time("2003-06-10T11:00:00Z"),
+                time("2003-06-10T04:00:00Z"),
+                None,
+                time("2003-06-10T04:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn corpus_markup_and_blank_fields() {
+        let feed = parse_fixture("rss-html-and-cdata.xml");
+        assert_eq!(feed.title.as_deref(), Some("Markup & Escapes"));
+
+        let entry = &feed.entries[0];
+        assert_eq!(
+            entry.title.as_deref(),
+            Some("Tom &amp; Jerry <b>return</b>")
+        );
+        assert_eq!(
+            entry.summary.as_deref(),
+            Some("<p>Escaped <em>HTML</em> summary</p>")
+        );
+        assert_eq!(
+            entry.content.as_deref(),
+            Some("<p>Full <strong>content</strong></p>")
+        );
+        assert_eq!(entry.categories, vec!["Cartoons"]);
+
+        let blank = &feed.entries[1];
+        assert_eq!(blank.title, None);
+        assert_eq!(blank.summary, None);
+        assert_eq!(blank.content, None);
+        assert_eq!(blank.link, None);
+    }
+
+    #[test]
+    fn corpus_links_enclosures_and_authors() {
+        let feed = parse_fixture("rss-duplicate-enclosures.xml");
+        assert_eq!(
+            feed.entries[0].enclosures,
+            vec![Enclosure {
+                url: "https://cdn.example.com/ep1.mp3".to_string(),
+                media_type: Some("audio/mpeg".to_string()),
+                length: Some(123456),
+            }]
+        );
+
+        let feed = parse_fixture("atom-relative-links.xml");
+        assert_eq!(feed.title.as_deref(), Some("Relative <Links>"));
+        let entry = &feed.entries[0];
+        assert_eq!(
+            entry.link.as_deref(),
+            Some("https://relative.example.com/posts/relative")
+        );
+        assert_eq!(
+            entry.enclosures,
+            vec![Enclosure {
+                url: "https://relative.example.com/media/clip.mp4".to_string(),
+                media_type: Some("video/mp4".to_string()),
+                length: Some(42),
+            }]
+        );
+        assert_eq!(
+            entry.authors,
+            vec![Author {
+                name: "editor@relative.example.com".to_string(),
+                email: Some("editor@relative.example.com".to_string()),
+                uri: None,
+            }]
+        );
+
+        let feed = parse_fixture("json-feed-loose.json");
+        let entry = &feed.entries[0];
+        assert_eq!(entry.link.as_deref(), Some("https://json.example.com/1"));
+        assert_eq!(entry.published, time("2025-10-06T07:30:00Z"));
+        assert_eq!(entry.categories, vec!["intro"]);
+        assert_eq!(entry.authors[0].name, "Jay Son");
+        assert_eq!(
+            entry.enclosures,
+            vec![Enclosure {
+                url: "https://json.example.com/1.mp3".to_string(),
+                media_type: Some("audio/mpeg".to_string()),
+                length: Some(10),
+            }]
+        );
+        let entry = &feed.entries[1];
+        assert_eq!(entry.link, None);
+        assert_eq!(entry.content.as_deref(), Some("No url, title or date"));
+    }
+
+    #[test]
+    fn corpus_mislabeled_feeds_parse_as_written() {
+        let feed = parse(
+            &fixture("mislabeled-atom-as-rss.xml"),
+            FeedType::Rss,
+            Some("application/rss+xml"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(feed.format, FeedType::Atom);
+        assert_eq!(feed.entries.len(), 1);
+    }
+
+    #[test]
+    fn corpus_rejects_documents_that_are_not_feeds() {
+        for (name, content_type) in [
+            ("truncated.xml", Some("application/rss+xml")),
+            ("html-page.html", Some("text/html; charset=utf-8")),
+            ("plain-text-error.txt", Some("text/plain")),
+            ("plain-text-error.txt", None),
+        ] {
+            let result = parse(&fixture(name), FeedType::Unspecified, content_type, None);
+            assert!(result.is_err(), "{} should not parse", name);
+        }
+
+        let err = parse(
+            &fixture("plain-text-error.txt"),
+            FeedType::Rss,
+            Some("text/plain"),
+            None,
+        )
+        .unwrap_err();
+        assert!(err.message.contains("text/plain"), "{}", err);
+    }
+
+    #[test]
+    fn detect_sniffs_the_document_before_the_content_type() {
+        for (name, content_type, format) in [
+            ("rss-bom-and-whitespace.xml", None, FeedType::Rss),
+            ("rss1-rdf.xml", Some("text/html"), FeedType::Rss),
+            (
+                "atom-relative-links.xml",
+                Some("application/rss+xml"),
+                FeedType::Atom,
+            ),
+            ("json-feed-loose.json", Some("text/plain"), FeedType::Json),
+            ("html-page.html", None, FeedType::Xml),
+            (
+                "plain-text-error.txt",
+                Some("application/atom+xml"),
+                FeedType::Atom,
+            ),
+            (
+                "plain-text-error.txt",
+                Some("text/plain"),
+                FeedType::Unspecified,
+            ),
+        ] {
+            assert_eq!(detect(&fixture(name), content_type), format, "{}", name);
+        }
+    }
+}
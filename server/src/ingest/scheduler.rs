@@ -0,0 +1,469 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::fetcher::{FetchOutcome, Fetcher};
+use super::parser;
+use crate::meta::EntryRepository;
+use geist_sdk::pb::meta::v1alpha::feed::Type as FeedType;
+use rand::Rng;
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+/// First retry delay after a failed fetch; doubled on every consecutive failure.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Fraction by which every scheduled delay is randomly stretched or shrunk.
+const JITTER_RATIO: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// How often the scheduler looks for feeds that are due.
+    pub tick: Duration,
+    /// Maximum number of feeds fetched at the same time.
+    pub concurrency: usize,
+    /// Upper bound on the retry delay for feeds that keep failing.
+    pub max_backoff: Duration,
+    /// How long a claimed feed is held before another worker may pick it up again.
+    pub lease: Duration,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct DueFeed {
+    id: Uuid,
+    name: String,
+    url: String,
+    feed_type: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    poll_interval_secs: i32,
+    fetch_failures: i32,
+}
+
+/// Polls registered feeds as they come due, recording the outcome of each fetch on the feed row.
+///
+/// Feeds are claimed with `FOR UPDATE SKIP LOCKED` and leased by pushing `next_fetch_time`
+/// forward, so several server instances can run a scheduler against the same database.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    pool: PgPool,
+    fetcher: Fetcher,
+    config: SchedulerConfig,
+}
+
+impl Scheduler {
+    pub fn new(pool: PgPool, fetcher: Fetcher, config: SchedulerConfig) -> Self {
+        Self {
+            pool,
+            fetcher,
+            config,
+        }
+    }
+
+    /// Run the polling loop forever.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.config.tick);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            match self.poll_due().await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!(count, "Polled due feeds"),
+                Err(e) => tracing::error!(error = %e, "Failed to poll due feeds"),
+            }
+        }
+    }
+
+    /// Fetch every feed that is currently due, returning how many were polled.
+    pub async fn poll_due(&self) -> Result<usize, sqlx::Error> {
+        let mut total = 0;
+
+        loop {
+            let feeds = self.claim_due().await?;
+            let claimed = feeds.len();
+            total += claimed;
+
+            let mut tasks = JoinSet::new();
+            for feed in feeds {
+                let this = self.clone();
+                tasks.spawn(async move { this.poll(feed).await });
+            }
+
+            while let Some(result) = tasks.join_next().await {
+                if let Err(e) = result {
+                    tracing::error!(error = %e, "Feed poll task failed");
+                }
+            }
+
+            if claimed < self.config.concurrency {
+                return Ok(total);
+            }
+        }
+    }
+
+    async fn claim_due(&self) -> Result<Vec<DueFeed>, sqlx::Error> {
+        // Subscriptions may ask for a feed to be polled more often than the feed itself does.
+        sqlx::query_as::<_, DueFeed>(
+            r#"
+            UPDATE public.feeds
+            SET next_fetch_time = now() + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id FROM public.feeds
+                WHERE next_fetch_time <= now()
+                ORDER BY next_fetch_time ASC
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, name::text AS name, url, type::text AS feed_type, etag, last_modified,
+                      LEAST(poll_interval_secs, (
+                          SELECT MIN(s.poll_interval_secs) FROM public.subscriptions s
+                          WHERE s.feed_id = feeds.id
+                      )) AS poll_interval_secs,
+                      fetch_failures
+            "#,
+        )
+        .bind(self.config.concurrency as i64)
+        .bind(self.config.lease.as_secs_f64())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    #[tracing::instrument(skip(self, feed), fields(feed = %feed.name))]
+    async fn poll(&self, feed: DueFeed) {
+        let interval = Duration::from_secs(feed.poll_interval_secs.max(1) as u64);
+
+        let result = match self
+            .fetcher
+            .fetch(
+                &feed.url,
+                feed.etag.as_deref(),
+                feed.last_modified.as_deref(),
+            )
+            .await
+        {
+            Ok(FetchOutcome::Modified {
+                status,
+                body,
+                content_type,
+                etag,
+                last_modified,
+            }) => {
+                metrics::counter!("feed_fetches_total", "outcome" => "modified").increment(1);
+                tracing::debug!(bytes = body.len(), "Fetched feed document");
+
+                match self.store(&feed, &body, content_type.as_deref()).await {
+                    Ok(count) => {
+                        metrics::counter!("feed_entries_stored_total").increment(count as u64);
+                        tracing::debug!(count, "Stored feed entries");
+
+                        self.record_success(feed.id, status, etag, last_modified, jitter(interval))
+                            .await
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to store feed document");
+
+                        let delay = self.retry_delay(feed.fetch_failures);
+                        self.record_failure(feed.id, Some(status), &e.to_string(), delay)
+                            .await
+                    }
+                }
+            }
+            Ok(FetchOutcome::NotModified) => {
+                metrics::counter!("feed_fetches_total", "outcome" => "not_modified").increment(1);
+
+                self.record_success(
+                    feed.id,
+                    304,
+                    feed.etag,
+                    feed.last_modified,
+                    jitter(interval),
+                )
+                .await
+            }
+            Err(e) => {
+                metrics::counter!("feed_fetches_total", "outcome" => "failed").increment(1);
+                tracing::warn!(error = %e, failures = feed.fetch_failures + 1, "Feed fetch failed");
+
+                let delay = e
+                    .retry_after
+                    .map(|d| d.min(self.config.max_backoff))
+                    .unwrap_or_else(|| self.retry_delay(feed.fetch_failures));
+
+                self.record_failure(feed.id, e.status, &e.message, delay)
+                    .await
+            }
+        };
+
+        if let Err(e) = result {
+            tracing::error!(error = %e, "Failed to record feed fetch");
+        }
+    }
+
+    /// Parse a fetched document and store its entries, returning how many were new or changed.
+    async fn store(
+        &self,
+        feed: &DueFeed,
+        body: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let declared = match feed.feed_type.as_str() {
+            "rss" => FeedType::Rss,
+            "atom" => FeedType::Atom,
+            "json" => FeedType::Json,
+            "xml" => FeedType::Xml,
+            _ => FeedType::Unspecified,
+        };
+
+        let parsed = parser::parse(body, declared, content_type, Some(&feed.url))?;
+        let stored = EntryRepository::new(self.pool.clone())
+            .upsert(feed.id, &parsed.entries)
+            .await?;
+
+        Ok(stored.len())
+    }
+
+    /// Jittered backoff before retrying a feed that has already failed `failures` times.
+    fn retry_delay(&self, failures: i32) -> Duration {
+        jitter(backoff((failures + 1) as u32, self.config.max_backoff)).min(self.config.max_backoff)
+    }
+
+    async fn record_success(
+        &self,
+        id: Uuid,
+        status: u16,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        next_fetch_in: Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.feeds
+            SET etag = $2,
+                last_modified = $3,
+                last_fetch_time = now(),
+                last_fetch_status = $4,
+                last_fetch_error = NULL,
+                fetch_failures = 0,
+                next_fetch_time = now() + make_interval(secs => $5)
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(etag)
+        .bind(last_modified)
+        .bind(status as i32)
+        .bind(next_fetch_in.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_failure(
+        &self,
+        id: Uuid,
+        status: Option<u16>,
+        error: &str,
+        next_fetch_in: Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.feeds
+            SET last_fetch_time = now(),
+                last_fetch_status = $2,
+                last_fetch_error = $3,
+                fetch_failures = fetch_failures + 1,
+                next_fetch_time = now() + make_interval(secs => $4)
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.map(i32::from))
+        .bind(error)
+        .bind(next_fetch_in.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Exponential retry delay for a feed that has failed `failures` times in a row.
+fn backoff(failures: u32, max: Duration) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    BASE_RETRY_DELAY
+        .checked_mul(1 << exponent)
+        .map_or(max, |delay| delay.min(max))
+}
+
+/// Spread a delay by up to `JITTER_RATIO` in either direction so feeds do not poll in lockstep.
+fn jitter(delay: Duration) -> Duration {
+    let factor = rand::rng().random_range((1.0 - JITTER_RATIO)..=(1.0 + JITTER_RATIO));
+    delay.mul_f64(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::fetcher::tests::{serve, RSS};
+    use crate::ingest::UrlPolicy;
+    use axum::http::header::{ETAG, IF_NONE_MATCH, RETRY_AFTER};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, FromRow)]
+    struct FetchState {
+        fetch_failures: i32,
+        last_fetch_status: Option<i32>,
+        etag: Option<String>,
+        next_fetch_in: f64,
+    }
+
+    async fn fetch_state(pool: &PgPool, id: Uuid) -> FetchState {
+        sqlx::query_as(
+            r#"
+            SELECT fetch_failures, last_fetch_status, etag,
+                   EXTRACT(EPOCH FROM next_fetch_time - now())::float8 AS next_fetch_in
+            FROM public.feeds WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    /// Poll the feed now, whenever it was scheduled.
+    async fn poll_now(scheduler: &Scheduler, pool: &PgPool, id: Uuid) -> FetchState {
+        sqlx::query("UPDATE public.feeds SET next_fetch_time = now() WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+        assert_eq!(scheduler.poll_due().await.unwrap(), 1);
+        fetch_state(pool, id).await
+    }
+
+    fn assert_delay(state: &FetchState, secs: f64) {
+        let (min, max) = (
+            secs * (1.0 - JITTER_RATIO) - 1.0,
+            secs * (1.0 + JITTER_RATIO),
+        );
+        assert!(
+            (min..=max).contains(&state.next_fetch_in),
+            "next fetch in {}s, expected about {}s",
+            state.next_fetch_in,
+            secs
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let max = Duration::from_secs(3600);
+        assert_eq!(backoff(1, max), Duration::from_secs(60));
+        assert_eq!(backoff(2, max), Duration::from_secs(120));
+        assert_eq!(backoff(5, max), Duration::from_secs(960));
+        assert_eq!(backoff(7, max), max);
+        assert_eq!(backoff(u32::MAX, max), max);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn poll_backs_off_on_failures_and_recovers(pool: PgPool) {
+        // The stand-in answers with the status in `mode`, serving the document on 200.
+        let mode = Arc::new(AtomicU16::new(500));
+        let status = mode.clone();
+        let app = Router::new().route(
+            "/feed.xml",
+            get(move |headers: HeaderMap| async move {
+                let response: Response = match status.load(Ordering::SeqCst) {
+                    200 if headers.get(IF_NONE_MATCH).is_some_and(|v| v == "\"v1\"") => {
+                        StatusCode::NOT_MODIFIED.into_response()
+                    }
+                    200 => ([(ETAG, "\"v1\"")], RSS).into_response(),
+                    503 => (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, "30")]).into_response(),
+                    code => StatusCode::from_u16(code).unwrap().into_response(),
+                };
+                response
+            }),
+        );
+        let url = format!("{}/feed.xml", serve(app).await);
+
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO public.feeds (name, url, type, visibility, poll_interval_secs)
+            VALUES ('Example', $1, 'rss', 'public', 600)
+            RETURNING id
+            "#,
+        )
+        .bind(&url)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let scheduler = Scheduler::new(
+            pool.clone(),
+            Fetcher::new(Duration::from_secs(5), UrlPolicy::new(true)).unwrap(),
+            SchedulerConfig {
+                tick: Duration::from_secs(1),
+                concurrency: 4,
+                max_backoff: Duration::from_secs(3600),
+                lease: Duration::from_secs(10),
+            },
+        );
+
+        let state = poll_now(&scheduler, &pool, id).await;
+        assert_eq!(
+            (state.fetch_failures, state.last_fetch_status),
+            (1, Some(500))
+        );
+        assert_delay(&state, 60.0);
+
+        let state = poll_now(&scheduler, &pool, id).await;
+        assert_eq!(
+            (state.fetch_failures, state.last_fetch_status),
+            (2, Some(500))
+        );
+        assert_delay(&state, 120.0);
+
+        // Retry-After takes precedence over the backoff.
+        mode.store(503, Ordering::SeqCst);
+        let state = poll_now(&scheduler, &pool, id).await;
+        assert_eq!(
+            (state.fetch_failures, state.last_fetch_status),
+            (3, Some(503))
+        );
+        assert!((29.0..=30.0).contains(&state.next_fetch_in));
+
+        mode.store(200, Ordering::SeqCst);
+        let state = poll_now(&scheduler, &pool, id).await;
+        assert_eq!(
+            (state.fetch_failures, state.last_fetch_status),
+            (0, Some(200))
+        );
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+        assert_delay(&state, 600.0);
+
+        // The stored etag turns the next poll into a conditional request.
+        let state = poll_now(&scheduler, &pool, id).await;
+        assert_eq!(
+            (state.fetch_failures, state.last_fetch_status),
+            (0, Some(304))
+        );
+        assert_delay(&state, 600.0);
+
+        let entries: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM public.feed_entries WHERE feed_id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(entries, 1);
+    }
+}
@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use url::{Host, Url};
+
+/// Decides which feed URLs the server may fetch. Feed URLs are chosen by users, so by default
+/// only http(s) URLs of hosts on the public internet are allowed, which keeps the fetcher from
+/// being pointed at the server's own network or a cloud metadata endpoint.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UrlPolicy {
+    allow_private_networks: bool,
+}
+
+#[derive(Debug)]
+pub struct UrlError {
+    pub message: String,
+}
+
+impl UrlError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for UrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+impl UrlPolicy {
+    /// A policy that also allows loopback and private addresses, for self-hosted setups that
+    /// poll feeds on their own network.
+    pub fn new(allow_private_networks: bool) -> Self {
+        Self {
+            allow_private_networks,
+        }
+    }
+
+    /// Parse a feed URL and check everything that can be checked without resolving its host.
+    /// Hostnames are checked again on every request by the fetcher's resolver.
+    pub fn check(&self, url: &str) -> Result<Url, UrlError> {
+        let url = Url::parse(url).map_err(|e| UrlError::new(format!("Invalid url: {}", e)))?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(UrlError::new("Feed url must use http or https"));
+        }
+
+        let allowed = match url.host() {
+            None => return Err(UrlError::new("Feed url must have a host")),
+            Some(Host::Ipv4(ip)) => self.allows(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => self.allows(IpAddr::V6(ip)),
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.');
+                self.allow_private_networks
+                    || !(domain.eq_ignore_ascii_case("localhost")
+                        || domain.to_ascii_lowercase().ends_with(".localhost"))
+            }
+        };
+        if !allowed {
+            return Err(UrlError::new(
+                "Feed url must not point to a private network",
+            ));
+        }
+
+        Ok(url)
+    }
+
+    /// Whether the fetcher may connect to `ip`.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allow_private_networks || is_public(ip)
+    }
+}
+
+/// Resolves hostnames for the fetcher, dropping the addresses the policy does not allow. As
+/// every connection goes through it, this also covers redirects and DNS records that change
+/// after a feed was registered.
+#[derive(Debug)]
+pub(super) struct PolicyResolver {
+    pub(super) policy: UrlPolicy,
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| policy.allows(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(UrlError::new(format!(
+                    "{} does not resolve to a public address",
+                    host
+                ))
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address. Loopback, private, link-local
+/// (including the 169.254.169.254 metadata endpoint), shared, documentation and reserved
+/// ranges are not.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // 0.0.0.0/8, "this network"
+        || a == 0
+        // 100.64.0.0/10, shared address space for carrier-grade NAT
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24, IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15, benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4, reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped (::ffff:0:0/96) and NAT64 (64:ff9b::/96) addresses reach IPv4 hosts.
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7, unique local addresses
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10, link-local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32, documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // ::/96, deprecated IPv4-compatible addresses
+        || segments[..6] == [0; 6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_public_rejects_internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(
+                !is_public(ip.parse().unwrap()),
+                "{} should not be public",
+                ip
+            );
+        }
+
+        for ip in [
+            "93.184.215.14",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn check_accepts_public_http_urls_only() {
+        let policy = UrlPolicy::default();
+
+        assert!(policy.check("https://example.com/feed.xml").is_ok());
+        assert!(policy.check("http://93.184.215.14/rss").is_ok());
+
+        for url in [
+            "",
+            "example.com/feed.xml",
+            "file:///etc/passwd",
+            "ftp://example.com/feed.xml",
+            "gopher://example.com",
+            "http://localhost:8080/feed.xml",
+            "http://feeds.localhost./feed.xml",
+            "http://127.0.0.1/feed.xml",
+            "http://[::1]/feed.xml",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::ffff:10.0.0.1]/feed.xml",
+            "http://2130706433/feed.xml",
+        ] {
+            assert!(policy.check(url).is_err(), "{} should be rejected", url);
+        }
+    }
+
+    #[test]
+    fn check_allows_private_networks_when_configured() {
+        let policy = UrlPolicy::new(true);
+
+        assert!(policy.check("http://localhost:8080/feed.xml").is_ok());
+        assert!(policy.check("http://10.0.0.5/feed.xml").is_ok());
+        assert!(policy.check("file:///etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_drops_private_addresses() {
+        let resolver = PolicyResolver {
+            policy: UrlPolicy::default(),
+        };
+        let err = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .err()
+            .expect("localhost must not resolve");
+        assert!(err.to_string().contains("public address"));
+
+        let resolver = PolicyResolver {
+            policy: UrlPolicy::new(true),
+        };
+        let addrs: Vec<_> = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+}
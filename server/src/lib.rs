@@ -1,8 +1,19 @@
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod auth;
 pub mod config;
+mod descriptor;
+pub mod error;
+pub mod gateway;
+pub mod ingest;
+pub mod mail;
 pub mod meta;
+pub mod validate;
 
+use auth::TokenVerifier;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::task::futures::TaskLocalFuture;
 use tonic::{metadata::AsciiMetadataValue, Request};
 use tracing::debug;
 use tracing::{Event, Level, Subscriber};
@@ -29,18 +40,99 @@ fn call(&mut self, mut req: Request<()>) -> InterceptResult<()> {
     }
 }
 
+tokio::task_local! {
+    static TRACE_ID: String;
+}
+
+/// The trace id of the request being served, when called within a `TraceScope`.
+pub fn current_trace_id() -> Option<String> {
+    TRACE_ID
+        .try_with(|id| id.clone())
+        .ok()
+        .filter(|id| !id.is_empty())
+}
+
+/// Makes the `x-trace-id` set by `TraceInterceptor` available to handlers through
+/// `current_trace_id`, so that errors can report it. It must be layered inside the interceptor.
 #[derive(Clone, Debug, Default)]
-pub struct TokenInterceptor;
+pub struct TraceScopeLayer;
+
+impl<S> tower::Layer<S> for TraceScopeLayer {
+    type Service = TraceScope<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceScope { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceScope<S> {
+    inner: S,
+}
+
+impl<S, B> tower::Service<http::Request<B>> for TraceScope<S>
+where
+    S: tower::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TaskLocalFuture<String, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let trace_id = req
+            .headers()
+            .get("x-trace-id")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        TRACE_ID.scope(trace_id, self.inner.call(req))
+    }
+}
+
+/// Verifies the bearer token of every request and records the caller as an `auth::Principal`
+/// in the request extensions.
+#[derive(Clone, Debug)]
+pub struct TokenInterceptor {
+    verifier: Arc<TokenVerifier>,
+}
+
+impl TokenInterceptor {
+    pub fn new(verifier: TokenVerifier) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+        }
+    }
+}
 
 impl tonic::service::Interceptor for TokenInterceptor {
-    #[tracing::instrument]
-    fn call(&mut self, req: Request<()>) -> InterceptResult<()> {
-        match req.metadata().get("authorization") {
-            Some(token) => {
-                debug!("Authorization token: {:?}", token);
+    #[tracing::instrument(skip_all)]
+    fn call(&mut self, mut req: Request<()>) -> InterceptResult<()> {
+        let token = req
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                value
+                    .split_once(' ')
+                    .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+                    .map(|(_, token)| token.trim())
+            })
+            .ok_or_else(|| tonic::Status::unauthenticated("Missing bearer token"))?;
+
+        match self.verifier.verify(token) {
+            Ok(principal) => {
+                debug!(user_uid = %principal.user_uid, "Authenticated request");
+                req.extensions_mut().insert(principal);
                 Ok(req)
             }
-            _ => Err(tonic::Status::unauthenticated("invalid token")),
+            Err(e) => {
+                debug!(error = %e, "Rejected access token");
+                Err(tonic::Status::unauthenticated(e.to_string()))
+            }
         }
     }
 }
@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outgoing email.
+
+use crate::config::AppConfig;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+#[derive(Debug)]
+pub enum MailError {
+    /// The SMTP settings in the configuration are invalid.
+    Config(String),
+    /// The message could not be built, e.g. because of an invalid recipient.
+    Message(String),
+    Send(String),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::Config(message) => write!(f, "Invalid mail configuration: {}", message),
+            MailError::Message(message) => write!(f, "Invalid message: {}", message),
+            MailError::Send(message) => write!(f, "Failed to send email: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Sends email through the SMTP relay in `SMTP_URL`. Without a relay, messages are dropped
+/// with a warning so development setups keep working.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl std::fmt::Debug for Mailer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mailer")
+            .field("enabled", &self.transport.is_some())
+            .field("from", &self.from.to_string())
+            .finish()
+    }
+}
+
+impl Mailer {
+    pub fn from_config(config: &AppConfig) -> Result<Self, MailError> {
+        let from = config
+            .smtp_from
+            .parse::<Mailbox>()
+            .map_err(|e| MailError::Config(format!("SMTP_FROM: {}", e)))?;
+
+        let transport = config
+            .smtp_url
+            .as_deref()
+            .map(|url| {
+                AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+                    .map(|builder| builder.build())
+                    .map_err(|e| MailError::Config(format!("SMTP_URL: {}", e)))
+            })
+            .transpose()?;
+
+        Ok(Self { transport, from })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Send a plain text email.
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), MailError> {
+        let Some(transport) = &self.transport else {
+            tracing::warn!(subject, "SMTP_URL is not set, dropping email");
+            return Ok(());
+        };
+
+        let to = to
+            .parse::<Mailbox>()
+            .map_err(|e| MailError::Message(e.to_string()))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body)
+            .map_err(|e| MailError::Message(e.to_string()))?;
+
+        transport
+            .send(message)
+            .await
+            .map_err(|e| MailError::Send(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use clap::Parser;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// A received email.
+    #[derive(Debug)]
+    pub(crate) struct Captured {
+        pub to: String,
+        pub subject: String,
+        pub body: String,
+    }
+
+    impl Captured {
+        /// The `token` query parameter of the first link in the body.
+        pub(crate) fn link_token(&self) -> String {
+            let start = self.body.find("token=").expect("no token link in email") + 6;
+            self.body[start..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                .collect()
+        }
+    }
+
+    /// A local SMTP server that accepts every message and hands it to the test.
+    pub(crate) struct SmtpCapture {
+        pub url: String,
+        messages: mpsc::UnboundedReceiver<Captured>,
+    }
+
+    impl SmtpCapture {
+        pub(crate) async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("smtp://{}", listener.local_addr().unwrap());
+            let (sender, messages) = mpsc::unbounded_channel();
+
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(Self::serve(stream, sender.clone()));
+                }
+            });
+
+            Self { url, messages }
+        }
+
+        async fn serve(stream: tokio::net::TcpStream, sender: mpsc::UnboundedSender<Captured>) {
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let _ = write.write_all(b"220 localhost ESMTP\r\n").await;
+
+            let mut to = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let command = line.to_ascii_uppercase();
+                let reply: &[u8] = if command.starts_with("RCPT TO:") {
+                    to = line[8..].trim().trim_matches(['<', '>']).to_string();
+                    b"250 OK\r\n"
+                } else if command.starts_with("DATA") {
+                    let _ = write.write_all(b"354 End data with .\r\n").await;
+                    let mut data = Vec::new();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line == "." {
+                            break;
+                        }
+                        data.push(line.strip_prefix('.').map(str::to_string).unwrap_or(line));
+                    }
+                    let _ = sender.send(Self::parse(std::mem::take(&mut to), &data));
+                    b"250 OK\r\n"
+                } else if command.starts_with("QUIT") {
+                    let _ = write.write_all(b"221 Bye\r\n").await;
+                    return;
+                } else {
+                    b"250 OK\r\n"
+                };
+                if write.write_all(reply).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        fn parse(to: String, data: &[String]) -> Captured {
+            let split = data.iter().position(String::is_empty).unwrap_or(data.len());
+            let (headers, body) = data.split_at(split);
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find_map(|h| h.strip_prefix(name)?.strip_prefix(": "))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            let body = body.iter().skip(1).cloned().collect::<Vec<_>>().join("\n");
+            let body = if header("Content-Transfer-Encoding") == "quoted-printable" {
+                decode_quoted_printable(&body)
+            } else {
+                body
+            };
+
+            Captured {
+                to,
+                subject: header("Subject"),
+                body,
+            }
+        }
+
+        /// The next email, failing the test if none arrives within a few seconds.
+        pub(crate) async fn next(&mut self) -> Captured {
+            tokio::time::timeout(std::time::Duration::from_secs(5), self.messages.recv())
+                .await
+                .expect("no email was sent")
+                .unwrap()
+        }
+
+        /// Fail the test if an email arrives within a short while.
+        pub(crate) async fn assert_none(&mut self) {
+            let wait = std::time::Duration::from_millis(300);
+            if let Ok(Some(mail)) = tokio::time::timeout(wait, self.messages.recv()).await {
+                panic!("unexpected email: {:?}", mail);
+            }
+        }
+    }
+
+    fn decode_quoted_printable(body: &str) -> String {
+        let joined = body.replace("=\n", "");
+        let mut decoded = Vec::new();
+        let mut bytes = joined.bytes();
+        while let Some(b) = bytes.next() {
+            if b == b'=' {
+                let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                decoded.push(u8::from_str_radix(&hex, 16).unwrap_or_default());
+            } else {
+                decoded.push(b);
+            }
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+
+    /// A configuration that sends email through `capture`, with links to example.com.
+    pub(crate) fn config(capture: &SmtpCapture) -> AppConfig {
+        AppConfig::try_parse_from([
+            "geist-server",
+            "--jwt-secret",
+            "a-test-secret-that-is-long-enough-for-hs256",
+            "--smtp-url",
+            &capture.url,
+            "--password-reset-url",
+            "https://geist.example.com/reset",
+            "--email-verification-url",
+            "https://geist.example.com/verify",
+        ])
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_through_the_relay() {
+        let mut capture = SmtpCapture::start().await;
+        let body = format!("A line long enough to be wrapped: {}\n", "x=y ".repeat(30));
+
+        Mailer::from_config(&config(&capture))
+            .unwrap()
+            .send("alice@example.com", "Hello", body.clone())
+            .await
+            .unwrap();
+
+        let mail = capture.next().await;
+        assert_eq!(mail.to, "alice@example.com");
+        assert_eq!(mail.subject, "Hello");
+        assert_eq!(mail.body.trim_end(), body.trim_end());
+    }
+}
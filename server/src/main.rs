@@ -1,23 +1,46 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use geist_server::{
-    config::AppConfig,
-    meta::{FeedServer, GroupServer, IdentityServer, UserServer},
+    auth::{
+        self, reencrypt_identity_tokens, AuthServer, OAuthLogin, PasswordReset, TokenCipher,
+        TokenRefresher, TokenSigner, TokenVerifier,
+    },
+    config::{AppConfig, Command},
+    gateway::{self, Gateway, Schema},
+    ingest::{Fetcher, Scheduler, SchedulerConfig},
+    mail::Mailer,
+    meta::{
+        AuditServer, EntryHub, EntryServer, FeedServer, GroupServer, IdentityServer,
+        Paginator, SubscriptionServer, UserRepository, UserServer, VisibilityPolicy,
+    },
     tracing_metrics_layer,
+    validate::{ValidateLayer, Validator},
+    TokenInterceptor, TraceInterceptor, TraceScopeLayer,
 };
 
 use geist_sdk::pb::meta::v1alpha::{
+    audit_service_server::AuditServiceServer,
+    auth_service_server::AuthServiceServer,
+    entry_service_server::EntryServiceServer,
     feed_service_server::FeedServiceServer,
     group_service_server::GroupServiceServer,
     identity_service_server::IdentityServiceServer,
+    subscription_service_server::SubscriptionServiceServer,
     user_service_server::UserServiceServer,
 };
 
 use dotenvy::dotenv;
 use metrics_exporter_prometheus::PrometheusBuilder;
+use sqlx::ConnectOptions;
 use std::error::Error;
 use std::str::FromStr;
+use std::sync::Arc;
+use tonic::body::Body;
+use tonic::service::{InterceptorLayer, Routes};
 use tonic::transport::Server;
+use tonic_web::GrpcWebLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::map_response_body::MapResponseBodyLayer;
 use tracing_subscriber::prelude::*;
 
 #[tokio::main]
@@ -87,22 +110,217 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     tracing::info!("Database migrations completed");
 
+    let cipher = TokenCipher::from_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to load token cipher: {}", e))?
+        .map(Arc::new);
+
+    if let Some(Command::ReencryptTokens) = config.command {
+        let cipher = cipher.ok_or_else(|| {
+            anyhow::anyhow!("OAUTH_ENCRYPTION_KEY is required to re-encrypt tokens")
+        })?;
+
+        tracing::info!(key_id = cipher.current_key_id(), "Re-encrypting identity tokens...");
+        let updated = reencrypt_identity_tokens(&pool, &cipher)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to re-encrypt tokens: {}", e))?;
+        tracing::info!(updated, "Identity tokens re-encrypted");
+
+        return Ok(());
+    }
+
+    if let Some(Command::SetAdmin { username, revoke }) = &config.command {
+        let user_id = UserRepository::new(pool.clone())
+            .set_admin(username, !revoke)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update user: {}", e))?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", username))?;
+        tracing::info!(user_uid = %user_id, is_admin = !revoke, "Updated admin rights");
+
+        return Ok(());
+    }
+
+    // Start polling registered feeds in the background
+    if config.fetch_enabled {
+        let fetcher = Fetcher::new(config.fetch_timeout(), config.url_policy())?;
+        let scheduler = Scheduler::new(
+            pool.clone(),
+            fetcher,
+            SchedulerConfig {
+                tick: config.fetch_tick(),
+                concurrency: config.fetch_concurrency,
+                max_backoff: config.fetch_max_backoff(),
+                lease: config.fetch_timeout() * 2,
+            },
+        );
+
+        tracing::info!(tick = ?config.fetch_tick(), "Starting feed scheduler");
+        tokio::spawn(scheduler.run());
+    }
+
+    // Keep stored provider tokens fresh; they can only be stored with a cipher configured
+    if config.oauth_token_refresh_enabled {
+        if let Some(cipher) = cipher.clone() {
+            let refresher = TokenRefresher::from_config(&config, pool.clone(), cipher)
+                .map_err(|e| anyhow::anyhow!("Failed to configure token refresh: {}", e))?;
+
+            tracing::info!(
+                tick = ?config.oauth_token_refresh_tick(),
+                "Starting OAuth token refresh"
+            );
+            tokio::spawn(refresher.run());
+        }
+    }
+
+    // Push newly ingested entries to WatchEntries streams
+    let hub = EntryHub::new(1024);
+    tokio::spawn(hub.clone().run(pool.clone()));
+
+    // Every service requires a valid access token
+    let verifier = TokenVerifier::from_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to load token verifier: {}", e))?;
+    let auth = TokenInterceptor::new(verifier);
+    let signer = TokenSigner::from_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to load token signer: {}", e))?;
+
     // Create service instances with database pool
-    let svc1 = UserServiceServer::new(UserServer::default());
-    let svc2 = FeedServiceServer::new(FeedServer::default());
-    let svc3 = GroupServiceServer::new(GroupServer::default());
-    let svc4 = IdentityServiceServer::new(IdentityServer::new(pool.clone()));
+    let visibility = VisibilityPolicy::new(config.environment);
+    let urls = config.url_policy();
+    if config.page_etag_secret.is_none() {
+        tracing::warn!("PAGE_ETAG_SECRET is not set, page etags will not survive a restart");
+    }
+    // Password reset and email verification tokens are sent by email
+    let mailer = Mailer::from_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to configure email: {}", e))?;
+    if !mailer.is_enabled() {
+        tracing::warn!("SMTP_URL is not set, password reset and verification emails will not be sent");
+    }
+    let password_reset = PasswordReset::from_config(&config, mailer)
+        .map(Arc::new)
+        .map_err(|e| anyhow::anyhow!("Failed to configure password reset: {}", e))?;
 
-    tracing::info!(address = %config.grpc_address, "Starting gRPC server");
+    let pages = Paginator::from_config(&config);
+    let svc1 = UserServiceServer::with_interceptor(
+        UserServer::new(pool.clone(), pages.clone()),
+        auth.clone(),
+    );
+    let svc2 = FeedServiceServer::with_interceptor(
+        FeedServer::new(pool.clone(), visibility, urls, pages.clone()),
+        auth.clone(),
+    );
+    let svc3 = GroupServiceServer::with_interceptor(
+        GroupServer::new(pool.clone(), visibility, pages.clone()),
+        auth.clone(),
+    );
+    let svc4 = IdentityServiceServer::with_interceptor(
+        IdentityServer::new(pool.clone(), pages.clone(), password_reset.clone()),
+        auth.clone(),
+    );
+    let svc5 = EntryServiceServer::with_interceptor(
+        EntryServer::new(pool.clone(), hub, visibility, pages.clone()),
+        auth.clone(),
+    );
+    let svc7 = AuditServiceServer::with_interceptor(
+        AuditServer::new(pool.clone(), pages.clone()),
+        auth.clone(),
+    );
+    let svc8 = SubscriptionServiceServer::with_interceptor(
+        SubscriptionServer::new(pool.clone(), visibility, urls, pages),
+        auth,
+    );
 
-    Server::builder()
-        .trace_fn(|_| tracing::info_span!("geist-server"))
-        .add_service(svc1)
+    // Token refresh and password sign-in are authenticated by the request itself
+    let auth_server = AuthServer::new(
+        pool.clone(),
+        signer,
+        config.refresh_token_ttl(),
+        password_reset,
+    );
+    let svc6 = AuthServiceServer::new(auth_server.clone());
+
+    // Sign-in through OAuth providers is served over HTTP
+    let login = OAuthLogin::from_config(&config, pool.clone(), cipher, auth_server)
+        .map_err(|e| anyhow::anyhow!("Failed to configure OAuth sign-in: {}", e))?;
+    let providers: Vec<_> = login.providers().collect();
+
+    // Requests are checked against the validation rules of their protobufs
+    let validator = Validator::from_descriptor_set(geist_sdk::pb::FILE_DESCRIPTOR_SET)
+        .map_err(|e| anyhow::anyhow!("Failed to load validation rules: {}", e))?;
+    let middleware = tower::ServiceBuilder::new()
+        .layer(InterceptorLayer::new(TraceInterceptor))
+        .layer(TraceScopeLayer)
+        .layer(ValidateLayer::new(validator));
+
+    let routes = Routes::new(svc1)
         .add_service(svc2)
         .add_service(svc3)
         .add_service(svc4)
+        .add_service(svc5)
+        .add_service(svc6)
+        .add_service(svc7)
+        .add_service(svc8);
+
+    // The JSON gateway calls the same services, through the same layers
+    let schema = Schema::from_descriptor_set(geist_sdk::pb::FILE_DESCRIPTOR_SET)
+        .map_err(|e| anyhow::anyhow!("Failed to load the JSON mapping: {}", e))?;
+    let gateway = Gateway::new(middleware.clone().service(routes.clone()), schema);
+
+    let router = auth::router(Arc::new(login)).merge(gateway::router(gateway));
+    let listener = tokio::net::TcpListener::bind(config.http_address).await?;
+
+    tracing::info!(address = %config.http_address, ?providers, "Starting HTTP server");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::error!(error = %e, "HTTP server failed");
+        }
+    });
+
+    // Browsers call the services with gRPC-Web, from the allowed origins only
+    let grpc_web = config.grpc_web_enabled.then(|| {
+        tracing::info!(origins = ?config.grpc_web_allowed_origins, "Enabling gRPC-Web");
+        tower::ServiceBuilder::new()
+            .layer(grpc_web_cors(&config.grpc_web_allowed_origins))
+            .layer(GrpcWebLayer::new())
+    });
+
+    tracing::info!(address = %config.grpc_address, "Starting gRPC server");
+
+    Server::builder()
+        .accept_http1(config.grpc_web_enabled)
+        .trace_fn(|_| tracing::info_span!("geist-server"))
+        .layer(tower::util::option_layer(grpc_web))
+        .layer(MapResponseBodyLayer::new(Body::new))
+        .layer(middleware)
+        .add_routes(routes)
         .serve(config.grpc_address)
         .await?;
 
     Ok(())
 }
+
+/// CORS for gRPC-Web requests from `origins`, which are validated by `AppConfig::validate`.
+fn grpc_web_cors(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|origin| origin.parse().ok()))
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([http::Method::POST])
+        .allow_headers([
+            http::header::AUTHORIZATION,
+            http::header::CONTENT_TYPE,
+            http::HeaderName::from_static("grpc-timeout"),
+            http::HeaderName::from_static("x-grpc-web"),
+            http::HeaderName::from_static("x-user-agent"),
+            http::HeaderName::from_static("x-trace-id"),
+        ])
+        .expose_headers([
+            http::HeaderName::from_static("grpc-status"),
+            http::HeaderName::from_static("grpc-message"),
+            http::HeaderName::from_static("grpc-status-details-bin"),
+            http::HeaderName::from_static("x-trace-id"),
+        ])
+        .max_age(std::time::Duration::from_secs(24 * 60 * 60))
+}
@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::{from_timestamp, parse_uid, to_timestamp};
+use crate::auth::{self, Principal};
+use crate::error::GeistError;
+use crate::ServerResult;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::{
+    audit_service_server::AuditService, AuditAction as ProtoAuditAction, AuditEvent,
+    AuditEventResponse, ListAuditEventsRequest,
+};
+use sqlx::types::ipnetwork::IpNetwork;
+use sqlx::{FromRow, PgPool};
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+/// A security-relevant change recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    IdentityLinked,
+    IdentityUnlinked,
+    PrimaryIdentityChanged,
+    PasswordRegistered,
+    PasswordChanged,
+    PasswordReset,
+}
+
+impl AuditAction {
+    /// The label stored in `audit_events.action`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::IdentityLinked => "identity.linked",
+            AuditAction::IdentityUnlinked => "identity.unlinked",
+            AuditAction::PrimaryIdentityChanged => "identity.primary_changed",
+            AuditAction::PasswordRegistered => "password.registered",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+        }
+    }
+
+    fn to_proto(label: &str) -> i32 {
+        let action = match label {
+            "identity.linked" => ProtoAuditAction::IdentityLinked,
+            "identity.unlinked" => ProtoAuditAction::IdentityUnlinked,
+            "identity.primary_changed" => ProtoAuditAction::PrimaryIdentityChanged,
+            "password.registered" => ProtoAuditAction::PasswordRegistered,
+            "password.changed" => ProtoAuditAction::PasswordChanged,
+            "password.reset" => ProtoAuditAction::PasswordReset,
+            _ => ProtoAuditAction::Unspecified,
+        };
+        action as i32
+    }
+
+    fn from_proto(action: i32) -> Result<Option<Self>, Status> {
+        match ProtoAuditAction::try_from(action) {
+            Ok(ProtoAuditAction::Unspecified) => Ok(None),
+            Ok(ProtoAuditAction::IdentityLinked) => Ok(Some(AuditAction::IdentityLinked)),
+            Ok(ProtoAuditAction::IdentityUnlinked) => Ok(Some(AuditAction::IdentityUnlinked)),
+            Ok(ProtoAuditAction::PrimaryIdentityChanged) => {
+                Ok(Some(AuditAction::PrimaryIdentityChanged))
+            }
+            Ok(ProtoAuditAction::PasswordRegistered) => Ok(Some(AuditAction::PasswordRegistered)),
+            Ok(ProtoAuditAction::PasswordChanged) => Ok(Some(AuditAction::PasswordChanged)),
+            Ok(ProtoAuditAction::PasswordReset) => Ok(Some(AuditAction::PasswordReset)),
+            Err(_) => Err(Status::invalid_argument("Invalid audit action")),
+        }
+    }
+}
+
+/// Who made a request and from where, captured for the audit log before the request is
+/// consumed.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor_user_id: Option<Uuid>,
+    pub ip_address: Option<IpNetwork>,
+    /// The `x-trace-id` set by `TraceInterceptor`.
+    pub trace_id: Option<String>,
+}
+
+impl AuditContext {
+    pub fn from_request<T>(request: &Request<T>) -> Self {
+        Self {
+            actor_user_id: request
+                .extensions()
+                .get::<Principal>()
+                .map(|principal| principal.user_uid),
+            ip_address: request.remote_addr().map(|addr| IpNetwork::from(addr.ip())),
+            trace_id: request
+                .metadata()
+                .get("x-trace-id")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+
+    /// Record an event. The change it describes has already been made, so a failure to
+    /// record it is logged rather than failing the request.
+    pub async fn record(
+        &self,
+        pool: &PgPool,
+        action: AuditAction,
+        target_user_id: Uuid,
+        identity_id: Option<Uuid>,
+    ) {
+        if let Err(e) = AuditRepository::new(pool.clone())
+            .create(self, action, target_user_id, identity_id)
+            .await
+        {
+            tracing::error!(
+                error = %e,
+                action = action.as_str(),
+                target_user_uid = %target_user_id,
+                "Failed to record audit event"
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditEventRecord {
+    pub id: Uuid,
+    pub actor_user_id: Option<Uuid>,
+    pub target_user_id: Uuid,
+    pub action: String,
+    pub identity_id: Option<Uuid>,
+    pub ip_address: Option<IpNetwork>,
+    pub trace_id: Option<String>,
+    pub create_time: DateTime<Utc>,
+}
+
+impl AuditEventRecord {
+    fn to_proto(&self) -> AuditEvent {
+        AuditEvent {
+            uid: self.id.to_string(),
+            actor_user_uid: self
+                .actor_user_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            target_user_uid: self.target_user_id.to_string(),
+            action: AuditAction::to_proto(&self.action),
+            identity_uid: self
+                .identity_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            ip_address: self
+                .ip_address
+                .map(|ip| ip.ip().to_string())
+                .unwrap_or_default(),
+            trace_id: self.trace_id.clone().unwrap_or_default(),
+            create_time: Some(to_timestamp(self.create_time)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub target_user_id: Option<Uuid>,
+    pub actor_user_id: Option<Uuid>,
+    pub action: Option<AuditAction>,
+    pub identity_id: Option<Uuid>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    fn page_scope(&self) -> PageScope {
+        PageScope::new("audit")
+            .with_opt("target", self.target_user_id)
+            .with_opt("actor", self.actor_user_id)
+            .with_opt("action", self.action.map(|action| action.as_str()))
+            .with_opt("identity", self.identity_id)
+            .with_opt("start", self.start_time.map(|t| t.to_rfc3339()))
+            .with_opt("end", self.end_time.map(|t| t.to_rfc3339()))
+    }
+}
+
+pub struct AuditRepository {
+    pool: PgPool,
+}
+
+impl AuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        context: &AuditContext,
+        action: AuditAction,
+        target_user_id: Uuid,
+        identity_id: Option<Uuid>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO public.audit_events
+                (id, actor_user_id, target_user_id, action, identity_id, ip_address, trace_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(context.actor_user_id)
+        .bind(target_user_id)
+        .bind(action.as_str())
+        .bind(identity_id)
+        .bind(context.ip_address)
+        .bind(&context.trace_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list(
+        &self,
+        filter: &AuditFilter,
+        page: &PageRequest,
+    ) -> Result<Vec<AuditEventRecord>, sqlx::Error> {
+        sqlx::query_as::<_, AuditEventRecord>(
+            r#"
+            SELECT id, actor_user_id, target_user_id, action, identity_id, ip_address, trace_id,
+                   create_time
+            FROM public.audit_events
+            WHERE ($1::uuid IS NULL OR target_user_id = $1)
+              AND ($2::uuid IS NULL OR actor_user_id = $2)
+              AND ($3::text IS NULL OR action = $3)
+              AND ($4::uuid IS NULL OR identity_id = $4)
+              AND ($5::timestamptz IS NULL OR create_time >= $5)
+              AND ($6::timestamptz IS NULL OR create_time < $6)
+              AND ($7::timestamptz IS NULL OR (create_time, id) < ($7, $8))
+            ORDER BY create_time DESC, id DESC
+            OFFSET $9
+            LIMIT $10
+            "#,
+        )
+        .bind(filter.target_user_id)
+        .bind(filter.actor_user_id)
+        .bind(filter.action.map(|a| a.as_str()))
+        .bind(filter.identity_id)
+        .bind(filter.start_time)
+        .bind(filter.end_time)
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, filter: &AuditFilter) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM public.audit_events
+            WHERE ($1::uuid IS NULL OR target_user_id = $1)
+              AND ($2::uuid IS NULL OR actor_user_id = $2)
+              AND ($3::text IS NULL OR action = $3)
+              AND ($4::uuid IS NULL OR identity_id = $4)
+              AND ($5::timestamptz IS NULL OR create_time >= $5)
+              AND ($6::timestamptz IS NULL OR create_time < $6)
+            "#,
+        )
+        .bind(filter.target_user_id)
+        .bind(filter.actor_user_id)
+        .bind(filter.action.map(|a| a.as_str()))
+        .bind(filter.identity_id)
+        .bind(filter.start_time)
+        .bind(filter.end_time)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuditServer {
+    pool: Option<PgPool>,
+    pages: Paginator,
+}
+
+impl AuditServer {
+    pub fn new(pool: PgPool, pages: Paginator) -> Self {
+        Self {
+            pool: Some(pool),
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+}
+
+#[tonic::async_trait]
+impl AuditService for AuditServer {
+    #[tracing::instrument(skip(self))]
+    async fn list_audit_events(
+        &self,
+        request: Request<ListAuditEventsRequest>,
+    ) -> ServerResult<AuditEventResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = AuditRepository::new(self.pool()?.clone());
+
+        let target_user_id = parse_uid(&req.target_user_uid)?.unwrap_or(principal.user_uid);
+        principal.authorize_user(target_user_id)?;
+
+        let filter = AuditFilter {
+            target_user_id: Some(target_user_id),
+            actor_user_id: parse_uid(&req.actor_user_uid)?,
+            action: AuditAction::from_proto(req.action)?,
+            identity_id: parse_uid(&req.identity_uid)?,
+            start_time: req.start_time.as_ref().map(from_timestamp).transpose()?,
+            end_time: req.end_time.as_ref().map(from_timestamp).transpose()?,
+        };
+
+        if let (Some(start), Some(end)) = (filter.start_time, filter.end_time) {
+            if start >= end {
+                return Err(Status::invalid_argument(
+                    "start_time must be before end_time",
+                ));
+            }
+        }
+
+        let page = self
+            .pages
+            .request(req.page.as_ref(), &filter.page_scope())?;
+
+        let events = repo.list(&filter, &page).await.map_err(GeistError::from)?;
+
+        let total = repo.count(&filter).await.map_err(GeistError::from)?;
+        let (events, page) = self.pages.response(&page, events, total, |event| {
+            Position::new(event.create_time, event.id)
+        });
+
+        Ok(tonic::Response::new(AuditEventResponse {
+            events: events.iter().map(AuditEventRecord::to_proto).collect(),
+            page: Some(page),
+        }))
+    }
+}
@@ -0,0 +1,903 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::feed::FeedRepository;
+use super::member::{authorize_role, GroupMemberRepository, GroupRole};
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::subscription::SubscriptionRepository;
+use super::visibility::{Viewer, VisibilityPolicy};
+use super::{from_timestamp, parse_uid, to_timestamp};
+use crate::auth;
+use crate::error::GeistError;
+use crate::ingest::Entry as ParsedEntry;
+use crate::ServerResult;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::{
+    entry::{Author, Enclosure},
+    entry_service_server::EntryService,
+    Entry, EntryRequest, EntryResponse, ListEntriesRequest, MarkEntryRequest, WatchEntriesRequest,
+    WatchEntriesResponse,
+};
+use serde_json::{json, Value};
+use sqlx::postgres::PgListener;
+use sqlx::{FromRow, PgPool};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Request, Status};
+use uuid::Uuid;
+
+/// Postgres channel the `feed_entries_notify` trigger announces the seq of new entries on.
+const ENTRY_CHANNEL: &str = "feed_entries";
+
+/// Number of missed entries replayed per query when a watcher resumes from a cursor.
+const REPLAY_BATCH_SIZE: i64 = 100;
+
+/// Responses buffered per WatchEntries stream before the sender waits on a slow client.
+const WATCH_BUFFER: usize = 32;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct EntryRecord {
+    pub id: Uuid,
+    pub feed_id: Uuid,
+    pub guid: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub summary: Option<String>,
+    pub content: Option<String>,
+    pub authors: Value,
+    pub enclosures: Value,
+    pub categories: Vec<String>,
+    pub publish_time: DateTime<Utc>,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+    /// Position in commit order, NULL until the inserting transaction commits.
+    pub seq: Option<i64>,
+    /// Per-user state, NULL when no user was requested or the user never touched the entry.
+    pub read: Option<bool>,
+    pub starred: Option<bool>,
+}
+
+impl EntryRecord {
+    pub(crate) fn to_proto(&self) -> Entry {
+        let field = |v: &Value, key: &str| v[key].as_str().unwrap_or_default().to_string();
+        let items = |v: &Value| v.as_array().cloned().unwrap_or_default();
+
+        Entry {
+            uid: self.id.to_string(),
+            feed_uid: self.feed_id.to_string(),
+            guid: self.guid.clone(),
+            title: self.title.clone().unwrap_or_default(),
+            link: self.link.clone().unwrap_or_default(),
+            summary: self.summary.clone().unwrap_or_default(),
+            content: self.content.clone().unwrap_or_default(),
+            authors: items(&self.authors)
+                .iter()
+                .map(|a| Author {
+                    name: field(a, "name"),
+                    email: field(a, "email"),
+                    uri: field(a, "uri"),
+                })
+                .collect(),
+            enclosures: items(&self.enclosures)
+                .iter()
+                .map(|e| Enclosure {
+                    url: field(e, "url"),
+                    media_type: field(e, "media_type"),
+                    length: e["length"].as_u64().unwrap_or_default(),
+                })
+                .collect(),
+            categories: self.categories.clone(),
+            publish_time: Some(to_timestamp(self.publish_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+            create_time: Some(to_timestamp(self.create_time)),
+            read: self.read.unwrap_or_default(),
+            starred: self.starred.unwrap_or_default(),
+        }
+    }
+}
+
+/// Filters for listing entries. `user_id` joins in that user's read and starred state, and
+/// `viewer` limits the entries to feeds the viewer can see.
+#[derive(Debug, Default)]
+pub struct EntryFilter {
+    pub feed_id: Option<Uuid>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub user_id: Option<Uuid>,
+    pub unread_only: bool,
+    pub starred_only: bool,
+    pub viewer: Option<Viewer>,
+}
+
+impl EntryFilter {
+    /// Whether entries of every feed match, bound alongside `visible_labels` and
+    /// `viewer_id`.
+    fn unrestricted(&self) -> bool {
+        self.viewer.as_ref().is_none_or(|viewer| viewer.is_admin)
+    }
+
+    fn page_scope(&self) -> PageScope {
+        let scope = PageScope::new("entries")
+            .with_opt("feed", self.feed_id)
+            .with_opt("start", self.start_time.map(|t| t.to_rfc3339()))
+            .with_opt("end", self.end_time.map(|t| t.to_rfc3339()))
+            .with_opt("user", self.user_id)
+            .with("unread", self.unread_only)
+            .with("starred", self.starred_only);
+        match &self.viewer {
+            Some(viewer) => viewer.page_scope(scope),
+            None => scope,
+        }
+    }
+
+    fn visible_labels(&self) -> Vec<&'static str> {
+        self.viewer
+            .as_ref()
+            .map(|viewer| viewer.labels.clone())
+            .unwrap_or_default()
+    }
+
+    fn viewer_id(&self) -> Option<Uuid> {
+        self.viewer.as_ref().and_then(|viewer| viewer.user_id)
+    }
+}
+
+pub struct EntryRepository {
+    pool: PgPool,
+}
+
+impl EntryRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_id(
+        &self,
+        id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> Result<Option<EntryRecord>, sqlx::Error> {
+        sqlx::query_as::<_, EntryRecord>(
+            r#"
+            SELECT e.id, e.feed_id, e.guid, e.title, e.link, e.summary, e.content,
+                   e.authors, e.enclosures, e.categories,
+                   e.publish_time, e.create_time, e.update_time, e.seq,
+                   s.read, s.starred
+            FROM public.feed_entries e
+            LEFT JOIN public.feed_entry_states s ON s.entry_id = e.id AND s.user_id = $2
+            WHERE e.id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// List entries newest first.
+    pub async fn list(
+        &self,
+        filter: &EntryFilter,
+        page: &PageRequest,
+    ) -> Result<Vec<EntryRecord>, sqlx::Error> {
+        sqlx::query_as::<_, EntryRecord>(
+            r#"
+            SELECT e.id, e.feed_id, e.guid, e.title, e.link, e.summary, e.content,
+                   e.authors, e.enclosures, e.categories,
+                   e.publish_time, e.create_time, e.update_time, e.seq,
+                   s.read, s.starred
+            FROM public.feed_entries e
+            LEFT JOIN public.feed_entry_states s ON s.entry_id = e.id AND s.user_id = $4
+            WHERE ($1::uuid IS NULL OR e.feed_id = $1)
+              AND ($2::timestamptz IS NULL OR e.publish_time >= $2)
+              AND ($3::timestamptz IS NULL OR e.publish_time < $3)
+              AND (NOT $5 OR NOT COALESCE(s.read, false))
+              AND (NOT $6 OR COALESCE(s.starred, false))
+              AND ($9 OR e.feed_id IN (
+                  SELECT f.id FROM public.feeds f
+                  WHERE f.visibility::text = ANY($10) OR f.owner_id = $11
+              ))
+              AND ($12::timestamptz IS NULL OR (e.publish_time, e.id) < ($12, $13))
+            ORDER BY e.publish_time DESC, e.id DESC
+            OFFSET $7
+            LIMIT $8
+            "#,
+        )
+        .bind(filter.feed_id)
+        .bind(filter.start_time)
+        .bind(filter.end_time)
+        .bind(filter.user_id)
+        .bind(filter.unread_only)
+        .bind(filter.starred_only)
+        .bind(page.skip)
+        .bind(page.limit())
+        .bind(filter.unrestricted())
+        .bind(filter.visible_labels())
+        .bind(filter.viewer_id())
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, filter: &EntryFilter) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM public.feed_entries e
+            LEFT JOIN public.feed_entry_states s ON s.entry_id = e.id AND s.user_id = $4
+            WHERE ($1::uuid IS NULL OR e.feed_id = $1)
+              AND ($2::timestamptz IS NULL OR e.publish_time >= $2)
+              AND ($3::timestamptz IS NULL OR e.publish_time < $3)
+              AND (NOT $5 OR NOT COALESCE(s.read, false))
+              AND (NOT $6 OR COALESCE(s.starred, false))
+              AND ($7 OR e.feed_id IN (
+                  SELECT f.id FROM public.feeds f
+                  WHERE f.visibility::text = ANY($8) OR f.owner_id = $9
+              ))
+            "#,
+        )
+        .bind(filter.feed_id)
+        .bind(filter.start_time)
+        .bind(filter.end_time)
+        .bind(filter.user_id)
+        .bind(filter.unread_only)
+        .bind(filter.starred_only)
+        .bind(filter.unrestricted())
+        .bind(filter.visible_labels())
+        .bind(filter.viewer_id())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// Find an entry by its position in commit order.
+    pub async fn find_by_seq(&self, seq: i64) -> Result<Option<EntryRecord>, sqlx::Error> {
+        sqlx::query_as::<_, EntryRecord>(
+            r#"
+            SELECT id, feed_id, guid, title, link, summary, content,
+                   authors, enclosures, categories,
+                   publish_time, create_time, update_time, seq,
+                   NULL::boolean AS read, NULL::boolean AS starred
+            FROM public.feed_entries
+            WHERE seq = $1
+            "#,
+        )
+        .bind(seq)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Entries committed after the entry at `after` in commit order. Ids can't be used for
+    /// this, as concurrent ingests commit their v7 uuids out of order. An empty `feed_ids`
+    /// matches every feed.
+    pub async fn list_after(
+        &self,
+        feed_ids: &[Uuid],
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<EntryRecord>, sqlx::Error> {
+        sqlx::query_as::<_, EntryRecord>(
+            r#"
+            SELECT id, feed_id, guid, title, link, summary, content,
+                   authors, enclosures, categories,
+                   publish_time, create_time, update_time, seq,
+                   NULL::boolean AS read, NULL::boolean AS starred
+            FROM public.feed_entries
+            WHERE (cardinality($1::uuid[]) = 0 OR feed_id = ANY($1))
+              AND seq > $2
+            ORDER BY seq ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(feed_ids)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Store the entries of a freshly parsed feed document, returning the entries that were
+    /// new or changed. Entries are matched on the hash of their guid, so re-fetching an
+    /// unchanged document writes nothing.
+    pub async fn upsert(
+        &self,
+        feed_id: Uuid,
+        entries: &[ParsedEntry],
+    ) -> Result<Vec<EntryRecord>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut stored = Vec::new();
+
+        for entry in entries {
+            if entry.guid.is_empty() {
+                continue;
+            }
+
+            let authors: Vec<Value> = entry
+                .authors
+                .iter()
+                .map(|a| json!({ "name": a.name, "email": a.email, "uri": a.uri }))
+                .collect();
+            let enclosures: Vec<Value> = entry
+                .enclosures
+                .iter()
+                .map(|e| json!({ "url": e.url, "media_type": e.media_type, "length": e.length }))
+                .collect();
+
+            let record = sqlx::query_as::<_, EntryRecord>(
+                r#"
+                INSERT INTO public.feed_entries
+                    (id, feed_id, guid, title, link, summary, content, authors, enclosures,
+                     categories, publish_time, update_time)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                        COALESCE($11, now()), COALESCE($12, now()))
+                ON CONFLICT (feed_id, guid_hash) DO UPDATE
+                SET title = EXCLUDED.title,
+                    link = EXCLUDED.link,
+                    summary = EXCLUDED.summary,
+                    content = EXCLUDED.content,
+                    authors = EXCLUDED.authors,
+                    enclosures = EXCLUDED.enclosures,
+                    categories = EXCLUDED.categories,
+                    update_time = EXCLUDED.update_time
+                WHERE (feed_entries.title, feed_entries.link, feed_entries.summary,
+                       feed_entries.content, feed_entries.authors, feed_entries.enclosures,
+                       feed_entries.categories)
+                      IS DISTINCT FROM
+                      (EXCLUDED.title, EXCLUDED.link, EXCLUDED.summary, EXCLUDED.content,
+                       EXCLUDED.authors, EXCLUDED.enclosures, EXCLUDED.categories)
+                RETURNING id, feed_id, guid, title, link, summary, content,
+                          authors, enclosures, categories,
+                          publish_time, create_time, update_time, seq,
+                          NULL::boolean AS read, NULL::boolean AS starred
+                "#,
+            )
+            .bind(Uuid::now_v7())
+            .bind(feed_id)
+            .bind(&entry.guid)
+            .bind(&entry.title)
+            .bind(&entry.link)
+            .bind(&entry.summary)
+            .bind(&entry.content)
+            .bind(Value::Array(authors))
+            .bind(Value::Array(enclosures))
+            .bind(&entry.categories)
+            .bind(entry.published)
+            .bind(entry.updated)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            stored.extend(record);
+        }
+
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    /// Set the read and/or starred state of an entry for a user. `None` keeps the current value.
+    pub async fn mark(
+        &self,
+        user_id: Uuid,
+        entry_id: Uuid,
+        read: Option<bool>,
+        starred: Option<bool>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO public.feed_entry_states (user_id, entry_id, read, starred, update_time)
+            VALUES ($1, $2, COALESCE($3, false), COALESCE($4, false), now())
+            ON CONFLICT (user_id, entry_id) DO UPDATE
+            SET read = COALESCE($3, feed_entry_states.read),
+                starred = COALESCE($4, feed_entry_states.starred),
+                update_time = now()
+            "#,
+        )
+        .bind(user_id)
+        .bind(entry_id)
+        .bind(read)
+        .bind(starred)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Fans entries announced through Postgres `NOTIFY` out to every WatchEntries stream served by
+/// this instance, regardless of which instance ingested them.
+#[derive(Debug, Clone)]
+pub struct EntryHub {
+    sender: broadcast::Sender<EntryRecord>,
+}
+
+impl EntryHub {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<EntryRecord> {
+        self.sender.subscribe()
+    }
+
+    /// Listen for new entries forever, reconnecting after database errors.
+    pub async fn run(self, pool: PgPool) {
+        loop {
+            if let Err(e) = self.listen(&pool).await {
+                tracing::error!(error = %e, "Entry listener failed");
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        }
+    }
+
+    async fn listen(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let repo = EntryRepository::new(pool.clone());
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(ENTRY_CHANNEL).await?;
+
+        loop {
+            let notification = listener.recv().await?;
+            let Ok(seq) = notification.payload().parse::<i64>() else {
+                tracing::warn!(
+                    payload = notification.payload(),
+                    "Ignoring invalid entry notification"
+                );
+                continue;
+            };
+
+            // Skip the lookup while nobody is watching.
+            if self.sender.receiver_count() == 0 {
+                continue;
+            }
+
+            if let Some(entry) = repo.find_by_seq(seq).await? {
+                let _ = self.sender.send(entry);
+            }
+        }
+    }
+}
+
+/// Feed a WatchEntries stream: replay whatever was missed since `cursor`, then forward live
+/// entries. A watcher that falls too far behind the hub goes back to replaying from the
+/// database, so lagging never drops entries.
+async fn watch(
+    repo: EntryRepository,
+    mut receiver: broadcast::Receiver<EntryRecord>,
+    feed_ids: Vec<Uuid>,
+    mut cursor: Option<i64>,
+    sender: mpsc::Sender<Result<WatchEntriesResponse, Status>>,
+) {
+    let send = |entry: &EntryRecord| {
+        sender.send(Ok(WatchEntriesResponse {
+            entry: Some(entry.to_proto()),
+            cursor: entry.seq.map(|seq| seq.to_string()).unwrap_or_default(),
+        }))
+    };
+
+    loop {
+        while let Some(after) = cursor {
+            let entries = match repo.list_after(&feed_ids, after, REPLAY_BATCH_SIZE).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let _ = sender.send(Err(GeistError::from(e).into())).await;
+                    return;
+                }
+            };
+
+            let done = (entries.len() as i64) < REPLAY_BATCH_SIZE;
+            for entry in &entries {
+                if send(entry).await.is_err() {
+                    return;
+                }
+                cursor = entry.seq;
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        loop {
+            match receiver.recv().await {
+                Ok(entry) => {
+                    let wanted = feed_ids.is_empty() || feed_ids.contains(&entry.feed_id);
+                    // Entries already replayed from the database may also arrive live.
+                    if !wanted || cursor.is_some_and(|c| entry.seq <= Some(c)) {
+                        continue;
+                    }
+                    if send(&entry).await.is_err() {
+                        return;
+                    }
+                    cursor = entry.seq;
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "Entry watcher lagged, replaying from cursor");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+/// Parse a WatchEntries cursor, the seq of the last entry received. Cursors handed out before
+/// entries had a seq are entry ids, which resume after that entry.
+async fn parse_cursor(repo: &EntryRepository, cursor: &str) -> Result<Option<i64>, Status> {
+    if cursor.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(seq) = cursor.parse::<i64>() {
+        return Ok(Some(seq));
+    }
+
+    let id = Uuid::parse_str(cursor).map_err(|_| Status::invalid_argument("Invalid cursor"))?;
+    let entry = repo
+        .find_by_id(id, None)
+        .await
+        .map_err(GeistError::from)?
+        .ok_or_else(|| Status::invalid_argument("Invalid cursor"))?;
+    Ok(entry.seq)
+}
+
+#[derive(Debug, Default)]
+pub struct EntryServer {
+    pool: Option<PgPool>,
+    hub: Option<EntryHub>,
+    visibility: VisibilityPolicy,
+    pages: Paginator,
+}
+
+impl EntryServer {
+    pub fn new(
+        pool: PgPool,
+        hub: EntryHub,
+        visibility: VisibilityPolicy,
+        pages: Paginator,
+    ) -> Self {
+        Self {
+            pool: Some(pool),
+            hub: Some(hub),
+            visibility,
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    /// Check that the viewer can see the feed an entry belongs to.
+    async fn check_visible(&self, entry: &EntryRecord, viewer: &Viewer) -> Result<(), Status> {
+        FeedRepository::new(self.pool()?.clone())
+            .find_visible(entry.feed_id, viewer)
+            .await
+            .map_err(|e| match e.code() {
+                Code::NotFound => Status::not_found("Entry not found"),
+                _ => e,
+            })?;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl EntryService for EntryServer {
+    type WatchEntriesStream = ReceiverStream<Result<WatchEntriesResponse, Status>>;
+
+    #[tracing::instrument(skip(self))]
+    async fn get_entry(&self, request: Request<EntryRequest>) -> ServerResult<EntryResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = EntryRepository::new(self.pool()?.clone());
+
+        let id = parse_uid(&req.uid)?.ok_or_else(|| Status::invalid_argument("uid is required"))?;
+        let user_id = parse_uid(&req.user_uid)?;
+        if let Some(user_id) = user_id {
+            principal.authorize_user(user_id)?;
+        }
+
+        let entry = repo
+            .find_by_id(id, user_id)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::not_found("Entry not found"))?;
+        self.check_visible(&entry, &viewer).await?;
+
+        Ok(tonic::Response::new(EntryResponse {
+            entries: vec![entry.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_entries(
+        &self,
+        request: Request<ListEntriesRequest>,
+    ) -> ServerResult<EntryResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let pool = self.pool()?.clone();
+        let repo = EntryRepository::new(pool.clone());
+
+        let filter = EntryFilter {
+            feed_id: parse_uid(&req.feed_uid)?,
+            start_time: req.start_time.as_ref().map(from_timestamp).transpose()?,
+            end_time: req.end_time.as_ref().map(from_timestamp).transpose()?,
+            user_id: parse_uid(&req.user_uid)?,
+            unread_only: req.unread_only,
+            starred_only: req.starred_only,
+            viewer: Some(viewer),
+        };
+
+        if let Some(user_id) = filter.user_id {
+            principal.authorize_user(user_id)?;
+        } else if filter.unread_only || filter.starred_only {
+            return Err(Status::invalid_argument(
+                "user_uid is required to filter by read or starred state",
+            ));
+        }
+
+        if let (Some(start), Some(end)) = (filter.start_time, filter.end_time) {
+            if start >= end {
+                return Err(Status::invalid_argument(
+                    "start_time must be before end_time",
+                ));
+            }
+        }
+
+        if let (Some(feed_id), Some(viewer)) = (filter.feed_id, filter.viewer.as_ref()) {
+            FeedRepository::new(pool)
+                .find_visible(feed_id, viewer)
+                .await?;
+        }
+
+        let page = self
+            .pages
+            .request(req.page.as_ref(), &filter.page_scope())?;
+
+        let entries = repo.list(&filter, &page).await.map_err(GeistError::from)?;
+
+        let total = repo.count(&filter).await.map_err(GeistError::from)?;
+        let (entries, page) = self.pages.response(&page, entries, total, |entry| {
+            Position::new(entry.publish_time, entry.id)
+        });
+
+        Ok(tonic::Response::new(EntryResponse {
+            entries: entries.iter().map(EntryRecord::to_proto).collect(),
+            page: Some(page),
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn mark_entry(&self, request: Request<MarkEntryRequest>) -> ServerResult<EntryResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = EntryRepository::new(self.pool()?.clone());
+
+        let entry_id = parse_uid(&req.entry_uid)?
+            .ok_or_else(|| Status::invalid_argument("entry_uid is required"))?;
+        let user_id = parse_uid(&req.user_uid)?
+            .ok_or_else(|| Status::invalid_argument("user_uid is required"))?;
+        principal.authorize_user(user_id)?;
+
+        if req.read.is_none() && req.starred.is_none() {
+            return Err(Status::invalid_argument(
+                "At least one of read or starred must be provided",
+            ));
+        }
+
+        let entry = repo
+            .find_by_id(entry_id, None)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::not_found("Entry not found"))?;
+        self.check_visible(&entry, &viewer).await?;
+
+        repo.mark(user_id, entry_id, req.read, req.starred)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+                    GeistError::NotFound("User not found".to_string())
+                }
+                _ => GeistError::from(e),
+            })?;
+
+        let entry = repo
+            .find_by_id(entry_id, Some(user_id))
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::not_found("Entry not found"))?;
+
+        Ok(tonic::Response::new(EntryResponse {
+            entries: vec![entry.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn watch_entries(
+        &self,
+        request: Request<WatchEntriesRequest>,
+    ) -> ServerResult<Self::WatchEntriesStream> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let pool = self.pool()?.clone();
+        let hub = self
+            .hub
+            .as_ref()
+            .ok_or_else(|| Status::internal("Entry hub not initialized"))?;
+
+        let feeds = FeedRepository::new(pool.clone());
+        let mut feed_ids = Vec::with_capacity(req.feed_uids.len());
+        for uid in &req.feed_uids {
+            let id = Uuid::parse_str(uid)
+                .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+            feeds
+                .find_visible(id, &viewer)
+                .await
+                .map_err(|e| match e.code() {
+                    Code::NotFound => Status::not_found(format!("Feed {} not found", uid)),
+                    _ => e,
+                })?;
+            feed_ids.push(id);
+        }
+
+        // Groups are watched through the feeds they are subscribed to at the time of the call.
+        if !req.group_uids.is_empty() {
+            let members = GroupMemberRepository::new(pool.clone());
+            let mut group_ids = Vec::with_capacity(req.group_uids.len());
+            for uid in &req.group_uids {
+                let id = Uuid::parse_str(uid)
+                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+                let role = members
+                    .find(id, principal.user_uid)
+                    .await
+                    .map_err(GeistError::from)?
+                    .map(|member| member.role())
+                    .transpose()?;
+                authorize_role(&principal, role, GroupRole::Member)?;
+                group_ids.push(id);
+            }
+
+            let group_feed_ids = SubscriptionRepository::new(pool.clone())
+                .feed_ids_for_groups(&group_ids)
+                .await
+                .map_err(GeistError::from)?;
+            // An empty list would watch every feed.
+            if group_feed_ids.is_empty() && feed_ids.is_empty() {
+                return Err(Status::failed_precondition(
+                    "The groups are not subscribed to any feeds",
+                ));
+            }
+            for id in group_feed_ids {
+                if !feed_ids.contains(&id) {
+                    feed_ids.push(id);
+                }
+            }
+        }
+
+        // Everything else is limited to the feeds visible when the call was made, as an empty
+        // list would watch every feed.
+        if feed_ids.is_empty() && !viewer.is_admin {
+            feed_ids = feeds.visible_ids(&viewer).await.map_err(GeistError::from)?;
+            if feed_ids.is_empty() {
+                return Err(Status::failed_precondition("There are no feeds to watch"));
+            }
+        }
+
+        let cursor = parse_cursor(&EntryRepository::new(pool.clone()), &req.cursor).await?;
+
+        // Subscribe before replaying so nothing ingested in between is lost.
+        let receiver = hub.subscribe();
+        let (sender, stream) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(watch(
+            EntryRepository::new(pool),
+            receiver,
+            feed_ids,
+            cursor,
+            sender,
+        ));
+
+        Ok(tonic::Response::new(ReceiverStream::new(stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_feed(pool: &PgPool) -> sqlx::Result<Uuid> {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO public.feeds (name, url, type, visibility)
+            VALUES ('Example', 'https://example.com/feed.xml', 'rss', 'public')
+            RETURNING id
+            "#,
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn list_after_follows_commit_order(pool: PgPool) -> sqlx::Result<()> {
+        let feed_id = create_feed(&pool).await?;
+        let repo = EntryRepository::new(pool.clone());
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(ENTRY_CHANNEL).await?;
+
+        let insert = "INSERT INTO public.feed_entries (id, feed_id, guid) VALUES ($1, $2, $3)";
+        let (first, second) = (Uuid::now_v7(), Uuid::now_v7());
+
+        // The entry with the lower id is inserted first but committed last.
+        let mut slow = pool.begin().await?;
+        sqlx::query(insert)
+            .bind(first)
+            .bind(feed_id)
+            .bind("first")
+            .execute(&mut *slow)
+            .await?;
+        let mut fast = pool.begin().await?;
+        sqlx::query(insert)
+            .bind(second)
+            .bind(feed_id)
+            .bind("second")
+            .execute(&mut *fast)
+            .await?;
+        fast.commit().await?;
+
+        let entries = repo.list_after(&[feed_id], 0, REPLAY_BATCH_SIZE).await?;
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), [second]);
+        let cursor = entries[0].seq.expect("committed entries have a seq");
+        assert_eq!(listener.recv().await?.payload(), cursor.to_string());
+
+        slow.commit().await?;
+
+        let entries = repo
+            .list_after(&[feed_id], cursor, REPLAY_BATCH_SIZE)
+            .await?;
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), [first]);
+        let seq = entries[0].seq.expect("committed entries have a seq");
+        assert!(seq > cursor);
+        assert_eq!(listener.recv().await?.payload(), seq.to_string());
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn parse_cursor_accepts_seqs_and_entry_ids(pool: PgPool) -> sqlx::Result<()> {
+        let feed_id = create_feed(&pool).await?;
+        let repo = EntryRepository::new(pool.clone());
+        let id: Uuid = sqlx::query_scalar(
+            "INSERT INTO public.feed_entries (id, feed_id, guid) VALUES ($1, $2, 'a') RETURNING id",
+        )
+        .bind(Uuid::now_v7())
+        .bind(feed_id)
+        .fetch_one(&pool)
+        .await?;
+        let seq = repo.find_by_id(id, None).await?.and_then(|e| e.seq);
+
+        assert_eq!(parse_cursor(&repo, "").await.unwrap(), None);
+        assert_eq!(parse_cursor(&repo, "42").await.unwrap(), Some(42));
+        assert_eq!(parse_cursor(&repo, &id.to_string()).await.unwrap(), seq);
+        assert_eq!(
+            parse_cursor(&repo, "not a cursor")
+                .await
+                .unwrap_err()
+                .code(),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            parse_cursor(&repo, &Uuid::now_v7().to_string())
+                .await
+                .unwrap_err()
+                .code(),
+            Code::InvalidArgument
+        );
+
+        Ok(())
+    }
+}
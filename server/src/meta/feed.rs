@@ -1,39 +1,760 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::visibility::{Viewer, VisibilityPolicy};
+use super::{non_empty, to_timestamp, visibility_from_str, visibility_to_str};
+use crate::auth;
+use crate::error::GeistError;
+use crate::ingest::UrlPolicy;
 use crate::ServerResult;
+use chrono::{DateTime, Utc};
 use geist_sdk::pb::meta::v1alpha::{
-    feed_service_server::FeedService, FeedRequest, FeedResponse, ListFeedsRequest,
-    MutateFeedRequest,
+    feed::Type as FeedType, feed_request::Params, feed_service_server::FeedService, Feed,
+    FeedRequest, FeedResponse, ListFeedsRequest, MutateFeedRequest,
 };
+use geist_sdk::pb::rpc::Visibility;
+use prost_types::Duration;
+use sqlx::{FromRow, PgPool};
 use tonic::{Request, Status};
+use uuid::Uuid;
+
+/// Poll interval applied to feeds created without one.
+pub(super) const DEFAULT_POLL_INTERVAL_SECS: i32 = 3600;
+
+/// Feeds are never polled more often than this.
+const MIN_POLL_INTERVAL_SECS: i32 = 60;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct FeedRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub url: String,
+    pub icon_url: Option<String>,
+    pub feed_type: String,
+    pub visibility: String,
+    pub poll_interval_secs: i32,
+    pub last_fetch_time: Option<DateTime<Utc>>,
+    pub last_fetch_status: Option<i32>,
+    pub owner_id: Option<Uuid>,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+impl FeedRecord {
+    pub(super) fn is_visible_to(&self, viewer: &Viewer) -> bool {
+        let is_owner = self.owner_id.is_some() && self.owner_id == viewer.user_id;
+        viewer.can_see(&self.visibility, is_owner)
+    }
+
+    /// Report feeds the viewer cannot see as not found, before checking what they may do with
+    /// them, so that changes to hidden feeds do not reveal that they exist.
+    fn filter_visible(self, viewer: &Viewer) -> Result<Self, Status> {
+        if self.is_visible_to(viewer) {
+            Ok(self)
+        } else {
+            Err(Status::not_found("Feed not found"))
+        }
+    }
+
+    fn to_proto(&self) -> Result<Feed, Status> {
+        let feed_type = match self.feed_type.as_str() {
+            "rss" => FeedType::Rss as i32,
+            "atom" => FeedType::Atom as i32,
+            "json" => FeedType::Json as i32,
+            "xml" => FeedType::Xml as i32,
+            _ => return Err(Status::internal("Invalid feed type")),
+        };
+
+        Ok(Feed {
+            uid: self.id.to_string(),
+            name: self.name.clone(),
+            description: self.description.clone().unwrap_or_default(),
+            url: self.url.clone(),
+            icon_url: self.icon_url.clone().unwrap_or_default(),
+            r#type: feed_type,
+            visibility: visibility_from_str(&self.visibility)?,
+            create_time: Some(to_timestamp(self.create_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+            delete_time: None,
+            poll_interval: Some(Duration {
+                seconds: self.poll_interval_secs as i64,
+                nanos: 0,
+            }),
+            last_fetch_time: self.last_fetch_time.map(to_timestamp),
+            last_fetch_status: self.last_fetch_status.unwrap_or_default() as u32,
+            owner_uid: self.owner_id.map(|id| id.to_string()).unwrap_or_default(),
+        })
+    }
+
+    /// Convert a requested poll interval into seconds, rejecting anything below the minimum.
+    pub(super) fn poll_interval_secs(interval: Option<&Duration>) -> Result<Option<i32>, Status> {
+        match interval {
+            None => Ok(None),
+            Some(d) if d.seconds == 0 && d.nanos == 0 => Ok(None),
+            Some(d) if d.seconds < MIN_POLL_INTERVAL_SECS as i64 => {
+                Err(Status::invalid_argument(format!(
+                    "Poll interval must be at least {} seconds",
+                    MIN_POLL_INTERVAL_SECS
+                )))
+            }
+            Some(d) => i32::try_from(d.seconds)
+                .map(Some)
+                .map_err(|_| Status::invalid_argument("Poll interval is too large")),
+        }
+    }
+
+    fn type_to_string(feed_type: i32) -> Result<String, Status> {
+        match feed_type {
+            x if x == FeedType::Rss as i32 => Ok("rss".to_string()),
+            x if x == FeedType::Atom as i32 => Ok("atom".to_string()),
+            x if x == FeedType::Json as i32 => Ok("json".to_string()),
+            x if x == FeedType::Xml as i32 => Ok("xml".to_string()),
+            _ => Err(Status::invalid_argument("Invalid feed type")),
+        }
+    }
+}
+
+pub struct FeedRepository {
+    pool: PgPool,
+}
+
+impl FeedRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<FeedRecord>, sqlx::Error> {
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            SELECT id, name::text AS name, description, url, icon_url,
+                   type::text AS feed_type, visibility::text AS visibility,
+                   poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                   create_time, update_time
+            FROM public.feeds
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> Result<Option<FeedRecord>, sqlx::Error> {
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            SELECT id, name::text AS name, description, url, icon_url,
+                   type::text AS feed_type, visibility::text AS visibility,
+                   poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                   create_time, update_time
+            FROM public.feeds
+            WHERE name = $1::citext
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// List the feeds the viewer can see.
+    pub async fn list(
+        &self,
+        viewer: &Viewer,
+        page: &PageRequest,
+    ) -> Result<Vec<FeedRecord>, sqlx::Error> {
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            SELECT id, name::text AS name, description, url, icon_url,
+                   type::text AS feed_type, visibility::text AS visibility,
+                   poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                   create_time, update_time
+            FROM public.feeds
+            WHERE ($1 OR visibility::text = ANY($2) OR owner_id = $3)
+              AND ($4::timestamptz IS NULL OR (create_time, id) > ($4, $5))
+            ORDER BY create_time ASC, id ASC
+            OFFSET $6
+            LIMIT $7
+            "#,
+        )
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, viewer: &Viewer) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM public.feeds
+            WHERE $1 OR visibility::text = ANY($2) OR owner_id = $3
+            "#,
+        )
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// The oldest feed with the given url that the viewer can see.
+    pub async fn find_visible_by_url(
+        &self,
+        url: &str,
+        viewer: &Viewer,
+    ) -> Result<Option<FeedRecord>, sqlx::Error> {
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            SELECT id, name::text AS name, description, url, icon_url,
+                   type::text AS feed_type, visibility::text AS visibility,
+                   poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                   create_time, update_time
+            FROM public.feeds
+            WHERE url = $1
+              AND ($2 OR visibility::text = ANY($3) OR owner_id = $4)
+            ORDER BY create_time ASC, id ASC
+            LIMIT 1
+            "#,
+        )
+        .bind(url)
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Ids of every feed the viewer can see.
+    pub async fn visible_ids(&self, viewer: &Viewer) -> Result<Vec<Uuid>, sqlx::Error> {
+        let rows: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            SELECT id FROM public.feeds
+            WHERE $1 OR visibility::text = ANY($2) OR owner_id = $3
+            "#,
+        )
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Load a feed the viewer can see. Feeds they cannot see are reported as not found.
+    pub async fn find_visible(&self, id: Uuid, viewer: &Viewer) -> Result<FeedRecord, Status> {
+        self.find_by_id(id)
+            .await
+            .map_err(GeistError::from)?
+            .filter(|feed| feed.is_visible_to(viewer))
+            .ok_or_else(|| Status::not_found("Feed not found"))
+    }
+
+    pub async fn create(&self, feed: &CreateFeed) -> Result<FeedRecord, sqlx::Error> {
+        let now = Utc::now();
+        let id = Uuid::now_v7();
+
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            INSERT INTO public.feeds
+                (id, name, description, url, icon_url, type, visibility, poll_interval_secs,
+                 owner_id, create_time, update_time)
+            VALUES ($1, $2, $3, $4, $5, $6::feed_type, $7::feed_visibility, $8, $9, $10, $11)
+            RETURNING id, name::text AS name, description, url, icon_url,
+                      type::text AS feed_type, visibility::text AS visibility,
+                      poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                      create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&feed.name)
+        .bind(&feed.description)
+        .bind(&feed.url)
+        .bind(&feed.icon_url)
+        .bind(&feed.feed_type)
+        .bind(&feed.visibility)
+        .bind(feed.poll_interval_secs)
+        .bind(feed.owner_id)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn update(
+        &self,
+        id: Uuid,
+        feed: &UpdateFeed,
+    ) -> Result<Option<FeedRecord>, sqlx::Error> {
+        sqlx::query_as::<_, FeedRecord>(
+            r#"
+            UPDATE public.feeds
+            SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                url = COALESCE($4, url),
+                icon_url = COALESCE($5, icon_url),
+                type = COALESCE($6::feed_type, type),
+                visibility = COALESCE($7::feed_visibility, visibility),
+                poll_interval_secs = COALESCE($8, poll_interval_secs),
+                update_time = now()
+            WHERE id = $1
+            RETURNING id, name::text AS name, description, url, icon_url,
+                      type::text AS feed_type, visibility::text AS visibility,
+                      poll_interval_secs, last_fetch_time, last_fetch_status, owner_id,
+                      create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&feed.name)
+        .bind(&feed.description)
+        .bind(&feed.url)
+        .bind(&feed.icon_url)
+        .bind(&feed.feed_type)
+        .bind(&feed.visibility)
+        .bind(feed.poll_interval_secs)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM public.feeds
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct CreateFeed {
+    pub name: String,
+    pub description: Option<String>,
+    pub url: String,
+    pub icon_url: Option<String>,
+    pub feed_type: String,
+    pub visibility: String,
+    pub poll_interval_secs: i32,
+    pub owner_id: Uuid,
+}
+
+/// Fields left as `None` keep their stored value.
+pub struct UpdateFeed {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub icon_url: Option<String>,
+    pub feed_type: Option<String>,
+    pub visibility: Option<String>,
+    pub poll_interval_secs: Option<i32>,
+}
+
+fn map_write_error(e: sqlx::Error) -> GeistError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            GeistError::AlreadyExists("A feed with this name already exists".to_string())
+        }
+        _ => GeistError::from(e),
+    }
+}
 
 #[derive(Debug, Default)]
-pub struct FeedServer;
+pub struct FeedServer {
+    pool: Option<PgPool>,
+    visibility: VisibilityPolicy,
+    urls: UrlPolicy,
+    pages: Paginator,
+}
+
+impl FeedServer {
+    pub fn new(
+        pool: PgPool,
+        visibility: VisibilityPolicy,
+        urls: UrlPolicy,
+        pages: Paginator,
+    ) -> Self {
+        Self {
+            pool: Some(pool),
+            visibility,
+            urls,
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    /// Resolve the stored feed a mutation refers to, by uid if present and otherwise by name.
+    async fn find_target(&self, repo: &FeedRepository, feed: &Feed) -> Result<FeedRecord, Status> {
+        let record = if !feed.uid.is_empty() {
+            let id = Uuid::parse_str(&feed.uid)
+                .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+            repo.find_by_id(id).await.map_err(GeistError::from)?
+        } else if !feed.name.is_empty() {
+            repo.find_by_name(&feed.name)
+                .await
+                .map_err(GeistError::from)?
+        } else {
+            return Err(Status::invalid_argument(
+                "One of uid or name must be provided",
+            ));
+        };
+
+        record.ok_or_else(|| Status::not_found("Feed not found"))
+    }
+}
 
 #[tonic::async_trait]
 impl FeedService for FeedServer {
-    #[tracing::instrument]
-    async fn get_feed(&self, _req: Request<FeedRequest>) -> ServerResult<FeedResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn get_feed(&self, request: Request<FeedRequest>) -> ServerResult<FeedResponse> {
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = FeedRepository::new(self.pool()?.clone());
+
+        let feed = match req.params {
+            Some(Params::Uid(uid)) => {
+                let id = Uuid::parse_str(&uid)
+                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+                repo.find_by_id(id).await.map_err(GeistError::from)?
+            }
+            Some(Params::Name(name)) => repo.find_by_name(&name).await.map_err(GeistError::from)?,
+            None => {
+                return Err(Status::invalid_argument(
+                    "One of uid or name must be provided",
+                ));
+            }
+        };
+
+        let feed = feed
+            .filter(|feed| feed.is_visible_to(&viewer))
+            .ok_or_else(|| Status::not_found("Feed not found"))?;
+
+        Ok(tonic::Response::new(FeedResponse {
+            feeds: vec![feed.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_feeds(&self, request: Request<ListFeedsRequest>) -> ServerResult<FeedResponse> {
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = FeedRepository::new(self.pool()?.clone());
+
+        let page = self.pages.request(
+            req.page.as_ref(),
+            &viewer.page_scope(PageScope::new("feeds")),
+        )?;
+
+        let feeds = repo.list(&viewer, &page).await.map_err(GeistError::from)?;
+        let total = repo.count(&viewer).await.map_err(GeistError::from)?;
+        let (feeds, page) = self.pages.response(&page, feeds, total, |feed| {
+            Position::new(feed.create_time, feed.id)
+        });
+
+        let proto_feeds: Result<Vec<_>, _> = feeds.iter().map(|f| f.to_proto()).collect();
+
+        Ok(tonic::Response::new(FeedResponse {
+            feeds: proto_feeds?,
+            page: Some(page),
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_feed(&self, request: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
+        let owner_id = auth::principal(&request)?.user_uid;
+        let req = request.into_inner();
+        let repo = FeedRepository::new(self.pool()?.clone());
+
+        let feed = req
+            .feed
+            .ok_or_else(|| Status::invalid_argument("Feed must be provided"))?;
+
+        if feed.name.is_empty() {
+            return Err(Status::invalid_argument("Feed name must be provided"));
+        }
+        if feed.url.is_empty() {
+            return Err(Status::invalid_argument("Feed url must be provided"));
+        }
+        self.urls
+            .check(&feed.url)
+            .map_err(|e| Status::invalid_argument(e.message))?;
+
+        let create_feed = CreateFeed {
+            feed_type: FeedRecord::type_to_string(feed.r#type)?,
+            visibility: visibility_to_str(feed.visibility)?.to_string(),
+            poll_interval_secs: FeedRecord::poll_interval_secs(feed.poll_interval.as_ref())?
+                .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+            name: feed.name,
+            description: non_empty(feed.description),
+            url: feed.url,
+            icon_url: non_empty(feed.icon_url),
+            owner_id,
+        };
+
+        let created = repo.create(&create_feed).await.map_err(map_write_error)?;
+
+        Ok(tonic::Response::new(FeedResponse {
+            feeds: vec![created.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn update_feed(&self, request: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = FeedRepository::new(self.pool()?.clone());
+
+        let feed = req
+            .feed
+            .ok_or_else(|| Status::invalid_argument("Feed must be provided"))?;
+        let existing = self
+            .find_target(&repo, &feed)
+            .await?
+            .filter_visible(&viewer)?;
+        principal.authorize_owner(existing.owner_id)?;
+        if !feed.url.is_empty() {
+            self.urls
+                .check(&feed.url)
+                .map_err(|e| Status::invalid_argument(e.message))?;
+        }
+
+        let update_feed = UpdateFeed {
+            feed_type: match feed.r#type {
+                x if x == FeedType::Unspecified as i32 => None,
+                x => Some(FeedRecord::type_to_string(x)?),
+            },
+            visibility: match feed.visibility {
+                x if x == Visibility::Unspecified as i32 => None,
+                x => Some(visibility_to_str(x)?.to_string()),
+            },
+            poll_interval_secs: FeedRecord::poll_interval_secs(feed.poll_interval.as_ref())?,
+            // Only rename when the feed was addressed by uid.
+            name: if feed.uid.is_empty() {
+                None
+            } else {
+                non_empty(feed.name)
+            },
+            description: non_empty(feed.description),
+            url: non_empty(feed.url),
+            icon_url: non_empty(feed.icon_url),
+        };
+
+        let updated = repo
+            .update(existing.id, &update_feed)
+            .await
+            .map_err(map_write_error)?
+            .ok_or_else(|| Status::not_found("Feed not found"))?;
+
+        Ok(tonic::Response::new(FeedResponse {
+            feeds: vec![updated.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_feed(&self, request: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = FeedRepository::new(self.pool()?.clone());
+
+        let feed = req
+            .feed
+            .ok_or_else(|| Status::invalid_argument("Feed must be provided"))?;
+        let existing = self
+            .find_target(&repo, &feed)
+            .await?
+            .filter_visible(&viewer)?;
+        principal.authorize_owner(existing.owner_id)?;
+
+        if !repo.delete(existing.id).await.map_err(GeistError::from)? {
+            return Err(Status::not_found("Feed not found"));
+        }
+
+        Ok(tonic::Response::new(FeedResponse {
+            feeds: vec![existing.to_proto()?],
+            page: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Principal;
+    use crate::meta::user::CreateUser;
+    use crate::meta::UserRepository;
+
+    fn request<T>(message: T, user_uid: Uuid) -> Request<T> {
+        let mut request = Request::new(message);
+        request.extensions_mut().insert(Principal {
+            user_uid,
+            scopes: Vec::new(),
+        });
+        request
     }
 
-    #[tracing::instrument]
-    async fn list_feeds(&self, _req: Request<ListFeedsRequest>) -> ServerResult<FeedResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    async fn create_feed(pool: &PgPool, name: &str, visibility: &str, owner_id: Uuid) -> Feed {
+        FeedRepository::new(pool.clone())
+            .create(&CreateFeed {
+                name: name.to_string(),
+                description: None,
+                url: format!("https://example.com/{}.xml", name),
+                icon_url: None,
+                feed_type: "rss".to_string(),
+                visibility: visibility.to_string(),
+                poll_interval_secs: 3600,
+                owner_id,
+            })
+            .await
+            .unwrap()
+            .to_proto()
+            .unwrap()
     }
 
-    #[tracing::instrument]
-    async fn create_feed(&self, _req: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    async fn create_user(pool: &PgPool, username: &str) -> Uuid {
+        UserRepository::new(pool.clone())
+            .create(&CreateUser {
+                name: None,
+                email: None,
+                username: username.to_string(),
+                avatar_url: None,
+                bio: None,
+                location: None,
+                links: None,
+            })
+            .await
+            .unwrap()
+            .id
     }
 
-    #[tracing::instrument]
-    async fn update_feed(&self, _req: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[sqlx::test(migrations = "./migrations")]
+    async fn changes_to_hidden_feeds_are_not_found(pool: PgPool) {
+        let server = FeedServer::new(
+            pool.clone(),
+            VisibilityPolicy::default(),
+            UrlPolicy::default(),
+            Paginator::default(),
+        );
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let private = create_feed(&pool, "private", "private", alice).await;
+        let preview = create_feed(&pool, "preview", "preview", alice).await;
+        let internal = create_feed(&pool, "internal", "internal", alice).await;
+        let public = create_feed(&pool, "public", "public", alice).await;
+
+        let update = |feed: &Feed, user_uid| {
+            request(
+                MutateFeedRequest {
+                    feed: Some(Feed {
+                        description: "Changed".to_string(),
+                        ..feed.clone()
+                    }),
+                },
+                user_uid,
+            )
+        };
+        let delete = |feed: &Feed, user_uid| {
+            request(
+                MutateFeedRequest {
+                    feed: Some(feed.clone()),
+                },
+                user_uid,
+            )
+        };
+
+        // Hidden from Bob in Production, whether addressed by uid or by name.
+        for feed in [&private, &preview] {
+            for feed in [
+                feed.clone(),
+                Feed {
+                    uid: String::new(),
+                    ..feed.clone()
+                },
+            ] {
+                let status = server.update_feed(update(&feed, bob)).await.unwrap_err();
+                assert_eq!(status.code(), tonic::Code::NotFound, "{}", feed.name);
+                let status = server.delete_feed(delete(&feed, bob)).await.unwrap_err();
+                assert_eq!(status.code(), tonic::Code::NotFound, "{}", feed.name);
+            }
+        }
+
+        // Visible to Bob, but not his.
+        for feed in [&internal, &public] {
+            let status = server.update_feed(update(feed, bob)).await.unwrap_err();
+            assert_eq!(
+                status.code(),
+                tonic::Code::PermissionDenied,
+                "{}",
+                feed.name
+            );
+            let status = server.delete_feed(delete(feed, bob)).await.unwrap_err();
+            assert_eq!(
+                status.code(),
+                tonic::Code::PermissionDenied,
+                "{}",
+                feed.name
+            );
+        }
+
+        // Alice still manages her private feed.
+        let updated = server
+            .update_feed(update(&private, alice))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(updated.feeds[0].description, "Changed");
+        server.delete_feed(delete(&private, alice)).await.unwrap();
     }
 
-    #[tracing::instrument]
-    async fn delete_feed(&self, _req: Request<MutateFeedRequest>) -> ServerResult<FeedResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tokio::test]
+    async fn create_feed_rejects_unsafe_urls() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let server = FeedServer::new(
+            pool,
+            VisibilityPolicy::default(),
+            UrlPolicy::default(),
+            Paginator::default(),
+        );
+
+        for url in [
+            "file:///etc/passwd",
+            "gopher://example.com/",
+            "http://localhost/feed.xml",
+            "http://127.0.0.1:8080/feed.xml",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[fd00:ec2::254]/latest/meta-data/",
+        ] {
+            let feed = Feed {
+                name: "Example".to_string(),
+                url: url.to_string(),
+                ..Default::default()
+            };
+            let status = server
+                .create_feed(request(
+                    MutateFeedRequest { feed: Some(feed) },
+                    Uuid::now_v7(),
+                ))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{}", url);
+        }
     }
 }
@@ -1,39 +1,723 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use super::member::{authorize_role, GroupMemberRepository, GroupRole};
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::visibility::{Viewer, VisibilityPolicy};
+use super::{non_empty, parse_uid, to_timestamp, visibility_from_str, visibility_to_str};
+use crate::auth::{self, Principal};
+use crate::error::GeistError;
 use crate::ServerResult;
+use chrono::{DateTime, Utc};
 use geist_sdk::pb::meta::v1alpha::{
-    group_service_server::GroupService, GroupRequest, GroupResponse, ListGroupsRequest,
-    MutateGroupRequest,
+    group_request::Params, group_service_server::GroupService, AddMemberRequest, Group,
+    GroupMemberResponse, GroupRequest, GroupResponse, ListGroupsRequest, ListMembersRequest,
+    MutateGroupRequest, RemoveMemberRequest, UpdateMemberRoleRequest,
 };
+use geist_sdk::pb::rpc::Visibility;
+use sqlx::{FromRow, PgPool};
 use tonic::{Request, Status};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct GroupRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub slug: String,
+    pub icon_url: Option<String>,
+    pub visibility: String,
+    pub owner_id: Option<Uuid>,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+impl GroupRecord {
+    pub(super) fn to_proto(&self) -> Result<Group, Status> {
+        Ok(Group {
+            uid: self.id.to_string(),
+            name: self.name.clone(),
+            description: self.description.clone().unwrap_or_default(),
+            slug: self.slug.clone(),
+            icon_url: self.icon_url.clone().unwrap_or_default(),
+            url: String::new(),
+            visibility: visibility_from_str(&self.visibility)?,
+            create_time: Some(to_timestamp(self.create_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+            owner_uid: self.owner_id.map(|id| id.to_string()).unwrap_or_default(),
+        })
+    }
+}
+
+/// Derive a URL-safe slug from a group name, e.g. "Rust & Friends!" becomes "rust-friends".
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+fn validate_slug(slug: &str) -> Result<(), Status> {
+    let valid = !slug.is_empty()
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Status::invalid_argument(
+            "Slug must contain only lowercase letters, digits and inner hyphens",
+        ))
+    }
+}
+
+pub struct GroupRepository {
+    pool: PgPool,
+}
+
+impl GroupRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<GroupRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupRecord>(
+            r#"
+            SELECT id, name::text AS name, description, slug::text AS slug, icon_url,
+                   visibility::text AS visibility, owner_id, create_time, update_time
+            FROM public.groups
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> Result<Option<GroupRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupRecord>(
+            r#"
+            SELECT id, name::text AS name, description, slug::text AS slug, icon_url,
+                   visibility::text AS visibility, owner_id, create_time, update_time
+            FROM public.groups
+            WHERE name = $1::citext
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_slug(&self, slug: &str) -> Result<Option<GroupRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupRecord>(
+            r#"
+            SELECT id, name::text AS name, description, slug::text AS slug, icon_url,
+                   visibility::text AS visibility, owner_id, create_time, update_time
+            FROM public.groups
+            WHERE slug = $1::citext
+            "#,
+        )
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// List the groups the viewer can see, which includes every group they are a member of.
+    pub async fn list(
+        &self,
+        viewer: &Viewer,
+        page: &PageRequest,
+    ) -> Result<Vec<GroupRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupRecord>(
+            r#"
+            SELECT id, name::text AS name, description, slug::text AS slug, icon_url,
+                   visibility::text AS visibility, owner_id, create_time, update_time
+            FROM public.groups g
+            WHERE ($1 OR visibility::text = ANY($2) OR EXISTS (
+                SELECT 1 FROM public.group_members m
+                WHERE m.group_id = g.id AND m.user_id = $3
+            ))
+              AND ($4::timestamptz IS NULL OR (create_time, id) > ($4, $5))
+            ORDER BY create_time ASC, id ASC
+            OFFSET $6
+            LIMIT $7
+            "#,
+        )
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, viewer: &Viewer) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM public.groups g
+            WHERE $1 OR visibility::text = ANY($2) OR EXISTS (
+                SELECT 1 FROM public.group_members m
+                WHERE m.group_id = g.id AND m.user_id = $3
+            )
+            "#,
+        )
+        .bind(viewer.is_admin)
+        .bind(&viewer.labels)
+        .bind(viewer.user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// Create a group with its creator as the owner.
+    pub async fn create(&self, group: &CreateGroup) -> Result<GroupRecord, sqlx::Error> {
+        let now = Utc::now();
+        let id = Uuid::now_v7();
+        let mut tx = self.pool.begin().await?;
+
+        let created = sqlx::query_as::<_, GroupRecord>(
+            r#"
+            INSERT INTO public.groups
+                (id, name, description, slug, icon_url, visibility, owner_id, create_time,
+                 update_time)
+            VALUES ($1, $2, $3, $4, $5, $6::group_visibility, $7, $8, $9)
+            RETURNING id, name::text AS name, description, slug::text AS slug, icon_url,
+                      visibility::text AS visibility, owner_id, create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&group.name)
+        .bind(&group.description)
+        .bind(&group.slug)
+        .bind(&group.icon_url)
+        .bind(&group.visibility)
+        .bind(group.owner_id)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO public.group_members (group_id, user_id, role, create_time, update_time)
+            VALUES ($1, $2, 'owner', $3, $3)
+            "#,
+        )
+        .bind(id)
+        .bind(group.owner_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    pub async fn update(
+        &self,
+        id: Uuid,
+        group: &UpdateGroup,
+    ) -> Result<Option<GroupRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupRecord>(
+            r#"
+            UPDATE public.groups
+            SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                slug = COALESCE($4, slug),
+                icon_url = COALESCE($5, icon_url),
+                visibility = COALESCE($6::group_visibility, visibility),
+                update_time = now()
+            WHERE id = $1
+            RETURNING id, name::text AS name, description, slug::text AS slug, icon_url,
+                      visibility::text AS visibility, owner_id, create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&group.name)
+        .bind(&group.description)
+        .bind(&group.slug)
+        .bind(&group.icon_url)
+        .bind(&group.visibility)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM public.groups
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct CreateGroup {
+    pub name: String,
+    pub description: Option<String>,
+    pub slug: String,
+    pub icon_url: Option<String>,
+    pub visibility: String,
+    pub owner_id: Uuid,
+}
+
+/// Fields left as `None` keep their stored value.
+pub struct UpdateGroup {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub slug: Option<String>,
+    pub icon_url: Option<String>,
+    pub visibility: Option<String>,
+}
+
+fn map_write_error(e: sqlx::Error) -> GeistError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => match db.constraint() {
+            Some("groups_slug_key") => {
+                GeistError::AlreadyExists("A group with this slug already exists".to_string())
+            }
+            _ => GeistError::AlreadyExists("A group with this name already exists".to_string()),
+        },
+        _ => GeistError::from(e),
+    }
+}
+
+/// Parse a required uid from a membership request.
+fn require_uid(uid: &str, field: &str) -> Result<Uuid, Status> {
+    parse_uid(uid)?.ok_or_else(|| Status::invalid_argument(format!("{} is required", field)))
+}
 
 #[derive(Debug, Default)]
-pub struct GroupServer;
+pub struct GroupServer {
+    pool: Option<PgPool>,
+    visibility: VisibilityPolicy,
+    pages: Paginator,
+}
+
+impl GroupServer {
+    pub fn new(pool: PgPool, visibility: VisibilityPolicy, pages: Paginator) -> Self {
+        Self {
+            pool: Some(pool),
+            visibility,
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    /// Resolve the stored group a mutation refers to, by uid, then slug, then name.
+    async fn find_target(
+        &self,
+        repo: &GroupRepository,
+        group: &Group,
+    ) -> Result<GroupRecord, Status> {
+        let record = if !group.uid.is_empty() {
+            let id = Uuid::parse_str(&group.uid)
+                .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+            repo.find_by_id(id).await
+        } else if !group.slug.is_empty() {
+            repo.find_by_slug(&group.slug).await
+        } else if !group.name.is_empty() {
+            repo.find_by_name(&group.name).await
+        } else {
+            return Err(Status::invalid_argument(
+                "One of uid, slug, or name must be provided",
+            ));
+        }
+        .map_err(GeistError::from)?;
+
+        record.ok_or_else(|| Status::not_found("Group not found"))
+    }
+
+    /// Look up a group by uid, returning it with the caller's role in it.
+    async fn find_with_role(
+        &self,
+        group_uid: &str,
+        principal: &Principal,
+    ) -> Result<(GroupRecord, Option<GroupRole>), Status> {
+        let id = require_uid(group_uid, "group_uid")?;
+        let group = GroupRepository::new(self.pool()?.clone())
+            .find_by_id(id)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::not_found("Group not found"))?;
+
+        let role = self.role_of(group.id, principal.user_uid).await?;
+        Ok((group, role))
+    }
+
+    /// Whether the viewer can see the group, as one of its members or by its visibility.
+    async fn is_visible_to(&self, group: &GroupRecord, viewer: &Viewer) -> Result<bool, Status> {
+        if viewer.can_see(&group.visibility, false) {
+            return Ok(true);
+        }
+
+        match viewer.user_id {
+            Some(user_id) => Ok(self.role_of(group.id, user_id).await?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    async fn role_of(&self, group_id: Uuid, user_id: Uuid) -> Result<Option<GroupRole>, Status> {
+        GroupMemberRepository::new(self.pool()?.clone())
+            .find(group_id, user_id)
+            .await
+            .map_err(GeistError::from)?
+            .map(|member| member.role())
+            .transpose()
+    }
+}
 
 #[tonic::async_trait]
 impl GroupService for GroupServer {
-    #[tracing::instrument]
-    async fn get_group(&self, _req: Request<GroupRequest>) -> ServerResult<GroupResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn get_group(&self, request: Request<GroupRequest>) -> ServerResult<GroupResponse> {
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = GroupRepository::new(self.pool()?.clone());
+
+        let group = match req.params {
+            Some(Params::Uid(uid)) => {
+                let id = Uuid::parse_str(&uid)
+                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+                repo.find_by_id(id).await
+            }
+            Some(Params::Name(name)) => repo.find_by_name(&name).await,
+            Some(Params::Slug(slug)) => repo.find_by_slug(&slug).await,
+            None => {
+                return Err(Status::invalid_argument(
+                    "One of uid, name, or slug must be provided",
+                ));
+            }
+        }
+        .map_err(GeistError::from)?
+        .ok_or_else(|| Status::not_found("Group not found"))?;
+
+        if !self.is_visible_to(&group, &viewer).await? {
+            return Err(Status::not_found("Group not found"));
+        }
+
+        Ok(tonic::Response::new(GroupResponse {
+            groups: vec![group.to_proto()?],
+            page: None,
+        }))
     }
 
-    #[tracing::instrument]
-    async fn list_groups(&self, _req: Request<ListGroupsRequest>) -> ServerResult<GroupResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn list_groups(
+        &self,
+        request: Request<ListGroupsRequest>,
+    ) -> ServerResult<GroupResponse> {
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = GroupRepository::new(self.pool()?.clone());
+
+        let page = self.pages.request(
+            req.page.as_ref(),
+            &viewer.page_scope(PageScope::new("groups")),
+        )?;
+
+        let groups = repo.list(&viewer, &page).await.map_err(GeistError::from)?;
+        let total = repo.count(&viewer).await.map_err(GeistError::from)?;
+        let (groups, page) = self.pages.response(&page, groups, total, |group| {
+            Position::new(group.create_time, group.id)
+        });
+
+        let proto_groups: Result<Vec<_>, _> = groups.iter().map(|g| g.to_proto()).collect();
+
+        Ok(tonic::Response::new(GroupResponse {
+            groups: proto_groups?,
+            page: Some(page),
+        }))
     }
 
-    #[tracing::instrument]
-    async fn create_group(&self, _req: Request<MutateGroupRequest>) -> ServerResult<GroupResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn create_group(
+        &self,
+        request: Request<MutateGroupRequest>,
+    ) -> ServerResult<GroupResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = GroupRepository::new(self.pool()?.clone());
+
+        let group = req
+            .group
+            .ok_or_else(|| Status::invalid_argument("Group must be provided"))?;
+
+        if group.name.is_empty() {
+            return Err(Status::invalid_argument("Group name must be provided"));
+        }
+
+        let slug = if group.slug.is_empty() {
+            slugify(&group.name)
+        } else {
+            group.slug
+        };
+        validate_slug(&slug)?;
+
+        let create_group = CreateGroup {
+            visibility: visibility_to_str(group.visibility)?.to_string(),
+            name: group.name,
+            description: non_empty(group.description),
+            slug,
+            icon_url: non_empty(group.icon_url),
+            owner_id: principal.user_uid,
+        };
+
+        let created = repo.create(&create_group).await.map_err(map_write_error)?;
+
+        Ok(tonic::Response::new(GroupResponse {
+            groups: vec![created.to_proto()?],
+            page: None,
+        }))
     }
 
-    #[tracing::instrument]
-    async fn update_group(&self, _req: Request<MutateGroupRequest>) -> ServerResult<GroupResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn update_group(
+        &self,
+        request: Request<MutateGroupRequest>,
+    ) -> ServerResult<GroupResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = GroupRepository::new(self.pool()?.clone());
+
+        let group = req
+            .group
+            .ok_or_else(|| Status::invalid_argument("Group must be provided"))?;
+        let existing = self.find_target(&repo, &group).await?;
+        if !self.is_visible_to(&existing, &viewer).await? {
+            return Err(Status::not_found("Group not found"));
+        }
+        let role = self.role_of(existing.id, principal.user_uid).await?;
+        authorize_role(&principal, role, GroupRole::Admin)?;
+
+        // Renames are only possible when the group was not addressed by that same field.
+        let addressed_by_uid = !group.uid.is_empty();
+        let addressed_by_slug = !addressed_by_uid && !group.slug.is_empty();
+
+        let slug = if addressed_by_uid {
+            non_empty(group.slug)
+        } else {
+            None
+        };
+        if let Some(slug) = &slug {
+            validate_slug(slug)?;
+        }
+
+        let update_group = UpdateGroup {
+            name: if addressed_by_uid || addressed_by_slug {
+                non_empty(group.name)
+            } else {
+                None
+            },
+            description: non_empty(group.description),
+            slug,
+            icon_url: non_empty(group.icon_url),
+            visibility: match group.visibility {
+                x if x == Visibility::Unspecified as i32 => None,
+                x => Some(visibility_to_str(x)?.to_string()),
+            },
+        };
+
+        let updated = repo
+            .update(existing.id, &update_group)
+            .await
+            .map_err(map_write_error)?
+            .ok_or_else(|| Status::not_found("Group not found"))?;
+
+        Ok(tonic::Response::new(GroupResponse {
+            groups: vec![updated.to_proto()?],
+            page: None,
+        }))
     }
 
-    #[tracing::instrument]
-    async fn delete_group(&self, _req: Request<MutateGroupRequest>) -> ServerResult<GroupResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn delete_group(
+        &self,
+        request: Request<MutateGroupRequest>,
+    ) -> ServerResult<GroupResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = GroupRepository::new(self.pool()?.clone());
+
+        let group = req
+            .group
+            .ok_or_else(|| Status::invalid_argument("Group must be provided"))?;
+        let existing = self.find_target(&repo, &group).await?;
+        if !self.is_visible_to(&existing, &viewer).await? {
+            return Err(Status::not_found("Group not found"));
+        }
+        let role = self.role_of(existing.id, principal.user_uid).await?;
+        authorize_role(&principal, role, GroupRole::Owner)?;
+
+        if !repo.delete(existing.id).await.map_err(GeistError::from)? {
+            return Err(Status::not_found("Group not found"));
+        }
+
+        Ok(tonic::Response::new(GroupResponse {
+            groups: vec![existing.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_member(
+        &self,
+        request: Request<AddMemberRequest>,
+    ) -> ServerResult<GroupMemberResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+
+        let (group, caller_role) = self.find_with_role(&req.group_uid, &principal).await?;
+        let user_id = require_uid(&req.user_uid, "user_uid")?;
+        let role = GroupRole::from_proto(req.role)?.unwrap_or(GroupRole::Member);
+
+        // Admins add members, only owners can add other owners and admins.
+        let required = if role.can_manage() {
+            GroupRole::Owner
+        } else {
+            GroupRole::Admin
+        };
+        authorize_role(&principal, caller_role, required)?;
+
+        let member = GroupMemberRepository::new(self.pool()?.clone())
+            .add(group.id, user_id, role)
+            .await?;
+
+        Ok(tonic::Response::new(GroupMemberResponse {
+            members: vec![member.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn remove_member(
+        &self,
+        request: Request<RemoveMemberRequest>,
+    ) -> ServerResult<GroupMemberResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = GroupMemberRepository::new(self.pool()?.clone());
+
+        let (group, caller_role) = self.find_with_role(&req.group_uid, &principal).await?;
+        let user_id = require_uid(&req.user_uid, "user_uid")?;
+
+        // Anyone can leave a group; removing someone else follows the same rules as adding them.
+        if user_id != principal.user_uid {
+            let member_role = self
+                .role_of(group.id, user_id)
+                .await?
+                .ok_or_else(|| Status::not_found("Member not found"))?;
+            let required = if member_role.can_manage() {
+                GroupRole::Owner
+            } else {
+                GroupRole::Admin
+            };
+            authorize_role(&principal, caller_role, required)?;
+        }
+
+        let member = repo.remove(group.id, user_id).await?;
+
+        Ok(tonic::Response::new(GroupMemberResponse {
+            members: vec![member.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_members(
+        &self,
+        request: Request<ListMembersRequest>,
+    ) -> ServerResult<GroupMemberResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = GroupMemberRepository::new(self.pool()?.clone());
+
+        let (group, caller_role) = self.find_with_role(&req.group_uid, &principal).await?;
+        authorize_role(&principal, caller_role, GroupRole::Member)?;
+
+        let role = GroupRole::from_proto(req.role)?;
+        let page = self.pages.request(
+            req.page.as_ref(),
+            &PageScope::new("members")
+                .with("group", group.id)
+                .with_opt("role", role.map(|role| role.as_str())),
+        )?;
+
+        let members = repo
+            .list(group.id, role, &page)
+            .await
+            .map_err(GeistError::from)?;
+        let total = repo.count(group.id, role).await.map_err(GeistError::from)?;
+        let (members, page) = self.pages.response(&page, members, total, |member| {
+            Position::with_key(member.role.clone(), member.create_time, member.user_id)
+        });
+
+        let proto_members: Result<Vec<_>, _> = members.iter().map(|m| m.to_proto()).collect();
+
+        Ok(tonic::Response::new(GroupMemberResponse {
+            members: proto_members?,
+            page: Some(page),
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn update_member_role(
+        &self,
+        request: Request<UpdateMemberRoleRequest>,
+    ) -> ServerResult<GroupMemberResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+
+        let (group, caller_role) = self.find_with_role(&req.group_uid, &principal).await?;
+        let user_id = require_uid(&req.user_uid, "user_uid")?;
+        let role = GroupRole::from_proto(req.role)?
+            .ok_or_else(|| Status::invalid_argument("role is required"))?;
+
+        let member_role = self
+            .role_of(group.id, user_id)
+            .await?
+            .ok_or_else(|| Status::not_found("Member not found"))?;
+
+        // Promoting to, or demoting from, owner or admin is up to the owners.
+        let required = if role.can_manage() || member_role.can_manage() {
+            GroupRole::Owner
+        } else {
+            GroupRole::Admin
+        };
+        authorize_role(&principal, caller_role, required)?;
+
+        let member = GroupMemberRepository::new(self.pool()?.clone())
+            .update_role(group.id, user_id, role)
+            .await?;
+
+        Ok(tonic::Response::new(GroupMemberResponse {
+            members: vec![member.to_proto()?],
+            page: None,
+        }))
     }
 }
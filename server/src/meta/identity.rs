@@ -1,19 +1,32 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::{AuditAction, AuditContext};
+use crate::auth::{
+    self, authorize_user_uid, check_policy, hash_password, normalize_email, verify_password,
+    PasswordRepository, PasswordReset, RefreshTokenRepository,
+};
+use crate::error::GeistError;
+use crate::validate;
 use crate::ServerResult;
 use chrono::{DateTime, Utc};
 use geist_sdk::pb::meta::v1alpha::{
-    identity_provider::IdentityProvider as ProtoIdentityProvider,
-    identity_service_server::IdentityService, Identity, IdentityProvider, IdentityRequest,
-    IdentityResponse, LinkIdentityRequest, ListIdentitiesRequest, SetPrimaryIdentityRequest,
+    identity_service_server::IdentityService, ChangePasswordRequest, Identity,
+    IdentityProvider as ProtoIdentityProvider, IdentityRequest, IdentityResponse,
+    ListIdentitiesRequest, RegisterPasswordRequest, SetPrimaryIdentityRequest,
     UnlinkIdentityRequest,
 };
 use prost_types::Timestamp;
+use rand::Rng;
 use serde_json::Value;
-use sqlx::{FromRow, PgPool, Postgres, Row};
+use sqlx::{FromRow, PgConnection, PgExecutor, PgPool};
+use std::sync::Arc;
 use tonic::{Request, Status};
 use uuid::Uuid;
 
+/// Longest username derived from a provider profile.
+const MAX_USERNAME_LEN: usize = 32;
+
 #[derive(Debug, Clone, FromRow)]
 pub struct UserIdentity {
     pub id: Uuid,
@@ -23,19 +36,18 @@ pub struct UserIdentity {
     pub provider_email: Option<String>,
     pub provider_username: Option<String>,
     pub provider_avatar_url: Option<String>,
-    pub access_token_encrypted: Option<String>,
-    pub refresh_token_encrypted: Option<String>,
-    pub token_expires_at: Option<DateTime<Utc>>,
-    pub metadata: Option<Value>,
     pub is_primary: bool,
     pub verified: bool,
     pub create_time: DateTime<Utc>,
     pub update_time: DateTime<Utc>,
     pub last_used_at: Option<DateTime<Utc>>,
+    /// The provider's refresh token stopped working, so the user has to sign in with the
+    /// provider again.
+    pub needs_reauth: bool,
 }
 
 impl UserIdentity {
-    fn to_proto(&self) -> Result<Identity, Status> {
+    pub(crate) fn to_proto(&self) -> Result<Identity, Status> {
         let provider = match self.provider.as_str() {
             "google" => ProtoIdentityProvider::Google as i32,
             "github" => ProtoIdentityProvider::Github as i32,
@@ -50,7 +62,7 @@ fn to_proto(&self) -> Result<Identity, Status> {
         Ok(Identity {
             uid: self.id.to_string(),
             user_uid: self.user_id.to_string(),
-            provider: provider,
+            provider,
             provider_user_id: self.provider_user_id.clone(),
             provider_email: self.provider_email.clone().unwrap_or_default(),
             provider_username: self.provider_username.clone().unwrap_or_default(),
@@ -69,6 +81,7 @@ fn to_proto(&self) -> Result<Identity, Status> {
                 seconds: dt.timestamp(),
                 nanos: dt.timestamp_subsec_nanos() as i32,
             }),
+            needs_reauth: self.needs_reauth,
         })
     }
 
@@ -98,10 +111,10 @@ pub fn new(pool: PgPool) -> Self {
     pub async fn find_by_id(&self, id: Uuid) -> Result<Option<UserIdentity>, sqlx::Error> {
         sqlx::query_as::<_, UserIdentity>(
             r#"
-            SELECT id, user_id, provider, provider_user_id, provider_email, 
+            SELECT id, user_id, provider::text AS provider, provider_user_id, provider_email,
                    provider_username, provider_avatar_url, access_token_encrypted,
                    refresh_token_encrypted, token_expires_at, metadata, is_primary,
-                   verified, create_time, update_time, last_used_at
+                   verified, create_time, update_time, last_used_at, needs_reauth
             FROM public.user_identities
             WHERE id = $1
             "#,
@@ -118,12 +131,12 @@ pub async fn find_by_provider(
     ) -> Result<Option<UserIdentity>, sqlx::Error> {
         sqlx::query_as::<_, UserIdentity>(
             r#"
-            SELECT id, user_id, provider, provider_user_id, provider_email, 
+            SELECT id, user_id, provider::text AS provider, provider_user_id, provider_email,
                    provider_username, provider_avatar_url, access_token_encrypted,
                    refresh_token_encrypted, token_expires_at, metadata, is_primary,
-                   verified, create_time, update_time, last_used_at
+                   verified, create_time, update_time, last_used_at, needs_reauth
             FROM public.user_identities
-            WHERE provider = $1 AND provider_user_id = $2
+            WHERE provider = $1::identity_provider AND provider_user_id = $2
             "#,
         )
         .bind(provider)
@@ -135,10 +148,10 @@ pub async fn find_by_provider(
     pub async fn find_by_user_id(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error> {
         sqlx::query_as::<_, UserIdentity>(
             r#"
-            SELECT id, user_id, provider, provider_user_id, provider_email, 
+            SELECT id, user_id, provider::text AS provider, provider_user_id, provider_email,
                    provider_username, provider_avatar_url, access_token_encrypted,
                    refresh_token_encrypted, token_expires_at, metadata, is_primary,
-                   verified, create_time, update_time, last_used_at
+                   verified, create_time, update_time, last_used_at, needs_reauth
             FROM public.user_identities
             WHERE user_id = $1
             ORDER BY is_primary DESC, create_time ASC
@@ -149,9 +162,76 @@ pub async fn find_by_user_id(&self, user_id: Uuid) -> Result<Vec<UserIdentity>,
         .await
     }
 
-    pub async fn create(&self, identity: &CreateIdentity) -> Result<UserIdentity, sqlx::Error> {
+    /// A page of a user's identities, primary first.
+    pub async fn list(
+        &self,
+        user_id: Uuid,
+        page: &PageRequest,
+    ) -> Result<Vec<UserIdentity>, sqlx::Error> {
+        sqlx::query_as::<_, UserIdentity>(
+            r#"
+            SELECT id, user_id, provider::text AS provider, provider_user_id, provider_email,
+                   provider_username, provider_avatar_url, access_token_encrypted,
+                   refresh_token_encrypted, token_expires_at, metadata, is_primary,
+                   verified, create_time, update_time, last_used_at, needs_reauth
+            FROM public.user_identities
+            WHERE user_id = $1
+              AND ($2::text IS NULL
+                   OR (NOT is_primary, create_time, id) > ($2::boolean, $3, $4))
+            ORDER BY is_primary DESC, create_time ASC, id ASC
+            OFFSET $5
+            LIMIT $6
+            "#,
+        )
+        .bind(user_id)
+        .bind(page.after_key())
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM public.user_identities
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    pub async fn find_by_user_ids(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<UserIdentity>, sqlx::Error> {
+        sqlx::query_as::<_, UserIdentity>(
+            r#"
+            SELECT id, user_id, provider::text AS provider, provider_user_id, provider_email,
+                   provider_username, provider_avatar_url, access_token_encrypted,
+                   refresh_token_encrypted, token_expires_at, metadata, is_primary,
+                   verified, create_time, update_time, last_used_at, needs_reauth
+            FROM public.user_identities
+            WHERE user_id = ANY($1)
+            ORDER BY is_primary DESC, create_time ASC
+            "#,
+        )
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub(crate) async fn insert<'e, E: PgExecutor<'e>>(
+        executor: E,
+        identity: &CreateIdentity,
+    ) -> Result<UserIdentity, sqlx::Error> {
         let now = Utc::now();
-        let id = Uuid::now_v7();
 
         sqlx::query_as::<_, UserIdentity>(
             r#"
@@ -160,14 +240,14 @@ pub async fn create(&self, identity: &CreateIdentity) -> Result<UserIdentity, sq
                  provider_username, provider_avatar_url, access_token_encrypted,
                  refresh_token_encrypted, token_expires_at, metadata, is_primary,
                  verified, create_time, update_time)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
-            RETURNING id, user_id, provider, provider_user_id, provider_email, 
+            VALUES ($1, $2, $3::identity_provider, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            RETURNING id, user_id, provider::text AS provider, provider_user_id, provider_email,
                       provider_username, provider_avatar_url, access_token_encrypted,
                       refresh_token_encrypted, token_expires_at, metadata, is_primary,
-                      verified, create_time, update_time, last_used_at
+                      verified, create_time, update_time, last_used_at, needs_reauth
             "#,
         )
-        .bind(id)
+        .bind(identity.id)
         .bind(identity.user_id)
         .bind(&identity.provider)
         .bind(&identity.provider_user_id)
@@ -182,28 +262,252 @@ pub async fn create(&self, identity: &CreateIdentity) -> Result<UserIdentity, sq
         .bind(identity.verified)
         .bind(now)
         .bind(now)
-        .fetch_one(&self.pool)
+        .fetch_one(executor)
         .await
     }
 
-    pub async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query(
+    /// Create the user `identity.user_id` on its first sign-in, with `identity` as its primary
+    /// identity. If a user's primary email is the verified provider email, nothing is created:
+    /// the identity may only join that user once they confirm it, see `merge`.
+    pub async fn link_new_user(
+        &self,
+        identity: &CreateIdentity,
+    ) -> Result<LinkOutcome, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let matching_user = match &identity.provider_email {
+            Some(email) if identity.verified => {
+                sqlx::query_as::<_, (Uuid,)>(
+                    r#"
+                    SELECT id FROM public.users
+                    WHERE primary_email = $1::citext
+                    LIMIT 1
+                    FOR UPDATE
+                    "#,
+                )
+                .bind(email)
+                .fetch_optional(&mut *tx)
+                .await?
+            }
+            _ => None,
+        };
+        if let Some((user_id,)) = matching_user {
+            return Ok(LinkOutcome::MergeRequired(user_id));
+        }
+
+        let created = Self::insert_with_user(&mut tx, identity).await?;
+        tx.commit().await?;
+
+        Ok(LinkOutcome::Created(created))
+    }
+
+    /// Attach `identity` to the user `identity.user_id` once they confirmed the merge, as the
+    /// primary identity if they have none. Returns `None` without attaching anything when the
+    /// user's primary email is no longer the provider email.
+    pub(crate) async fn merge(
+        tx: &mut PgConnection,
+        identity: &CreateIdentity,
+    ) -> Result<Option<UserIdentity>, sqlx::Error> {
+        let matching_user: Option<(bool,)> = sqlx::query_as(
             r#"
-            DELETE FROM public.user_identities
-            WHERE id = $1 AND user_id = $2
+            SELECT EXISTS (SELECT 1 FROM public.user_identities WHERE user_id = u.id)
+            FROM public.users u
+            WHERE u.id = $1 AND u.primary_email = $2::citext
+            FOR UPDATE
+            "#,
+        )
+        .bind(identity.user_id)
+        .bind(&identity.provider_email)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((has_identities,)) = matching_user else {
+            return Ok(None);
+        };
+
+        let merged = CreateIdentity {
+            is_primary: !has_identities,
+            ..identity.clone()
+        };
+        let created = Self::insert(&mut *tx, &merged).await?;
+        if created.is_primary {
+            Self::make_primary(tx, created.id, created.user_id).await?;
+        }
+
+        Ok(Some(created))
+    }
+
+    /// Add `identity` to its existing user, as the primary identity if the user has none.
+    pub async fn attach(&self, identity: &CreateIdentity) -> Result<UserIdentity, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let created = Self::attach_in(&mut tx, identity).await?;
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    /// [`Self::attach`] within the transaction `tx`.
+    pub(crate) async fn attach_in(
+        tx: &mut PgConnection,
+        identity: &CreateIdentity,
+    ) -> Result<UserIdentity, sqlx::Error> {
+        // Serializes links to the same user, so only one of them can become primary.
+        let (has_identities,): (bool,) = sqlx::query_as(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM public.user_identities WHERE user_id = u.id)
+            FROM public.users u
+            WHERE u.id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(identity.user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let attached = CreateIdentity {
+            is_primary: !has_identities,
+            ..identity.clone()
+        };
+        let created = Self::insert(&mut *tx, &attached).await?;
+
+        if created.is_primary {
+            Self::make_primary(tx, created.id, created.user_id).await?;
+        }
+
+        Ok(created)
+    }
+
+    /// Create the user `identity.user_id` with `identity` as its primary identity. The username
+    /// comes from the provider profile and gets a numeric suffix when taken. The provider's
+    /// email is only copied to the user when it is verified and no other user has it yet.
+    pub(crate) async fn insert_with_user(
+        tx: &mut PgConnection,
+        identity: &CreateIdentity,
+    ) -> Result<UserIdentity, sqlx::Error> {
+        let username_hint = identity.username_hint();
+        let mut username = username_hint.clone();
+        loop {
+            let (taken,): (bool,) = sqlx::query_as(
+                r#"
+                SELECT EXISTS (
+                    SELECT 1 FROM public.users
+                    WHERE username = $1::citext OR name = $1::citext
+                )
+                "#,
+            )
+            .bind(&username)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if !taken {
+                break;
+            }
+            username = format!("{}{}", username_hint, rand::rng().random_range(1000..10000));
+        }
+
+        let email = match &identity.provider_email {
+            Some(email) if identity.verified => {
+                let (taken,): (bool,) = sqlx::query_as(
+                    r#"
+                    SELECT EXISTS (
+                        SELECT 1 FROM public.users
+                        WHERE email = $1::citext OR primary_email = $1::citext
+                    )
+                    "#,
+                )
+                .bind(email)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                (!taken).then(|| email.clone())
+            }
+            _ => None,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO public.users
+                (id, name, email, primary_email, username, avatar_url, create_time, update_time)
+            VALUES ($1, $2, $3, $3, $2, $4, now(), now())
+            "#,
+        )
+        .bind(identity.user_id)
+        .bind(&username)
+        .bind(email)
+        .bind(&identity.provider_avatar_url)
+        .execute(&mut *tx)
+        .await?;
+
+        let created = Self::insert(&mut *tx, identity).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE public.users
+            SET primary_identity_id = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(created.id)
+        .bind(created.user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(created)
+    }
+
+    /// Refresh an identity with what its provider returned on sign-in. Absent values keep
+    /// their stored value, except that a new access token replaces the old expiry time and
+    /// clears any earlier token refresh failure.
+    pub async fn update_from_provider(
+        &self,
+        id: Uuid,
+        identity: &CreateIdentity,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.user_identities
+            SET provider_email = COALESCE($2, provider_email),
+                provider_username = COALESCE($3, provider_username),
+                provider_avatar_url = COALESCE($4, provider_avatar_url),
+                token_expires_at = CASE WHEN $5::text IS NULL THEN token_expires_at ELSE $7 END,
+                access_token_encrypted = COALESCE($5, access_token_encrypted),
+                refresh_token_encrypted = COALESCE($6, refresh_token_encrypted),
+                needs_reauth = needs_reauth AND $5::text IS NULL,
+                token_refresh_failures = CASE WHEN $5::text IS NULL
+                    THEN token_refresh_failures ELSE 0 END,
+                token_refresh_error = CASE WHEN $5::text IS NULL
+                    THEN token_refresh_error ELSE NULL END,
+                verified = $8,
+                update_time = now()
+            WHERE id = $1
             "#,
         )
         .bind(id)
-        .bind(user_id)
+        .bind(&identity.provider_email)
+        .bind(&identity.provider_username)
+        .bind(&identity.provider_avatar_url)
+        .bind(&identity.access_token_encrypted)
+        .bind(&identity.refresh_token_encrypted)
+        .bind(identity.token_expires_at)
+        .bind(identity.verified)
         .execute(&self.pool)
         .await?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(())
     }
 
     pub async fn set_primary(&self, id: Uuid, user_id: Uuid) -> Result<(), sqlx::Error> {
         let mut tx = self.pool.begin().await?;
+        Self::set_primary_in(&mut tx, id, user_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
 
+    async fn set_primary_in(
+        tx: &mut PgConnection,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
         // Unset all primary flags for this user
         sqlx::query(
             r#"
@@ -229,12 +533,53 @@ pub async fn set_primary(&self, id: Uuid, user_id: Uuid) -> Result<(), sqlx::Err
         .execute(&mut *tx)
         .await?;
 
-        // Update user's primary_identity_id
+        Self::make_primary(tx, id, user_id).await
+    }
+
+    /// Delete the identity `id` of `user_id`, refusing to delete the user's last identity. If
+    /// it was the primary identity, the next one in [`Self::find_by_user_id`] order becomes
+    /// primary and is returned. Locks the user's identities, so concurrent unlinks cannot each
+    /// see another identity left and together remove them all.
+    pub async fn unlink(&self, id: Uuid, user_id: Uuid) -> Result<Option<Uuid>, UnlinkError> {
+        let mut tx = self.pool.begin().await?;
+
+        let identities: Vec<(Uuid, bool)> = sqlx::query_as(
+            r#"
+            SELECT id, is_primary
+            FROM public.user_identities
+            WHERE user_id = $1
+            ORDER BY is_primary DESC, create_time ASC
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let (_, is_primary) = identities
+            .iter()
+            .find(|(identity_id, _)| *identity_id == id)
+            .ok_or(UnlinkError::NotFound)?;
+        if identities.len() <= 1 {
+            return Err(UnlinkError::LastIdentity);
+        }
+
+        let new_primary = if *is_primary {
+            identities
+                .iter()
+                .map(|(identity_id, _)| *identity_id)
+                .find(|identity_id| *identity_id != id)
+        } else {
+            None
+        };
+        if let Some(new_primary) = new_primary {
+            Self::set_primary_in(&mut tx, new_primary, user_id).await?;
+        }
+
         sqlx::query(
             r#"
-            UPDATE public.users
-            SET primary_identity_id = $1, update_time = now()
-            WHERE id = $2
+            DELETE FROM public.user_identities
+            WHERE id = $1 AND user_id = $2
             "#,
         )
         .bind(id)
@@ -243,6 +588,38 @@ pub async fn set_primary(&self, id: Uuid, user_id: Uuid) -> Result<(), sqlx::Err
         .await?;
 
         tx.commit().await?;
+        Ok(new_primary)
+    }
+
+    /// Point the user at its primary identity. The user's primary email follows the identity's
+    /// email when the provider verified it and no other user has it, since identities are matched
+    /// to users by that address.
+    async fn make_primary(
+        tx: &mut PgConnection,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE public.users u
+            SET primary_identity_id = i.id,
+                primary_email = CASE
+                    WHEN i.verified AND i.provider_email IS NOT NULL AND NOT EXISTS (
+                        SELECT 1 FROM public.users o
+                        WHERE o.id <> u.id AND o.primary_email = i.provider_email
+                    ) THEN i.provider_email
+                    ELSE u.primary_email
+                END,
+                update_time = now()
+            FROM public.user_identities i
+            WHERE i.id = $1 AND i.user_id = u.id AND u.id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
         Ok(())
     }
 
@@ -260,23 +637,40 @@ pub async fn update_last_used(&self, id: Uuid) -> Result<(), sqlx::Error> {
 
         Ok(())
     }
+}
 
-    pub async fn count_by_user_id(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
-        let count: (i64,) = sqlx::query_as(
-            r#"
-            SELECT COUNT(*) FROM public.user_identities
-            WHERE user_id = $1
-            "#,
-        )
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await?;
+/// Why an identity could not be unlinked.
+#[derive(Debug)]
+pub enum UnlinkError {
+    NotFound,
+    /// The user would be left without a way to sign in.
+    LastIdentity,
+    Database(sqlx::Error),
+}
 
-        Ok(count.0)
+impl From<sqlx::Error> for UnlinkError {
+    fn from(e: sqlx::Error) -> Self {
+        UnlinkError::Database(e)
     }
 }
 
+impl From<UnlinkError> for Status {
+    fn from(e: UnlinkError) -> Self {
+        match e {
+            UnlinkError::NotFound => GeistError::NotFound("Identity not found".to_string()),
+            UnlinkError::LastIdentity => GeistError::FailedPrecondition(
+                "Cannot unlink the last identity for a user".to_string(),
+            ),
+            UnlinkError::Database(db) => GeistError::from(db),
+        }
+        .into()
+    }
+}
+
+#[derive(Clone)]
 pub struct CreateIdentity {
+    /// Chosen before the identity is stored, since its encrypted tokens are bound to it.
+    pub id: Uuid,
     pub user_id: Uuid,
     pub provider: String,
     pub provider_user_id: String,
@@ -291,14 +685,58 @@ pub struct CreateIdentity {
     pub verified: bool,
 }
 
+/// Result of signing in with an identity that has no user yet.
+#[derive(Debug)]
+pub enum LinkOutcome {
+    /// A new user was created for the identity.
+    Created(UserIdentity),
+    /// The verified provider email is the primary email of this user, who has to confirm the
+    /// merge.
+    MergeRequired(Uuid),
+}
+
+impl CreateIdentity {
+    /// A starting point for the username of a user created from this identity.
+    fn username_hint(&self) -> String {
+        let source = self
+            .provider_username
+            .as_deref()
+            .or_else(|| {
+                self.provider_email
+                    .as_deref()
+                    .and_then(|email| email.split('@').next())
+            })
+            .unwrap_or_default();
+
+        let hint: String = source
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            .take(MAX_USERNAME_LEN)
+            .collect();
+
+        if hint.is_empty() {
+            "user".to_string()
+        } else {
+            hint
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct IdentityServer {
     pool: Option<PgPool>,
+    pages: Paginator,
+    password_reset: Option<Arc<PasswordReset>>,
 }
 
 impl IdentityServer {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool: Some(pool) }
+    pub fn new(pool: PgPool, pages: Paginator, password_reset: Arc<PasswordReset>) -> Self {
+        Self {
+            pool: Some(pool),
+            pages,
+            password_reset: Some(password_reset),
+        }
     }
 
     fn pool(&self) -> Result<&PgPool, Status> {
@@ -306,6 +744,21 @@ fn pool(&self) -> Result<&PgPool, Status> {
             .as_ref()
             .ok_or_else(|| Status::internal("Database pool not initialized"))
     }
+
+    fn password_reset(&self) -> Result<&Arc<PasswordReset>, Status> {
+        self.password_reset
+            .as_ref()
+            .ok_or_else(|| Status::internal("Password reset not initialized"))
+    }
+
+    /// Parse `user_uid` and check that it is the caller, since passwords can only be managed
+    /// by their own user.
+    fn own_user<T>(request: &Request<T>, user_uid: &str) -> Result<Uuid, Status> {
+        let user_id = validate::parse_uuid("user_uid", user_uid)?;
+        auth::principal(request)?.authorize_self(user_id)?;
+
+        Ok(user_id)
+    }
 }
 
 #[tonic::async_trait]
@@ -315,6 +768,7 @@ async fn get_identity(
         &self,
         request: Request<IdentityRequest>,
     ) -> ServerResult<IdentityResponse> {
+        let principal = auth::principal(&request)?.clone();
         let req = request.into_inner();
         let repo = IdentityRepository::new(self.pool()?.clone());
 
@@ -322,19 +776,16 @@ async fn get_identity(
         // We'll use a helper to extract the value
         let identity = match req.params {
             Some(geist_sdk::pb::meta::v1alpha::identity_request::Params::Uid(uid)) => {
-                let id = Uuid::parse_str(&uid)
-                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
-                repo.find_by_id(id)
-                    .await
-                    .map_err(|e| Status::internal(format!("Database error: {}", e)))?
+                let id = validate::parse_uuid("uid", &uid)?;
+                repo.find_by_id(id).await.map_err(GeistError::from)?
             }
             Some(geist_sdk::pb::meta::v1alpha::identity_request::Params::UserUid(user_uid)) => {
-                let user_id = Uuid::parse_str(&user_uid)
-                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+                let user_id = validate::parse_uuid("user_uid", &user_uid)?;
+                principal.authorize_user(user_id)?;
                 let identities = repo
                     .find_by_user_id(user_id)
                     .await
-                    .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+                    .map_err(GeistError::from)?;
                 if identities.is_empty() {
                     None
                 } else {
@@ -347,7 +798,7 @@ async fn get_identity(
                 let provider = UserIdentity::provider_to_string(req.provider)?;
                 repo.find_by_provider(&provider, &provider_user_id)
                     .await
-                    .map_err(|e| Status::internal(format!("Database error: {}", e)))?
+                    .map_err(GeistError::from)?
             }
             None => {
                 return Err(Status::invalid_argument(
@@ -357,6 +808,7 @@ async fn get_identity(
         };
 
         let identities = if let Some(ident) = identity {
+            principal.authorize_user(ident.user_id)?;
             vec![ident.to_proto()?]
         } else {
             vec![]
@@ -373,203 +825,156 @@ async fn list_identities(
         &self,
         request: Request<ListIdentitiesRequest>,
     ) -> ServerResult<IdentityResponse> {
-        let req = request.into_inner();
+        let user_id = authorize_user_uid(&request, &request.get_ref().user_uid)?;
         let repo = IdentityRepository::new(self.pool()?.clone());
 
-        let user_id = Uuid::parse_str(&req.user_uid)
-            .map_err(|e| Status::invalid_argument(format!("Invalid user UUID: {}", e)))?;
-
-        let identities = repo
-            .find_by_user_id(user_id)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+        let page = self.pages.request(
+            request.get_ref().page.as_ref(),
+            &PageScope::new("identities").with("user", user_id),
+        )?;
+
+        let identities = repo.list(user_id, &page).await.map_err(GeistError::from)?;
+        let total = repo.count(user_id).await.map_err(GeistError::from)?;
+        let (identities, page) = self.pages.response(&page, identities, total, |identity| {
+            Position::with_key(
+                (!identity.is_primary).to_string(),
+                identity.create_time,
+                identity.id,
+            )
+        });
 
         let proto_identities: Result<Vec<_>, _> = identities.iter().map(|i| i.to_proto()).collect();
         let proto_identities = proto_identities?;
 
         Ok(tonic::Response::new(IdentityResponse {
             identities: proto_identities,
-            page: None,
+            page: Some(page),
         }))
     }
 
     #[tracing::instrument(skip(self))]
-    async fn link_identity(
+    async fn unlink_identity(
         &self,
-        request: Request<LinkIdentityRequest>,
+        request: Request<UnlinkIdentityRequest>,
     ) -> ServerResult<IdentityResponse> {
+        let user_id = authorize_user_uid(&request, &request.get_ref().user_uid)?;
+        let audit = AuditContext::from_request(&request);
         let req = request.into_inner();
         let repo = IdentityRepository::new(self.pool()?.clone());
 
-        let provider = UserIdentity::provider_to_string(req.provider)?;
+        let identity_id = validate::parse_uuid("identity_uid", &req.identity_uid)?;
 
-        // Check if identity already exists
-        if let Some(existing) = repo
-            .find_by_provider(&provider, &req.provider_user_id)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?
-        {
-            // Update last used
-            repo.update_last_used(existing.id)
-                .await
-                .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+        let new_primary = repo.unlink(identity_id, user_id).await?;
 
-            let updated = repo
-                .find_by_id(existing.id)
-                .await
-                .map_err(|e| Status::internal(format!("Database error: {}", e)))?
-                .ok_or_else(|| Status::internal("Identity not found after update"))?;
-
-            return Ok(tonic::Response::new(IdentityResponse {
-                identities: vec![updated.to_proto()?],
-                page: None,
-            }));
+        if let Some(new_primary) = new_primary {
+            audit
+                .record(
+                    self.pool()?,
+                    AuditAction::PrimaryIdentityChanged,
+                    user_id,
+                    Some(new_primary),
+                )
+                .await;
         }
 
-        // Determine user_id
-        let user_id = if let Some(user_uid) = req.user_uid {
-            Uuid::parse_str(&user_uid)
-                .map_err(|e| Status::invalid_argument(format!("Invalid user UUID: {}", e)))?
-        } else {
-            // Check if user exists by email (for account merging)
-            // For now, create a new user - this can be enhanced later
-            // TODO: Implement user creation or lookup logic
-            return Err(Status::unimplemented(
-                "Automatic user creation not yet implemented. Provide user_uid.",
-            ));
-        };
-
-        // Check if user already has this provider linked
-        let existing_identities = repo
-            .find_by_user_id(user_id)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
-
-        let is_primary = existing_identities.is_empty();
-
-        let create_identity = CreateIdentity {
-            user_id,
-            provider,
-            provider_user_id: req.provider_user_id,
-            provider_email: if req.provider_email.is_empty() {
-                None
-            } else {
-                Some(req.provider_email)
-            },
-            provider_username: if req.provider_username.is_empty() {
-                None
-            } else {
-                Some(req.provider_username)
-            },
-            provider_avatar_url: if req.provider_avatar_url.is_empty() {
-                None
-            } else {
-                Some(req.provider_avatar_url)
-            },
-            access_token_encrypted: if req.access_token.is_empty() {
-                None
-            } else {
-                // TODO: Encrypt token before storing
-                Some(req.access_token)
-            },
-            refresh_token_encrypted: if req.refresh_token.is_empty() {
-                None
-            } else {
-                // TODO: Encrypt token before storing
-                Some(req.refresh_token)
-            },
-            token_expires_at: req.token_expires_at.map(|ts| {
-                DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
-                    .unwrap_or_else(Utc::now)
-            }),
-            metadata: None,
-            is_primary,
-            verified: req.verified,
-        };
-
-        let identity = repo
-            .create(&create_identity)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
-
-        // If this is the primary identity, update user's primary_identity_id
-        if is_primary {
-            sqlx::query(
-                r#"
-                UPDATE public.users
-                SET primary_identity_id = $1, update_time = now()
-                WHERE id = $2
-                "#,
+        audit
+            .record(
+                self.pool()?,
+                AuditAction::IdentityUnlinked,
+                user_id,
+                Some(identity_id),
             )
-            .bind(identity.id)
-            .bind(user_id)
-            .execute(self.pool()?)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
-        }
+            .await;
 
         Ok(tonic::Response::new(IdentityResponse {
-            identities: vec![identity.to_proto()?],
+            identities: vec![],
             page: None,
         }))
     }
 
     #[tracing::instrument(skip(self))]
-    async fn unlink_identity(
+    async fn set_primary_identity(
         &self,
-        request: Request<UnlinkIdentityRequest>,
+        request: Request<SetPrimaryIdentityRequest>,
     ) -> ServerResult<IdentityResponse> {
+        let user_id = authorize_user_uid(&request, &request.get_ref().user_uid)?;
+        let audit = AuditContext::from_request(&request);
         let req = request.into_inner();
         let repo = IdentityRepository::new(self.pool()?.clone());
 
-        let identity_id = Uuid::parse_str(&req.identity_uid)
-            .map_err(|e| Status::invalid_argument(format!("Invalid identity UUID: {}", e)))?;
-        let user_id = Uuid::parse_str(&req.user_uid)
-            .map_err(|e| Status::invalid_argument(format!("Invalid user UUID: {}", e)))?;
-
-        // Check if this is the only identity
-        let count = repo
-            .count_by_user_id(user_id)
-            .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
-
-        if count <= 1 {
-            return Err(Status::failed_precondition(
-                "Cannot unlink the last identity for a user",
-            ));
-        }
+        let identity_id = validate::parse_uuid("identity_uid", &req.identity_uid)?;
 
         // Verify the identity belongs to the user
         let identity = repo
             .find_by_id(identity_id)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?
+            .map_err(GeistError::from)?
             .ok_or_else(|| Status::not_found("Identity not found"))?;
 
         if identity.user_id != user_id {
-            return Err(Status::permission_denied(
-                "Identity does not belong to the specified user",
-            ));
+            return Err(auth::permission_denied());
         }
 
-        // If this is the primary identity, set another one as primary
-        if identity.is_primary {
-            let identities = repo
-                .find_by_user_id(user_id)
-                .await
-                .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+        repo.set_primary(identity_id, user_id)
+            .await
+            .map_err(GeistError::from)?;
+
+        audit
+            .record(
+                self.pool()?,
+                AuditAction::PrimaryIdentityChanged,
+                user_id,
+                Some(identity_id),
+            )
+            .await;
 
-            if let Some(new_primary) = identities.iter().find(|i| i.id != identity_id) {
-                repo.set_primary(new_primary.id, user_id)
-                    .await
-                    .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
-            }
-        }
+        let updated = repo
+            .find_by_id(identity_id)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::internal("Identity not found after update"))?;
+
+        Ok(tonic::Response::new(IdentityResponse {
+            identities: vec![updated.to_proto()?],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn register_password(
+        &self,
+        request: Request<RegisterPasswordRequest>,
+    ) -> ServerResult<IdentityResponse> {
+        let user_id = Self::own_user(&request, &request.get_ref().user_uid)?;
+        let req = request.into_inner();
+
+        let email = normalize_email(&req.email)?;
+        check_policy(&req.password, &email)?;
+        let reset = self.password_reset()?.clone();
+        let repo = PasswordRepository::new(self.pool()?.clone());
 
-        // Delete the identity
-        repo.delete(identity_id, user_id)
+        if repo
+            .find_by_user_id(user_id)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+            .map_err(GeistError::from)?
+            .is_some()
+        {
+            return Err(Status::already_exists("The user already has a password"));
+        }
+
+        // The identity is added once the owner of the email verifies it, and the response is
+        // the same whether or not the email is registered to someone else.
+        let password_hash = hash_password(req.password).await?;
+        let registered = repo.find_by_email(&email).await.map_err(GeistError::from)?;
+        if registered.is_none() {
+            let token = repo
+                .create_verification(&email, Some(user_id), None, &password_hash, reset.ttl())
+                .await
+                .map_err(GeistError::from)?;
+
+            tracing::info!(user_uid = %user_id, "Issued email verification token for password");
+            tokio::spawn(async move { reset.send_verification(&email, &token).await });
+        }
 
         Ok(tonic::Response::new(IdentityResponse {
             identities: vec![],
@@ -577,46 +982,267 @@ async fn unlink_identity(
         }))
     }
 
-    #[tracing::instrument(skip(self))]
-    async fn set_primary_identity(
+    #[tracing::instrument(skip_all)]
+    async fn change_password(
         &self,
-        request: Request<SetPrimaryIdentityRequest>,
+        request: Request<ChangePasswordRequest>,
     ) -> ServerResult<IdentityResponse> {
+        let user_id = Self::own_user(&request, &request.get_ref().user_uid)?;
+        let audit = AuditContext::from_request(&request);
         let req = request.into_inner();
-        let repo = IdentityRepository::new(self.pool()?.clone());
+        let pool = self.pool()?.clone();
+        let repo = PasswordRepository::new(pool.clone());
 
-        let identity_id = Uuid::parse_str(&req.identity_uid)
-            .map_err(|e| Status::invalid_argument(format!("Invalid identity UUID: {}", e)))?;
-        let user_id = Uuid::parse_str(&req.user_uid)
-            .map_err(|e| Status::invalid_argument(format!("Invalid user UUID: {}", e)))?;
-
-        // Verify the identity belongs to the user
-        let identity = repo
-            .find_by_id(identity_id)
+        let record = repo
+            .find_by_user_id(user_id)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?
-            .ok_or_else(|| Status::not_found("Identity not found"))?;
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::failed_precondition("The user has no password"))?;
 
-        if identity.user_id != user_id {
-            return Err(Status::permission_denied(
-                "Identity does not belong to the specified user",
-            ));
+        if !verify_password(req.current_password, Some(record.password_hash)).await? {
+            return Err(Status::permission_denied("Current password is incorrect"));
         }
+        check_policy(&req.new_password, &record.email)?;
 
-        repo.set_primary(identity_id, user_id)
+        let password_hash = hash_password(req.new_password).await?;
+        repo.update(record.identity_id, &password_hash)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+            .map_err(GeistError::from)?;
 
-        let updated = repo
-            .find_by_id(identity_id)
+        let revoked = RefreshTokenRepository::new(pool.clone())
+            .revoke_all(user_id)
+            .await
+            .map_err(GeistError::from)?;
+        tracing::info!(user_uid = %user_id, revoked, "Changed password");
+
+        audit
+            .record(
+                &pool,
+                AuditAction::PasswordChanged,
+                user_id,
+                Some(record.identity_id),
+            )
+            .await;
+
+        let identity = IdentityRepository::new(pool)
+            .find_by_id(record.identity_id)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?
+            .map_err(GeistError::from)?
             .ok_or_else(|| Status::internal("Identity not found after update"))?;
 
         Ok(tonic::Response::new(IdentityResponse {
-            identities: vec![updated.to_proto()?],
+            identities: vec![identity.to_proto()?],
             page: None,
         }))
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Principal;
+    use crate::mail::tests::{config, SmtpCapture};
+    use crate::mail::Mailer;
+
+    const PASSWORD: &str = "a long and unusual password";
+
+    fn request<T>(message: T, user_uid: Uuid, scopes: &[&str]) -> Request<T> {
+        let mut request = Request::new(message);
+        request.extensions_mut().insert(Principal {
+            user_uid,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        });
+        request
+    }
+
+    fn google_identity(user_id: Uuid, username: &str) -> CreateIdentity {
+        CreateIdentity {
+            id: Uuid::now_v7(),
+            user_id,
+            provider: "google".to_string(),
+            provider_user_id: username.to_string(),
+            provider_email: Some(format!("{}@example.com", username)),
+            provider_username: Some(username.to_string()),
+            provider_avatar_url: None,
+            access_token_encrypted: None,
+            refresh_token_encrypted: None,
+            token_expires_at: None,
+            metadata: None,
+            is_primary: true,
+            verified: true,
+        }
+    }
+
+    async fn create_user(pool: &PgPool, username: &str) -> Uuid {
+        let mut tx = pool.begin().await.unwrap();
+        let identity = IdentityRepository::insert_with_user(
+            &mut tx,
+            &google_identity(Uuid::now_v7(), username),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        identity.user_id
+    }
+
+    fn server(pool: &PgPool, mail: &SmtpCapture) -> IdentityServer {
+        let config = config(mail);
+        let mailer = Mailer::from_config(&config).unwrap();
+        let password_reset = PasswordReset::from_config(&config, mailer).unwrap();
+        IdentityServer::new(pool.clone(), Paginator::default(), Arc::new(password_reset))
+    }
+
+    fn register(user_id: Uuid, email: &str) -> RegisterPasswordRequest {
+        RegisterPasswordRequest {
+            user_uid: user_id.to_string(),
+            email: email.to_string(),
+            password: PASSWORD.to_string(),
+        }
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn register_password_adds_the_identity_once_the_email_is_verified(pool: PgPool) {
+        let mut mail = SmtpCapture::start().await;
+        let server = server(&pool, &mail);
+        let passwords = PasswordRepository::new(pool.clone());
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+
+        let err = server
+            .register_password(request(register(alice, "bob@work.example.com"), bob, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        let response = server
+            .register_password(request(
+                register(alice, "alice@work.example.com"),
+                alice,
+                &[],
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.identities.is_empty());
+        assert!(passwords.find_by_user_id(alice).await.unwrap().is_none());
+
+        let email = mail.next().await;
+        assert_eq!(email.to, "alice@work.example.com");
+        let created = passwords
+            .verify(&email.link_token())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.user_id, alice);
+        assert!(created.verified);
+        assert!(!created.is_primary);
+
+        let err = server
+            .register_password(request(
+                register(alice, "alice@home.example.com"),
+                alice,
+                &[],
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        // Registering an email someone else has succeeds without sending anything.
+        server
+            .register_password(request(register(bob, "alice@work.example.com"), bob, &[]))
+            .await
+            .unwrap();
+        mail.assert_none().await;
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn change_password_requires_the_current_password(pool: PgPool) {
+        let mut mail = SmtpCapture::start().await;
+        let server = server(&pool, &mail);
+        let passwords = PasswordRepository::new(pool.clone());
+        let alice = create_user(&pool, "alice").await;
+        server
+            .register_password(request(
+                register(alice, "alice@work.example.com"),
+                alice,
+                &[],
+            ))
+            .await
+            .unwrap();
+        passwords
+            .verify(&mail.next().await.link_token())
+            .await
+            .unwrap()
+            .unwrap();
+        let change = |current: &str| ChangePasswordRequest {
+            user_uid: alice.to_string(),
+            current_password: current.to_string(),
+            new_password: "a new and unusual password".to_string(),
+        };
+
+        let err = server
+            .change_password(request(change("a wrong password"), alice, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        server
+            .change_password(request(change(PASSWORD), alice, &[]))
+            .await
+            .unwrap();
+
+        let hash = passwords
+            .find_by_user_id(alice)
+            .await
+            .unwrap()
+            .map(|record| record.password_hash);
+        assert!(!verify_password(PASSWORD.to_string(), hash.clone())
+            .await
+            .unwrap());
+        assert!(
+            verify_password("a new and unusual password".to_string(), hash)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn concurrent_unlinks_keep_one_identity(pool: PgPool) {
+        let repo = IdentityRepository::new(pool.clone());
+        let alice = create_user(&pool, "alice").await;
+        let github = repo
+            .attach(&CreateIdentity {
+                id: Uuid::now_v7(),
+                provider: "github".to_string(),
+                provider_user_id: "alice".to_string(),
+                is_primary: false,
+                ..google_identity(alice, "alice")
+            })
+            .await
+            .unwrap();
+        let google = repo
+            .find_by_provider("google", "alice")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (unlink_google, unlink_github) =
+            tokio::join!(repo.unlink(google.id, alice), repo.unlink(github.id, alice));
+
+        let remaining = repo.find_by_user_id(alice).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert!(remaining[0].is_primary);
+        match (unlink_google, unlink_github) {
+            (Ok(new_primary), Err(UnlinkError::LastIdentity)) => {
+                assert_eq!(new_primary, Some(github.id));
+                assert_eq!(remaining[0].id, github.id);
+            }
+            (Err(UnlinkError::LastIdentity), Ok(new_primary)) => {
+                assert_eq!(new_primary, None);
+                assert_eq!(remaining[0].id, google.id);
+            }
+            other => panic!("expected exactly one unlink to succeed: {:?}", other),
+        }
+    }
+}
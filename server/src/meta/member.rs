@@ -0,0 +1,390 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::group::GroupRecord;
+use super::page::PageRequest;
+use super::to_timestamp;
+use crate::auth::{self, Principal};
+use crate::error::GeistError;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::{GroupMember, GroupMembership, GroupRole as ProtoGroupRole};
+use sqlx::{FromRow, PgConnection, PgPool};
+use tonic::Status;
+use uuid::Uuid;
+
+/// A member's role in a group, ordered from most to least privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl GroupRole {
+    /// The label stored in `group_members.role`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupRole::Owner => "owner",
+            GroupRole::Admin => "admin",
+            GroupRole::Member => "member",
+        }
+    }
+
+    fn from_str(label: &str) -> Result<Self, Status> {
+        match label {
+            "owner" => Ok(GroupRole::Owner),
+            "admin" => Ok(GroupRole::Admin),
+            "member" => Ok(GroupRole::Member),
+            _ => Err(Status::internal("Invalid group role")),
+        }
+    }
+
+    pub fn to_proto(self) -> i32 {
+        let role = match self {
+            GroupRole::Owner => ProtoGroupRole::Owner,
+            GroupRole::Admin => ProtoGroupRole::Admin,
+            GroupRole::Member => ProtoGroupRole::Member,
+        };
+        role as i32
+    }
+
+    /// Convert a requested role, where `GROUP_ROLE_UNSPECIFIED` means none was given.
+    pub fn from_proto(role: i32) -> Result<Option<Self>, Status> {
+        match ProtoGroupRole::try_from(role) {
+            Ok(ProtoGroupRole::Unspecified) => Ok(None),
+            Ok(ProtoGroupRole::Owner) => Ok(Some(GroupRole::Owner)),
+            Ok(ProtoGroupRole::Admin) => Ok(Some(GroupRole::Admin)),
+            Ok(ProtoGroupRole::Member) => Ok(Some(GroupRole::Member)),
+            Err(_) => Err(Status::invalid_argument("Invalid group role")),
+        }
+    }
+
+    /// Whether this role can manage members, and the group itself.
+    pub fn can_manage(&self) -> bool {
+        *self <= GroupRole::Admin
+    }
+}
+
+/// Check that a member's role in a group is at least `required`. Admins pass every check, even
+/// for groups they are not a member of.
+pub(super) fn authorize_role(
+    principal: &Principal,
+    role: Option<GroupRole>,
+    required: GroupRole,
+) -> Result<(), Status> {
+    if principal.is_admin() || role.is_some_and(|role| role <= required) {
+        Ok(())
+    } else {
+        Err(auth::permission_denied())
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct GroupMemberRecord {
+    pub group_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+impl GroupMemberRecord {
+    pub fn role(&self) -> Result<GroupRole, Status> {
+        GroupRole::from_str(&self.role)
+    }
+
+    pub fn to_proto(&self) -> Result<GroupMember, Status> {
+        Ok(GroupMember {
+            group_uid: self.group_id.to_string(),
+            user_uid: self.user_id.to_string(),
+            role: self.role()?.to_proto(),
+            create_time: Some(to_timestamp(self.create_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+        })
+    }
+}
+
+/// A group together with one user's membership of it.
+#[derive(Debug, Clone, FromRow)]
+pub struct MembershipRecord {
+    #[sqlx(flatten)]
+    pub group: GroupRecord,
+    pub member_role: String,
+    pub join_time: DateTime<Utc>,
+}
+
+impl MembershipRecord {
+    pub fn to_proto(&self) -> Result<GroupMembership, Status> {
+        Ok(GroupMembership {
+            group: Some(self.group.to_proto()?),
+            role: GroupRole::from_str(&self.member_role)?.to_proto(),
+            create_time: Some(to_timestamp(self.join_time)),
+        })
+    }
+}
+
+/// Why a membership change was refused.
+#[derive(Debug)]
+pub enum MemberError {
+    NotFound,
+    /// The change would leave the group without an owner.
+    LastOwner,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for MemberError {
+    fn from(e: sqlx::Error) -> Self {
+        MemberError::Database(e)
+    }
+}
+
+impl From<MemberError> for Status {
+    fn from(e: MemberError) -> Self {
+        match e {
+            MemberError::NotFound => GeistError::NotFound("Member not found".to_string()),
+            MemberError::LastOwner => {
+                GeistError::FailedPrecondition("A group must keep at least one owner".to_string())
+            }
+            MemberError::Database(db) => match db {
+                sqlx::Error::Database(ref err) if err.is_unique_violation() => {
+                    GeistError::AlreadyExists(
+                        "The user is already a member of the group".to_string(),
+                    )
+                }
+                sqlx::Error::Database(ref err) if err.is_foreign_key_violation() => {
+                    GeistError::NotFound("User not found".to_string())
+                }
+                _ => GeistError::from(db),
+            },
+        }
+        .into()
+    }
+}
+
+pub struct GroupMemberRepository {
+    pool: PgPool,
+}
+
+impl GroupMemberRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find(
+        &self,
+        group_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<GroupMemberRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupMemberRecord>(
+            r#"
+            SELECT group_id, user_id, role::text AS role, create_time, update_time
+            FROM public.group_members
+            WHERE group_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn list(
+        &self,
+        group_id: Uuid,
+        role: Option<GroupRole>,
+        page: &PageRequest,
+    ) -> Result<Vec<GroupMemberRecord>, sqlx::Error> {
+        sqlx::query_as::<_, GroupMemberRecord>(
+            r#"
+            SELECT group_id, user_id, role::text AS role, create_time, update_time
+            FROM public.group_members
+            WHERE group_id = $1
+              AND ($2::text IS NULL OR role::text = $2)
+              AND ($3::text IS NULL
+                   OR (role, create_time, user_id) > ($3::group_member_role, $4, $5))
+            ORDER BY role ASC, create_time ASC, user_id ASC
+            OFFSET $6
+            LIMIT $7
+            "#,
+        )
+        .bind(group_id)
+        .bind(role.map(|r| r.as_str()))
+        .bind(page.after_key())
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, group_id: Uuid, role: Option<GroupRole>) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM public.group_members
+            WHERE group_id = $1
+              AND ($2::text IS NULL OR role::text = $2)
+            "#,
+        )
+        .bind(group_id)
+        .bind(role.map(|r| r.as_str()))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// The groups a user is a member of, oldest membership first.
+    pub async fn list_by_user(
+        &self,
+        user_id: Uuid,
+        page: &PageRequest,
+    ) -> Result<Vec<MembershipRecord>, sqlx::Error> {
+        sqlx::query_as::<_, MembershipRecord>(
+            r#"
+            SELECT g.id, g.name::text AS name, g.description, g.slug::text AS slug, g.icon_url,
+                   g.visibility::text AS visibility, g.owner_id, g.create_time, g.update_time,
+                   m.role::text AS member_role, m.create_time AS join_time
+            FROM public.group_members m
+            JOIN public.groups g ON g.id = m.group_id
+            WHERE m.user_id = $1
+              AND ($2::timestamptz IS NULL OR (m.create_time, g.id) > ($2, $3))
+            ORDER BY m.create_time ASC, g.id ASC
+            OFFSET $4
+            LIMIT $5
+            "#,
+        )
+        .bind(user_id)
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count_by_user(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM public.group_members
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    pub async fn add(
+        &self,
+        group_id: Uuid,
+        user_id: Uuid,
+        role: GroupRole,
+    ) -> Result<GroupMemberRecord, MemberError> {
+        let member = sqlx::query_as::<_, GroupMemberRecord>(
+            r#"
+            INSERT INTO public.group_members (group_id, user_id, role)
+            VALUES ($1, $2, $3::group_member_role)
+            RETURNING group_id, user_id, role::text AS role, create_time, update_time
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .bind(role.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Change a member's role, refusing to demote the last owner.
+    pub async fn update_role(
+        &self,
+        group_id: Uuid,
+        user_id: Uuid,
+        role: GroupRole,
+    ) -> Result<GroupMemberRecord, MemberError> {
+        let mut tx = self.pool.begin().await?;
+
+        if Self::is_last_owner(&mut tx, group_id, user_id).await? && role != GroupRole::Owner {
+            return Err(MemberError::LastOwner);
+        }
+
+        let member = sqlx::query_as::<_, GroupMemberRecord>(
+            r#"
+            UPDATE public.group_members
+            SET role = $3::group_member_role, update_time = now()
+            WHERE group_id = $1 AND user_id = $2
+            RETURNING group_id, user_id, role::text AS role, create_time, update_time
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .bind(role.as_str())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(member)
+    }
+
+    /// Remove a member, refusing to remove the last owner.
+    pub async fn remove(
+        &self,
+        group_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<GroupMemberRecord, MemberError> {
+        let mut tx = self.pool.begin().await?;
+
+        if Self::is_last_owner(&mut tx, group_id, user_id).await? {
+            return Err(MemberError::LastOwner);
+        }
+
+        let member = sqlx::query_as::<_, GroupMemberRecord>(
+            r#"
+            DELETE FROM public.group_members
+            WHERE group_id = $1 AND user_id = $2
+            RETURNING group_id, user_id, role::text AS role, create_time, update_time
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(member)
+    }
+
+    /// Whether the member is the group's only owner. Locks the owners and the member, so
+    /// concurrent changes cannot each remove an owner and leave the group without one.
+    async fn is_last_owner(
+        tx: &mut PgConnection,
+        group_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<bool, MemberError> {
+        let rows: Vec<(Uuid, String)> = sqlx::query_as(
+            r#"
+            SELECT user_id, role::text
+            FROM public.group_members
+            WHERE group_id = $1 AND (role = 'owner' OR user_id = $2)
+            FOR UPDATE
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_all(tx)
+        .await?;
+
+        let role = rows
+            .iter()
+            .find(|(id, _)| *id == user_id)
+            .map(|(_, role)| role.as_str())
+            .ok_or(MemberError::NotFound)?;
+        let owners = rows.iter().filter(|(_, role)| role == "owner").count();
+
+        Ok(role == "owner" && owners == 1)
+    }
+}
@@ -1,9 +1,85 @@
+mod audit;
+mod entry;
 mod feed;
 mod group;
 mod identity;
+mod member;
+mod page;
+mod subscription;
 mod user;
+mod visibility;
 
+pub(crate) use audit::{AuditAction, AuditContext};
+pub(crate) use entry::EntryRepository;
+pub(crate) use identity::{CreateIdentity, IdentityRepository, LinkOutcome, UserIdentity};
+pub use audit::AuditServer;
+pub use entry::{EntryHub, EntryServer};
 pub use feed::FeedServer;
 pub use group::GroupServer;
 pub use identity::IdentityServer;
-pub use user::UserServer;
+pub use page::Paginator;
+pub use subscription::SubscriptionServer;
+pub use user::{UserRepository, UserServer};
+pub use visibility::VisibilityPolicy;
+
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::rpc::Visibility;
+use prost_types::Timestamp;
+use tonic::Status;
+use uuid::Uuid;
+
+pub(crate) fn to_timestamp(dt: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    }
+}
+
+pub(crate) fn from_timestamp(ts: &Timestamp) -> Result<DateTime<Utc>, Status> {
+    DateTime::from_timestamp(ts.seconds, ts.nanos.max(0) as u32)
+        .ok_or_else(|| Status::invalid_argument("Invalid timestamp"))
+}
+
+/// Treat an empty proto3 string as an absent value.
+pub(crate) fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Parse an optional UID, where an empty string means absent.
+pub(crate) fn parse_uid(uid: &str) -> Result<Option<Uuid>, Status> {
+    if uid.is_empty() {
+        return Ok(None);
+    }
+
+    Uuid::parse_str(uid)
+        .map(Some)
+        .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))
+}
+
+/// Convert a protobuf visibility into the label shared by the `*_visibility` Postgres enums.
+/// An unset visibility falls back to private.
+pub(crate) fn visibility_to_str(visibility: i32) -> Result<&'static str, Status> {
+    match Visibility::try_from(visibility) {
+        Ok(Visibility::Unspecified) | Ok(Visibility::Private) => Ok("private"),
+        Ok(Visibility::Internal) => Ok("internal"),
+        Ok(Visibility::Public) => Ok("public"),
+        Ok(Visibility::Preview) => Ok("preview"),
+        Ok(Visibility::Global) => Ok("global"),
+        Err(_) => Err(Status::invalid_argument("Invalid visibility")),
+    }
+}
+
+pub(crate) fn visibility_from_str(visibility: &str) -> Result<i32, Status> {
+    match visibility {
+        "internal" => Ok(Visibility::Internal as i32),
+        "public" => Ok(Visibility::Public as i32),
+        "private" => Ok(Visibility::Private as i32),
+        "preview" => Ok(Visibility::Preview as i32),
+        "global" => Ok(Visibility::Global as i32),
+        _ => Err(Status::internal("Invalid visibility type")),
+    }
+}
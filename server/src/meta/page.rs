@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Keyset pagination for the List RPCs. Each page ends with an `etag` that records the sort
+//! key of its last result, so the next page continues after that row instead of re-counting an
+//! offset that shifts whenever rows are inserted or deleted.
+//!
+//! Etags are signed, expire after a configurable time and are bound to the query that issued
+//! them, so they cannot be forged, kept forever or replayed against a different filter.
+
+use super::to_timestamp;
+use crate::config::AppConfig;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use geist_sdk::pb::rpc::Pagination;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use tonic::Status;
+use uuid::Uuid;
+
+/// Number of results returned by a List RPC when the request does not specify a size.
+const DEFAULT_PAGE_SIZE: u32 = 25;
+
+/// Upper bound on the page size, mirroring the constraint on `geist.rpc.Pagination.size`.
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Lifetime of etags issued by a default `Paginator`.
+const DEFAULT_ETAG_TTL_SECS: u64 = 900;
+
+/// Bumped whenever the etag payload changes, which invalidates all outstanding etags.
+const ETAG_VERSION: &str = "1";
+
+/// The sort key of a row: an optional leading key, such as a folder or role, followed by a
+/// time and an id that make it unique.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub key: Option<String>,
+    pub time: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Position {
+    pub fn new(time: DateTime<Utc>, id: Uuid) -> Self {
+        Self {
+            key: None,
+            time,
+            id,
+        }
+    }
+
+    pub fn with_key(key: impl Into<String>, time: DateTime<Utc>, id: Uuid) -> Self {
+        Self {
+            key: Some(key.into()),
+            time,
+            id,
+        }
+    }
+}
+
+/// The query a listing answers, which its etags are bound to. Every parameter that changes the
+/// results is added by name, so two different queries never share a scope.
+#[derive(Debug, Clone)]
+pub struct PageScope(String);
+
+impl PageScope {
+    pub fn new(listing: &str) -> Self {
+        Self(listing.to_string())
+    }
+
+    /// Add a parameter. Values are quoted, so that free text cannot pass for other parameters.
+    pub fn with(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.0
+            .push_str(&format!(";{}={:?}", name, value.to_string()));
+        self
+    }
+
+    /// Add a parameter that may be unset, which is distinct from every value.
+    pub fn with_opt(self, name: &str, value: Option<impl std::fmt::Display>) -> Self {
+        match value {
+            Some(value) => self.with(name, value),
+            None => Self(format!("{};{}", self.0, name)),
+        }
+    }
+}
+
+/// A validated page request. Queries return the `limit` rows after `after`, skipping `skip`
+/// of them, in their usual order. Only first pages skip rows: the etag of a later page already
+/// points past the rows skipped before it.
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub after: Option<Position>,
+    pub skip: i64,
+    pub size: i64,
+    scope: String,
+}
+
+impl PageRequest {
+    pub fn after_key(&self) -> Option<&str> {
+        self.after.as_ref().and_then(|p| p.key.as_deref())
+    }
+
+    pub fn after_time(&self) -> Option<DateTime<Utc>> {
+        self.after.as_ref().map(|p| p.time)
+    }
+
+    pub fn after_id(&self) -> Option<Uuid> {
+        self.after.as_ref().map(|p| p.id)
+    }
+
+    /// Rows to fetch: one more than the page size tells whether another page follows.
+    pub fn limit(&self) -> i64 {
+        self.size + 1
+    }
+}
+
+/// Issues and verifies page etags.
+#[derive(Clone)]
+pub struct Paginator {
+    key: Hmac<Sha256>,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for Paginator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Paginator")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Paginator {
+    /// Signs with a random key, so etags do not survive a restart.
+    fn default() -> Self {
+        let mut secret = [0u8; 32];
+        rand::rng().fill_bytes(&mut secret);
+        Self::new(&secret, DEFAULT_ETAG_TTL_SECS)
+    }
+}
+
+impl Paginator {
+    pub fn new(secret: &[u8], ttl_secs: u64) -> Self {
+        Self {
+            key: Hmac::new_from_slice(secret).expect("HMAC accepts keys of any length"),
+            ttl: Duration::seconds(ttl_secs as i64),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        match &config.page_etag_secret {
+            Some(secret) => Self::new(secret.as_bytes(), config.page_etag_ttl_secs),
+            None => Self {
+                ttl: Duration::seconds(config.page_etag_ttl_secs as i64),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Validate the requested page. `scope` identifies the query, including every filter, so
+    /// that an etag only continues the listing that issued it. `skip` is ignored along with an
+    /// etag, so a client that sends back the page it received does not skip rows again.
+    pub fn request(
+        &self,
+        page: Option<&Pagination>,
+        scope: &PageScope,
+    ) -> Result<PageRequest, Status> {
+        let (skip, size, etag) = page
+            .map(|p| (p.skip, p.size, p.etag.as_str()))
+            .unwrap_or((0, 0, ""));
+
+        if size > MAX_PAGE_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "Page size must not exceed {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+
+        let scope = fingerprint(&scope.0);
+        let after = if etag.is_empty() {
+            None
+        } else {
+            Some(self.verify(etag, &scope)?)
+        };
+        let skip = if after.is_some() { 0 } else { skip };
+
+        Ok(PageRequest {
+            after,
+            skip: skip as i64,
+            size: if size == 0 { DEFAULT_PAGE_SIZE } else { size } as i64,
+            scope,
+        })
+    }
+
+    /// Trim the rows fetched for a page to its size and describe it, with an etag for the next
+    /// page when there is one.
+    pub fn response<T>(
+        &self,
+        request: &PageRequest,
+        mut rows: Vec<T>,
+        total: i64,
+        position: impl Fn(&T) -> Position,
+    ) -> (Vec<T>, Pagination) {
+        let more = rows.len() as i64 > request.size;
+        rows.truncate(request.size as usize);
+
+        let mut page = Pagination {
+            skip: request.skip as u32,
+            size: request.size as u32,
+            total: total as u32,
+            ..Default::default()
+        };
+        if let Some(last) = rows.last().filter(|_| more) {
+            let expire_time = Utc::now() + self.ttl;
+            page.etag = self.sign(&request.scope, expire_time, &position(last));
+            page.expire_time = Some(to_timestamp(expire_time));
+        }
+
+        (rows, page)
+    }
+
+    fn sign(&self, scope: &str, expire_time: DateTime<Utc>, position: &Position) -> String {
+        // The key goes last, as it is free text that may contain the separator.
+        let payload = format!(
+            "{}|{}|{}|{}|{}|{}",
+            ETAG_VERSION,
+            scope,
+            expire_time.timestamp(),
+            position.time.timestamp_micros(),
+            position.id,
+            position
+                .key
+                .as_deref()
+                .map(|key| format!("k{}", key))
+                .unwrap_or_default(),
+        );
+
+        let mut mac = self.key.clone();
+        mac.update(payload.as_bytes());
+        let signature = mac.finalize().into_bytes();
+
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    fn verify(&self, etag: &str, scope: &str) -> Result<Position, Status> {
+        let invalid = || Status::invalid_argument("Invalid page etag");
+
+        let (payload, signature) = etag.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+
+        let mut mac = self.key.clone();
+        mac.update(&payload);
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+
+        let payload = String::from_utf8(payload).map_err(|_| invalid())?;
+        let fields: Vec<&str> = payload.splitn(6, '|').collect();
+        let [version, etag_scope, expire, time, id, key] = fields[..] else {
+            return Err(invalid());
+        };
+
+        if version != ETAG_VERSION || etag_scope != scope {
+            return Err(Status::invalid_argument(
+                "Page etag does not belong to this query",
+            ));
+        }
+
+        let expire: i64 = expire.parse().map_err(|_| invalid())?;
+        if expire <= Utc::now().timestamp() {
+            return Err(Status::failed_precondition(
+                "Page etag has expired, list again from the first page",
+            ));
+        }
+
+        let time = time
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(invalid)?;
+        let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+        let key = key.strip_prefix('k').map(str::to_string);
+
+        Ok(Position { key, time, id })
+    }
+}
+
+/// A short digest of a query scope, kept in etags instead of the scope itself.
+fn fingerprint(scope: &str) -> String {
+    let digest = Sha256::digest(scope.as_bytes());
+    URL_SAFE_NO_PAD.encode(&digest[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_keep_parameters_apart() {
+        let scope = |folder: Option<&str>, tag: Option<&str>| {
+            PageScope::new("subscriptions")
+                .with_opt("folder", folder)
+                .with_opt("tag", tag)
+                .0
+        };
+
+        assert_ne!(
+            scope(Some("a;tag=\"b\""), None),
+            scope(Some("a"), Some("b"))
+        );
+        assert_ne!(scope(Some(""), None), scope(None, None));
+        assert_ne!(scope(Some("a"), None), scope(None, Some("a")));
+        assert_eq!(scope(Some("a"), None), scope(Some("a"), None));
+    }
+
+    #[test]
+    fn etags_only_continue_their_own_scope() {
+        let pages = Paginator::default();
+        let scope = PageScope::new("feeds").with("user", Uuid::nil());
+        let page = Pagination {
+            size: 1,
+            ..Default::default()
+        };
+
+        let request = pages.request(Some(&page), &scope).unwrap();
+        let rows = vec![Uuid::now_v7(), Uuid::now_v7()];
+        let (_, response) = pages.response(&request, rows, 2, |id| Position::new(Utc::now(), *id));
+        let next = Pagination {
+            etag: response.etag,
+            ..page
+        };
+
+        assert!(pages.request(Some(&next), &scope).unwrap().after.is_some());
+        let other = PageScope::new("feeds").with("user", Uuid::now_v7());
+        let status = pages.request(Some(&next), &other).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn skip_only_applies_to_the_first_page() {
+        let pages = Paginator::default();
+        let scope = PageScope::new("feeds");
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let rows: Vec<Position> = (0..10)
+            .map(|i| Position::new(start + Duration::seconds(i), Uuid::now_v7()))
+            .collect();
+        // The rows a list query returns: those after the etag's position, minus `skip`.
+        let fetch = |request: &PageRequest| -> Vec<Position> {
+            rows.iter()
+                .filter(|row| request.after_time().is_none_or(|time| row.time > time))
+                .skip(request.skip as usize)
+                .take(request.limit() as usize)
+                .cloned()
+                .collect()
+        };
+
+        let mut page = Pagination {
+            skip: 2,
+            size: 2,
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        for expected_skip in [2, 0, 0] {
+            let request = pages.request(Some(&page), &scope).unwrap();
+            let (results, response) =
+                pages.response(&request, fetch(&request), 10, Position::clone);
+            assert_eq!(response.skip, expected_skip);
+            seen.extend(results);
+
+            // Send back the page as received, but with the skip of the first request.
+            page = Pagination {
+                skip: 2,
+                ..response
+            };
+        }
+
+        assert_eq!(seen, rows[2..8]);
+    }
+}
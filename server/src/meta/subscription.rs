@@ -0,0 +1,927 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use super::feed::{CreateFeed, FeedRecord, FeedRepository, DEFAULT_POLL_INTERVAL_SECS};
+use super::member::{authorize_role, GroupMemberRepository, GroupRole};
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::visibility::{Viewer, VisibilityPolicy};
+use super::{non_empty, parse_uid, to_timestamp};
+use crate::auth::{self, Principal};
+use crate::error::GeistError;
+use crate::ingest::{parse_opml, write_opml, OpmlFeed, UrlPolicy};
+use crate::ServerResult;
+use chrono::{DateTime, Utc};
+use geist_sdk::pb::meta::v1alpha::{
+    opml_outline_result::Outcome, subscription_service_server::SubscriptionService,
+    ExportOpmlRequest, ExportOpmlResponse, ImportOpmlRequest, ImportOpmlResponse,
+    ListSubscriptionsRequest, MutateSubscriptionRequest, OpmlOutlineResult, Subscription,
+    SubscriptionRequest, SubscriptionResponse,
+};
+use prost_types::Duration;
+use sqlx::{FromRow, PgPool};
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+/// Most feed outlines a single OPML import may contain.
+const MAX_OPML_OUTLINES: usize = 1000;
+
+/// Separates the nested folders of an OPML outline in a subscription's folder.
+const FOLDER_SEPARATOR: &str = "/";
+
+/// Who a subscription belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscriber {
+    User(Uuid),
+    Group(Uuid),
+}
+
+impl Subscriber {
+    /// Parse the subscriber of a request, falling back to `default` when neither is set.
+    fn from_uids(
+        user_uid: &str,
+        group_uid: &str,
+        default: impl FnOnce() -> Subscriber,
+    ) -> Result<Self, Status> {
+        match (parse_uid(user_uid)?, parse_uid(group_uid)?) {
+            (Some(_), Some(_)) => Err(Status::invalid_argument(
+                "Only one of user_uid and group_uid can be set",
+            )),
+            (Some(user_id), None) => Ok(Subscriber::User(user_id)),
+            (None, Some(group_id)) => Ok(Subscriber::Group(group_id)),
+            (None, None) => Ok(default()),
+        }
+    }
+
+    fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Subscriber::User(id) => Some(*id),
+            Subscriber::Group(_) => None,
+        }
+    }
+
+    fn group_id(&self) -> Option<Uuid> {
+        match self {
+            Subscriber::User(_) => None,
+            Subscriber::Group(id) => Some(*id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub feed_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub poll_interval_secs: Option<i32>,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+impl SubscriptionRecord {
+    fn subscriber(&self) -> Result<Subscriber, Status> {
+        match (self.user_id, self.group_id) {
+            (Some(user_id), None) => Ok(Subscriber::User(user_id)),
+            (None, Some(group_id)) => Ok(Subscriber::Group(group_id)),
+            _ => Err(Status::internal("Subscription has no single subscriber")),
+        }
+    }
+
+    fn to_proto(&self) -> Subscription {
+        Subscription {
+            uid: self.id.to_string(),
+            feed_uid: self.feed_id.to_string(),
+            user_uid: self.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            group_uid: self.group_id.map(|id| id.to_string()).unwrap_or_default(),
+            title: self.title.clone().unwrap_or_default(),
+            folder: self.folder.clone().unwrap_or_default(),
+            tags: self.tags.clone(),
+            poll_interval: self.poll_interval_secs.map(|secs| Duration {
+                seconds: secs as i64,
+                nanos: 0,
+            }),
+            create_time: Some(to_timestamp(self.create_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+        }
+    }
+}
+
+/// A subscription together with the feed it is for, as exported to OPML.
+#[derive(Debug, Clone, FromRow)]
+pub struct ExportedSubscription {
+    #[sqlx(flatten)]
+    pub subscription: SubscriptionRecord,
+    pub feed_name: String,
+    pub feed_url: String,
+    pub feed_type: String,
+}
+
+impl ExportedSubscription {
+    fn to_opml(&self) -> OpmlFeed {
+        let folders: Vec<String> = self
+            .subscription
+            .folder
+            .as_deref()
+            .map(|folder| {
+                folder
+                    .split(FOLDER_SEPARATOR)
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        OpmlFeed {
+            xml_url: self.feed_url.clone(),
+            title: Some(
+                self.subscription
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| self.feed_name.clone()),
+            ),
+            html_url: None,
+            feed_type: Some(self.feed_type.clone()),
+            // Tags imported from the folders are implied by the nesting.
+            categories: self
+                .subscription
+                .tags
+                .iter()
+                .filter(|tag| !folders.contains(tag))
+                .cloned()
+                .collect(),
+            folders,
+        }
+    }
+}
+
+/// Trim tags, dropping empty and duplicate ones while keeping their order.
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+/// Treat a blank string as an absent value, trimming the rest.
+fn non_blank(value: String) -> Option<String> {
+    non_empty(value.trim().to_string())
+}
+
+#[derive(Debug)]
+pub struct SubscriptionFilter {
+    pub subscriber: Subscriber,
+    pub folder: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl SubscriptionFilter {
+    fn page_scope(&self) -> PageScope {
+        let scope = match self.subscriber {
+            Subscriber::User(user_id) => PageScope::new("subscriptions").with("user", user_id),
+            Subscriber::Group(group_id) => PageScope::new("subscriptions").with("group", group_id),
+        };
+        scope
+            .with_opt("folder", self.folder.as_deref())
+            .with_opt("tag", self.tag.as_deref())
+    }
+}
+
+pub struct SubscriptionRepository {
+    pool: PgPool,
+}
+
+impl SubscriptionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<SubscriptionRecord>, sqlx::Error> {
+        sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            SELECT id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                   create_time, update_time
+            FROM public.subscriptions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn list(
+        &self,
+        filter: &SubscriptionFilter,
+        page: &PageRequest,
+    ) -> Result<Vec<SubscriptionRecord>, sqlx::Error> {
+        sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            SELECT id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                   create_time, update_time
+            FROM public.subscriptions
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+              AND ($2::uuid IS NULL OR group_id = $2)
+              AND ($3::text IS NULL OR folder = $3)
+              AND ($4::text IS NULL OR tags @> ARRAY[$4])
+              AND ($5::text IS NULL
+                   OR (COALESCE(folder, ''), create_time, id) > ($5, $6, $7))
+            ORDER BY COALESCE(folder, '') ASC, create_time ASC, id ASC
+            OFFSET $8
+            LIMIT $9
+            "#,
+        )
+        .bind(filter.subscriber.user_id())
+        .bind(filter.subscriber.group_id())
+        .bind(&filter.folder)
+        .bind(&filter.tag)
+        .bind(page.after_key())
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self, filter: &SubscriptionFilter) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM public.subscriptions
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+              AND ($2::uuid IS NULL OR group_id = $2)
+              AND ($3::text IS NULL OR folder = $3)
+              AND ($4::text IS NULL OR tags @> ARRAY[$4])
+            "#,
+        )
+        .bind(filter.subscriber.user_id())
+        .bind(filter.subscriber.group_id())
+        .bind(&filter.folder)
+        .bind(&filter.tag)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    pub async fn find_by_feed(
+        &self,
+        subscriber: Subscriber,
+        feed_id: Uuid,
+    ) -> Result<Option<SubscriptionRecord>, sqlx::Error> {
+        sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            SELECT id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                   create_time, update_time
+            FROM public.subscriptions
+            WHERE feed_id = $1
+              AND ($2::uuid IS NULL OR user_id = $2)
+              AND ($3::uuid IS NULL OR group_id = $3)
+            "#,
+        )
+        .bind(feed_id)
+        .bind(subscriber.user_id())
+        .bind(subscriber.group_id())
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Every subscription of a subscriber with its feed, for exporting.
+    pub async fn list_with_feeds(
+        &self,
+        subscriber: Subscriber,
+    ) -> Result<Vec<ExportedSubscription>, sqlx::Error> {
+        sqlx::query_as::<_, ExportedSubscription>(
+            r#"
+            SELECT s.id, s.feed_id, s.user_id, s.group_id, s.title, s.folder, s.tags,
+                   s.poll_interval_secs, s.create_time, s.update_time,
+                   f.name::text AS feed_name, f.url AS feed_url, f.type::text AS feed_type
+            FROM public.subscriptions s
+            JOIN public.feeds f ON f.id = s.feed_id
+            WHERE ($1::uuid IS NULL OR s.user_id = $1)
+              AND ($2::uuid IS NULL OR s.group_id = $2)
+            ORDER BY s.folder ASC NULLS FIRST, s.create_time ASC, s.id ASC
+            "#,
+        )
+        .bind(subscriber.user_id())
+        .bind(subscriber.group_id())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// The distinct feeds the given groups are subscribed to.
+    pub async fn feed_ids_for_groups(&self, group_ids: &[Uuid]) -> Result<Vec<Uuid>, sqlx::Error> {
+        let rows: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT feed_id
+            FROM public.subscriptions
+            WHERE group_id = ANY($1)
+            "#,
+        )
+        .bind(group_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    pub async fn create(
+        &self,
+        subscription: &CreateSubscription,
+    ) -> Result<SubscriptionRecord, sqlx::Error> {
+        let now = Utc::now();
+
+        sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            INSERT INTO public.subscriptions
+                (id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                 create_time, update_time)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                      create_time, update_time
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(subscription.feed_id)
+        .bind(subscription.subscriber.user_id())
+        .bind(subscription.subscriber.group_id())
+        .bind(&subscription.title)
+        .bind(&subscription.folder)
+        .bind(&subscription.tags)
+        .bind(subscription.poll_interval_secs)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn update(
+        &self,
+        id: Uuid,
+        subscription: &UpdateSubscription,
+    ) -> Result<Option<SubscriptionRecord>, sqlx::Error> {
+        sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            UPDATE public.subscriptions
+            SET title = COALESCE($2, title),
+                folder = COALESCE($3, folder),
+                tags = COALESCE($4, tags),
+                poll_interval_secs = COALESCE($5, poll_interval_secs),
+                update_time = now()
+            WHERE id = $1
+            RETURNING id, feed_id, user_id, group_id, title, folder, tags, poll_interval_secs,
+                      create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&subscription.title)
+        .bind(&subscription.folder)
+        .bind(&subscription.tags)
+        .bind(subscription.poll_interval_secs)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM public.subscriptions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct CreateSubscription {
+    pub feed_id: Uuid,
+    pub subscriber: Subscriber,
+    pub title: Option<String>,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub poll_interval_secs: Option<i32>,
+}
+
+/// Fields left as `None` keep their stored value.
+pub struct UpdateSubscription {
+    pub title: Option<String>,
+    pub folder: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub poll_interval_secs: Option<i32>,
+}
+
+fn map_write_error(e: sqlx::Error) -> GeistError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            GeistError::AlreadyExists("Already subscribed to this feed".to_string())
+        }
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => match db.constraint() {
+            Some("subscriptions_user_id_fkey") => {
+                GeistError::NotFound("User not found".to_string())
+            }
+            Some("subscriptions_group_id_fkey") => {
+                GeistError::NotFound("Group not found".to_string())
+            }
+            _ => GeistError::NotFound("Feed not found".to_string()),
+        },
+        _ => GeistError::from(e),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SubscriptionServer {
+    pool: Option<PgPool>,
+    visibility: VisibilityPolicy,
+    urls: UrlPolicy,
+    pages: Paginator,
+}
+
+impl SubscriptionServer {
+    pub fn new(
+        pool: PgPool,
+        visibility: VisibilityPolicy,
+        urls: UrlPolicy,
+        pages: Paginator,
+    ) -> Self {
+        Self {
+            pool: Some(pool),
+            visibility,
+            urls,
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    /// Check that the caller may read, or with `manage` change, the subscriptions of a
+    /// subscriber: users their own, group members those of their groups, and group owners and
+    /// admins may change them.
+    async fn authorize(
+        &self,
+        principal: &Principal,
+        subscriber: Subscriber,
+        manage: bool,
+    ) -> Result<(), Status> {
+        match subscriber {
+            Subscriber::User(user_id) => principal.authorize_user(user_id),
+            Subscriber::Group(group_id) => {
+                let role = GroupMemberRepository::new(self.pool()?.clone())
+                    .find(group_id, principal.user_uid)
+                    .await
+                    .map_err(GeistError::from)?
+                    .map(|member| member.role())
+                    .transpose()?;
+                let required = if manage {
+                    GroupRole::Admin
+                } else {
+                    GroupRole::Member
+                };
+                authorize_role(principal, role, required)
+            }
+        }
+    }
+
+    /// Resolve the stored subscription a request refers to and check the caller's access to it.
+    async fn find_target(
+        &self,
+        repo: &SubscriptionRepository,
+        uid: &str,
+        principal: &Principal,
+        manage: bool,
+    ) -> Result<SubscriptionRecord, Status> {
+        let id = parse_uid(uid)?.ok_or_else(|| Status::invalid_argument("uid is required"))?;
+        let record = repo
+            .find_by_id(id)
+            .await
+            .map_err(GeistError::from)?
+            .ok_or_else(|| Status::not_found("Subscription not found"))?;
+
+        self.authorize(principal, record.subscriber()?, manage)
+            .await?;
+        Ok(record)
+    }
+
+    /// Subscribe to the feed of one OPML outline, reporting failures in the result rather than
+    /// failing the whole import.
+    async fn import_outline(
+        &self,
+        owner_id: Uuid,
+        viewer: &Viewer,
+        subscriber: Subscriber,
+        outline: OpmlFeed,
+    ) -> OpmlOutlineResult {
+        let mut result = OpmlOutlineResult {
+            xml_url: outline.xml_url.clone(),
+            title: outline.title.clone().unwrap_or_default(),
+            folder: outline.folders.join(FOLDER_SEPARATOR),
+            ..Default::default()
+        };
+
+        match self
+            .subscribe_outline(owner_id, viewer, subscriber, outline)
+            .await
+        {
+            Ok((outcome, subscription, feed_created)) => {
+                result.set_outcome(outcome);
+                result.subscription = Some(subscription.to_proto());
+                result.feed_created = feed_created;
+            }
+            Err(status) => {
+                result.set_outcome(Outcome::Failed);
+                result.message = status.message().to_string();
+            }
+        }
+
+        result
+    }
+
+    /// Subscribe to an outline's feed, reusing a visible feed with the same url or creating
+    /// one owned by the importer.
+    async fn subscribe_outline(
+        &self,
+        owner_id: Uuid,
+        viewer: &Viewer,
+        subscriber: Subscriber,
+        outline: OpmlFeed,
+    ) -> Result<(Outcome, SubscriptionRecord, bool), Status> {
+        if outline.xml_url.is_empty() {
+            return Err(Status::invalid_argument("Outline has no xmlUrl"));
+        }
+        self.urls
+            .check(&outline.xml_url)
+            .map_err(|e| Status::invalid_argument(e.message))?;
+
+        let pool = self.pool()?.clone();
+        let feeds = FeedRepository::new(pool.clone());
+        let repo = SubscriptionRepository::new(pool);
+
+        let existing = feeds
+            .find_visible_by_url(&outline.xml_url, viewer)
+            .await
+            .map_err(GeistError::from)?;
+        let (feed, feed_created) = match existing {
+            Some(feed) => (feed, false),
+            None => (create_outline_feed(&feeds, &outline, owner_id).await?, true),
+        };
+
+        let mut tags = outline.folders.clone();
+        tags.extend(outline.categories);
+        let create_subscription = CreateSubscription {
+            feed_id: feed.id,
+            subscriber,
+            title: outline.title.filter(|title| *title != feed.name),
+            folder: non_blank(outline.folders.join(FOLDER_SEPARATOR)),
+            tags: normalize_tags(tags),
+            poll_interval_secs: None,
+        };
+
+        match repo.create(&create_subscription).await {
+            Ok(created) => Ok((Outcome::Created, created, feed_created)),
+            Err(sqlx::Error::Database(ref db)) if db.is_unique_violation() => {
+                let existing = repo
+                    .find_by_feed(subscriber, feed.id)
+                    .await
+                    .map_err(GeistError::from)?
+                    .ok_or_else(|| Status::internal("Subscription not found"))?;
+                Ok((Outcome::Duplicate, existing, feed_created))
+            }
+            Err(e) => Err(map_write_error(e).into()),
+        }
+    }
+}
+
+/// Create a private feed for an outline, named after its title or, when that name is taken,
+/// its url.
+async fn create_outline_feed(
+    feeds: &FeedRepository,
+    outline: &OpmlFeed,
+    owner_id: Uuid,
+) -> Result<FeedRecord, Status> {
+    let feed_type = match outline.feed_type.as_deref() {
+        Some("atom") => "atom",
+        Some("json") => "json",
+        _ => "rss",
+    };
+
+    for name in outline.title.iter().chain([&outline.xml_url]) {
+        let create_feed = CreateFeed {
+            name: name.clone(),
+            description: None,
+            url: outline.xml_url.clone(),
+            icon_url: None,
+            feed_type: feed_type.to_string(),
+            visibility: "private".to_string(),
+            poll_interval_secs: DEFAULT_POLL_INTERVAL_SECS,
+            owner_id,
+        };
+
+        match feeds.create(&create_feed).await {
+            Ok(feed) => return Ok(feed),
+            Err(sqlx::Error::Database(ref db)) if db.is_unique_violation() => continue,
+            Err(e) => return Err(GeistError::from(e).into()),
+        }
+    }
+
+    Err(Status::already_exists(
+        "A feed with this name already exists",
+    ))
+}
+
+#[tonic::async_trait]
+impl SubscriptionService for SubscriptionServer {
+    #[tracing::instrument(skip(self))]
+    async fn get_subscription(
+        &self,
+        request: Request<SubscriptionRequest>,
+    ) -> ServerResult<SubscriptionResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscription = self.find_target(&repo, &req.uid, &principal, false).await?;
+
+        Ok(tonic::Response::new(SubscriptionResponse {
+            subscriptions: vec![subscription.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_subscriptions(
+        &self,
+        request: Request<ListSubscriptionsRequest>,
+    ) -> ServerResult<SubscriptionResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscriber = Subscriber::from_uids(&req.user_uid, &req.group_uid, || {
+            Subscriber::User(principal.user_uid)
+        })?;
+        self.authorize(&principal, subscriber, false).await?;
+
+        let filter = SubscriptionFilter {
+            subscriber,
+            folder: non_blank(req.folder),
+            tag: non_blank(req.tag),
+        };
+
+        let page = self
+            .pages
+            .request(req.page.as_ref(), &filter.page_scope())?;
+
+        let subscriptions = repo.list(&filter, &page).await.map_err(GeistError::from)?;
+        let total = repo.count(&filter).await.map_err(GeistError::from)?;
+        let (subscriptions, page) = self.pages.response(&page, subscriptions, total, |s| {
+            Position::with_key(s.folder.clone().unwrap_or_default(), s.create_time, s.id)
+        });
+
+        Ok(tonic::Response::new(SubscriptionResponse {
+            subscriptions: subscriptions
+                .iter()
+                .map(SubscriptionRecord::to_proto)
+                .collect(),
+            page: Some(page),
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_subscription(
+        &self,
+        request: Request<MutateSubscriptionRequest>,
+    ) -> ServerResult<SubscriptionResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscription = req
+            .subscription
+            .ok_or_else(|| Status::invalid_argument("Subscription must be provided"))?;
+
+        let feed_id = parse_uid(&subscription.feed_uid)?
+            .ok_or_else(|| Status::invalid_argument("feed_uid is required"))?;
+        let subscriber =
+            Subscriber::from_uids(&subscription.user_uid, &subscription.group_uid, || {
+                Subscriber::User(principal.user_uid)
+            })?;
+        self.authorize(&principal, subscriber, true).await?;
+        FeedRepository::new(self.pool()?.clone())
+            .find_visible(feed_id, &viewer)
+            .await?;
+
+        let create_subscription = CreateSubscription {
+            feed_id,
+            subscriber,
+            title: non_blank(subscription.title),
+            folder: non_blank(subscription.folder),
+            tags: normalize_tags(subscription.tags),
+            poll_interval_secs: FeedRecord::poll_interval_secs(
+                subscription.poll_interval.as_ref(),
+            )?,
+        };
+
+        let created = repo
+            .create(&create_subscription)
+            .await
+            .map_err(map_write_error)?;
+
+        Ok(tonic::Response::new(SubscriptionResponse {
+            subscriptions: vec![created.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn update_subscription(
+        &self,
+        request: Request<MutateSubscriptionRequest>,
+    ) -> ServerResult<SubscriptionResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscription = req
+            .subscription
+            .ok_or_else(|| Status::invalid_argument("Subscription must be provided"))?;
+        let existing = self
+            .find_target(&repo, &subscription.uid, &principal, true)
+            .await?;
+
+        let tags = normalize_tags(subscription.tags);
+        let update_subscription = UpdateSubscription {
+            title: non_blank(subscription.title),
+            folder: non_blank(subscription.folder),
+            tags: if tags.is_empty() { None } else { Some(tags) },
+            poll_interval_secs: FeedRecord::poll_interval_secs(
+                subscription.poll_interval.as_ref(),
+            )?,
+        };
+
+        let updated = repo
+            .update(existing.id, &update_subscription)
+            .await
+            .map_err(map_write_error)?
+            .ok_or_else(|| Status::not_found("Subscription not found"))?;
+
+        Ok(tonic::Response::new(SubscriptionResponse {
+            subscriptions: vec![updated.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_subscription(
+        &self,
+        request: Request<MutateSubscriptionRequest>,
+    ) -> ServerResult<SubscriptionResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscription = req
+            .subscription
+            .ok_or_else(|| Status::invalid_argument("Subscription must be provided"))?;
+        let existing = self
+            .find_target(&repo, &subscription.uid, &principal, true)
+            .await?;
+
+        if !repo.delete(existing.id).await.map_err(GeistError::from)? {
+            return Err(Status::not_found("Subscription not found"));
+        }
+
+        Ok(tonic::Response::new(SubscriptionResponse {
+            subscriptions: vec![existing.to_proto()],
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self, request))]
+    async fn import_opml(
+        &self,
+        request: Request<ImportOpmlRequest>,
+    ) -> ServerResult<ImportOpmlResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let viewer = self.visibility.viewer(&request);
+        let req = request.into_inner();
+
+        let subscriber = Subscriber::from_uids(&req.user_uid, &req.group_uid, || {
+            Subscriber::User(principal.user_uid)
+        })?;
+        self.authorize(&principal, subscriber, true).await?;
+
+        let outlines =
+            parse_opml(&req.document).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if outlines.len() > MAX_OPML_OUTLINES {
+            return Err(Status::invalid_argument(format!(
+                "OPML documents can contain at most {} feeds",
+                MAX_OPML_OUTLINES
+            )));
+        }
+
+        let mut response = ImportOpmlResponse::default();
+        for outline in outlines {
+            let result = self
+                .import_outline(principal.user_uid, &viewer, subscriber, outline)
+                .await;
+            match result.outcome() {
+                Outcome::Created => response.created += 1,
+                Outcome::Duplicate => response.duplicates += 1,
+                _ => response.failed += 1,
+            }
+            response.results.push(result);
+        }
+
+        tracing::info!(
+            created = response.created,
+            duplicates = response.duplicates,
+            failed = response.failed,
+            "Imported OPML document"
+        );
+
+        Ok(tonic::Response::new(response))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn export_opml(
+        &self,
+        request: Request<ExportOpmlRequest>,
+    ) -> ServerResult<ExportOpmlResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = SubscriptionRepository::new(self.pool()?.clone());
+
+        let subscriber = Subscriber::from_uids(&req.user_uid, &req.group_uid, || {
+            Subscriber::User(principal.user_uid)
+        })?;
+        self.authorize(&principal, subscriber, false).await?;
+
+        let mut feeds: Vec<OpmlFeed> = repo
+            .list_with_feeds(subscriber)
+            .await
+            .map_err(GeistError::from)?
+            .iter()
+            .map(ExportedSubscription::to_opml)
+            .collect();
+        // Keep each folder's feeds together; the sort is stable, so they stay in creation order.
+        feeds.sort_by(|a, b| a.folders.cmp(&b.folders));
+
+        Ok(tonic::Response::new(ExportOpmlResponse {
+            document: write_opml("Geist subscriptions", &feeds).into_bytes(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn import_opml_rejects_unsafe_urls() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let server = SubscriptionServer::new(
+            pool,
+            VisibilityPolicy::default(),
+            UrlPolicy::default(),
+            Paginator::default(),
+        );
+        let document = r#"<?xml version="1.0"?>
+<opml version="2.0"><body>
+  <outline type="rss" text="Files" xmlUrl="file:///etc/passwd"/>
+  <outline type="rss" text="Local" xmlUrl="http://localhost:8080/feed.xml"/>
+  <outline type="rss" text="Metadata" xmlUrl="http://169.254.169.254/latest/meta-data/"/>
+  <outline type="rss" text="Mapped" xmlUrl="http://[::ffff:10.0.0.1]/feed.xml"/>
+  <outline type="rss" text="Unspecified" xmlUrl="http://0.0.0.0:8080/feed.xml"/>
+</body></opml>"#;
+
+        let mut request = Request::new(ImportOpmlRequest {
+            document: document.as_bytes().to_vec(),
+            ..Default::default()
+        });
+        request.extensions_mut().insert(Principal {
+            user_uid: Uuid::now_v7(),
+            scopes: Vec::new(),
+        });
+
+        let response = server.import_opml(request).await.unwrap().into_inner();
+        assert_eq!((response.created, response.failed), (0, 5));
+        for result in &response.results {
+            assert_eq!(result.outcome(), Outcome::Failed, "{}", result.xml_url);
+        }
+    }
+}
@@ -1,39 +1,801 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use super::identity::{IdentityRepository, UserIdentity};
+use super::member::GroupMemberRepository;
+use super::page::{PageRequest, PageScope, Paginator, Position};
+use super::{non_empty, parse_uid, to_timestamp};
+use crate::auth;
+use crate::error::GeistError;
 use crate::ServerResult;
+use chrono::{DateTime, Utc};
 use geist_sdk::pb::meta::v1alpha::{
-    user_service_server::UserService, ListUsersRequest, MutateUserRequest, UserRequest,
-    UserResponse,
+    user_request::Params, user_service_server::UserService, ListUserGroupsRequest,
+    ListUserGroupsResponse, ListUsersRequest, MutateUserRequest, User, UserRequest, UserResponse,
 };
+use sqlx::postgres::types::PgHstore;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
 use tonic::{Request, Status};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct UserRecord {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub location: Option<String>,
+    pub links: Option<PgHstore>,
+    pub primary_email: Option<String>,
+    pub primary_identity_id: Option<Uuid>,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+impl UserRecord {
+    /// Build the protobuf user, attaching whichever of the given identities belong to it.
+    fn to_proto(&self, identities: &[UserIdentity]) -> Result<User, Status> {
+        let identities: Vec<_> = identities.iter().filter(|i| i.user_id == self.id).collect();
+
+        let primary_identity = identities
+            .iter()
+            .find(|i| Some(i.id) == self.primary_identity_id)
+            .or_else(|| identities.iter().find(|i| i.is_primary))
+            .map(|i| i.to_proto())
+            .transpose()?;
+
+        let identities: Result<Vec<_>, _> = identities.iter().map(|i| i.to_proto()).collect();
+
+        Ok(User {
+            uid: self.id.to_string(),
+            name: self.name.clone().unwrap_or_default(),
+            email: self
+                .email
+                .clone()
+                .or_else(|| self.primary_email.clone())
+                .unwrap_or_default(),
+            username: self.username.clone(),
+            avatar_url: self.avatar_url.clone().unwrap_or_default(),
+            bio: self.bio.clone().unwrap_or_default(),
+            location: self.location.clone().unwrap_or_default(),
+            links: self
+                .links
+                .as_ref()
+                .map(|links| {
+                    links
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone().unwrap_or_default()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            create_time: Some(to_timestamp(self.create_time)),
+            update_time: Some(to_timestamp(self.update_time)),
+            identities: identities?,
+            primary_identity,
+        })
+    }
+}
+
+fn links_to_hstore(links: HashMap<String, String>) -> Option<PgHstore> {
+    if links.is_empty() {
+        None
+    } else {
+        Some(links.into_iter().collect())
+    }
+}
+
+pub struct UserRepository {
+    pool: PgPool,
+}
+
+impl UserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, name, email, username, avatar_url, bio, location, links,
+                   primary_email, primary_identity_id, create_time, update_time
+            FROM public.users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> Result<Option<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, name, email, username, avatar_url, bio, location, links,
+                   primary_email, primary_identity_id, create_time, update_time
+            FROM public.users
+            WHERE name = $1::citext
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, name, email, username, avatar_url, bio, location, links,
+                   primary_email, primary_identity_id, create_time, update_time
+            FROM public.users
+            WHERE email = $1::citext OR primary_email = $1::citext
+            ORDER BY (email = $1::citext) DESC NULLS LAST
+            LIMIT 1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn find_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, name, email, username, avatar_url, bio, location, links,
+                   primary_email, primary_identity_id, create_time, update_time
+            FROM public.users
+            WHERE username = $1::citext
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn list(&self, page: &PageRequest) -> Result<Vec<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, name, email, username, avatar_url, bio, location, links,
+                   primary_email, primary_identity_id, create_time, update_time
+            FROM public.users
+            WHERE ($1::timestamptz IS NULL OR (create_time, id) > ($1, $2))
+            ORDER BY create_time ASC, id ASC
+            OFFSET $3
+            LIMIT $4
+            "#,
+        )
+        .bind(page.after_time())
+        .bind(page.after_id())
+        .bind(page.skip)
+        .bind(page.limit())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn count(&self) -> Result<i64, sqlx::Error> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM public.users
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// Whether the user is an admin. Unknown users are not.
+    pub async fn is_admin(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let is_admin: Option<(bool,)> = sqlx::query_as(
+            r#"
+            SELECT is_admin FROM public.users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(is_admin.is_some_and(|(is_admin,)| is_admin))
+    }
+
+    /// Grant or revoke admin rights, returning the user's id, or `None` if there is no user
+    /// with that username.
+    pub async fn set_admin(
+        &self,
+        username: &str,
+        is_admin: bool,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let id: Option<(Uuid,)> = sqlx::query_as(
+            r#"
+            UPDATE public.users
+            SET is_admin = $2, update_time = now()
+            WHERE username = $1::citext
+            RETURNING id
+            "#,
+        )
+        .bind(username)
+        .bind(is_admin)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(id.map(|(id,)| id))
+    }
+
+    pub async fn create(&self, user: &CreateUser) -> Result<UserRecord, sqlx::Error> {
+        let now = Utc::now();
+        let id = Uuid::now_v7();
+
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            INSERT INTO public.users
+                (id, name, email, username, avatar_url, bio, location, links,
+                 create_time, update_time)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id, name, email, username, avatar_url, bio, location, links,
+                      primary_email, primary_identity_id, create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&user.name)
+        .bind(&user.email)
+        .bind(&user.username)
+        .bind(&user.avatar_url)
+        .bind(&user.bio)
+        .bind(&user.location)
+        .bind(&user.links)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn update(
+        &self,
+        id: Uuid,
+        user: &UpdateUser,
+    ) -> Result<Option<UserRecord>, sqlx::Error> {
+        sqlx::query_as::<_, UserRecord>(
+            r#"
+            UPDATE public.users
+            SET name = COALESCE($2, name),
+                email = COALESCE($3, email),
+                username = COALESCE($4, username),
+                avatar_url = COALESCE($5, avatar_url),
+                bio = COALESCE($6, bio),
+                location = COALESCE($7, location),
+                links = COALESCE($8, links),
+                update_time = now()
+            WHERE id = $1
+            RETURNING id, name, email, username, avatar_url, bio, location, links,
+                      primary_email, primary_identity_id, create_time, update_time
+            "#,
+        )
+        .bind(id)
+        .bind(&user.name)
+        .bind(&user.email)
+        .bind(&user.username)
+        .bind(&user.avatar_url)
+        .bind(&user.bio)
+        .bind(&user.location)
+        .bind(&user.links)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Break the users -> user_identities reference before the identities cascade away
+        sqlx::query(
+            r#"
+            UPDATE public.users
+            SET primary_identity_id = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM public.users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct CreateUser {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub location: Option<String>,
+    pub links: Option<PgHstore>,
+}
+
+/// Fields left as `None` keep their stored value. The primary email is not among them, it only
+/// changes with the primary identity.
+pub struct UpdateUser {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub location: Option<String>,
+    pub links: Option<PgHstore>,
+}
+
+fn map_write_error(e: sqlx::Error) -> GeistError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => GeistError::AlreadyExists(
+            "A user with this name, email or username already exists".to_string(),
+        ),
+        _ => GeistError::from(e),
+    }
+}
 
 #[derive(Debug, Default)]
-pub struct UserServer;
+pub struct UserServer {
+    pool: Option<PgPool>,
+    pages: Paginator,
+}
+
+impl UserServer {
+    pub fn new(pool: PgPool, pages: Paginator) -> Self {
+        Self {
+            pool: Some(pool),
+            pages,
+        }
+    }
+
+    fn pool(&self) -> Result<&PgPool, Status> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| Status::internal("Database pool not initialized"))
+    }
+
+    /// Convert users to protobuf, loading their linked identities in a single query.
+    async fn hydrate(&self, users: &[UserRecord]) -> Result<Vec<User>, Status> {
+        let repo = IdentityRepository::new(self.pool()?.clone());
+        let ids: Vec<Uuid> = users.iter().map(|u| u.id).collect();
+
+        let identities = repo
+            .find_by_user_ids(&ids)
+            .await
+            .map_err(GeistError::from)?;
+
+        users.iter().map(|u| u.to_proto(&identities)).collect()
+    }
+
+    /// The profile other users may see, without the email and linked identities.
+    fn public_profile(user: &UserRecord) -> Result<User, Status> {
+        Ok(User {
+            email: String::new(),
+            ..user.to_proto(&[])?
+        })
+    }
+
+    /// Resolve the stored user a mutation refers to, by uid if present and otherwise by username.
+    async fn find_target(&self, repo: &UserRepository, user: &User) -> Result<UserRecord, Status> {
+        let record = if !user.uid.is_empty() {
+            let id = Uuid::parse_str(&user.uid)
+                .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+            repo.find_by_id(id).await.map_err(GeistError::from)?
+        } else if !user.username.is_empty() {
+            repo.find_by_username(&user.username)
+                .await
+                .map_err(GeistError::from)?
+        } else {
+            return Err(Status::invalid_argument(
+                "One of uid or username must be provided",
+            ));
+        };
+
+        record.ok_or_else(|| Status::not_found("User not found"))
+    }
+}
 
 #[tonic::async_trait]
 impl UserService for UserServer {
-    #[tracing::instrument]
-    async fn get_user(&self, _req: Request<UserRequest>) -> ServerResult<UserResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn get_user(&self, request: Request<UserRequest>) -> ServerResult<UserResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = UserRepository::new(self.pool()?.clone());
+        let by_email = matches!(req.params, Some(Params::Email(_)));
+
+        let user = match req.params {
+            Some(Params::Uid(uid)) => {
+                let id = Uuid::parse_str(&uid)
+                    .map_err(|e| Status::invalid_argument(format!("Invalid UUID: {}", e)))?;
+                repo.find_by_id(id).await
+            }
+            Some(Params::Name(name)) => repo.find_by_name(&name).await,
+            Some(Params::Email(email)) => repo.find_by_email(&email).await,
+            Some(Params::Username(username)) => repo.find_by_username(&username).await,
+            None => {
+                return Err(Status::invalid_argument(
+                    "One of uid, name, email, or username must be provided",
+                ));
+            }
+        }
+        .map_err(GeistError::from)?
+        .ok_or_else(|| Status::not_found("User not found"))?;
+
+        // The user themselves and admins see everything. Others only see the public profile,
+        // and cannot look users up by email, which would tell them who has an account.
+        let users = if principal.authorize_user(user.id).is_ok() {
+            self.hydrate(&[user]).await?
+        } else if by_email {
+            return Err(Status::not_found("User not found"));
+        } else {
+            vec![Self::public_profile(&user)?]
+        };
+
+        Ok(tonic::Response::new(UserResponse { users, page: None }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_users(&self, request: Request<ListUsersRequest>) -> ServerResult<UserResponse> {
+        // Listing every user, with their emails and identities, is for admins.
+        auth::principal(&request)?.authorize_admin()?;
+        let req = request.into_inner();
+        let repo = UserRepository::new(self.pool()?.clone());
+
+        let page = self
+            .pages
+            .request(req.page.as_ref(), &PageScope::new("users"))?;
+
+        let users = repo.list(&page).await.map_err(GeistError::from)?;
+        let total = repo.count().await.map_err(GeistError::from)?;
+        let (users, page) = self.pages.response(&page, users, total, |user| {
+            Position::new(user.create_time, user.id)
+        });
+
+        Ok(tonic::Response::new(UserResponse {
+            users: self.hydrate(&users).await?,
+            page: Some(page),
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_user(&self, request: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
+        // Users normally come into existence by signing in, creating them directly is for admins.
+        auth::principal(&request)?.authorize_admin()?;
+        let req = request.into_inner();
+        let repo = UserRepository::new(self.pool()?.clone());
+
+        let user = req
+            .user
+            .ok_or_else(|| Status::invalid_argument("User must be provided"))?;
+
+        if user.username.is_empty() {
+            return Err(Status::invalid_argument("Username must be provided"));
+        }
+
+        let create_user = CreateUser {
+            name: non_empty(user.name),
+            email: non_empty(user.email),
+            username: user.username,
+            avatar_url: non_empty(user.avatar_url),
+            bio: non_empty(user.bio),
+            location: non_empty(user.location),
+            links: links_to_hstore(user.links),
+        };
+
+        let created = repo.create(&create_user).await.map_err(map_write_error)?;
+
+        Ok(tonic::Response::new(UserResponse {
+            users: self.hydrate(&[created]).await?,
+            page: None,
+        }))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn update_user(&self, request: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = UserRepository::new(self.pool()?.clone());
+
+        let user = req
+            .user
+            .ok_or_else(|| Status::invalid_argument("User must be provided"))?;
+        let existing = self.find_target(&repo, &user).await?;
+        principal.authorize_user(existing.id)?;
+
+        let update_user = UpdateUser {
+            // Only rename when the user was addressed by uid.
+            username: if user.uid.is_empty() {
+                None
+            } else {
+                non_empty(user.username)
+            },
+            name: non_empty(user.name),
+            email: non_empty(user.email),
+            avatar_url: non_empty(user.avatar_url),
+            bio: non_empty(user.bio),
+            location: non_empty(user.location),
+            links: links_to_hstore(user.links),
+        };
+
+        let updated = repo
+            .update(existing.id, &update_user)
+            .await
+            .map_err(map_write_error)?
+            .ok_or_else(|| Status::not_found("User not found"))?;
+
+        Ok(tonic::Response::new(UserResponse {
+            users: self.hydrate(&[updated]).await?,
+            page: None,
+        }))
     }
 
-    #[tracing::instrument]
-    async fn list_users(&self, _req: Request<ListUsersRequest>) -> ServerResult<UserResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn delete_user(&self, request: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = UserRepository::new(self.pool()?.clone());
+
+        let user = req
+            .user
+            .ok_or_else(|| Status::invalid_argument("User must be provided"))?;
+        let existing = self.find_target(&repo, &user).await?;
+        principal.authorize_user(existing.id)?;
+
+        // Hydrate before deleting, the identities cascade away with the user.
+        let users = self.hydrate(std::slice::from_ref(&existing)).await?;
+
+        if !repo.delete(existing.id).await.map_err(GeistError::from)? {
+            return Err(Status::not_found("User not found"));
+        }
+
+        Ok(tonic::Response::new(UserResponse { users, page: None }))
     }
 
-    #[tracing::instrument]
-    async fn create_user(&self, _req: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    #[tracing::instrument(skip(self))]
+    async fn list_user_groups(
+        &self,
+        request: Request<ListUserGroupsRequest>,
+    ) -> ServerResult<ListUserGroupsResponse> {
+        let principal = auth::principal(&request)?.clone();
+        let req = request.into_inner();
+        let repo = GroupMemberRepository::new(self.pool()?.clone());
+
+        let user_id = parse_uid(&req.user_uid)?.unwrap_or(principal.user_uid);
+        principal.authorize_user(user_id)?;
+
+        let page = self.pages.request(
+            req.page.as_ref(),
+            &PageScope::new("user_groups").with("user", user_id),
+        )?;
+
+        let memberships = repo
+            .list_by_user(user_id, &page)
+            .await
+            .map_err(GeistError::from)?;
+        let total = repo
+            .count_by_user(user_id)
+            .await
+            .map_err(GeistError::from)?;
+        let (memberships, page) = self.pages.response(&page, memberships, total, |m| {
+            Position::new(m.join_time, m.group.id)
+        });
+
+        let proto_memberships: Result<Vec<_>, _> =
+            memberships.iter().map(|m| m.to_proto()).collect();
+
+        Ok(tonic::Response::new(ListUserGroupsResponse {
+            memberships: proto_memberships?,
+            page: Some(page),
+        }))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Principal, ADMIN_SCOPE};
+    use crate::meta::CreateIdentity;
 
-    #[tracing::instrument]
-    async fn update_user(&self, _req: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    fn request<T>(message: T, user_uid: Uuid, scopes: &[&str]) -> Request<T> {
+        let mut request = Request::new(message);
+        request.extensions_mut().insert(Principal {
+            user_uid,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        });
+        request
     }
 
-    #[tracing::instrument]
-    async fn delete_user(&self, _req: Request<MutateUserRequest>) -> ServerResult<UserResponse> {
-        Err(Status::unimplemented("rpc not implemented"))
+    async fn create_user(pool: &PgPool, username: &str) -> UserRecord {
+        let user = UserRepository::new(pool.clone())
+            .create(&CreateUser {
+                name: None,
+                email: Some(format!("{}@example.com", username)),
+                username: username.to_string(),
+                avatar_url: None,
+                bio: None,
+                location: None,
+                links: None,
+            })
+            .await
+            .unwrap();
+
+        IdentityRepository::new(pool.clone())
+            .attach(&CreateIdentity {
+                id: Uuid::now_v7(),
+                user_id: user.id,
+                provider: "google".to_string(),
+                provider_user_id: username.to_string(),
+                provider_email: Some(format!("{}@example.com", username)),
+                provider_username: None,
+                provider_avatar_url: None,
+                access_token_encrypted: None,
+                refresh_token_encrypted: None,
+                token_expires_at: None,
+                metadata: None,
+                is_primary: true,
+                verified: true,
+            })
+            .await
+            .unwrap();
+
+        user
+    }
+
+    fn by_uid(user: &UserRecord) -> UserRequest {
+        UserRequest {
+            params: Some(Params::Uid(user.id.to_string())),
+            page: None,
+        }
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn get_user_shows_private_fields_to_the_user_and_admins(pool: PgPool) {
+        let server = UserServer::new(pool.clone(), Paginator::default());
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+
+        for (caller, scopes) in [(alice.id, &[][..]), (bob.id, &[ADMIN_SCOPE][..])] {
+            let users = server
+                .get_user(request(by_uid(&alice), caller, scopes))
+                .await
+                .unwrap()
+                .into_inner()
+                .users;
+            assert_eq!(users[0].email, "alice@example.com");
+            assert_eq!(users[0].identities.len(), 1);
+            assert!(users[0].primary_identity.is_some());
+        }
+
+        let users = server
+            .get_user(request(by_uid(&alice), bob.id, &[]))
+            .await
+            .unwrap()
+            .into_inner()
+            .users;
+        assert_eq!(users[0].username, "alice");
+        assert_eq!(users[0].email, "");
+        assert!(users[0].identities.is_empty());
+        assert!(users[0].primary_identity.is_none());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn only_admins_find_other_users_by_email_or_list_users(pool: PgPool) {
+        let server = UserServer::new(pool.clone(), Paginator::default());
+        let alice = create_user(&pool, "alice").await;
+        let bob = create_user(&pool, "bob").await;
+        let by_email = || UserRequest {
+            params: Some(Params::Email("alice@example.com".to_string())),
+            page: None,
+        };
+
+        let status = server
+            .get_user(request(by_email(), bob.id, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        for (caller, scopes) in [(alice.id, &[][..]), (bob.id, &[ADMIN_SCOPE][..])] {
+            let users = server
+                .get_user(request(by_email(), caller, scopes))
+                .await
+                .unwrap()
+                .into_inner()
+                .users;
+            assert_eq!(users[0].uid, alice.id.to_string());
+        }
+
+        let status = server
+            .list_users(request(ListUsersRequest { page: None }, bob.id, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let users = server
+            .list_users(request(
+                ListUsersRequest { page: None },
+                bob.id,
+                &[ADMIN_SCOPE],
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .users;
+        assert_eq!(users.len(), 2);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn primary_email_only_comes_from_verified_identities(pool: PgPool) {
+        let server = UserServer::new(pool.clone(), Paginator::default());
+        let repo = UserRepository::new(pool.clone());
+        let identities = IdentityRepository::new(pool.clone());
+        let alice = create_user(&pool, "alice").await;
+        let primary_email = |user: Option<UserRecord>| user.unwrap().primary_email;
+        assert_eq!(
+            primary_email(repo.find_by_id(alice.id).await.unwrap()).as_deref(),
+            Some("alice@example.com")
+        );
+
+        let update = MutateUserRequest {
+            user: Some(User {
+                uid: alice.id.to_string(),
+                email: "victim@example.com".to_string(),
+                ..Default::default()
+            }),
+        };
+        let users = server
+            .update_user(request(update, alice.id, &[]))
+            .await
+            .unwrap()
+            .into_inner()
+            .users;
+        assert_eq!(users[0].email, "victim@example.com");
+        assert_eq!(
+            primary_email(repo.find_by_id(alice.id).await.unwrap()).as_deref(),
+            Some("alice@example.com")
+        );
+
+        for (provider, email, verified) in [
+            ("github", "unverified@example.com", false),
+            ("microsoft", "work@example.com", true),
+        ] {
+            let identity = identities
+                .attach(&CreateIdentity {
+                    id: Uuid::now_v7(),
+                    user_id: alice.id,
+                    provider: provider.to_string(),
+                    provider_user_id: "alice".to_string(),
+                    provider_email: Some(email.to_string()),
+                    provider_username: None,
+                    provider_avatar_url: None,
+                    access_token_encrypted: None,
+                    refresh_token_encrypted: None,
+                    token_expires_at: None,
+                    metadata: None,
+                    is_primary: false,
+                    verified,
+                })
+                .await
+                .unwrap();
+            identities.set_primary(identity.id, alice.id).await.unwrap();
+
+            let expected = if verified { email } else { "alice@example.com" };
+            assert_eq!(
+                primary_email(repo.find_by_id(alice.id).await.unwrap()).as_deref(),
+                Some(expected)
+            );
+        }
     }
 }
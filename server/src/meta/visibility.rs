@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Who can see feeds and groups. Owners (the creator of a feed, the members of a group) and
+//! admins always see them; everyone else depends on the visibility level and the environment
+//! the server runs in:
+//!
+//! | Visibility | Who else can see it                                   |
+//! |------------|-------------------------------------------------------|
+//! | GLOBAL     | Every signed-in user                                  |
+//! | PUBLIC     | Every signed-in user                                  |
+//! | INTERNAL   | Every signed-in user                                  |
+//! | PREVIEW    | Signed-in users in Preview and Staging                |
+//! | PRIVATE    | Nobody                                                |
+//!
+//! Every request must be signed in, so GLOBAL, PUBLIC and INTERNAL currently grant the same
+//! access, in every environment.
+//!
+//! Resources that cannot be seen are reported as not found, so their existence is not revealed.
+
+use super::page::PageScope;
+use crate::auth::Principal;
+use geist_sdk::Environment;
+use tonic::Request;
+use uuid::Uuid;
+
+/// Every label of the `*_visibility` Postgres enums.
+const LABELS: [&str; 5] = ["global", "public", "internal", "preview", "private"];
+
+/// Whether a resource with the visibility `label` can be seen by a caller that does not own it.
+pub fn is_visible(label: &str, signed_in: bool, environment: Environment) -> bool {
+    match label {
+        "global" | "public" | "internal" => signed_in,
+        "preview" => {
+            signed_in && matches!(environment, Environment::Preview | Environment::Staging)
+        }
+        _ => false,
+    }
+}
+
+/// Applies the visibility rules for the environment the server runs in.
+#[derive(Debug, Clone, Copy)]
+pub struct VisibilityPolicy {
+    environment: Environment,
+}
+
+impl Default for VisibilityPolicy {
+    /// Production is the most restrictive environment.
+    fn default() -> Self {
+        Self::new(Environment::Production)
+    }
+}
+
+impl VisibilityPolicy {
+    pub fn new(environment: Environment) -> Self {
+        Self { environment }
+    }
+
+    /// The viewer making a request, anonymous when it did not pass through `TokenInterceptor`.
+    pub fn viewer<T>(&self, request: &Request<T>) -> Viewer {
+        let principal = request.extensions().get::<Principal>();
+
+        Viewer {
+            user_id: principal.map(|p| p.user_uid),
+            is_admin: principal.is_some_and(Principal::is_admin),
+            labels: LABELS
+                .into_iter()
+                .filter(|label| is_visible(label, principal.is_some(), self.environment))
+                .collect(),
+        }
+    }
+}
+
+/// The caller of a request, as far as visibility is concerned.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub user_id: Option<Uuid>,
+    pub is_admin: bool,
+    /// Visibility labels the viewer can see without owning the resource.
+    pub labels: Vec<&'static str>,
+}
+
+impl Viewer {
+    /// Whether the viewer can see a resource with the given visibility label.
+    pub fn can_see(&self, label: &str, is_owner: bool) -> bool {
+        self.is_admin || is_owner || self.labels.contains(&label)
+    }
+
+    /// Add what the viewer can see to the scope of a listing that is filtered by visibility.
+    pub fn page_scope(&self, scope: PageScope) -> PageScope {
+        scope
+            .with_opt("viewer", self.user_id)
+            .with("admin", self.is_admin)
+            .with("labels", self.labels.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ADMIN_SCOPE;
+
+    #[test]
+    fn visibility_matrix() {
+        use Environment::*;
+
+        // Who besides owners and admins sees each label, as (environment, label, signed in,
+        // anonymous).
+        let matrix = [
+            (Development, "global", true, false),
+            (Development, "public", true, false),
+            (Development, "internal", true, false),
+            (Development, "preview", false, false),
+            (Development, "private", false, false),
+            (Preview, "global", true, false),
+            (Preview, "public", true, false),
+            (Preview, "internal", true, false),
+            (Preview, "preview", true, false),
+            (Preview, "private", false, false),
+            (Staging, "global", true, false),
+            (Staging, "public", true, false),
+            (Staging, "internal", true, false),
+            (Staging, "preview", true, false),
+            (Staging, "private", false, false),
+            (Production, "global", true, false),
+            (Production, "public", true, false),
+            (Production, "internal", true, false),
+            (Production, "preview", false, false),
+            (Production, "private", false, false),
+        ];
+
+        for (environment, label, signed_in, anonymous) in matrix {
+            let case = format!("{} in {:?}", label, environment);
+            let policy = VisibilityPolicy::new(environment);
+
+            let mut request = Request::new(());
+            let user = policy.viewer(&request);
+            assert_eq!(user.can_see(label, false), anonymous, "anonymous, {}", case);
+
+            request.extensions_mut().insert(Principal {
+                user_uid: Uuid::now_v7(),
+                scopes: Vec::new(),
+            });
+            let user = policy.viewer(&request);
+            assert_eq!(user.can_see(label, false), signed_in, "signed in, {}", case);
+            assert!(user.can_see(label, true), "owner, {}", case);
+
+            request.extensions_mut().insert(Principal {
+                user_uid: Uuid::now_v7(),
+                scopes: vec![ADMIN_SCOPE.to_string()],
+            });
+            let admin = policy.viewer(&request);
+            assert!(admin.can_see(label, false), "admin, {}", case);
+        }
+    }
+}
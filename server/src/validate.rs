@@ -0,0 +1,764 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request validation from the `buf.validate` annotations in the protobufs. The rules are read
+//! from the SDK's file descriptor set when the server starts, and every request message is
+//! checked against the rules of its method before it reaches a service.
+//!
+//! The rules the protobufs use are evaluated: `uuid`, `email`, `uri`, `min_len` and `max_len`
+//! on strings, the range rules on `uint32`, and `ignore`. As in protovalidate, proto3 fields
+//! without explicit presence are checked at their zero value when they are not on the wire,
+//! unless they set `ignore = IGNORE_IF_ZERO_VALUE`. Loading fails on any other rule, so that
+//! the protobufs cannot declare a rule that is not enforced.
+
+use crate::descriptor::{self, collect_messages, qualify, DescriptorProto, FieldDescriptorProto};
+use crate::descriptor::{FieldRules, StringRules, UInt32Rules};
+use http_body_util::{BodyExt, Full, Limited};
+use prost::bytes::Buf;
+use prost::encoding::{decode_varint, WireType};
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::Body;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, FieldViolation, StatusExt};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+/// Matches the limit tonic places on decoded messages by default.
+const MAX_REQUEST_SIZE: usize = 4 * 1024 * 1024;
+
+/// A gRPC frame starts with a compression flag and a big-endian length.
+const FRAME_HEADER_LEN: usize = 5;
+
+const UUID_VIOLATION: &str = "value must be a valid UUID";
+
+/// The rules the validator evaluates, by field of `buf.validate.FieldRules`: the field type a
+/// rule applies to, if any, and the fields of its rule message that are evaluated.
+const SUPPORTED_RULES: &[(&str, Option<i32>, &[&str])] = &[
+    ("ignore", None, &[]),
+    (
+        "string",
+        Some(descriptor::TYPE_STRING),
+        &["min_len", "max_len", "email", "uri", "uuid"],
+    ),
+    (
+        "uint32",
+        Some(descriptor::TYPE_UINT32),
+        &["lt", "lte", "gt", "gte"],
+    ),
+];
+
+#[derive(Debug)]
+pub enum RulesError {
+    /// The descriptor set, or the rules in it, could not be decoded.
+    Decode(prost::DecodeError),
+    /// Rules the validator does not evaluate, as `<element>: <rule>`.
+    Unsupported(Vec<String>),
+}
+
+impl std::fmt::Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RulesError::Decode(e) => write!(f, "{}", e),
+            RulesError::Unsupported(rules) => {
+                write!(f, "Unsupported rules: {}", rules.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RulesError {}
+
+impl From<prost::DecodeError> for RulesError {
+    fn from(e: prost::DecodeError) -> Self {
+        RulesError::Decode(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Check {
+    String(StringRules),
+    UInt32(UInt32Rules),
+    /// A message with rules on some of its fields, by fully qualified name.
+    Message(String),
+}
+
+#[derive(Debug, Clone)]
+struct FieldCheck {
+    name: String,
+    repeated: bool,
+    /// A proto3 scalar without explicit presence, which is not on the wire when it holds its
+    /// zero value.
+    implicit: bool,
+    /// Not checked at its zero value, from `ignore = IGNORE_IF_ZERO_VALUE`.
+    ignore_zero: bool,
+    check: Check,
+}
+
+/// The validation rules of every request message, by field number.
+#[derive(Debug, Default)]
+pub struct Validator {
+    messages: HashMap<String, HashMap<u32, FieldCheck>>,
+    /// The request message of each method with rules, by gRPC path.
+    methods: HashMap<String, String>,
+}
+
+impl Validator {
+    /// Read the rules from an encoded `FileDescriptorSet`, such as
+    /// `geist_sdk::pb::FILE_DESCRIPTOR_SET`. Fails when the protobufs use a rule that is not
+    /// evaluated.
+    pub fn from_descriptor_set(bytes: &[u8]) -> Result<Self, RulesError> {
+        let set = descriptor::FileDescriptorSet::decode(bytes)?;
+
+        let mut descriptors = HashMap::new();
+        let mut proto3 = HashSet::new();
+        for file in &set.file {
+            let mut file_descriptors = HashMap::new();
+            collect_messages(
+                &qualify("", &file.package),
+                &file.message_type,
+                &mut file_descriptors,
+            );
+            if file.syntax == "proto3" {
+                proto3.extend(file_descriptors.keys().cloned());
+            }
+            descriptors.extend(file_descriptors);
+        }
+
+        let unsupported = unsupported_rules(&descriptors)?;
+        if !unsupported.is_empty() {
+            return Err(RulesError::Unsupported(unsupported));
+        }
+
+        // A message is checked when one of its fields has a rule, or is a checked message.
+        let mut checked: HashSet<&str> = HashSet::new();
+        loop {
+            let before = checked.len();
+            for (name, message) in &descriptors {
+                let has_rules = message.field.iter().any(|field| {
+                    scalar_check(field).is_some()
+                        || (field.r#type == descriptor::TYPE_MESSAGE
+                            && checked.contains(field.type_name.as_str()))
+                });
+                if has_rules {
+                    checked.insert(name.as_str());
+                }
+            }
+            if checked.len() == before {
+                break;
+            }
+        }
+
+        let mut messages = HashMap::new();
+        for &name in &checked {
+            let fields = descriptors[name]
+                .field
+                .iter()
+                .filter_map(|field| {
+                    let check = scalar_check(field).or_else(|| {
+                        (field.r#type == descriptor::TYPE_MESSAGE
+                            && checked.contains(field.type_name.as_str()))
+                        .then(|| Check::Message(field.type_name.clone()))
+                    })?;
+                    Some((
+                        field.number as u32,
+                        FieldCheck {
+                            name: field.name.clone(),
+                            repeated: field.label == descriptor::LABEL_REPEATED,
+                            implicit: proto3.contains(name)
+                                && field.label != descriptor::LABEL_REPEATED
+                                && field.r#type != descriptor::TYPE_MESSAGE
+                                && field.oneof_index.is_none(),
+                            ignore_zero: field_rules(field).is_some_and(|rules| {
+                                rules.ignore == descriptor::IGNORE_IF_ZERO_VALUE
+                            }),
+                            check,
+                        },
+                    ))
+                })
+                .collect();
+            messages.insert(name.to_string(), fields);
+        }
+
+        // Streamed requests are not buffered, so only unary requests are checked.
+        let mut methods = HashMap::new();
+        for file in &set.file {
+            for service in &file.service {
+                let service_name = qualify(&qualify("", &file.package), &service.name);
+                for method in &service.method {
+                    if !method.client_streaming && messages.contains_key(&method.input_type) {
+                        let path = format!("/{}/{}", &service_name[1..], method.name);
+                        methods.insert(path, method.input_type.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(Self { messages, methods })
+    }
+
+    /// The request message of the method at `path`, when it has rules.
+    fn request_message(&self, path: &str) -> Option<&str> {
+        self.methods.get(path).map(String::as_str)
+    }
+
+    /// Check each gRPC frame of a request body. Compressed or truncated frames are passed on
+    /// unchecked, for the service to accept or reject.
+    fn check_frames(&self, message: &str, mut body: &[u8]) -> Vec<FieldViolation> {
+        let mut violations = Vec::new();
+        while body.len() >= FRAME_HEADER_LEN {
+            let compressed = body[0] != 0;
+            let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+            let Some(frame) = body.get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len) else {
+                break;
+            };
+            if !compressed {
+                self.check(message, frame, "", &mut violations);
+            }
+            body = &body[FRAME_HEADER_LEN + len..];
+        }
+        violations
+    }
+
+    /// Check the fields of an encoded message. Decoding stops at the first malformed field,
+    /// which the service rejects when it decodes the message itself.
+    fn check(
+        &self,
+        message: &str,
+        mut buf: &[u8],
+        path: &str,
+        violations: &mut Vec<FieldViolation>,
+    ) {
+        let Some(fields) = self.messages.get(message) else {
+            return;
+        };
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+
+        while buf.has_remaining() {
+            let Some((number, value)) = next_field(&mut buf) else {
+                return;
+            };
+            let Some(field) = fields.get(&number) else {
+                continue;
+            };
+
+            let count = counts.entry(number).or_default();
+            *count += 1;
+            if field.ignore_zero && value.is_zero() {
+                continue;
+            }
+            let field_path = field_path(path, &field.name, field.repeated.then_some(*count - 1));
+
+            let description = match (&field.check, value) {
+                (Check::String(rules), Value::Bytes(bytes)) => match std::str::from_utf8(bytes) {
+                    Ok(value) => check_string(rules, value),
+                    Err(_) => None,
+                },
+                (Check::UInt32(rules), Value::Varint(value)) => check_uint32(rules, value as u32),
+                (Check::Message(name), Value::Bytes(bytes)) => {
+                    self.check(name, bytes, &field_path, violations);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(description) = description {
+                violations.push(FieldViolation::new(field_path, description));
+            }
+        }
+
+        // Fields without presence holding their zero value are not on the wire, but their
+        // rules still apply to it.
+        let mut absent: Vec<_> = fields
+            .iter()
+            .filter(|(number, field)| {
+                field.implicit && !field.ignore_zero && !counts.contains_key(number)
+            })
+            .collect();
+        absent.sort_by_key(|(number, _)| **number);
+        for (_, field) in absent {
+            let description = match &field.check {
+                Check::String(rules) => check_string(rules, ""),
+                Check::UInt32(rules) => check_uint32(rules, 0),
+                Check::Message(_) => None,
+            };
+            if let Some(description) = description {
+                violations.push(FieldViolation::new(
+                    field_path(path, &field.name, None),
+                    description,
+                ));
+            }
+        }
+    }
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// A 32 or 64-bit value, which no rule applies to.
+    Fixed,
+}
+
+impl Value<'_> {
+    fn is_zero(&self) -> bool {
+        match self {
+            Value::Varint(value) => *value == 0,
+            Value::Bytes(bytes) => bytes.is_empty(),
+            Value::Fixed => false,
+        }
+    }
+}
+
+/// Decode the next field of an encoded message, or `None` when it is malformed.
+fn next_field<'a>(buf: &mut &'a [u8]) -> Option<(u32, Value<'a>)> {
+    let key = decode_varint(buf).ok()?;
+    let wire_type = WireType::try_from(key & 0x7).ok()?;
+    let number = (key >> 3) as u32;
+
+    let value = match wire_type {
+        WireType::Varint => Value::Varint(decode_varint(buf).ok()?),
+        WireType::LengthDelimited => {
+            let len = decode_varint(buf).ok()?;
+            let bytes = buf.get(..usize::try_from(len).ok()?)?;
+            buf.advance(bytes.len());
+            Value::Bytes(bytes)
+        }
+        WireType::SixtyFourBit | WireType::ThirtyTwoBit => {
+            let len = if wire_type == WireType::SixtyFourBit {
+                8
+            } else {
+                4
+            };
+            if buf.remaining() < len {
+                return None;
+            }
+            buf.advance(len);
+            Value::Fixed
+        }
+        WireType::StartGroup | WireType::EndGroup => return None,
+    };
+    Some((number, value))
+}
+
+/// The `(buf.validate.field)` rules of a field. They were decoded once when looking for
+/// unsupported rules, so decoding them again does not fail.
+fn field_rules(field: &FieldDescriptorProto) -> Option<FieldRules> {
+    let bytes = field.options.as_ref()?.rules.as_deref()?;
+    FieldRules::decode(bytes).ok()
+}
+
+fn scalar_check(field: &FieldDescriptorProto) -> Option<Check> {
+    let rules = field_rules(field)?;
+    if rules.ignore == descriptor::IGNORE_ALWAYS {
+        return None;
+    }
+    match field.r#type {
+        descriptor::TYPE_STRING => rules.string.map(Check::String),
+        descriptor::TYPE_UINT32 => rules.uint32.map(Check::UInt32),
+        _ => None,
+    }
+}
+
+/// Find the `buf.validate` rules of the messages that the validator does not evaluate. Rules
+/// are named after their fields in `buf/validate/validate.proto`, which the descriptor set
+/// includes.
+fn unsupported_rules(
+    descriptors: &HashMap<String, &DescriptorProto>,
+) -> Result<Vec<String>, prost::DecodeError> {
+    let mut unsupported = Vec::new();
+    let mut names: Vec<_> = descriptors.keys().collect();
+    names.sort();
+    for name in names {
+        let message = descriptors[name];
+        let name = &name[1..];
+        if message
+            .options
+            .as_ref()
+            .is_some_and(|options| options.rules.is_some())
+        {
+            unsupported.push(format!("{}: (buf.validate.message)", name));
+        }
+        for oneof in &message.oneof_decl {
+            if oneof
+                .options
+                .as_ref()
+                .is_some_and(|options| options.rules.is_some())
+            {
+                unsupported.push(format!("{}.{}: (buf.validate.oneof)", name, oneof.name));
+            }
+        }
+
+        for field in &message.field {
+            let Some(mut buf) = field
+                .options
+                .as_ref()
+                .and_then(|options| options.rules.as_deref())
+            else {
+                continue;
+            };
+            FieldRules::decode(buf)?;
+
+            let mut rules = Vec::new();
+            while let Some((number, value)) = next_field(&mut buf) {
+                let (rule, type_name) = rule_field(descriptors, ".buf.validate.FieldRules", number);
+                let supported = SUPPORTED_RULES.iter().find(|(supported, field_type, _)| {
+                    *supported == rule && field_type.is_none_or(|t| t == field.r#type)
+                });
+                let (Some((_, _, evaluated)), Value::Bytes(mut nested)) = (supported, value) else {
+                    if supported.is_none() {
+                        rules.push(rule);
+                    }
+                    continue;
+                };
+                while let Some((number, _)) = next_field(&mut nested) {
+                    let (nested_rule, _) = rule_field(descriptors, &type_name, number);
+                    if !evaluated.contains(&nested_rule.as_str()) {
+                        rules.push(format!("{}.{}", rule, nested_rule));
+                    }
+                }
+            }
+            rules.dedup();
+            for rule in rules {
+                unsupported.push(format!("{}.{}: {}", name, field.name, rule));
+            }
+        }
+    }
+    Ok(unsupported)
+}
+
+/// The name and message type of a field of a `buf.validate` message, or its number when the
+/// descriptor set does not have it.
+fn rule_field(
+    descriptors: &HashMap<String, &DescriptorProto>,
+    message: &str,
+    number: u32,
+) -> (String, String) {
+    descriptors
+        .get(message)
+        .and_then(|message| {
+            message
+                .field
+                .iter()
+                .find(|field| field.number as u32 == number)
+        })
+        .map(|field| (field.name.clone(), field.type_name.clone()))
+        .unwrap_or_else(|| (number.to_string(), String::new()))
+}
+
+fn field_path(parent: &str, name: &str, index: Option<usize>) -> String {
+    let mut path = if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    };
+    if let Some(index) = index {
+        path.push_str(&format!("[{}]", index));
+    }
+    path
+}
+
+fn check_string(rules: &StringRules, value: &str) -> Option<String> {
+    let len = value.chars().count() as u64;
+    if let Some(min_len) = rules.min_len.filter(|min_len| len < *min_len) {
+        return Some(format!(
+            "value length must be at least {} characters",
+            min_len
+        ));
+    }
+    if let Some(max_len) = rules.max_len.filter(|max_len| len > *max_len) {
+        return Some(format!(
+            "value length must be at most {} characters",
+            max_len
+        ));
+    }
+    if rules.uuid && !is_uuid(value) {
+        return Some(UUID_VIOLATION.to_string());
+    }
+    if rules.email && !is_email(value) {
+        return Some("value must be a valid email address".to_string());
+    }
+    if rules.uri && url::Url::parse(value).is_err() {
+        return Some("value must be a valid URI".to_string());
+    }
+    None
+}
+
+fn check_uint32(rules: &UInt32Rules, value: u32) -> Option<String> {
+    if let Some(lt) = rules.lt.filter(|lt| value >= *lt) {
+        return Some(format!("value must be less than {}", lt));
+    }
+    if let Some(lte) = rules.lte.filter(|lte| value > *lte) {
+        return Some(format!("value must be less than or equal to {}", lte));
+    }
+    if let Some(gt) = rules.gt.filter(|gt| value <= *gt) {
+        return Some(format!("value must be greater than {}", gt));
+    }
+    if let Some(gte) = rules.gte.filter(|gte| value < *gte) {
+        return Some(format!("value must be greater than or equal to {}", gte));
+    }
+    None
+}
+
+/// A UUID in its hyphenated form, the only one the `uuid` rule accepts.
+fn is_uuid(value: &str) -> bool {
+    value.len() == 36 && Uuid::parse_str(value).is_ok()
+}
+
+/// An email address as accepted by HTML forms, which is what the `email` rule checks.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// An `INVALID_ARGUMENT` status with the violations as `google.rpc.BadRequest` details.
+pub fn invalid_argument(violations: Vec<FieldViolation>) -> Status {
+    let summary = violations
+        .iter()
+        .map(|violation| format!("{}: {}", violation.field, violation.description))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    Status::with_error_details(
+        Code::InvalidArgument,
+        format!("Invalid request: {}", summary),
+        ErrorDetails::with_bad_request(violations),
+    )
+}
+
+/// Parse a UUID field that the handler requires, failing like the `uuid` rule when it is
+/// empty or malformed.
+pub fn parse_uuid(field: &str, value: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(value)
+        .ok()
+        .filter(|_| is_uuid(value))
+        .ok_or_else(|| invalid_argument(vec![FieldViolation::new(field, UUID_VIOLATION)]))
+}
+
+/// Rejects requests that break the validation rules of their method with `INVALID_ARGUMENT`.
+#[derive(Debug, Clone)]
+pub struct ValidateLayer {
+    validator: Arc<Validator>,
+}
+
+impl ValidateLayer {
+    pub fn new(validator: Validator) -> Self {
+        Self {
+            validator: Arc::new(validator),
+        }
+    }
+}
+
+impl<S> Layer<S> for ValidateLayer {
+    type Service = ValidateService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ValidateService {
+            inner,
+            validator: self.validator.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidateService<S> {
+    inner: S,
+    validator: Arc<Validator>,
+}
+
+impl<S, ResBody> Service<http::Request<Body>> for ValidateService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // Call the service that was polled ready, leaving a clone for the next request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let Some(message) = self.validator.request_message(request.uri().path()) else {
+            return Box::pin(inner.call(request));
+        };
+        let message = message.to_string();
+        let validator = self.validator.clone();
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let bytes = match Limited::new(body, MAX_REQUEST_SIZE).collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(e) => {
+                    let status = Status::invalid_argument(format!("Failed to read request: {}", e));
+                    return Ok(status.into_http());
+                }
+            };
+
+            let violations = validator.check_frames(&message, &bytes);
+            if !violations.is_empty() {
+                tracing::debug!(path = %parts.uri.path(), ?violations, "Rejected invalid request");
+                return Ok(invalid_argument(violations).into_http());
+            }
+
+            inner
+                .call(http::Request::from_parts(
+                    parts,
+                    Body::new(Full::new(bytes)),
+                ))
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{FieldOptions, FileDescriptorProto, FileDescriptorSet};
+    use geist_sdk::pb::meta::v1alpha::{EntryRequest, Feed, FeedRequest, MutateFeedRequest};
+    use geist_sdk::pb::rpc::Pagination;
+
+    fn violations(message: &str, request: impl Message) -> Vec<(String, String)> {
+        let validator = Validator::from_descriptor_set(geist_sdk::pb::FILE_DESCRIPTOR_SET).unwrap();
+        let mut violations = Vec::new();
+        validator.check(message, &request.encode_to_vec(), "", &mut violations);
+        violations
+            .into_iter()
+            .map(|violation| (violation.field, violation.description))
+            .collect()
+    }
+
+    /// A string field of `test.Request` with the given encoded `(buf.validate.field)` rules.
+    fn string_field(name: &str, number: i32, rules: Vec<u8>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: name.to_string(),
+            number,
+            label: 1,
+            r#type: descriptor::TYPE_STRING,
+            options: Some(FieldOptions { rules: Some(rules) }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn zero_values_are_checked() {
+        assert_eq!(
+            violations(".geist.meta.v1alpha.EntryRequest", EntryRequest::default()),
+            [("uid".to_string(), UUID_VIOLATION.to_string())]
+        );
+        assert_eq!(
+            violations(
+                ".geist.meta.v1alpha.EntryRequest",
+                EntryRequest {
+                    uid: Uuid::now_v7().to_string(),
+                    ..Default::default()
+                }
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn fields_with_presence_are_only_checked_when_set() {
+        // Fields of a oneof, and messages.
+        assert_eq!(
+            violations(".geist.meta.v1alpha.FeedRequest", FeedRequest::default()),
+            []
+        );
+        assert_eq!(
+            violations(
+                ".geist.meta.v1alpha.MutateFeedRequest",
+                MutateFeedRequest::default()
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn ignored_zero_values_are_not_checked() {
+        let request = |uid: &str| MutateFeedRequest {
+            feed: Some(Feed {
+                uid: uid.to_string(),
+                ..Default::default()
+            }),
+        };
+
+        assert_eq!(
+            violations(".geist.meta.v1alpha.MutateFeedRequest", request("")),
+            []
+        );
+        assert_eq!(
+            violations(".geist.meta.v1alpha.MutateFeedRequest", request("feed")),
+            [("feed.uid".to_string(), UUID_VIOLATION.to_string())]
+        );
+        assert_eq!(
+            violations(
+                ".geist.rpc.Pagination",
+                Pagination {
+                    size: 101,
+                    ..Default::default()
+                }
+            ),
+            [(
+                "size".to_string(),
+                "value must be less than or equal to 100".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn unsupported_rules_fail_to_load() {
+        let mut set = FileDescriptorSet::decode(geist_sdk::pb::FILE_DESCRIPTOR_SET).unwrap();
+        set.file.push(FileDescriptorProto {
+            package: "test".to_string(),
+            syntax: "proto3".to_string(),
+            message_type: vec![DescriptorProto {
+                name: "Request".to_string(),
+                field: vec![
+                    // string.uuid and string.pattern
+                    string_field("uid", 1, vec![0x72, 0x05, 0xb0, 0x01, 0x01, 0x32, 0x00]),
+                    // required
+                    string_field("name", 2, vec![0xc8, 0x01, 0x01]),
+                    // uint32.lte on a string
+                    string_field("email", 3, vec![0x2a, 0x02, 0x18, 0x01]),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+
+        let Err(RulesError::Unsupported(rules)) =
+            Validator::from_descriptor_set(&set.encode_to_vec())
+        else {
+            panic!("unsupported rules were accepted");
+        };
+        assert_eq!(
+            rules,
+            [
+                "test.Request.uid: string.pattern",
+                "test.Request.name: required",
+                "test.Request.email: uint32",
+            ]
+        );
+    }
+}